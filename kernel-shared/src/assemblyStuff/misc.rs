@@ -1,9 +1,9 @@
-const PIC1_DATA: u16 = 0x21;
-const PIC2_DATA: u16 = 0xA1;
+// Interrupt Flag (IF) in the (E/R)FLAGS register
+const FLAGS_INTERRUPT_ENABLE: usize = 1 << 9;
 
 use core::arch::asm;
 
-use super::ports::outB;
+use crate::pic;
 
 pub fn Breakpoint() {
     unsafe {
@@ -17,9 +17,52 @@ pub fn DivideByZero() {
     }
 }
 
+// Mask only. Stage2 calls this in real mode and still needs the BIOS, which expects the PICs where it put them, so
+// moving them off the CPU exceptions is left to kernel64's irq::init.
 pub fn disablePic() {
+    pic::maskAll();
+}
+
+pub fn disableInterrupts() {
     unsafe {
-        outB(PIC1_DATA, 0xFF);
-        outB(PIC2_DATA, 0xFF);
+        asm!("cli", options(nomem, nostack));
     }
 }
+
+pub fn enableInterrupts() {
+    unsafe {
+        asm!("sti", options(nomem, nostack));
+    }
+}
+
+// STI doesn't take effect until after the next instruction, so nothing can fire between the
+// two and we're guaranteed to wake up for whatever interrupt comes next.
+pub fn enableInterruptsAndHalt() {
+    unsafe {
+        asm!("sti", "hlt", options(nomem, nostack));
+    }
+}
+
+pub fn areInterruptsEnabled() -> bool {
+    let flags: usize;
+
+    unsafe {
+        #[cfg(target_pointer_width = "32")]
+        asm!(
+            "pushfd",
+            "pop {}",
+            out(reg) flags,
+            options(nomem, preserves_flags),
+        );
+
+        #[cfg(target_pointer_width = "64")]
+        asm!(
+            "pushfq",
+            "pop {}",
+            out(reg) flags,
+            options(nomem, preserves_flags),
+        );
+    }
+
+    flags & FLAGS_INTERRUPT_ENABLE != 0
+}
@@ -0,0 +1,266 @@
+use super::blockDevice::{BlockDevice, checkRequest};
+
+// Most sectors one cache can hold. The bookkeeping is a fixed array so this works without a heap.
+pub const MAX_CACHE_ENTRIES: usize = 64;
+
+#[derive(Clone, Copy)]
+struct CacheEntry {
+    lba: u64,
+    valid: bool,
+    dirty: bool,   // Changed here and not written to the device yet
+    lastUsed: u64, // Bigger is more recent
+}
+
+// Write-back sector cache over another BlockDevice. It's a BlockDevice itself, so whoever is on top can't tell the
+// difference. Writes only reach the device when a dirty sector gets evicted or on flush, so dropping one without
+// flushing loses them.
+// The caller provides the storage, one sector per entry, so it can live wherever suits.
+pub struct BlockCache<'a, D: BlockDevice> {
+    device: D,
+    storage: &'a mut [u8],
+    entries: [CacheEntry; MAX_CACHE_ENTRIES],
+    entryCount: usize,
+    sectorSize: usize,
+    useCounter: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<'a, D: BlockDevice> BlockCache<'a, D> {
+    pub fn new(device: D, storage: &'a mut [u8]) -> Result<Self, &'static str> {
+        let sectorSize = device.getSectorSize();
+        let entryCount = (storage.len() / sectorSize).min(MAX_CACHE_ENTRIES);
+        if entryCount == 0 {
+            return Err("Cache storage doesn't fit a single sector");
+        }
+
+        Ok(BlockCache {
+            device,
+            storage,
+            entries: [CacheEntry {
+                lba: 0,
+                valid: false,
+                dirty: false,
+                lastUsed: 0,
+            }; MAX_CACHE_ENTRIES],
+            entryCount,
+            sectorSize,
+            useCounter: 0,
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    pub fn getDevice(&self) -> &D {
+        &self.device
+    }
+
+    pub fn getEntryCount(&self) -> usize {
+        self.entryCount
+    }
+
+    // (hits, misses) since this was created
+    pub fn getStats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+
+    pub fn getDirtyCount(&self) -> usize {
+        self.entries[..self.entryCount]
+            .iter()
+            .filter(|entry| entry.valid && entry.dirty)
+            .count()
+    }
+
+    // Forgets everything that isn't dirty, for when something went around the cache to the device
+    pub fn invalidate(&mut self) {
+        for entry in self.entries[..self.entryCount].iter_mut() {
+            if !entry.dirty {
+                entry.valid = false;
+            }
+        }
+    }
+
+    fn find(&self, lba: u64) -> Option<usize> {
+        self.entries[..self.entryCount]
+            .iter()
+            .position(|entry| entry.valid && entry.lba == lba)
+    }
+
+    // Picks an empty entry if there is one, otherwise the least recently used, writing it back first if needed
+    fn evict(&mut self) -> Result<usize, &'static str> {
+        let index = match self.entries[..self.entryCount]
+            .iter()
+            .position(|entry| !entry.valid)
+        {
+            Some(index) => index,
+            None => (0..self.entryCount)
+                .min_by_key(|&index| self.entries[index].lastUsed)
+                .unwrap_or(0),
+        };
+
+        self.writeBack(index)?;
+        self.entries[index].valid = false;
+        Ok(index)
+    }
+
+    fn writeBack(&mut self, index: usize) -> Result<(), &'static str> {
+        let entry = self.entries[index];
+        if !entry.valid || !entry.dirty {
+            return Ok(());
+        }
+
+        let range = self.getRange(index);
+        self.device.write(entry.lba, &self.storage[range])?;
+        self.entries[index].dirty = false;
+        Ok(())
+    }
+
+    // Entry holding lba. There's no point reading it in when the caller is about to overwrite all of it.
+    fn load(&mut self, lba: u64, willOverwrite: bool) -> Result<usize, &'static str> {
+        self.useCounter += 1;
+
+        if let Some(index) = self.find(lba) {
+            self.hits += 1;
+            self.entries[index].lastUsed = self.useCounter;
+            return Ok(index);
+        }
+
+        self.misses += 1;
+        let index = self.evict()?;
+        if !willOverwrite {
+            let range = self.getRange(index);
+            self.device.read(lba, &mut self.storage[range])?;
+        }
+
+        self.entries[index] = CacheEntry {
+            lba,
+            valid: true,
+            dirty: false,
+            lastUsed: self.useCounter,
+        };
+
+        Ok(index)
+    }
+
+    fn getRange(&self, index: usize) -> core::ops::Range<usize> {
+        index * self.sectorSize..(index + 1) * self.sectorSize
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for BlockCache<'_, D> {
+    fn getSectorSize(&self) -> usize {
+        self.sectorSize
+    }
+
+    fn getSectorCount(&self) -> u64 {
+        self.device.getSectorCount()
+    }
+
+    fn read(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+        let sectorCount = checkRequest(self, lba, buffer.len())?;
+        for (sector, chunk) in (0..sectorCount).zip(buffer.chunks_exact_mut(self.sectorSize)) {
+            let index = self.load(lba + sector as u64, false)?;
+            chunk.copy_from_slice(&self.storage[self.getRange(index)]);
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, lba: u64, buffer: &[u8]) -> Result<(), &'static str> {
+        let sectorCount = checkRequest(self, lba, buffer.len())?;
+        for (sector, chunk) in (0..sectorCount).zip(buffer.chunks_exact(self.sectorSize)) {
+            let index = self.load(lba + sector as u64, true)?;
+            let range = self.getRange(index);
+            self.storage[range].copy_from_slice(chunk);
+            self.entries[index].dirty = true;
+        }
+
+        Ok(())
+    }
+
+    // Oldest LBA first, so the device sees something closer to sequential
+    fn flush(&mut self) -> Result<(), &'static str> {
+        loop {
+            let next = (0..self.entryCount)
+                .filter(|&index| self.entries[index].valid && self.entries[index].dirty)
+                .min_by_key(|&index| self.entries[index].lba);
+
+            let Some(index) = next else {
+                break;
+            };
+
+            self.writeBack(index)?;
+        }
+
+        self.device.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::memoryBlockDevice::MemoryBlockDevice;
+
+    #[test]
+    fn readsComeFromTheCache() {
+        let mut image: Vec<u8> = (0..512 * 8).map(|index| (index / 512) as u8).collect();
+        let mut device = MemoryBlockDevice::new(&mut image, 512).unwrap();
+        let mut storage = [0u8; 512 * 4];
+        let mut cache = BlockCache::new(&mut device, &mut storage).unwrap();
+
+        let mut first = [0u8; 512];
+        let mut second = [0u8; 512];
+        cache.read(3, &mut first).unwrap();
+        cache.read(3, &mut second).unwrap();
+        assert_eq!(first, second);
+        assert!(first.iter().all(|&byte| byte == 3));
+        assert_eq!(cache.getStats(), (1, 1));
+    }
+
+    #[test]
+    fn writesWaitForFlush() {
+        let mut image = vec![0u8; 512 * 8];
+        let mut device = MemoryBlockDevice::new(&mut image, 512).unwrap();
+        let mut storage = [0u8; 512 * 4];
+        let mut cache = BlockCache::new(&mut device, &mut storage).unwrap();
+
+        cache.write(0, &[0xAA; 1024]).unwrap();
+        assert_eq!(cache.getDirtyCount(), 2);
+        assert!(cache.getDevice().getImage().iter().all(|&byte| byte == 0));
+
+        cache.flush().unwrap();
+        assert_eq!(cache.getDirtyCount(), 0);
+        assert!(
+            cache.getDevice().getImage()[..1024]
+                .iter()
+                .all(|&byte| byte == 0xAA)
+        );
+        assert!(
+            cache.getDevice().getImage()[1024..]
+                .iter()
+                .all(|&byte| byte == 0)
+        );
+    }
+
+    #[test]
+    fn evictionWritesBack() {
+        let mut image = vec![0u8; 512 * 8];
+        let mut device = MemoryBlockDevice::new(&mut image, 512).unwrap();
+        let mut storage = [0u8; 512 * 2];
+        let mut cache = BlockCache::new(&mut device, &mut storage).unwrap();
+
+        // Two entries, so reading two more pushes the dirty sector out
+        cache.write(5, &[0x55; 512]).unwrap();
+        let mut buffer = [0u8; 1024];
+        cache.read(0, &mut buffer).unwrap();
+        assert_eq!(cache.getDirtyCount(), 0);
+        assert!(
+            cache.getDevice().getImage()[512 * 5..512 * 6]
+                .iter()
+                .all(|&byte| byte == 0x55)
+        );
+
+        cache.read(5, &mut buffer[..512]).unwrap();
+        assert!(buffer[..512].iter().all(|&byte| byte == 0x55));
+    }
+}
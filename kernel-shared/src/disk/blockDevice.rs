@@ -0,0 +1,70 @@
+// Biggest sector anything here is expected to deal with; code that needs a sector sized buffer without a heap uses
+// this
+pub const MAX_SECTOR_SIZE: usize = 4096;
+
+// Anything that reads and writes in whole sectors: the BIOS in stage2, AHCI in kernel64, a cache on top of either.
+// Filesystems and partition tables only talk to this, so they don't care which one they got.
+pub trait BlockDevice {
+    fn getSectorSize(&self) -> usize;
+
+    fn getSectorCount(&self) -> u64;
+
+    // Fills buffer starting at lba. buffer's length has to be a whole number of sectors.
+    fn read(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), &'static str>;
+
+    fn write(&mut self, lba: u64, buffer: &[u8]) -> Result<(), &'static str>;
+
+    // Everything written so far is on the media once this returns
+    fn flush(&mut self) -> Result<(), &'static str>;
+
+    // Saturates, since a device that doesn't know its size says it has u64::MAX sectors
+    fn getCapacity(&self) -> u64 {
+        self.getSectorCount()
+            .saturating_mul(self.getSectorSize() as u64)
+    }
+}
+
+// So a cache or filesystem can borrow a device instead of taking it over
+impl<T: BlockDevice> BlockDevice for &mut T {
+    fn getSectorSize(&self) -> usize {
+        (**self).getSectorSize()
+    }
+
+    fn getSectorCount(&self) -> u64 {
+        (**self).getSectorCount()
+    }
+
+    fn read(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+        (**self).read(lba, buffer)
+    }
+
+    fn write(&mut self, lba: u64, buffer: &[u8]) -> Result<(), &'static str> {
+        (**self).write(lba, buffer)
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        (**self).flush()
+    }
+}
+
+// The checks every implementation wants before touching the hardware. Returns how many sectors buffer covers.
+pub fn checkRequest(
+    device: &impl BlockDevice,
+    lba: u64,
+    bufferLength: usize,
+) -> Result<usize, &'static str> {
+    let sectorSize = device.getSectorSize();
+    if bufferLength % sectorSize != 0 {
+        return Err("Buffer isn't a whole number of sectors");
+    }
+
+    let sectorCount = bufferLength / sectorSize;
+    if lba
+        .checked_add(sectorCount as u64)
+        .is_none_or(|end| end > device.getSectorCount())
+    {
+        return Err("Past the end of the device");
+    }
+
+    Ok(sectorCount)
+}
@@ -0,0 +1,480 @@
+use core::fmt;
+
+use crate::crc32::{Crc32, crc32};
+
+use super::blockDevice::{BlockDevice, MAX_SECTOR_SIZE};
+
+// UEFI 2.10, 5.3 GUID Partition Table (GPT) Disk Layout
+// https://wiki.osdev.org/GPT
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+const PRIMARY_HEADER_LBA: u64 = 1;
+
+// 5.3.2 Table 5-5 GPT Header
+const HEADER_SIZE_OFFSET: usize = 12;
+const HEADER_CRC_OFFSET: usize = 16;
+const MY_LBA_OFFSET: usize = 24;
+const ALTERNATE_LBA_OFFSET: usize = 32;
+const FIRST_USABLE_LBA_OFFSET: usize = 40;
+const LAST_USABLE_LBA_OFFSET: usize = 48;
+const DISK_GUID_OFFSET: usize = 56;
+const ENTRIES_LBA_OFFSET: usize = 72;
+const ENTRY_COUNT_OFFSET: usize = 80;
+const ENTRY_SIZE_OFFSET: usize = 84;
+const ENTRIES_CRC_OFFSET: usize = 88;
+const MINIMUM_HEADER_SIZE: usize = 92;
+
+// 5.3.3 Table 5-6 GPT Partition Entry
+const MINIMUM_ENTRY_SIZE: usize = 128;
+const TYPE_GUID_OFFSET: usize = 0;
+const UNIQUE_GUID_OFFSET: usize = 16;
+const STARTING_LBA_OFFSET: usize = 32;
+const ENDING_LBA_OFFSET: usize = 40;
+const ATTRIBUTES_OFFSET: usize = 48;
+const NAME_OFFSET: usize = 56;
+pub const NAME_LENGTH: usize = 36; // UTF-16 code units
+
+// Table 5-7 Defined GPT Partition Entry - Attributes
+pub const ATTRIBUTE_REQUIRED: u64 = 1 << 0;
+pub const ATTRIBUTE_NO_BLOCK_IO: u64 = 1 << 1;
+pub const ATTRIBUTE_LEGACY_BIOS_BOOTABLE: u64 = 1 << 2;
+
+// Nobody sane has more than this, and it keeps a corrupt header from sending us off reading the whole disk
+const MAX_ENTRY_COUNT: u32 = 1024;
+
+// Stored with the first three fields little endian and the rest as bytes, which is why the text form looks shuffled
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    pub const UNUSED: Guid = Guid([0; 16]);
+
+    // C12A7328-F81F-11D2-BA4B-00A0C93EC93B
+    pub const EFI_SYSTEM: Guid = Guid([
+        0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9,
+        0x3B,
+    ]);
+
+    // EBD0A0A2-B9E5-4433-87C0-68B6B72699C7, what Windows and most tools use for FAT
+    pub const BASIC_DATA: Guid = Guid([
+        0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99,
+        0xC7,
+    ]);
+
+    fn read(bytes: &[u8]) -> Guid {
+        let mut result = [0; 16];
+        result.copy_from_slice(&bytes[..16]);
+        Guid(result)
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-",
+            b[3], b[2], b[1], b[0], b[5], b[4], b[7], b[6], b[8], b[9]
+        )?;
+
+        for byte in &b[10..] {
+            write!(f, "{:02X}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct GptHeader {
+    pub myLba: u64,
+    pub alternateLba: u64,
+    pub firstUsableLba: u64,
+    pub lastUsableLba: u64,
+    pub diskGuid: Guid,
+    pub entriesLba: u64,
+    pub entryCount: u32,
+    pub entrySize: u32,
+    pub entriesCrc: u32,
+}
+
+impl GptHeader {
+    // 5.3.2 GPT Header validity: signature, CRC, MyLBA being where we found it and everything fitting on the disk
+    fn parse(sector: &mut [u8], lba: u64, deviceSectors: u64) -> Result<GptHeader, &'static str> {
+        if sector[..8] != *SIGNATURE {
+            return Err("No GPT signature");
+        }
+
+        let headerSize = readU32(sector, HEADER_SIZE_OFFSET) as usize;
+        if headerSize < MINIMUM_HEADER_SIZE || headerSize > sector.len() {
+            return Err("Bad GPT header size");
+        }
+
+        // The CRC covers the header with its own CRC field zeroed
+        let expected = readU32(sector, HEADER_CRC_OFFSET);
+        sector[HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 4].fill(0);
+        let actual = crc32(&sector[..headerSize]);
+        sector[HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 4].copy_from_slice(&expected.to_le_bytes());
+        if actual != expected {
+            return Err("GPT header CRC mismatch");
+        }
+
+        let header = GptHeader {
+            myLba: readU64(sector, MY_LBA_OFFSET),
+            alternateLba: readU64(sector, ALTERNATE_LBA_OFFSET),
+            firstUsableLba: readU64(sector, FIRST_USABLE_LBA_OFFSET),
+            lastUsableLba: readU64(sector, LAST_USABLE_LBA_OFFSET),
+            diskGuid: Guid::read(&sector[DISK_GUID_OFFSET..]),
+            entriesLba: readU64(sector, ENTRIES_LBA_OFFSET),
+            entryCount: readU32(sector, ENTRY_COUNT_OFFSET),
+            entrySize: readU32(sector, ENTRY_SIZE_OFFSET),
+            entriesCrc: readU32(sector, ENTRIES_CRC_OFFSET),
+        };
+
+        if header.myLba != lba {
+            return Err("GPT header isn't where it says it is");
+        }
+
+        // 128 x 2^n
+        let entrySize = header.entrySize as usize;
+        if entrySize < MINIMUM_ENTRY_SIZE || !entrySize.is_power_of_two() {
+            return Err("Bad GPT entry size");
+        }
+
+        // BUGBUG: Entries bigger than a sector are legal but nobody makes them, and they'd need stitching together
+        if entrySize > sector.len() {
+            return Err("GPT entries bigger than a sector aren't supported");
+        }
+
+        if header.entryCount > MAX_ENTRY_COUNT {
+            return Err("Too many GPT entries");
+        }
+
+        if header.firstUsableLba > header.lastUsableLba || header.lastUsableLba >= deviceSectors {
+            return Err("GPT usable range is off the disk");
+        }
+
+        if header
+            .entriesLba
+            .checked_add(header.getEntrySectors(sector.len()))
+            .is_none_or(|end| end > deviceSectors)
+        {
+            return Err("GPT entries are off the disk");
+        }
+
+        Ok(header)
+    }
+
+    // The primary was bad and this came from the end of the disk
+    pub fn isBackup(&self) -> bool {
+        self.myLba != PRIMARY_HEADER_LBA
+    }
+
+    fn getEntrySectors(&self, sectorSize: usize) -> u64 {
+        let length = self.entryCount as u64 * self.entrySize as u64;
+        length.div_ceil(sectorSize as u64)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct GptPartition {
+    pub index: u32, // Where it was in the entry array, which is what tools call the partition number (minus 1)
+    pub typeGuid: Guid,
+    pub uniqueGuid: Guid,
+    pub startLba: u64,
+    pub endLba: u64, // Inclusive
+    pub attributes: u64,
+    pub name: [u16; NAME_LENGTH],
+}
+
+impl GptPartition {
+    pub fn getSectorCount(&self) -> u64 {
+        self.endLba - self.startLba + 1
+    }
+
+    pub fn isLegacyBootable(&self) -> bool {
+        self.attributes & ATTRIBUTE_LEGACY_BIOS_BOOTABLE != 0
+    }
+}
+
+// UTF-16 and 0 padded, so it needs converting on the way to the screen
+pub struct GptName<'a>(pub &'a [u16; NAME_LENGTH]);
+
+impl fmt::Display for GptName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let length = self
+            .0
+            .iter()
+            .position(|&unit| unit == 0)
+            .unwrap_or(NAME_LENGTH);
+        for c in char::decode_utf16(self.0[..length].iter().copied()) {
+            write!(f, "{}", c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+
+        Ok(())
+    }
+}
+
+// The primary header and entries if they check out, otherwise the backup copy at the end of the disk. Every used
+// entry is handed to found along with how many came before it. The CRC can't be checked until the whole array has been
+// read, so if the primary turns out to be bad found starts again from 0 with the backup's entries and whatever it was
+// given before should be thrown away. Returns the header that was used, which says if it was the backup, and how many
+// entries were in use. Nothing is logged here since stage2 uses this and can't afford the formatting.
+pub fn readPartitions(
+    device: &mut impl BlockDevice,
+    mut found: impl FnMut(usize, GptPartition),
+) -> Result<(GptHeader, usize), &'static str> {
+    let sectorSize = device.getSectorSize();
+    if !(512..=MAX_SECTOR_SIZE).contains(&sectorSize) {
+        return Err("Sector size doesn't fit a GPT");
+    }
+
+    let deviceSectors = device.getSectorCount();
+    if deviceSectors <= PRIMARY_HEADER_LBA {
+        return Err("Disk is too small for a GPT");
+    }
+
+    let mut buffer = [0u8; MAX_SECTOR_SIZE];
+    let sector = &mut buffer[..sectorSize];
+
+    let primary = readHeader(device, sector, PRIMARY_HEADER_LBA, deviceSectors);
+    if let Ok(header) = primary {
+        if let Ok(count) = readEntries(device, sector, &header, &mut found) {
+            return Ok((header, count));
+        }
+    }
+
+    // The primary says where the backup is, but if the primary is trash the spec says to try the last sector
+    let backupLba = match primary {
+        Ok(header) if header.alternateLba < deviceSectors => header.alternateLba,
+        _ => deviceSectors - 1,
+    };
+
+    let backup = readHeader(device, sector, backupLba, deviceSectors)?;
+    let count = readEntries(device, sector, &backup, &mut found)?;
+
+    // BUGBUG: Should rewrite the primary from the backup, but nothing writes partition tables yet
+    Ok((backup, count))
+}
+
+fn readHeader(
+    device: &mut impl BlockDevice,
+    sector: &mut [u8],
+    lba: u64,
+    deviceSectors: u64,
+) -> Result<GptHeader, &'static str> {
+    device.read(lba, sector)?;
+    GptHeader::parse(sector, lba, deviceSectors)
+}
+
+// Streams the entry array a sector at a time, checking the CRC over the whole thing as it goes
+fn readEntries(
+    device: &mut impl BlockDevice,
+    sector: &mut [u8],
+    header: &GptHeader,
+    found: &mut impl FnMut(usize, GptPartition),
+) -> Result<usize, &'static str> {
+    let sectorSize = sector.len();
+    let entrySize = header.entrySize as usize;
+    let totalLength = header.entryCount as usize * entrySize;
+    let mut crc = Crc32::new();
+    let mut used = 0;
+
+    // Entry sizes are powers of 2 of at least 128 and so are sector sizes, so an entry never straddles sectors
+    for (sectorIndex, lba) in (header.entriesLba..)
+        .take(header.getEntrySectors(sectorSize) as usize)
+        .enumerate()
+    {
+        device.read(lba, sector)?;
+
+        let start = sectorIndex * sectorSize;
+        let length = sectorSize.min(totalLength - start);
+        crc.update(&sector[..length]);
+
+        for (offset, entry) in sector[..length].chunks_exact(entrySize).enumerate() {
+            let typeGuid = Guid::read(&entry[TYPE_GUID_OFFSET..]);
+            if typeGuid == Guid::UNUSED {
+                continue;
+            }
+
+            let partition = GptPartition {
+                index: ((start / entrySize) + offset) as u32,
+                typeGuid,
+                uniqueGuid: Guid::read(&entry[UNIQUE_GUID_OFFSET..]),
+                startLba: readU64(entry, STARTING_LBA_OFFSET),
+                endLba: readU64(entry, ENDING_LBA_OFFSET),
+                attributes: readU64(entry, ATTRIBUTES_OFFSET),
+                name: readName(entry),
+            };
+
+            if partition.startLba > partition.endLba
+                || partition.startLba < header.firstUsableLba
+                || partition.endLba > header.lastUsableLba
+            {
+                return Err("GPT partition is outside the usable range");
+            }
+
+            found(used, partition);
+            used += 1;
+        }
+    }
+
+    if crc.finish() != header.entriesCrc {
+        return Err("GPT entries CRC mismatch");
+    }
+
+    Ok(used)
+}
+
+fn readName(entry: &[u8]) -> [u16; NAME_LENGTH] {
+    let mut result = [0; NAME_LENGTH];
+    for (index, unit) in result.iter_mut().enumerate() {
+        let offset = NAME_OFFSET + index * 2;
+        *unit = u16::from_le_bytes([entry[offset], entry[offset + 1]]);
+    }
+
+    result
+}
+
+fn readU32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn readU64(bytes: &[u8], offset: usize) -> u64 {
+    readU32(bytes, offset) as u64 | (readU32(bytes, offset + 4) as u64) << 32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::{
+        memoryBlockDevice::MemoryBlockDevice,
+        testImage::{
+            BACKUP_ENTRIES_LBA, BACKUP_HEADER_LBA, DISK_GUID, ENTRY_NAME_OFFSET, PARTITIONS,
+            PRIMARY_ENTRIES_LBA, SECTOR_COUNT, SECTOR_SIZE, corrupt, getSector, makeImage,
+        },
+    };
+
+    // Every call to found, in order
+    type Calls = Vec<(usize, GptPartition)>;
+
+    fn read(image: &mut [u8]) -> Result<(GptHeader, usize, Calls), &'static str> {
+        let mut device = MemoryBlockDevice::new(image, SECTOR_SIZE).unwrap();
+        let mut calls = Vec::new();
+        let (header, count) = readPartitions(&mut device, |index, partition| {
+            calls.push((index, partition))
+        })?;
+        Ok((header, count, calls))
+    }
+
+    fn checkPartitions(partitions: &[(usize, GptPartition)]) {
+        assert_eq!(partitions.len(), PARTITIONS.len());
+        for (used, ((index, partition), expected)) in partitions.iter().zip(&PARTITIONS).enumerate()
+        {
+            let (entryIndex, typeGuid, start, end, attributes, name) = *expected;
+            assert_eq!(*index, used);
+            assert_eq!(partition.index, entryIndex);
+            assert_eq!(partition.typeGuid, typeGuid);
+            assert_eq!(partition.uniqueGuid, Guid([0x40 + entryIndex as u8; 16]));
+            assert_eq!((partition.startLba, partition.endLba), (start, end));
+            assert_eq!(partition.attributes, attributes);
+            assert_eq!(GptName(&partition.name).to_string(), name);
+        }
+    }
+
+    #[test]
+    fn readsThePrimary() {
+        let mut image = makeImage();
+        let (header, count, calls) = read(&mut image).unwrap();
+        assert!(!header.isBackup());
+        assert_eq!(header.myLba, PRIMARY_HEADER_LBA);
+        assert_eq!(header.diskGuid, DISK_GUID);
+        assert_eq!(count, PARTITIONS.len());
+        checkPartitions(&calls);
+        assert!(calls[1].1.isLegacyBootable());
+    }
+
+    #[test]
+    fn rejectsBadHeaderCrc() {
+        let mut image = makeImage();
+        corrupt(&mut image, PRIMARY_HEADER_LBA, DISK_GUID_OFFSET);
+        let sector = &mut getSector(&mut image, PRIMARY_HEADER_LBA)[..SECTOR_SIZE];
+        assert_eq!(
+            GptHeader::parse(sector, PRIMARY_HEADER_LBA, SECTOR_COUNT).err(),
+            Some("GPT header CRC mismatch")
+        );
+    }
+
+    #[test]
+    fn rejectsBadEntriesCrc() {
+        let mut image = makeImage();
+        corrupt(&mut image, PRIMARY_ENTRIES_LBA, ENTRY_NAME_OFFSET);
+        let mut device = MemoryBlockDevice::new(&mut image, SECTOR_SIZE).unwrap();
+        let mut sector = [0u8; SECTOR_SIZE];
+        let header =
+            readHeader(&mut device, &mut sector, PRIMARY_HEADER_LBA, SECTOR_COUNT).unwrap();
+        assert_eq!(
+            readEntries(&mut device, &mut sector, &header, &mut |_, _| {}),
+            Err("GPT entries CRC mismatch")
+        );
+    }
+
+    // A trashed primary header doesn't say where the backup is, so it has to come from the last sector
+    #[test]
+    fn fallsBackToTheLastSector() {
+        let mut image = makeImage();
+        corrupt(&mut image, PRIMARY_HEADER_LBA, DISK_GUID_OFFSET);
+        let (header, count, calls) = read(&mut image).unwrap();
+        assert!(header.isBackup());
+        assert_eq!(header.myLba, BACKUP_HEADER_LBA);
+        assert_eq!(count, PARTITIONS.len());
+        checkPartitions(&calls);
+    }
+
+    // With a good primary header and bad entries the backup comes from AlternateLBA, which here isn't the last sector
+    #[test]
+    fn fallsBackToTheAlternateLba() {
+        let mut image = makeImage();
+        image.resize(image.len() + 8 * SECTOR_SIZE, 0);
+        corrupt(&mut image, PRIMARY_ENTRIES_LBA, ENTRY_NAME_OFFSET);
+        let (header, _, calls) = read(&mut image.clone()).unwrap();
+        assert_eq!(header.myLba, BACKUP_HEADER_LBA);
+        checkPartitions(&calls[PARTITIONS.len()..]);
+
+        // Without the primary header there's nothing at the last sector to find
+        corrupt(&mut image, PRIMARY_HEADER_LBA, DISK_GUID_OFFSET);
+        assert_eq!(read(&mut image).err(), Some("No GPT signature"));
+    }
+
+    // The primary's entries were handed out before its CRC was found to be bad, so the backup's start again from 0
+    #[test]
+    fn restartsFoundAfterABadPrimary() {
+        let mut image = makeImage();
+        corrupt(&mut image, PRIMARY_ENTRIES_LBA, ENTRY_NAME_OFFSET);
+        let (header, count, calls) = read(&mut image).unwrap();
+        assert!(header.isBackup());
+        assert_eq!(count, PARTITIONS.len());
+        assert_eq!(calls.len(), PARTITIONS.len() * 2);
+        assert_eq!(calls[0].0, 0);
+        assert_eq!(calls[1].0, 1);
+        checkPartitions(&calls[PARTITIONS.len()..]);
+    }
+
+    #[test]
+    fn failsWhenBothCopiesAreBad() {
+        let mut image = makeImage();
+        corrupt(&mut image, PRIMARY_HEADER_LBA, DISK_GUID_OFFSET);
+        corrupt(&mut image, BACKUP_ENTRIES_LBA, ENTRY_NAME_OFFSET);
+        assert_eq!(read(&mut image).err(), Some("GPT entries CRC mismatch"));
+    }
+}
@@ -0,0 +1,83 @@
+use super::blockDevice::{BlockDevice, MAX_SECTOR_SIZE};
+
+// https://wiki.osdev.org/MBR_(x86)
+const PARTITION_TABLE_OFFSET: usize = 0x1BE;
+const PARTITION_ENTRY_LENGTH: usize = 16;
+const SIGNATURE_OFFSET: usize = 0x1FE;
+const BOOTABLE: u8 = 0x80;
+
+// Partition types that hold FAT
+pub const PARTITION_TYPE_FAT16_SMALL: u8 = 0x04;
+pub const PARTITION_TYPE_FAT16: u8 = 0x06;
+pub const PARTITION_TYPE_FAT32_CHS: u8 = 0x0B;
+pub const PARTITION_TYPE_FAT32_LBA: u8 = 0x0C;
+pub const PARTITION_TYPE_FAT16_LBA: u8 = 0x0E;
+
+// UEFI 2.10, 5.2.3 Protective MBR: a single entry of this type covering the disk says the real table is a GPT
+pub const PARTITION_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+// Just the parts of an entry that matter once CHS is out of the picture
+#[derive(Clone, Copy)]
+pub struct MbrPartition {
+    pub bootable: bool,
+    pub partitionType: u8,
+    pub startLba: u32,
+    pub sectorCount: u32,
+}
+
+impl MbrPartition {
+    pub fn isFat(&self) -> bool {
+        isFatType(self.partitionType)
+    }
+
+    pub fn isGptProtective(&self) -> bool {
+        self.partitionType == PARTITION_TYPE_GPT_PROTECTIVE
+    }
+}
+
+pub fn isFatType(partitionType: u8) -> bool {
+    matches!(
+        partitionType,
+        PARTITION_TYPE_FAT16_SMALL
+            | PARTITION_TYPE_FAT16
+            | PARTITION_TYPE_FAT32_CHS
+            | PARTITION_TYPE_FAT32_LBA
+            | PARTITION_TYPE_FAT16_LBA
+    )
+}
+
+// The 4 primary entries, None for the empty ones. Extended partitions aren't followed.
+pub fn readPartitions(
+    device: &mut impl BlockDevice,
+) -> Result<[Option<MbrPartition>; 4], &'static str> {
+    let sectorSize = device.getSectorSize();
+    if !(512..=MAX_SECTOR_SIZE).contains(&sectorSize) {
+        return Err("Sector size doesn't fit an MBR");
+    }
+
+    let mut buffer = [0u8; MAX_SECTOR_SIZE];
+    device.read(0, &mut buffer[..sectorSize])?;
+
+    if buffer[SIGNATURE_OFFSET..SIGNATURE_OFFSET + 2] != [0x55, 0xAA] {
+        return Err("Invalid MBR signature");
+    }
+
+    let mut result = [None; 4];
+    for (index, partition) in result.iter_mut().enumerate() {
+        let entry = &buffer[PARTITION_TABLE_OFFSET + index * PARTITION_ENTRY_LENGTH..];
+        let partitionType = entry[4];
+        let sectorCount = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]);
+        if partitionType == 0 || sectorCount == 0 {
+            continue;
+        }
+
+        *partition = Some(MbrPartition {
+            bootable: entry[0] == BOOTABLE,
+            partitionType,
+            startLba: u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]),
+            sectorCount,
+        });
+    }
+
+    Ok(result)
+}
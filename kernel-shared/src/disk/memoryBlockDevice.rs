@@ -0,0 +1,102 @@
+use super::blockDevice::{BlockDevice, checkRequest};
+
+// A disk image that's already sitting in memory. Handy for poking at filesystem code without real hardware, or for
+// an image someone loaded for us.
+pub struct MemoryBlockDevice<'a> {
+    image: &'a mut [u8],
+    sectorSize: usize,
+}
+
+impl<'a> MemoryBlockDevice<'a> {
+    // Anything past the last whole sector is ignored
+    pub fn new(image: &'a mut [u8], sectorSize: usize) -> Result<Self, &'static str> {
+        if sectorSize == 0 || image.len() < sectorSize {
+            return Err("Image doesn't hold a single sector");
+        }
+
+        Ok(MemoryBlockDevice { image, sectorSize })
+    }
+
+    pub fn getImage(&self) -> &[u8] {
+        self.image
+    }
+
+    fn getRange(&self, lba: u64, length: usize) -> core::ops::Range<usize> {
+        let start = lba as usize * self.sectorSize;
+        start..start + length
+    }
+}
+
+impl BlockDevice for MemoryBlockDevice<'_> {
+    fn getSectorSize(&self) -> usize {
+        self.sectorSize
+    }
+
+    fn getSectorCount(&self) -> u64 {
+        (self.image.len() / self.sectorSize) as u64
+    }
+
+    fn read(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+        checkRequest(self, lba, buffer.len())?;
+        buffer.copy_from_slice(&self.image[self.getRange(lba, buffer.len())]);
+        Ok(())
+    }
+
+    fn write(&mut self, lba: u64, buffer: &[u8]) -> Result<(), &'static str> {
+        checkRequest(self, lba, buffer.len())?;
+        let range = self.getRange(lba, buffer.len());
+        self.image[range].copy_from_slice(buffer);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readsAndWritesWholeSectors() {
+        let mut image = vec![0u8; 512 * 4 + 100];
+        let mut device = MemoryBlockDevice::new(&mut image, 512).unwrap();
+        assert_eq!(device.getSectorCount(), 4);
+
+        device.write(1, &[0xAB; 1024]).unwrap();
+
+        let mut buffer = [0u8; 512];
+        device.read(2, &mut buffer).unwrap();
+        assert!(buffer.iter().all(|&byte| byte == 0xAB));
+
+        device.read(0, &mut buffer).unwrap();
+        assert!(buffer.iter().all(|&byte| byte == 0));
+
+        assert!(image[512..1536].iter().all(|&byte| byte == 0xAB));
+        assert!(image[1536..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn rejectsBadRequests() {
+        let mut small = [0u8; 100];
+        assert!(MemoryBlockDevice::new(&mut small, 512).is_err());
+        assert!(MemoryBlockDevice::new(&mut small, 0).is_err());
+
+        let mut image = vec![0u8; 512 * 4];
+        let mut device = MemoryBlockDevice::new(&mut image, 512).unwrap();
+        let mut buffer = [0u8; 1024];
+        assert_eq!(
+            device.read(0, &mut buffer[..100]),
+            Err("Buffer isn't a whole number of sectors")
+        );
+        assert_eq!(
+            device.read(3, &mut buffer),
+            Err("Past the end of the device")
+        );
+        assert_eq!(
+            device.write(u64::MAX, &buffer),
+            Err("Past the end of the device")
+        );
+    }
+}
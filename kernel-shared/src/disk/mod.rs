@@ -0,0 +1,10 @@
+pub mod blockCache;
+pub mod blockDevice;
+pub mod gpt;
+pub mod mbr;
+pub mod memoryBlockDevice;
+pub mod partition;
+pub mod partitionTable;
+
+#[cfg(test)]
+mod testImage;
@@ -0,0 +1,57 @@
+use super::blockDevice::{BlockDevice, checkRequest};
+
+// A run of sectors on another device that looks like a whole device, so a filesystem can start counting from 0
+pub struct Partition<D: BlockDevice> {
+    device: D,
+    startLba: u64,
+    sectorCount: u64,
+}
+
+impl<D: BlockDevice> Partition<D> {
+    pub fn new(device: D, startLba: u64, sectorCount: u64) -> Result<Self, &'static str> {
+        if startLba
+            .checked_add(sectorCount)
+            .is_none_or(|end| end > device.getSectorCount())
+        {
+            return Err("Partition runs past the end of the device");
+        }
+
+        Ok(Partition {
+            device,
+            startLba,
+            sectorCount,
+        })
+    }
+
+    pub fn getStartLba(&self) -> u64 {
+        self.startLba
+    }
+
+    pub fn getDevice(&self) -> &D {
+        &self.device
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for Partition<D> {
+    fn getSectorSize(&self) -> usize {
+        self.device.getSectorSize()
+    }
+
+    fn getSectorCount(&self) -> u64 {
+        self.sectorCount
+    }
+
+    fn read(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+        checkRequest(self, lba, buffer.len())?;
+        self.device.read(self.startLba + lba, buffer)
+    }
+
+    fn write(&mut self, lba: u64, buffer: &[u8]) -> Result<(), &'static str> {
+        checkRequest(self, lba, buffer.len())?;
+        self.device.write(self.startLba + lba, buffer)
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        self.device.flush()
+    }
+}
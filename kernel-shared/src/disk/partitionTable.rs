@@ -0,0 +1,230 @@
+use crate::loggerWriteLine;
+
+use super::{
+    blockDevice::BlockDevice,
+    gpt::{self, GptName, Guid, NAME_LENGTH},
+    mbr,
+    partition::Partition,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionScheme {
+    Mbr,
+    Gpt,
+    GptBackup, // The primary GPT was bad, so this came from the backup at the end of the disk
+}
+
+// Whatever the table said the partition is for
+#[derive(Clone, Copy)]
+pub enum PartitionKind {
+    Mbr {
+        partitionType: u8,
+    },
+    Gpt {
+        typeGuid: Guid,
+        uniqueGuid: Guid,
+        attributes: u64,
+        name: [u16; NAME_LENGTH],
+    },
+}
+
+// One partition from either kind of table, so callers don't have to care which the disk has
+#[derive(Clone, Copy)]
+pub struct PartitionInfo {
+    pub number: u32, // 1 based, the way fdisk and friends count
+    pub startLba: u64,
+    pub sectorCount: u64,
+    pub bootable: bool, // The MBR active flag or the GPT legacy BIOS bootable attribute
+    pub kind: PartitionKind,
+}
+
+impl PartitionInfo {
+    pub const EMPTY: PartitionInfo = PartitionInfo {
+        number: 0,
+        startLba: 0,
+        sectorCount: 0,
+        bootable: false,
+        kind: PartitionKind::Mbr { partitionType: 0 },
+    };
+
+    // Going by the type alone; only mounting it will tell for sure
+    pub fn isFat(&self) -> bool {
+        match self.kind {
+            PartitionKind::Mbr { partitionType } => mbr::isFatType(partitionType),
+            PartitionKind::Gpt { typeGuid, .. } => {
+                typeGuid == Guid::BASIC_DATA || typeGuid == Guid::EFI_SYSTEM
+            }
+        }
+    }
+
+    // Just this partition's sectors of device
+    pub fn open<D: BlockDevice>(&self, device: D) -> Result<Partition<D>, &'static str> {
+        Partition::new(device, self.startLba, self.sectorCount)
+    }
+
+    pub fn dump(&self) {
+        match self.kind {
+            PartitionKind::Mbr { partitionType } => {
+                loggerWriteLine!(
+                    "  {}: type 0x{:02X} @ LBA {} for {} sectors{}",
+                    self.number,
+                    partitionType,
+                    self.startLba,
+                    self.sectorCount,
+                    if self.bootable { ", bootable" } else { "" }
+                );
+            }
+            PartitionKind::Gpt {
+                typeGuid,
+                uniqueGuid,
+                attributes,
+                ref name,
+            } => {
+                loggerWriteLine!(
+                    "  {}: '{}' @ LBA {} for {} sectors{}",
+                    self.number,
+                    GptName(name),
+                    self.startLba,
+                    self.sectorCount,
+                    if self.bootable { ", bootable" } else { "" }
+                );
+                loggerWriteLine!(
+                    "     type {} id {} attributes 0x{:X}",
+                    typeGuid,
+                    uniqueGuid,
+                    attributes
+                );
+            }
+        }
+    }
+}
+
+// Reads whichever partition table the disk has into storage. A protective MBR (UEFI 2.10, 5.2.3) means the real table
+// is the GPT behind it; anything else is taken as a plain MBR. Returns which it was and how many partitions there are,
+// which can be more than storage had room for.
+pub fn readPartitions(
+    device: &mut impl BlockDevice,
+    storage: &mut [PartitionInfo],
+) -> Result<(PartitionScheme, usize), &'static str> {
+    let mbrPartitions = mbr::readPartitions(device)?;
+
+    // BUGBUG: A hybrid MBR has real entries next to the 0xEE one; we go with the GPT like everyone else does
+    if mbrPartitions
+        .iter()
+        .flatten()
+        .any(|partition| partition.isGptProtective())
+    {
+        let (header, count) = gpt::readPartitions(device, |index, partition| {
+            if let Some(slot) = storage.get_mut(index) {
+                *slot = PartitionInfo {
+                    number: partition.index + 1,
+                    startLba: partition.startLba,
+                    sectorCount: partition.getSectorCount(),
+                    bootable: partition.isLegacyBootable(),
+                    kind: PartitionKind::Gpt {
+                        typeGuid: partition.typeGuid,
+                        uniqueGuid: partition.uniqueGuid,
+                        attributes: partition.attributes,
+                        name: partition.name,
+                    },
+                };
+            }
+        })?;
+
+        let scheme = if header.isBackup() {
+            PartitionScheme::GptBackup
+        } else {
+            PartitionScheme::Gpt
+        };
+
+        return Ok((scheme, count));
+    }
+
+    let mut count = 0;
+    for (index, partition) in mbrPartitions.iter().enumerate() {
+        let Some(partition) = partition else {
+            continue;
+        };
+
+        if let Some(slot) = storage.get_mut(count) {
+            *slot = PartitionInfo {
+                number: index as u32 + 1,
+                startLba: partition.startLba as u64,
+                sectorCount: partition.sectorCount as u64,
+                bootable: partition.bootable,
+                kind: PartitionKind::Mbr {
+                    partitionType: partition.partitionType,
+                },
+            };
+        }
+
+        count += 1;
+    }
+
+    Ok((PartitionScheme::Mbr, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::{memoryBlockDevice::MemoryBlockDevice, testImage::*};
+
+    fn read(image: &mut [u8]) -> Result<(PartitionScheme, Vec<PartitionInfo>), &'static str> {
+        let mut device = MemoryBlockDevice::new(image, SECTOR_SIZE).unwrap();
+        let mut storage = [PartitionInfo::EMPTY; 4];
+        let (scheme, count) = readPartitions(&mut device, &mut storage)?;
+        Ok((scheme, storage[..count].to_vec()))
+    }
+
+    fn checkPartitions(partitions: &[PartitionInfo]) {
+        assert_eq!(partitions.len(), PARTITIONS.len());
+        for (partition, expected) in partitions.iter().zip(&PARTITIONS) {
+            let (entryIndex, expectedType, start, end, expectedAttributes, expectedName) =
+                *expected;
+            assert_eq!(partition.number, entryIndex + 1);
+            assert_eq!(partition.startLba, start);
+            assert_eq!(partition.sectorCount, end - start + 1);
+            assert_eq!(
+                partition.bootable,
+                expectedAttributes & gpt::ATTRIBUTE_LEGACY_BIOS_BOOTABLE != 0
+            );
+            assert!(partition.isFat());
+
+            let PartitionKind::Gpt {
+                typeGuid,
+                attributes,
+                ref name,
+                ..
+            } = partition.kind
+            else {
+                panic!("Partition {} isn't a GPT one", partition.number);
+            };
+            assert_eq!(typeGuid, expectedType);
+            assert_eq!(attributes, expectedAttributes);
+            assert_eq!(GptName(name).to_string(), expectedName);
+        }
+    }
+
+    #[test]
+    fn readsTheGptBehindAProtectiveMbr() {
+        let mut image = makeImage();
+        let (scheme, partitions) = read(&mut image).unwrap();
+        assert_eq!(scheme, PartitionScheme::Gpt);
+        checkPartitions(&partitions);
+    }
+
+    #[test]
+    fn usesTheBackupGpt() {
+        let mut image = makeImage();
+        corrupt(&mut image, PRIMARY_HEADER_LBA, DISK_GUID_OFFSET);
+        let (scheme, partitions) = read(&mut image).unwrap();
+        assert_eq!(scheme, PartitionScheme::GptBackup);
+        checkPartitions(&partitions);
+
+        let mut image = makeImage();
+        corrupt(&mut image, PRIMARY_ENTRIES_LBA, ENTRY_NAME_OFFSET);
+        let (scheme, partitions) = read(&mut image).unwrap();
+        assert_eq!(scheme, PartitionScheme::GptBackup);
+        checkPartitions(&partitions);
+    }
+}
@@ -0,0 +1,116 @@
+// A small GPT disk built straight into memory for the tests, with a protective MBR, the primary header and entries at
+// the front and the backup copies at the end. Nothing in here goes through the parser, so what it builds is what the
+// spec says and not just what the parser expects.
+use crate::crc32::crc32;
+
+use super::gpt::{ATTRIBUTE_LEGACY_BIOS_BOOTABLE, Guid};
+
+pub const SECTOR_SIZE: usize = 512;
+pub const SECTOR_COUNT: u64 = 64;
+pub const PRIMARY_HEADER_LBA: u64 = 1;
+pub const PRIMARY_ENTRIES_LBA: u64 = 2;
+pub const BACKUP_HEADER_LBA: u64 = SECTOR_COUNT - 1;
+pub const BACKUP_ENTRIES_LBA: u64 = BACKUP_HEADER_LBA - ENTRY_SECTORS;
+pub const FIRST_USABLE_LBA: u64 = PRIMARY_ENTRIES_LBA + ENTRY_SECTORS;
+pub const LAST_USABLE_LBA: u64 = BACKUP_ENTRIES_LBA - 1;
+
+const ENTRY_COUNT: usize = 16;
+const ENTRY_SIZE: usize = 128;
+const ENTRY_SECTORS: u64 = (ENTRY_COUNT * ENTRY_SIZE / SECTOR_SIZE) as u64;
+const HEADER_SIZE: usize = 92;
+
+// Where fields live in a header sector and an entry, for tests that want to break one
+pub const DISK_GUID_OFFSET: usize = 56;
+pub const ENTRY_NAME_OFFSET: usize = 56;
+
+pub const DISK_GUID: Guid = Guid([0x11; 16]);
+
+// (entry index, type, start, end, attributes, name). Entry 1 is left unused so the gap shows up in the numbering.
+pub const PARTITIONS: [(u32, Guid, u64, u64, u64, &str); 2] = [
+    (0, Guid::EFI_SYSTEM, FIRST_USABLE_LBA, 20, 0, "EFI system"),
+    (
+        2,
+        Guid::BASIC_DATA,
+        21,
+        LAST_USABLE_LBA,
+        ATTRIBUTE_LEGACY_BIOS_BOOTABLE,
+        "Data",
+    ),
+];
+
+// SECTOR_COUNT sectors holding the table above
+pub fn makeImage() -> Vec<u8> {
+    let mut image = vec![0; SECTOR_COUNT as usize * SECTOR_SIZE];
+
+    // UEFI 2.10, 5.2.3 Protective MBR: one 0xEE partition covering the rest of the disk
+    let entry = &mut image[0x1BE..0x1CE];
+    entry[4] = 0xEE;
+    entry[8..12].copy_from_slice(&(PRIMARY_HEADER_LBA as u32).to_le_bytes());
+    entry[12..16].copy_from_slice(&((SECTOR_COUNT - 1) as u32).to_le_bytes());
+    image[0x1FE..0x200].copy_from_slice(&[0x55, 0xAA]);
+
+    let mut entries = vec![0u8; ENTRY_COUNT * ENTRY_SIZE];
+    for &(index, typeGuid, start, end, attributes, name) in &PARTITIONS {
+        let entry = &mut entries[index as usize * ENTRY_SIZE..][..ENTRY_SIZE];
+        entry[0..16].copy_from_slice(&typeGuid.0);
+        entry[16..32].fill(0x40 + index as u8);
+        entry[32..40].copy_from_slice(&start.to_le_bytes());
+        entry[40..48].copy_from_slice(&end.to_le_bytes());
+        entry[48..56].copy_from_slice(&attributes.to_le_bytes());
+        for (unit, offset) in name.encode_utf16().zip((ENTRY_NAME_OFFSET..).step_by(2)) {
+            entry[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+    }
+
+    let entriesCrc = crc32(&entries);
+    for lba in [PRIMARY_ENTRIES_LBA, BACKUP_ENTRIES_LBA] {
+        getSector(&mut image, lba)[..entries.len()].copy_from_slice(&entries);
+    }
+
+    writeHeader(
+        &mut image,
+        PRIMARY_HEADER_LBA,
+        BACKUP_HEADER_LBA,
+        PRIMARY_ENTRIES_LBA,
+        entriesCrc,
+    );
+    writeHeader(
+        &mut image,
+        BACKUP_HEADER_LBA,
+        PRIMARY_HEADER_LBA,
+        BACKUP_ENTRIES_LBA,
+        entriesCrc,
+    );
+
+    image
+}
+
+// From the start of lba to the end of the image, so the entry array can be written in one go
+pub fn getSector(image: &mut [u8], lba: u64) -> &mut [u8] {
+    &mut image[lba as usize * SECTOR_SIZE..]
+}
+
+// Flips a byte without fixing up any CRC that covers it
+pub fn corrupt(image: &mut [u8], lba: u64, offset: usize) {
+    getSector(image, lba)[offset] ^= 0xFF;
+}
+
+// 5.3.2 Table 5-5 GPT Header
+fn writeHeader(image: &mut [u8], lba: u64, alternateLba: u64, entriesLba: u64, entriesCrc: u32) {
+    let header = &mut getSector(image, lba)[..SECTOR_SIZE];
+    header[0..8].copy_from_slice(b"EFI PART");
+    header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+    header[12..16].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+    header[24..32].copy_from_slice(&lba.to_le_bytes());
+    header[32..40].copy_from_slice(&alternateLba.to_le_bytes());
+    header[40..48].copy_from_slice(&FIRST_USABLE_LBA.to_le_bytes());
+    header[48..56].copy_from_slice(&LAST_USABLE_LBA.to_le_bytes());
+    header[DISK_GUID_OFFSET..DISK_GUID_OFFSET + 16].copy_from_slice(&DISK_GUID.0);
+    header[72..80].copy_from_slice(&entriesLba.to_le_bytes());
+    header[80..84].copy_from_slice(&(ENTRY_COUNT as u32).to_le_bytes());
+    header[84..88].copy_from_slice(&(ENTRY_SIZE as u32).to_le_bytes());
+    header[88..92].copy_from_slice(&entriesCrc.to_le_bytes());
+
+    let headerCrc = crc32(&header[..HEADER_SIZE]);
+    header[16..20].copy_from_slice(&headerCrc.to_le_bytes());
+}
@@ -0,0 +1,265 @@
+use crate::loggerWriteLine;
+
+use super::{readU16, readU32};
+
+// 3.5 Determination of FAT type: it's only the cluster count that matters, whatever the volume calls itself
+const FAT12_MAX_CLUSTERS: u32 = 4085;
+const FAT16_MAX_CLUSTERS: u32 = 65525;
+
+// 3.1 / 3.2 offsets into the first sector
+const OFFSET_BYTES_PER_SECTOR: usize = 11;
+const OFFSET_SECTORS_PER_CLUSTER: usize = 13;
+const OFFSET_RESERVED_SECTORS: usize = 14;
+const OFFSET_FAT_COUNT: usize = 16;
+const OFFSET_ROOT_ENTRIES: usize = 17;
+const OFFSET_TOTAL_SECTORS_16: usize = 19;
+const OFFSET_SECTORS_PER_FAT_16: usize = 22;
+const OFFSET_TOTAL_SECTORS_32: usize = 32;
+const OFFSET_BOOT_SIGNATURE: usize = 38; // 0x29 when the volume label after it is real
+const OFFSET_VOLUME_LABEL: usize = 43;
+const OFFSET_SIGNATURE: usize = 510;
+
+// 3.3 FAT32 moves the extended boot record down to make room for these
+const OFFSET_SECTORS_PER_FAT_32: usize = 36;
+const OFFSET_EXTENDED_FLAGS: usize = 40;
+const OFFSET_VERSION: usize = 42;
+const OFFSET_ROOT_CLUSTER: usize = 44;
+const OFFSET_FS_INFO_SECTOR: usize = 48;
+const OFFSET_BOOT_SIGNATURE_32: usize = 66;
+const OFFSET_VOLUME_LABEL_32: usize = 71;
+
+// Bit 7 of the extended flags turns off mirroring, and then bits 3:0 say which FAT is the real one
+const EXTENDED_FLAGS_NO_MIRRORING: u16 = 1 << 7;
+const EXTENDED_FLAGS_ACTIVE_FAT_MASK: u16 = 0xF;
+
+const EXTENDED_BOOT_SIGNATURE: u8 = 0x29;
+pub const DIRECTORY_ENTRY_LENGTH: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    // 4 FAT Data Structure. FAT32 entries are really 28 bits; the top 4 belong to nobody.
+    pub fn getEntryMask(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    pub fn getBadCluster(&self) -> u32 {
+        self.getEntryMask() - 8
+    }
+
+    // The smallest end of chain value; anything from here up counts
+    pub fn getEndOfChain(&self) -> u32 {
+        self.getEntryMask() - 7
+    }
+}
+
+// BIOS Parameter Block, the parts we use
+#[derive(Clone, Copy)]
+pub struct BootSector {
+    pub bytesPerSector: u16,
+    pub sectorsPerCluster: u8,
+    pub reservedSectors: u16,
+    pub fatCount: u8,
+    pub rootEntries: u16,
+    pub totalSectors: u32,
+    pub sectorsPerFat: u32,
+    // FAT32 only, 0 otherwise
+    pub rootCluster: u32,
+    pub fsInfoSector: u16,
+    pub extendedFlags: u16,
+    volumeLabel: [u8; 11],
+}
+
+// Where everything is, in sectors from the start of the volume
+#[derive(Clone, Copy)]
+pub struct Layout {
+    pub fatType: FatType,
+    pub fatStart: u64,
+    pub rootStart: u64, // Fixed root directory, FAT12/16 only
+    pub rootSectors: u64,
+    pub rootCluster: u32, // Where the root starts on FAT32, which doesn't have a fixed one
+    pub dataStart: u64,   // Cluster 2
+    pub clusterCount: u32,
+}
+
+impl BootSector {
+    pub fn parse(sector: &[u8]) -> Result<BootSector, &'static str> {
+        if sector.len() < 512 {
+            return Err("Boot sector is too short");
+        }
+
+        if sector[OFFSET_SIGNATURE..OFFSET_SIGNATURE + 2] != [0x55, 0xAA] {
+            return Err("Missing boot sector signature");
+        }
+
+        let bytesPerSector = readU16(sector, OFFSET_BYTES_PER_SECTOR);
+        if !bytesPerSector.is_power_of_two() || !(512..=4096).contains(&bytesPerSector) {
+            return Err("Bogus bytes per sector");
+        }
+
+        let sectorsPerCluster = sector[OFFSET_SECTORS_PER_CLUSTER];
+        if !sectorsPerCluster.is_power_of_two() {
+            return Err("Bogus sectors per cluster");
+        }
+
+        let reservedSectors = readU16(sector, OFFSET_RESERVED_SECTORS);
+        let fatCount = sector[OFFSET_FAT_COUNT];
+        if reservedSectors == 0 || fatCount == 0 {
+            return Err("No reserved sectors or FATs");
+        }
+
+        // The 16-bit count is 0 when it doesn't fit
+        let totalSectors = match readU16(sector, OFFSET_TOTAL_SECTORS_16) {
+            0 => readU32(sector, OFFSET_TOTAL_SECTORS_32),
+            small => small as u32,
+        };
+
+        // No 16-bit FAT size is how FAT32 says it's FAT32; the cluster count has to agree later
+        let mut rootCluster = 0;
+        let mut fsInfoSector = 0;
+        let mut extendedFlags = 0;
+        let mut bootSignatureOffset = OFFSET_BOOT_SIGNATURE;
+        let mut volumeLabelOffset = OFFSET_VOLUME_LABEL;
+        let mut sectorsPerFat = readU16(sector, OFFSET_SECTORS_PER_FAT_16) as u32;
+        if sectorsPerFat == 0 {
+            if readU16(sector, OFFSET_VERSION) != 0 {
+                return Err("Unknown FAT32 version");
+            }
+
+            sectorsPerFat = readU32(sector, OFFSET_SECTORS_PER_FAT_32);
+            rootCluster = readU32(sector, OFFSET_ROOT_CLUSTER);
+            fsInfoSector = readU16(sector, OFFSET_FS_INFO_SECTOR);
+            extendedFlags = readU16(sector, OFFSET_EXTENDED_FLAGS);
+            bootSignatureOffset = OFFSET_BOOT_SIGNATURE_32;
+            volumeLabelOffset = OFFSET_VOLUME_LABEL_32;
+
+            if sectorsPerFat == 0 {
+                return Err("No FAT size");
+            }
+        }
+
+        let mut volumeLabel = [b' '; 11];
+        if sector[bootSignatureOffset] == EXTENDED_BOOT_SIGNATURE {
+            volumeLabel.copy_from_slice(&sector[volumeLabelOffset..volumeLabelOffset + 11]);
+        }
+
+        Ok(BootSector {
+            bytesPerSector,
+            sectorsPerCluster,
+            reservedSectors,
+            fatCount,
+            rootEntries: readU16(sector, OFFSET_ROOT_ENTRIES),
+            totalSectors,
+            sectorsPerFat,
+            rootCluster,
+            fsInfoSector,
+            extendedFlags,
+            volumeLabel,
+        })
+    }
+
+    pub fn getVolumeLabel(&self) -> &str {
+        core::str::from_utf8(&self.volumeLabel)
+            .unwrap_or("???")
+            .trim_end()
+    }
+
+    // Which FAT to use when they aren't all kept the same, None when they are
+    pub fn getActiveFat(&self) -> Option<u8> {
+        if self.extendedFlags & EXTENDED_FLAGS_NO_MIRRORING == 0 {
+            return None;
+        }
+
+        Some((self.extendedFlags & EXTENDED_FLAGS_ACTIVE_FAT_MASK) as u8)
+    }
+
+    pub fn getLayout(&self) -> Result<Layout, &'static str> {
+        let bytesPerSector = self.bytesPerSector as u64;
+        let rootBytes = self.rootEntries as u64 * DIRECTORY_ENTRY_LENGTH as u64;
+        let rootSectors = rootBytes.div_ceil(bytesPerSector);
+
+        let fatStart = self.reservedSectors as u64;
+        let rootStart = fatStart + self.fatCount as u64 * self.sectorsPerFat as u64;
+        let dataStart = rootStart + rootSectors;
+        let Some(dataSectors) = (self.totalSectors as u64).checked_sub(dataStart) else {
+            return Err("Volume is smaller than its own metadata");
+        };
+
+        let clusterCount = (dataSectors / self.sectorsPerCluster as u64) as u32;
+        let fatType = if clusterCount < FAT12_MAX_CLUSTERS {
+            FatType::Fat12
+        } else if clusterCount < FAT16_MAX_CLUSTERS {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        // Whatever the sizes said, the layout has to match the type the cluster count picked
+        let isFat32 = fatType == FatType::Fat32;
+        if isFat32 != (self.rootEntries == 0) || isFat32 != (self.rootCluster != 0) {
+            return Err("Root directory doesn't match the FAT type");
+        }
+
+        let entrySize = match fatType {
+            FatType::Fat12 => 1, // Really 1.5, but this is only a sanity check
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        };
+
+        if (clusterCount as u64 + 2) * entrySize > self.sectorsPerFat as u64 * bytesPerSector {
+            return Err("FAT is too small for the cluster count");
+        }
+
+        if self
+            .getActiveFat()
+            .is_some_and(|active| active >= self.fatCount)
+        {
+            return Err("Active FAT doesn't exist");
+        }
+
+        Ok(Layout {
+            fatType,
+            fatStart,
+            rootStart,
+            rootSectors,
+            rootCluster: self.rootCluster,
+            dataStart,
+            clusterCount,
+        })
+    }
+
+    pub fn dump(&self) {
+        loggerWriteLine!(
+            "  Label '{}', {} bytes per sector, {} sectors per cluster, {} total sectors",
+            self.getVolumeLabel(),
+            self.bytesPerSector,
+            self.sectorsPerCluster,
+            self.totalSectors
+        );
+        loggerWriteLine!(
+            "  {} reserved sectors, {} FATs of {} sectors, {} root entries",
+            self.reservedSectors,
+            self.fatCount,
+            self.sectorsPerFat,
+            self.rootEntries
+        );
+
+        if self.rootCluster != 0 {
+            loggerWriteLine!(
+                "  Root cluster {}, FSInfo @ {}, active FAT {:?}",
+                self.rootCluster,
+                self.fsInfoSector,
+                self.getActiveFat()
+            );
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use super::{
+    bootSector::DIRECTORY_ENTRY_LENGTH,
+    longName::{MAX_LONG_NAME_LENGTH, isLongNameEntry},
+    readU16, readU32, writeU16, writeU32,
+};
+
+// 6 Directory Structure
+pub const ATTRIBUTE_READ_ONLY: u8 = 0x01;
+pub const ATTRIBUTE_HIDDEN: u8 = 0x02;
+pub const ATTRIBUTE_SYSTEM: u8 = 0x04;
+pub const ATTRIBUTE_VOLUME_ID: u8 = 0x08;
+pub const ATTRIBUTE_DIRECTORY: u8 = 0x10;
+pub const ATTRIBUTE_ARCHIVE: u8 = 0x20;
+
+const OFFSET_ATTRIBUTES: usize = 11;
+const OFFSET_CASE: usize = 12; // Reserved in the spec, but Windows NT and Linux keep case here
+const OFFSET_CLUSTER_HIGH: usize = 20;
+const OFFSET_MODIFIED_TIME: usize = 22;
+const OFFSET_MODIFIED_DATE: usize = 24;
+const OFFSET_CLUSTER_LOW: usize = 26;
+const OFFSET_SIZE: usize = 28;
+
+// First byte of the name
+const NAME_END: u8 = 0x00; // This and everything after it is unused
+const NAME_FREE: u8 = 0xE5;
+const NAME_KANJI_E5: u8 = 0x05; // A real 0xE5 as the first character
+
+// Lower case short names get these rather than a long name
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXTENSION: u8 = 0x10;
+
+const MAX_NAME_LENGTH: usize = 12; // 8 + '.' + 3
+const MAX_LONG_NAME_BYTES: usize = MAX_LONG_NAME_LENGTH * 3; // Worst case UCS-2 to UTF-8
+pub const SHORT_NAME_LENGTH: usize = 11; // Space padded, no '.'
+
+// 6.1 Characters a short name can't have, on top of anything below 0x20
+const INVALID_NAME_CHARACTERS: &[u8] = b" \"*+,./:;<=>?[\\]|";
+
+// 7 Long names allow some of those back
+const INVALID_LONG_NAME_CHARACTERS: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+// The two entries at the start of every directory but the root
+pub const DOT_NAME: [u8; SHORT_NAME_LENGTH] = *b".          ";
+pub const DOT_DOT_NAME: [u8; SHORT_NAME_LENGTH] = *b"..         ";
+
+// What a directory entry says about a file, with the space padded 8.3 name turned into BASE.EXT. If there was a long
+// name in front of it, that's kept as UTF-8 too.
+#[derive(Clone, Copy)]
+pub struct FileInfo {
+    name: [u8; MAX_NAME_LENGTH],
+    nameLength: usize,
+    longName: [u8; MAX_LONG_NAME_BYTES],
+    longNameLength: usize,
+    pub attributes: u8,
+    pub size: u32,
+    pub firstCluster: u32, // 0 for empty files, and for directories it means the root
+    pub modifiedDate: u16,
+    pub modifiedTime: u16,
+}
+
+pub enum DirectoryEntry {
+    End,
+    Unused, // Deleted or volume label
+    LongName,
+    File(FileInfo),
+}
+
+impl FileInfo {
+    // The root doesn't have an entry of its own, so this stands in for it
+    pub fn root() -> FileInfo {
+        let mut name = [0; MAX_NAME_LENGTH];
+        name[0] = b'/';
+
+        FileInfo {
+            name,
+            nameLength: 1,
+            longName: [0; MAX_LONG_NAME_BYTES],
+            longNameLength: 0,
+            attributes: ATTRIBUTE_DIRECTORY,
+            size: 0,
+            firstCluster: 0,
+            modifiedDate: 0,
+            modifiedTime: 0,
+        }
+    }
+
+    // The long name if there is one
+    pub fn getName(&self) -> &str {
+        if self.longNameLength != 0 {
+            return core::str::from_utf8(&self.longName[..self.longNameLength]).unwrap_or("???");
+        }
+
+        self.getShortName()
+    }
+
+    pub fn getShortName(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.nameLength]).unwrap_or("???")
+    }
+
+    pub fn hasLongName(&self) -> bool {
+        self.longNameLength != 0
+    }
+
+    pub fn setLongName(&mut self, characters: &[u16]) {
+        self.longNameLength = 0;
+        for character in char::decode_utf16(characters.iter().copied()) {
+            let character = character.unwrap_or(char::REPLACEMENT_CHARACTER);
+            let encoded = character.encode_utf8(&mut self.longName[self.longNameLength..]);
+            self.longNameLength += encoded.len();
+        }
+    }
+
+    pub fn isDirectory(&self) -> bool {
+        self.attributes & ATTRIBUTE_DIRECTORY != 0
+    }
+
+    // Names are case insensitive, and either the long or short one will do
+    pub fn nameMatches(&self, name: &str) -> bool {
+        equalsIgnoringCase(self.getShortName(), name)
+            || (self.hasLongName() && equalsIgnoringCase(self.getName(), name))
+    }
+}
+
+impl DirectoryEntry {
+    pub fn parse(bytes: &[u8]) -> DirectoryEntry {
+        let attributes = bytes[OFFSET_ATTRIBUTES];
+        match bytes[0] {
+            NAME_END => return DirectoryEntry::End,
+            NAME_FREE => return DirectoryEntry::Unused,
+            _ => {}
+        }
+
+        if isLongNameEntry(bytes) {
+            return DirectoryEntry::LongName;
+        }
+
+        if attributes & ATTRIBUTE_VOLUME_ID != 0 {
+            return DirectoryEntry::Unused;
+        }
+
+        let case = bytes[OFFSET_CASE];
+        let mut name = [0; MAX_NAME_LENGTH];
+        let mut nameLength = 0;
+        for &byte in trimPadding(&bytes[0..8]) {
+            name[nameLength] = applyCase(byte, case & CASE_LOWER_BASE != 0);
+            nameLength += 1;
+        }
+
+        if name[0] == NAME_KANJI_E5 {
+            name[0] = NAME_FREE;
+        }
+
+        let extension = trimPadding(&bytes[8..11]);
+        if !extension.is_empty() {
+            name[nameLength] = b'.';
+            nameLength += 1;
+            for &byte in extension {
+                name[nameLength] = applyCase(byte, case & CASE_LOWER_EXTENSION != 0);
+                nameLength += 1;
+            }
+        }
+
+        DirectoryEntry::File(FileInfo {
+            name,
+            nameLength,
+            longName: [0; MAX_LONG_NAME_BYTES],
+            longNameLength: 0,
+            attributes,
+            size: readU32(bytes, OFFSET_SIZE),
+            firstCluster: (readU16(bytes, OFFSET_CLUSTER_HIGH) as u32) << 16
+                | readU16(bytes, OFFSET_CLUSTER_LOW) as u32,
+            modifiedDate: readU16(bytes, OFFSET_MODIFIED_DATE),
+            modifiedTime: readU16(bytes, OFFSET_MODIFIED_TIME),
+        })
+    }
+}
+
+// Slots that are free to be reused for a new entry
+pub fn isFree(bytes: &[u8]) -> bool {
+    matches!(bytes[0], NAME_END | NAME_FREE)
+}
+
+pub fn markDeleted(bytes: &mut [u8]) {
+    bytes[0] = NAME_FREE;
+}
+
+// A fresh entry. BUGBUG: Nothing in here knows the time, so everything is created at the 1980 epoch.
+pub fn encodeEntry(
+    bytes: &mut [u8],
+    shortName: &[u8; SHORT_NAME_LENGTH],
+    attributes: u8,
+    firstCluster: u32,
+) {
+    bytes[..DIRECTORY_ENTRY_LENGTH].fill(0);
+    bytes[..SHORT_NAME_LENGTH].copy_from_slice(shortName);
+    bytes[OFFSET_ATTRIBUTES] = attributes;
+    setFirstCluster(bytes, firstCluster);
+}
+
+pub fn setFirstCluster(bytes: &mut [u8], cluster: u32) {
+    writeU16(bytes, OFFSET_CLUSTER_HIGH, (cluster >> 16) as u16);
+    writeU16(bytes, OFFSET_CLUSTER_LOW, cluster as u16);
+}
+
+pub fn setSize(bytes: &mut [u8], size: u32) {
+    writeU32(bytes, OFFSET_SIZE, size);
+}
+
+// NAME.EXT to the upper case, space padded form that's on disk. Only plain ASCII 8.3 names are allowed.
+pub fn toShortName(name: &str) -> Result<[u8; SHORT_NAME_LENGTH], &'static str> {
+    if name == "." || name == ".." {
+        return Err("Name is reserved");
+    }
+
+    let (base, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return Err("Name doesn't fit in 8.3");
+    }
+
+    let mut result = [b' '; SHORT_NAME_LENGTH];
+    copyNamePart(&mut result[..8], base)?;
+    copyNamePart(&mut result[8..], extension)?;
+    Ok(result)
+}
+
+// True if name can be stored exactly as a short name, without losing its case
+pub fn isShortName(name: &str) -> bool {
+    toShortName(name).is_ok() && !name.bytes().any(|byte| byte.is_ascii_lowercase())
+}
+
+// Checks a name is allowed as a long name and converts it to the UCS-2 that goes on disk, returning how many
+// characters it is
+pub fn toLongName(
+    name: &str,
+    characters: &mut [u16; MAX_LONG_NAME_LENGTH],
+) -> Result<usize, &'static str> {
+    if name.is_empty() || name.ends_with(['.', ' ']) {
+        return Err("Long names can't be empty or end in '.' or ' '");
+    }
+
+    let mut length = 0;
+    for character in name.chars() {
+        if character < ' ' || INVALID_LONG_NAME_CHARACTERS.contains(&character) {
+            return Err("Name has a character long names can't");
+        }
+
+        let mut encoded = [0; 2];
+        for &unit in character.encode_utf16(&mut encoded).iter() {
+            if length == MAX_LONG_NAME_LENGTH {
+                return Err("Name is too long");
+            }
+
+            characters[length] = unit;
+            length += 1;
+        }
+    }
+
+    Ok(length)
+}
+
+// 7.4 The numeric tail short name that goes with a long name: as much of the name as fits in 8.3 with '~' and a
+// number on the end of the base
+pub fn makeShortAlias(name: &str, number: u32) -> [u8; SHORT_NAME_LENGTH] {
+    let name = name.trim_start_matches('.');
+    let (base, extension) = match name.rsplit_once('.') {
+        Some((base, extension)) if !base.is_empty() => (base, extension),
+        _ => (name, ""),
+    };
+
+    let mut result = [b' '; SHORT_NAME_LENGTH];
+    let mut tail = [0u8; 8];
+    let tailLength = {
+        let mut digits = [0u8; 7];
+        let mut count = 0;
+        let mut value = number;
+        loop {
+            digits[count] = b'0' + (value % 10) as u8;
+            count += 1;
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+
+        tail[0] = b'~';
+        for index in 0..count {
+            tail[index + 1] = digits[count - 1 - index];
+        }
+
+        count + 1
+    };
+
+    let baseLength = fillAliasPart(&mut result[..8 - tailLength], base);
+    result[baseLength..baseLength + tailLength].copy_from_slice(&tail[..tailLength]);
+    fillAliasPart(&mut result[8..], extension);
+    result
+}
+
+// Copies what it can of source as upper case, with anything a short name can't have as '_'. Spaces are dropped.
+fn fillAliasPart(target: &mut [u8], source: &str) -> usize {
+    let mut length = 0;
+    for character in source
+        .chars()
+        .filter(|&character| character != ' ' && character != '.')
+    {
+        if length == target.len() {
+            break;
+        }
+
+        target[length] = match character {
+            ' '..='~' if !INVALID_NAME_CHARACTERS.contains(&(character as u8)) => {
+                character.to_ascii_uppercase() as u8
+            }
+            _ => b'_',
+        };
+        length += 1;
+    }
+
+    length
+}
+
+fn equalsIgnoringCase(left: &str, right: &str) -> bool {
+    left.chars()
+        .flat_map(char::to_uppercase)
+        .eq(right.chars().flat_map(char::to_uppercase))
+}
+
+fn applyCase(byte: u8, lower: bool) -> u8 {
+    if lower {
+        byte.to_ascii_lowercase()
+    } else {
+        byte
+    }
+}
+
+fn copyNamePart(target: &mut [u8], source: &str) -> Result<(), &'static str> {
+    for (target, &byte) in target.iter_mut().zip(source.as_bytes()) {
+        if byte < 0x20 || byte > 0x7E || INVALID_NAME_CHARACTERS.contains(&byte) {
+            return Err("Name has a character short names can't");
+        }
+
+        *target = byte.to_ascii_uppercase();
+    }
+
+    Ok(())
+}
+
+fn trimPadding(bytes: &[u8]) -> &[u8] {
+    let length = bytes
+        .iter()
+        .rposition(|&byte| byte != b' ')
+        .map_or(0, |last| last + 1);
+    &bytes[..length]
+}
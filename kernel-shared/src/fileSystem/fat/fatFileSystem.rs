@@ -0,0 +1,1473 @@
+use crate::{
+    disk::blockDevice::{BlockDevice, MAX_SECTOR_SIZE},
+    fileSystem::SeekFrom,
+    loggerWriteLine,
+};
+
+use super::{
+    bootSector::{BootSector, DIRECTORY_ENTRY_LENGTH, FatType, Layout},
+    directory::{
+        ATTRIBUTE_ARCHIVE, ATTRIBUTE_DIRECTORY, DOT_DOT_NAME, DOT_NAME, DirectoryEntry, FileInfo,
+        SHORT_NAME_LENGTH, encodeEntry, isFree, isShortName, makeShortAlias, markDeleted,
+        setFirstCluster, setSize, toLongName, toShortName,
+    },
+    fsInfo::FsInfo,
+    longName::{
+        LongNameBuilder, MAX_LONG_NAME_LENGTH, encodeLongEntry, getChecksum, getEntryCount,
+    },
+    readU16, readU32, writeU16, writeU32,
+};
+
+const FREE_CLUSTER: u32 = 0;
+const FIRST_DATA_CLUSTER: u32 = 2;
+
+// How many ~N short names to try for a long name before giving up
+const MAX_ALIAS_NUMBER: u32 = 9999;
+
+// Where we last were in a cluster chain, so walking forwards doesn't start from the beginning every time
+#[derive(Clone, Copy)]
+struct ChainCursor {
+    index: u32, // Which cluster of the file this is
+    cluster: u32,
+}
+
+// Where a file's directory entry lives, so its size and first cluster can be updated. firstIndex is where its long
+// name starts, so it can be removed along with it.
+#[derive(Clone, Copy)]
+struct EntryLocation {
+    lba: u64,
+    offset: usize,
+    directory: u32,
+    firstIndex: u32,
+    index: u32,
+}
+
+// An open file. Only the file system knows how to read and write it.
+pub struct FatFile {
+    info: FileInfo,
+    position: u64,
+    cursor: Option<ChainCursor>,
+    entry: EntryLocation,
+}
+
+// An open directory being enumerated with readDirectory
+pub struct FatDirectory {
+    firstCluster: u32, // 0 is the fixed FAT12/16 root directory
+    index: u32,        // Next entry to look at
+    cursor: Option<ChainCursor>,
+}
+
+pub struct FatFileSystem<D: BlockDevice> {
+    device: D,
+    bootSector: BootSector,
+    layout: Layout,
+    sectorSize: usize,
+    clusterSize: usize,
+    // One sector of the FAT and one of everything else; file systems don't get a heap
+    fatBuffer: [u8; MAX_SECTOR_SIZE],
+    fatBufferLba: Option<u64>,
+    dataBuffer: [u8; MAX_SECTOR_SIZE],
+    dataBufferLba: Option<u64>,
+    nextFreeHint: u32, // Where to start looking for a free cluster
+    fsInfo: Option<FsInfo>,
+    fsInfoDirty: bool,
+}
+
+impl FatFile {
+    pub fn getInfo(&self) -> &FileInfo {
+        &self.info
+    }
+
+    pub fn getPosition(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<D: BlockDevice> FatFileSystem<D> {
+    pub fn mount(mut device: D) -> Result<Self, &'static str> {
+        let sectorSize = device.getSectorSize();
+        if sectorSize > MAX_SECTOR_SIZE {
+            return Err("Sector size is too big");
+        }
+
+        let mut buffer = [0; MAX_SECTOR_SIZE];
+        device.read(0, &mut buffer[..sectorSize])?;
+
+        let bootSector = BootSector::parse(&buffer[..sectorSize])?;
+        if bootSector.bytesPerSector as usize != sectorSize {
+            return Err("Volume's sector size doesn't match the device");
+        }
+
+        let layout = bootSector.getLayout()?;
+        if layout.fatType == FatType::Fat12 {
+            return Err("FAT12 isn't supported");
+        }
+
+        if bootSector.totalSectors as u64 > device.getSectorCount() {
+            return Err("Volume is bigger than the device");
+        }
+
+        // A bad or missing FSInfo just means no hints
+        let mut fsInfo = None;
+        if layout.fatType == FatType::Fat32
+            && bootSector.fsInfoSector != 0
+            && (bootSector.fsInfoSector as u64) < layout.fatStart
+        {
+            device.read(bootSector.fsInfoSector as u64, &mut buffer[..sectorSize])?;
+            fsInfo = FsInfo::parse(&buffer[..sectorSize]).ok();
+        }
+
+        let mut result = FatFileSystem {
+            device,
+            bootSector,
+            layout,
+            sectorSize,
+            clusterSize: sectorSize * bootSector.sectorsPerCluster as usize,
+            fatBuffer: [0; MAX_SECTOR_SIZE],
+            fatBufferLba: None,
+            dataBuffer: [0; MAX_SECTOR_SIZE],
+            dataBufferLba: None,
+            nextFreeHint: FIRST_DATA_CLUSTER,
+            fsInfo,
+            fsInfoDirty: false,
+        };
+
+        if layout.fatType == FatType::Fat32 {
+            result.checkCluster(layout.rootCluster)?;
+        }
+
+        if let Some(nextFree) = fsInfo.and_then(|fsInfo| fsInfo.nextFree) {
+            if result.checkCluster(nextFree).is_ok() {
+                result.nextFreeHint = nextFree;
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn getDevice(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    pub fn getFatType(&self) -> FatType {
+        self.layout.fatType
+    }
+
+    pub fn getClusterSize(&self) -> usize {
+        self.clusterSize
+    }
+
+    pub fn getVolumeLabel(&self) -> &str {
+        self.bootSector.getVolumeLabel()
+    }
+
+    pub fn dump(&self) {
+        loggerWriteLine!(
+            "{:?} volume, {} clusters of {} bytes",
+            self.layout.fatType,
+            self.layout.clusterCount,
+            self.clusterSize
+        );
+        self.bootSector.dump();
+    }
+
+    pub fn stat(&mut self, path: &str) -> Result<FileInfo, &'static str> {
+        Ok(self.lookup(path)?.0)
+    }
+
+    // Makes an empty file. The parent directory has to exist and the file can't.
+    pub fn create(&mut self, path: &str) -> Result<FatFile, &'static str> {
+        let (parent, name) = self.prepareCreate(path)?;
+        let (info, entry) = self.addEntry(parent, name, ATTRIBUTE_ARCHIVE, 0)?;
+
+        Ok(FatFile {
+            info,
+            position: 0,
+            cursor: None,
+            entry,
+        })
+    }
+
+    pub fn createDirectory(&mut self, path: &str) -> Result<(), &'static str> {
+        let (parent, name) = self.prepareCreate(path)?;
+        let cluster = self.allocateCluster(None, true)?;
+
+        // 6.1 Every directory but the root starts with . and ..; .. is 0 when the parent is the root
+        let lba = self.clusterToLba(cluster);
+        self.loadDataSector(lba)?;
+        encodeEntry(
+            &mut self.dataBuffer,
+            &DOT_NAME,
+            ATTRIBUTE_DIRECTORY,
+            cluster,
+        );
+        encodeEntry(
+            &mut self.dataBuffer[DIRECTORY_ENTRY_LENGTH..],
+            &DOT_DOT_NAME,
+            ATTRIBUTE_DIRECTORY,
+            parent,
+        );
+        self.writeDataSector(lba)?;
+
+        if let Err(reason) = self.addEntry(parent, name, ATTRIBUTE_DIRECTORY, cluster) {
+            self.freeChain(cluster)?;
+            return Err(reason);
+        }
+
+        Ok(())
+    }
+
+    // Deletes a file, or a directory as long as it's empty
+    pub fn remove(&mut self, path: &str) -> Result<(), &'static str> {
+        let (_, name) = splitPath(path)?;
+        if name == "." || name == ".." {
+            return Err("Can't remove . or ..");
+        }
+
+        let (info, entry) = self.lookup(path)?;
+        let entry = entry.ok_or("Can't remove the root")?;
+
+        if info.isDirectory() {
+            let mut directory = self.getDirectory(info.firstCluster);
+            while let Some(child) = self.readDirectory(&mut directory)? {
+                if child.getName() != "." && child.getName() != ".." {
+                    return Err("Directory isn't empty");
+                }
+            }
+        }
+
+        // Entries first, so a failure part way through leaks clusters rather than leaving an entry pointing at free
+        // ones. The short entry goes last so the long name is never left without it.
+        let mut directory = FatDirectory {
+            firstCluster: entry.directory,
+            index: entry.firstIndex,
+            cursor: None,
+        };
+
+        while directory.index <= entry.index {
+            let lba = self
+                .getDirectoryEntryLba(&mut directory)?
+                .ok_or("Directory entry went missing")?;
+            let offset = self.getEntryOffset(directory.index);
+            self.loadDataSector(lba)?;
+            markDeleted(&mut self.dataBuffer[offset..]);
+            self.writeDataSector(lba)?;
+            directory.index += 1;
+        }
+
+        if info.firstCluster != 0 {
+            self.freeChain(info.firstCluster)?;
+        }
+
+        Ok(())
+    }
+
+    // Writes at the current position, growing the file when it goes past the end
+    pub fn write(&mut self, file: &mut FatFile, buffer: &[u8]) -> Result<usize, &'static str> {
+        if file.position + buffer.len() as u64 > u32::MAX as u64 {
+            return Err("File would be too big for FAT");
+        }
+
+        let mut done = 0;
+        while done < buffer.len() {
+            let clusterIndex = (file.position / self.clusterSize as u64) as u32;
+            let cluster =
+                match self.clusterAt(&mut file.cursor, file.info.firstCluster, clusterIndex)? {
+                    Some(cluster) => cluster,
+                    None => self.extendFile(file, clusterIndex)?,
+                };
+
+            let withinCluster = (file.position % self.clusterSize as u64) as usize;
+            let lba = self.clusterToLba(cluster) + (withinCluster / self.sectorSize) as u64;
+            let withinSector = withinCluster % self.sectorSize;
+            let remaining = buffer.len() - done;
+
+            let length = if withinSector == 0 && remaining >= self.sectorSize {
+                let sectors = (remaining / self.sectorSize)
+                    .min((self.clusterSize - withinCluster) / self.sectorSize);
+                let length = sectors * self.sectorSize;
+                self.device.write(lba, &buffer[done..done + length])?;
+                if self
+                    .dataBufferLba
+                    .is_some_and(|buffered| (lba..lba + sectors as u64).contains(&buffered))
+                {
+                    self.dataBufferLba = None;
+                }
+
+                length
+            } else {
+                let length = remaining.min(self.sectorSize - withinSector);
+                self.loadDataSector(lba)?;
+                self.dataBuffer[withinSector..withinSector + length]
+                    .copy_from_slice(&buffer[done..done + length]);
+                self.writeDataSector(lba)?;
+                length
+            };
+
+            done += length;
+            file.position += length as u64;
+        }
+
+        if file.position > file.info.size as u64 {
+            file.info.size = file.position as u32;
+        }
+
+        self.updateEntry(file)?;
+        Ok(done)
+    }
+
+    // Shrinks the file to length, giving back any clusters it no longer needs
+    pub fn truncate(&mut self, file: &mut FatFile, length: u64) -> Result<(), &'static str> {
+        if length > file.info.size as u64 {
+            return Err("Truncate can only shrink files");
+        }
+
+        let clusters = length.div_ceil(self.clusterSize as u64) as u32;
+        let first = file.info.firstCluster;
+        if first != 0 {
+            if clusters == 0 {
+                file.info.firstCluster = 0;
+                self.updateEntry(file)?;
+                self.freeChain(first)?;
+            } else {
+                let last = self
+                    .clusterAt(&mut file.cursor, first, clusters - 1)?
+                    .ok_or("Cluster chain is shorter than the file")?;
+
+                if let Some(next) = self.nextCluster(last)? {
+                    self.writeFatEntry(last, self.layout.fatType.getEntryMask())?;
+                    self.freeChain(next)?;
+                }
+            }
+        }
+
+        file.info.size = length as u32;
+        file.position = file.position.min(length);
+        file.cursor = None;
+        self.updateEntry(file)
+    }
+
+    // Everything written so far, on to the disk, with FSInfo brought up to date
+    pub fn flush(&mut self) -> Result<(), &'static str> {
+        if let Some(fsInfo) = self.fsInfo.filter(|_| self.fsInfoDirty) {
+            let lba = self.bootSector.fsInfoSector as u64;
+            self.loadDataSector(lba)?;
+            fsInfo.update(&mut self.dataBuffer);
+            self.writeDataSector(lba)?;
+            self.fsInfoDirty = false;
+        }
+
+        self.device.flush()
+    }
+
+    // The file or directory at path and where its entry is; the root doesn't have one
+    fn lookup(&mut self, path: &str) -> Result<(FileInfo, Option<EntryLocation>), &'static str> {
+        let mut current = FileInfo::root();
+        let mut location = None;
+        for name in path.split('/') {
+            if name.is_empty() || name == "." {
+                continue;
+            }
+
+            if !current.isDirectory() {
+                return Err("Not a directory");
+            }
+
+            // The root doesn't have a .. entry, but going up from it is harmless. Subdirectories' .. entries point
+            // at the root with cluster 0, same as root() does.
+            if name == ".." && current.firstCluster == 0 {
+                continue;
+            }
+
+            let (info, entry) = self
+                .findInDirectory(current.firstCluster, name)?
+                .ok_or("File not found")?;
+            current = info;
+            location = Some(entry);
+        }
+
+        Ok((current, location))
+    }
+
+    pub fn open(&mut self, path: &str) -> Result<FatFile, &'static str> {
+        let (info, entry) = self.lookup(path)?;
+        if info.isDirectory() {
+            return Err("Is a directory");
+        }
+
+        Ok(FatFile {
+            info,
+            position: 0,
+            cursor: None,
+            entry: entry.ok_or("Is a directory")?,
+        })
+    }
+
+    pub fn openDirectory(&mut self, path: &str) -> Result<FatDirectory, &'static str> {
+        let info = self.stat(path)?;
+        if !info.isDirectory() {
+            return Err("Not a directory");
+        }
+
+        Ok(self.getDirectory(info.firstCluster))
+    }
+
+    // Next thing in the directory, including . and .., or None when there's nothing left
+    pub fn readDirectory(
+        &mut self,
+        directory: &mut FatDirectory,
+    ) -> Result<Option<FileInfo>, &'static str> {
+        Ok(self.nextEntry(directory)?.map(|(info, _)| info))
+    }
+
+    // Next entry with its long name, if it has one. The long name entries can span sectors and clusters, so they're
+    // collected as we go.
+    fn nextEntry(
+        &mut self,
+        directory: &mut FatDirectory,
+    ) -> Result<Option<(FileInfo, EntryLocation)>, &'static str> {
+        let mut longName = LongNameBuilder::new();
+        let mut longNameStart = directory.index;
+
+        loop {
+            let Some(lba) = self.getDirectoryEntryLba(directory)? else {
+                return Ok(None);
+            };
+
+            let index = directory.index;
+            let offset = self.getEntryOffset(index);
+            self.loadDataSector(lba)?;
+            let bytes = &self.dataBuffer[offset..offset + DIRECTORY_ENTRY_LENGTH];
+            directory.index += 1;
+
+            match DirectoryEntry::parse(bytes) {
+                DirectoryEntry::End => return Ok(None),
+                DirectoryEntry::Unused => longName.reset(),
+                DirectoryEntry::LongName => {
+                    if !longName.isInProgress() {
+                        longNameStart = index;
+                    }
+
+                    longName.add(bytes);
+                    if !longName.isInProgress() {
+                        // Orphan; if this was the start of a new one it'll get picked up next time
+                        longNameStart = index + 1;
+                    }
+                }
+                DirectoryEntry::File(mut info) => {
+                    let firstIndex = match longName.finish(bytes) {
+                        Some(characters) => {
+                            info.setLongName(characters);
+                            longNameStart
+                        }
+                        None => index,
+                    };
+
+                    let location = EntryLocation {
+                        lba,
+                        offset,
+                        directory: directory.firstCluster,
+                        firstIndex,
+                        index,
+                    };
+
+                    return Ok(Some((info, location)));
+                }
+            }
+        }
+    }
+
+    // Reads from the current position, returning how much was read; 0 means end of file
+    pub fn read(&mut self, file: &mut FatFile, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        let size = file.info.size as u64;
+        let mut done = 0;
+
+        while done < buffer.len() && file.position < size {
+            let clusterIndex = (file.position / self.clusterSize as u64) as u32;
+            let Some(cluster) =
+                self.clusterAt(&mut file.cursor, file.info.firstCluster, clusterIndex)?
+            else {
+                return Err("Cluster chain is shorter than the file");
+            };
+
+            let withinCluster = (file.position % self.clusterSize as u64) as usize;
+            let lba = self.clusterToLba(cluster) + (withinCluster / self.sectorSize) as u64;
+            let withinSector = withinCluster % self.sectorSize;
+            let remaining = (buffer.len() - done).min((size - file.position) as usize);
+
+            let length = if withinSector == 0 && remaining >= self.sectorSize {
+                // Whole sectors go straight to the caller, as many as are left in this cluster
+                let sectors = (remaining / self.sectorSize)
+                    .min((self.clusterSize - withinCluster) / self.sectorSize);
+                let length = sectors * self.sectorSize;
+                self.device.read(lba, &mut buffer[done..done + length])?;
+                length
+            } else {
+                let length = remaining.min(self.sectorSize - withinSector);
+                self.loadDataSector(lba)?;
+                buffer[done..done + length]
+                    .copy_from_slice(&self.dataBuffer[withinSector..withinSector + length]);
+                length
+            };
+
+            done += length;
+            file.position += length as u64;
+        }
+
+        Ok(done)
+    }
+
+    // Moves the position, returning where it ended up. The end is fine, past it isn't.
+    pub fn seek(&mut self, file: &mut FatFile, from: SeekFrom) -> Result<u64, &'static str> {
+        let size = file.info.size as u64;
+        let position = from.resolve(file.position, size)?;
+        if position > size {
+            return Err("Seek past the end");
+        }
+
+        file.position = position;
+        Ok(position)
+    }
+
+    fn findInDirectory(
+        &mut self,
+        firstCluster: u32,
+        name: &str,
+    ) -> Result<Option<(FileInfo, EntryLocation)>, &'static str> {
+        let mut directory = self.getDirectory(firstCluster);
+        while let Some((info, entry)) = self.nextEntry(&mut directory)? {
+            if info.nameMatches(name) {
+                return Ok(Some((info, entry)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Finds the first cluster of the directory a new name goes in, making sure it isn't already there
+    fn prepareCreate<'p>(&mut self, path: &'p str) -> Result<(u32, &'p str), &'static str> {
+        let (parentPath, name) = splitPath(path)?;
+        if name == "." || name == ".." {
+            return Err("Name is reserved");
+        }
+
+        let parent = self.stat(parentPath)?;
+        if !parent.isDirectory() {
+            return Err("Not a directory");
+        }
+
+        if self.findInDirectory(parent.firstCluster, name)?.is_some() {
+            return Err("File already exists");
+        }
+
+        Ok((parent.firstCluster, name))
+    }
+
+    // The short name a new entry gets: name itself if it fits, otherwise a ~N alias nothing else has
+    fn chooseShortName(
+        &mut self,
+        directoryCluster: u32,
+        name: &str,
+    ) -> Result<[u8; SHORT_NAME_LENGTH], &'static str> {
+        if isShortName(name) {
+            return toShortName(name);
+        }
+
+        for number in 1..=MAX_ALIAS_NUMBER {
+            let alias = makeShortAlias(name, number);
+            let mut directory = self.getDirectory(directoryCluster);
+            let mut taken = false;
+            while let Some((_, entry)) = self.nextEntry(&mut directory)? {
+                self.loadDataSector(entry.lba)?;
+                if self.dataBuffer[entry.offset..entry.offset + SHORT_NAME_LENGTH] == alias {
+                    taken = true;
+                    break;
+                }
+            }
+
+            if !taken {
+                return Ok(alias);
+            }
+        }
+
+        Err("Ran out of short names")
+    }
+
+    // Puts a new entry, and its long name if it needs one, in the first run of free slots big enough. Directories
+    // grow a cluster at a time when there isn't one, apart from the FAT12/16 root which can't.
+    fn addEntry(
+        &mut self,
+        directoryCluster: u32,
+        name: &str,
+        attributes: u8,
+        firstCluster: u32,
+    ) -> Result<(FileInfo, EntryLocation), &'static str> {
+        let shortName = self.chooseShortName(directoryCluster, name)?;
+        let mut longName = [0; MAX_LONG_NAME_LENGTH];
+        let longNameLength = match isShortName(name) {
+            true => 0,
+            false => toLongName(name, &mut longName)?,
+        };
+
+        let needed = getEntryCount(longNameLength) as u32 + 1;
+        let mut directory = self.getDirectory(directoryCluster);
+        let mut runStart = 0;
+        let mut runLength = 0;
+
+        while runLength < needed {
+            let Some(lba) = self.getDirectoryEntryLba(&mut directory)? else {
+                if directory.firstCluster == 0 {
+                    return Err("Root directory is full");
+                }
+
+                // Index is at the start of the cluster that isn't there yet, so the cursor is on the last one
+                let entriesPerCluster = (self.clusterSize / DIRECTORY_ENTRY_LENGTH) as u32;
+                let last = self
+                    .clusterAt(
+                        &mut directory.cursor,
+                        directory.firstCluster,
+                        directory.index / entriesPerCluster - 1,
+                    )?
+                    .ok_or("Directory's cluster chain is broken")?;
+                self.allocateCluster(Some(last), true)?;
+                continue;
+            };
+
+            let offset = self.getEntryOffset(directory.index);
+            self.loadDataSector(lba)?;
+            if isFree(&self.dataBuffer[offset..]) {
+                if runLength == 0 {
+                    runStart = directory.index;
+                }
+
+                runLength += 1;
+            } else {
+                runLength = 0;
+            }
+
+            directory.index += 1;
+        }
+
+        // 7.1 Long entries go in reverse order: the end of the name first, then the short entry last
+        let checksum = getChecksum(&shortName);
+        let longNameEntries = needed - 1;
+        let mut directory = FatDirectory {
+            firstCluster: directory.firstCluster,
+            index: runStart,
+            cursor: None,
+        };
+
+        loop {
+            let lba = self
+                .getDirectoryEntryLba(&mut directory)?
+                .ok_or("Free directory entry went missing")?;
+            let offset = self.getEntryOffset(directory.index);
+            let order = runStart + longNameEntries - directory.index;
+
+            self.loadDataSector(lba)?;
+            let bytes = &mut self.dataBuffer[offset..];
+            if order == 0 {
+                encodeEntry(bytes, &shortName, attributes, firstCluster);
+            } else {
+                encodeLongEntry(bytes, &longName[..longNameLength], order as usize, checksum);
+            }
+
+            self.writeDataSector(lba)?;
+
+            if order == 0 {
+                let DirectoryEntry::File(mut info) = DirectoryEntry::parse(
+                    &self.dataBuffer[offset..offset + DIRECTORY_ENTRY_LENGTH],
+                ) else {
+                    return Err("New directory entry didn't parse");
+                };
+
+                if longNameLength != 0 {
+                    info.setLongName(&longName[..longNameLength]);
+                }
+
+                let location = EntryLocation {
+                    lba,
+                    offset,
+                    directory: directory.firstCluster,
+                    firstIndex: runStart,
+                    index: directory.index,
+                };
+
+                return Ok((info, location));
+            }
+
+            directory.index += 1;
+        }
+    }
+
+    // Copies a file's size and first cluster back to its directory entry
+    fn updateEntry(&mut self, file: &FatFile) -> Result<(), &'static str> {
+        self.loadDataSector(file.entry.lba)?;
+        let bytes = &mut self.dataBuffer[file.entry.offset..];
+        setFirstCluster(bytes, file.info.firstCluster);
+        setSize(bytes, file.info.size);
+        self.writeDataSector(file.entry.lba)
+    }
+
+    // Adds the clusterIndex'th cluster to a file that's exactly that many clusters long
+    fn extendFile(&mut self, file: &mut FatFile, clusterIndex: u32) -> Result<u32, &'static str> {
+        let previous = match clusterIndex {
+            0 => None,
+            _ => Some(
+                self.clusterAt(&mut file.cursor, file.info.firstCluster, clusterIndex - 1)?
+                    .ok_or("Cluster chain is shorter than the file")?,
+            ),
+        };
+
+        let cluster = self.allocateCluster(previous, false)?;
+        if previous.is_none() {
+            file.info.firstCluster = cluster;
+        }
+
+        file.cursor = Some(ChainCursor {
+            index: clusterIndex,
+            cluster,
+        });
+        Ok(cluster)
+    }
+
+    // Finds a free cluster, marks it as the end of a chain and links it after previous. Directories need their new
+    // clusters zeroed so the entries read as the end.
+    fn allocateCluster(&mut self, previous: Option<u32>, zero: bool) -> Result<u32, &'static str> {
+        let count = self.layout.clusterCount;
+        for step in 0..count {
+            let cluster =
+                FIRST_DATA_CLUSTER + (self.nextFreeHint - FIRST_DATA_CLUSTER + step) % count;
+            if self.readFatEntry(cluster)? != FREE_CLUSTER {
+                continue;
+            }
+
+            if zero {
+                self.zeroCluster(cluster)?;
+            }
+
+            self.writeFatEntry(cluster, self.layout.fatType.getEntryMask())?;
+            if let Some(previous) = previous {
+                self.writeFatEntry(previous, cluster)?;
+            }
+
+            self.nextFreeHint = FIRST_DATA_CLUSTER + (cluster + 1 - FIRST_DATA_CLUSTER) % count;
+            self.updateFsInfo(-1);
+            return Ok(cluster);
+        }
+
+        Err("Volume is full")
+    }
+
+    fn freeChain(&mut self, first: u32) -> Result<(), &'static str> {
+        let mut cluster = first;
+        for _ in 0..self.layout.clusterCount {
+            let next = self.nextCluster(cluster)?;
+            self.writeFatEntry(cluster, FREE_CLUSTER)?;
+            self.updateFsInfo(1);
+
+            match next {
+                Some(next) => cluster = next,
+                None => return Ok(()),
+            }
+        }
+
+        Err("Cluster chain loops")
+    }
+
+    // Keeps FSInfo's free count and hint in step with an allocation (-1) or free (1)
+    fn updateFsInfo(&mut self, freeChange: i32) {
+        if let Some(fsInfo) = self.fsInfo.as_mut() {
+            fsInfo.freeCount = fsInfo
+                .freeCount
+                .and_then(|count| count.checked_add_signed(freeChange));
+            fsInfo.nextFree = Some(self.nextFreeHint);
+            self.fsInfoDirty = true;
+        }
+    }
+
+    fn zeroCluster(&mut self, cluster: u32) -> Result<(), &'static str> {
+        let lba = self.clusterToLba(cluster);
+        self.dataBuffer.fill(0);
+        for sector in 0..self.bootSector.sectorsPerCluster as u64 {
+            self.dataBufferLba = Some(lba + sector);
+            self.writeDataSector(lba + sector)?;
+        }
+
+        Ok(())
+    }
+
+    // The directory starting at firstCluster, where 0 is the root whichever kind of FAT it is
+    fn getDirectory(&self, firstCluster: u32) -> FatDirectory {
+        let firstCluster = match firstCluster {
+            0 if self.layout.fatType == FatType::Fat32 => self.layout.rootCluster,
+            _ => firstCluster,
+        };
+
+        FatDirectory {
+            firstCluster,
+            index: 0,
+            cursor: None,
+        }
+    }
+
+    fn getEntryOffset(&self, index: u32) -> usize {
+        index as usize * DIRECTORY_ENTRY_LENGTH % self.sectorSize
+    }
+
+    // Which sector the directory's next entry is in, or None if the directory has run out of space
+    fn getDirectoryEntryLba(
+        &mut self,
+        directory: &mut FatDirectory,
+    ) -> Result<Option<u64>, &'static str> {
+        let offset = directory.index as u64 * DIRECTORY_ENTRY_LENGTH as u64;
+
+        if directory.firstCluster == 0 {
+            if directory.index >= self.bootSector.rootEntries as u32 {
+                return Ok(None);
+            }
+
+            return Ok(Some(
+                self.layout.rootStart + offset / self.sectorSize as u64,
+            ));
+        }
+
+        let clusterIndex = (offset / self.clusterSize as u64) as u32;
+        let Some(cluster) =
+            self.clusterAt(&mut directory.cursor, directory.firstCluster, clusterIndex)?
+        else {
+            return Ok(None);
+        };
+
+        let withinCluster = offset % self.clusterSize as u64;
+        Ok(Some(
+            self.clusterToLba(cluster) + withinCluster / self.sectorSize as u64,
+        ))
+    }
+
+    // The index'th cluster of the chain starting at first, or None if the chain is shorter than that. Only follows
+    // as much of the chain as it needs to, starting from the cursor when that's behind where we're going.
+    fn clusterAt(
+        &mut self,
+        cursor: &mut Option<ChainCursor>,
+        first: u32,
+        index: u32,
+    ) -> Result<Option<u32>, &'static str> {
+        if first == 0 {
+            return Ok(None);
+        }
+
+        let mut current = match *cursor {
+            Some(saved) if saved.index <= index => saved,
+            _ => ChainCursor {
+                index: 0,
+                cluster: first,
+            },
+        };
+
+        while current.index < index {
+            let Some(next) = self.nextCluster(current.cluster)? else {
+                return Ok(None);
+            };
+
+            current = ChainCursor {
+                index: current.index + 1,
+                cluster: next,
+            };
+        }
+
+        self.checkCluster(current.cluster)?;
+        *cursor = Some(current);
+        Ok(Some(current.cluster))
+    }
+
+    // What follows cluster in its chain, None at the end
+    fn nextCluster(&mut self, cluster: u32) -> Result<Option<u32>, &'static str> {
+        let entry = self.readFatEntry(cluster)?;
+        let fatType = self.layout.fatType;
+        if entry >= fatType.getEndOfChain() {
+            return Ok(None);
+        }
+
+        if entry == FREE_CLUSTER {
+            return Err("Cluster chain runs into a free cluster");
+        }
+
+        if entry == fatType.getBadCluster() {
+            return Err("Cluster chain runs into a bad cluster");
+        }
+
+        self.checkCluster(entry)?;
+        Ok(Some(entry))
+    }
+
+    // Only ever reads the active FAT, which is the first one unless FAT32 turned mirroring off; the others are just
+    // copies
+    fn readFatEntry(&mut self, cluster: u32) -> Result<u32, &'static str> {
+        let offset = self.loadFatSector(cluster)?;
+        Ok(match self.layout.fatType {
+            FatType::Fat32 => readU32(&self.fatBuffer, offset) & self.layout.fatType.getEntryMask(),
+            _ => readU16(&self.fatBuffer, offset) as u32,
+        })
+    }
+
+    // Writes every copy of the FAT, or just the active one when they aren't mirrored. They all get the active one's
+    // sector, so any that had drifted are brought back in line.
+    fn writeFatEntry(&mut self, cluster: u32, value: u32) -> Result<(), &'static str> {
+        let offset = self.loadFatSector(cluster)?;
+        match self.layout.fatType {
+            FatType::Fat32 => {
+                // The top 4 bits aren't ours to change
+                let mask = self.layout.fatType.getEntryMask();
+                let old = readU32(&self.fatBuffer, offset);
+                writeU32(&mut self.fatBuffer, offset, old & !mask | value & mask);
+            }
+            _ => writeU16(&mut self.fatBuffer, offset, value as u16),
+        }
+
+        let lba = self.fatBufferLba.ok_or("FAT sector went missing")?;
+        let sector = lba - self.getFatStart(self.bootSector.getActiveFat().unwrap_or(0));
+        for copy in 0..self.bootSector.fatCount {
+            if self
+                .bootSector
+                .getActiveFat()
+                .is_some_and(|active| active != copy)
+            {
+                continue;
+            }
+
+            let copyLba = self.getFatStart(copy) + sector;
+            self.device
+                .write(copyLba, &self.fatBuffer[..self.sectorSize])?;
+        }
+
+        Ok(())
+    }
+
+    fn getFatStart(&self, copy: u8) -> u64 {
+        self.layout.fatStart + copy as u64 * self.bootSector.sectorsPerFat as u64
+    }
+
+    // Reads the sector of the active FAT that has cluster's entry, returning where in it the entry is
+    fn loadFatSector(&mut self, cluster: u32) -> Result<usize, &'static str> {
+        self.checkCluster(cluster)?;
+
+        let entrySize = match self.layout.fatType {
+            FatType::Fat32 => 4,
+            _ => 2,
+        };
+
+        let offset = cluster as usize * entrySize;
+        let fatStart = self.getFatStart(self.bootSector.getActiveFat().unwrap_or(0));
+        let lba = fatStart + (offset / self.sectorSize) as u64;
+        if self.fatBufferLba != Some(lba) {
+            self.fatBufferLba = None;
+            self.device
+                .read(lba, &mut self.fatBuffer[..self.sectorSize])?;
+            self.fatBufferLba = Some(lba);
+        }
+
+        Ok(offset % self.sectorSize)
+    }
+
+    fn loadDataSector(&mut self, lba: u64) -> Result<(), &'static str> {
+        if self.dataBufferLba != Some(lba) {
+            self.dataBufferLba = None;
+            self.device
+                .read(lba, &mut self.dataBuffer[..self.sectorSize])?;
+            self.dataBufferLba = Some(lba);
+        }
+
+        Ok(())
+    }
+
+    // dataBuffer has to already hold lba, with whatever changes are being made
+    fn writeDataSector(&mut self, lba: u64) -> Result<(), &'static str> {
+        if self.dataBufferLba != Some(lba) {
+            return Err("Writing a sector that isn't loaded");
+        }
+
+        self.device.write(lba, &self.dataBuffer[..self.sectorSize])
+    }
+
+    fn checkCluster(&self, cluster: u32) -> Result<(), &'static str> {
+        if cluster < FIRST_DATA_CLUSTER || cluster >= FIRST_DATA_CLUSTER + self.layout.clusterCount
+        {
+            return Err("Cluster number is out of range");
+        }
+
+        Ok(())
+    }
+
+    fn clusterToLba(&self, cluster: u32) -> u64 {
+        self.layout.dataStart
+            + (cluster - FIRST_DATA_CLUSTER) as u64 * self.bootSector.sectorsPerCluster as u64
+    }
+}
+
+// Splits off the last component of a path; the parent of a bare name is the root
+fn splitPath(path: &str) -> Result<(&str, &str), &'static str> {
+    let path = path.trim_end_matches('/');
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if name.is_empty() {
+        return Err("Path doesn't have a name");
+    }
+
+    Ok((parent, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        disk::memoryBlockDevice::MemoryBlockDevice, fileSystem::fat::testImage::TestImage,
+    };
+
+    const TYPES: [FatType; 2] = [FatType::Fat16, FatType::Fat32];
+    const HELLO: &[u8] = b"Hello from the test image";
+
+    // Doesn't repeat on a sector boundary, so a sector read from the wrong place shows up
+    fn pattern(length: usize, seed: u8) -> Vec<u8> {
+        (0..length)
+            .map(|index| (index % 251) as u8 ^ seed)
+            .collect()
+    }
+
+    fn getKernel(image: &TestImage) -> Vec<u8> {
+        pattern(image.getClusterSize() * 3 + 10, 0x5A)
+    }
+
+    // A few files in the root, one with a long name, and BOOT/KERNEL.BIN spread out of order over the disk
+    fn makeImage(fatType: FatType) -> TestImage {
+        let mut image = TestImage::new(fatType);
+        let kernel = getKernel(&image);
+        image.addFile(0, "HI.TXT", None, HELLO, &[10]);
+        image.addFile(
+            0,
+            "LONGFI~1.TXT",
+            Some("Long File Name.txt"),
+            &pattern(100, 1),
+            &[11],
+        );
+        image.addDirectory(0, "BOOT", 12);
+        image.addFile(12, "KERNEL.BIN", None, &kernel, &[20, 15, 30, 13]);
+        image.addFile(0, "EMPTY.TXT", None, &[], &[]);
+        image
+    }
+
+    fn mount(image: &mut TestImage) -> FatFileSystem<MemoryBlockDevice<'_>> {
+        FatFileSystem::mount(MemoryBlockDevice::new(&mut image.image, 512).unwrap()).unwrap()
+    }
+
+    fn listDirectory(fileSystem: &mut FatFileSystem<MemoryBlockDevice>, path: &str) -> Vec<String> {
+        let mut directory = fileSystem.openDirectory(path).unwrap();
+        let mut names = Vec::new();
+        while let Some(info) = fileSystem.readDirectory(&mut directory).unwrap() {
+            names.push(info.getName().to_string());
+        }
+
+        names
+    }
+
+    #[test]
+    fn mountsBothTypes() {
+        for fatType in TYPES {
+            let mut image = makeImage(fatType);
+            let clusterSize = image.getClusterSize();
+            let fileSystem = mount(&mut image);
+
+            assert_eq!(fileSystem.getFatType(), fatType);
+            assert_eq!(fileSystem.getVolumeLabel(), "TEST VOLUME");
+            assert_eq!(fileSystem.getClusterSize(), clusterSize);
+        }
+    }
+
+    #[test]
+    fn findsFilesByPath() {
+        for fatType in TYPES {
+            let mut image = makeImage(fatType);
+            let kernelSize = getKernel(&image).len() as u32;
+            let mut fileSystem = mount(&mut image);
+
+            assert_eq!(fileSystem.stat("/HI.TXT").unwrap().size, HELLO.len() as u32);
+            assert_eq!(fileSystem.stat("hi.txt").unwrap().size, HELLO.len() as u32);
+            assert!(fileSystem.stat("/").unwrap().isDirectory());
+            assert!(fileSystem.stat("/BOOT").unwrap().isDirectory());
+            assert!(fileSystem.stat("/boot/").unwrap().isDirectory());
+
+            let kernel = fileSystem.stat("/BOOT/KERNEL.BIN").unwrap();
+            assert_eq!(kernel.size, kernelSize);
+            assert_eq!(kernel.firstCluster, 20);
+            let again = fileSystem.stat("/boot/./../BOOT/kernel.bin").unwrap();
+            assert_eq!(again.firstCluster, 20);
+
+            // Either name will do for one with a long name
+            let long = fileSystem.stat("/Long File Name.txt").unwrap();
+            assert_eq!(long.getName(), "Long File Name.txt");
+            assert_eq!(long.getShortName(), "LONGFI~1.TXT");
+            assert_eq!(fileSystem.stat("/LONG FILE NAME.TXT").unwrap().size, 100);
+            assert_eq!(fileSystem.stat("/longfi~1.txt").unwrap().size, 100);
+
+            assert_eq!(fileSystem.stat("/NOPE.TXT").err(), Some("File not found"));
+            assert_eq!(
+                fileSystem.stat("/BOOT/HI.TXT").err(),
+                Some("File not found")
+            );
+            assert_eq!(fileSystem.stat("/HI.TXT/X").err(), Some("Not a directory"));
+            assert_eq!(fileSystem.open("/BOOT").err(), Some("Is a directory"));
+            assert_eq!(
+                fileSystem.openDirectory("/HI.TXT").err(),
+                Some("Not a directory")
+            );
+        }
+    }
+
+    #[test]
+    fn listsDirectories() {
+        for fatType in TYPES {
+            let mut image = makeImage(fatType);
+            let mut fileSystem = mount(&mut image);
+
+            assert_eq!(
+                listDirectory(&mut fileSystem, "/"),
+                ["HI.TXT", "Long File Name.txt", "BOOT", "EMPTY.TXT"]
+            );
+            assert_eq!(
+                listDirectory(&mut fileSystem, "/BOOT"),
+                [".", "..", "KERNEL.BIN"]
+            );
+        }
+    }
+
+    #[test]
+    fn readsFragmentedFiles() {
+        for fatType in TYPES {
+            let mut image = makeImage(fatType);
+            let expected = getKernel(&image);
+            let mut fileSystem = mount(&mut image);
+
+            let mut file = fileSystem.open("/BOOT/KERNEL.BIN").unwrap();
+            let mut buffer = vec![0u8; expected.len() + 10];
+            assert_eq!(
+                fileSystem.read(&mut file, &mut buffer).unwrap(),
+                expected.len()
+            );
+            assert_eq!(buffer[..expected.len()], expected);
+            assert_eq!(fileSystem.read(&mut file, &mut buffer).unwrap(), 0);
+
+            // Odd sized pieces end up part way through sectors and clusters
+            let mut file = fileSystem.open("/BOOT/KERNEL.BIN").unwrap();
+            let mut pieces = Vec::new();
+            let mut piece = [0u8; 100];
+            loop {
+                let length = fileSystem.read(&mut file, &mut piece).unwrap();
+                if length == 0 {
+                    break;
+                }
+
+                pieces.extend_from_slice(&piece[..length]);
+            }
+
+            assert_eq!(pieces, expected);
+
+            let mut file = fileSystem.open("/EMPTY.TXT").unwrap();
+            assert_eq!(fileSystem.read(&mut file, &mut buffer).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn followsChainsLazily() {
+        for fatType in TYPES {
+            // KERNEL.BIN's chain goes 20 -> 15 -> 30 -> 13; breaking the link out of 15 only matters once the read
+            // gets that far
+            let mut image = makeImage(fatType);
+            let expected = getKernel(&image);
+            let clusterSize = image.getClusterSize();
+            image.setFatEntry(15, 0);
+            let mut fileSystem = mount(&mut image);
+
+            let mut file = fileSystem.open("/BOOT/KERNEL.BIN").unwrap();
+            let mut buffer = vec![0u8; clusterSize * 2];
+            assert_eq!(
+                fileSystem.read(&mut file, &mut buffer).unwrap(),
+                buffer.len()
+            );
+            assert_eq!(buffer, expected[..buffer.len()]);
+
+            assert_eq!(
+                fileSystem.read(&mut file, &mut buffer).err(),
+                Some("Cluster chain runs into a free cluster")
+            );
+
+            // Going back to somewhere it's already been still works
+            fileSystem.seek(&mut file, SeekFrom::Start(10)).unwrap();
+            assert_eq!(fileSystem.read(&mut file, &mut buffer[..20]).unwrap(), 20);
+            assert_eq!(buffer[..20], expected[10..30]);
+        }
+    }
+
+    #[test]
+    fn seeks() {
+        for fatType in TYPES {
+            let mut image = makeImage(fatType);
+            let expected = getKernel(&image);
+            let clusterSize = image.getClusterSize();
+            let size = expected.len() as u64;
+            let mut fileSystem = mount(&mut image);
+            let mut file = fileSystem.open("/BOOT/KERNEL.BIN").unwrap();
+            let mut buffer = [0u8; 20];
+
+            // Across a cluster boundary
+            let start = clusterSize as u64 - 7;
+            assert_eq!(
+                fileSystem.seek(&mut file, SeekFrom::Start(start)).unwrap(),
+                start
+            );
+            assert_eq!(fileSystem.read(&mut file, &mut buffer).unwrap(), 20);
+            assert_eq!(buffer, expected[clusterSize - 7..clusterSize + 13]);
+
+            // Forwards into the third cluster, then back into the first, which has to start the chain again
+            let position = fileSystem
+                .seek(&mut file, SeekFrom::Current(clusterSize as i64 + 100))
+                .unwrap();
+            assert_eq!(position, clusterSize as u64 * 2 + 113);
+            fileSystem.read(&mut file, &mut buffer).unwrap();
+            assert_eq!(
+                buffer,
+                expected[clusterSize * 2 + 113..clusterSize * 2 + 133]
+            );
+
+            let position = fileSystem
+                .seek(&mut file, SeekFrom::Current(-(clusterSize as i64 * 2)))
+                .unwrap();
+            assert_eq!(position, 133);
+            fileSystem.read(&mut file, &mut buffer).unwrap();
+            assert_eq!(buffer, expected[133..153]);
+
+            // The end is fine, and reads nothing
+            assert_eq!(
+                fileSystem.seek(&mut file, SeekFrom::End(-3)).unwrap(),
+                size - 3
+            );
+            assert_eq!(fileSystem.read(&mut file, &mut buffer).unwrap(), 3);
+            assert_eq!(buffer[..3], expected[expected.len() - 3..]);
+            assert_eq!(fileSystem.read(&mut file, &mut buffer).unwrap(), 0);
+
+            assert_eq!(
+                fileSystem.seek(&mut file, SeekFrom::End(1)).err(),
+                Some("Seek past the end")
+            );
+            assert_eq!(
+                fileSystem
+                    .seek(&mut file, SeekFrom::Current(-(size as i64) - 1))
+                    .err(),
+                Some("Seek before the start")
+            );
+            assert_eq!(file.getPosition(), size);
+        }
+    }
+
+    // Empty volume written to by the driver, then checked without it
+    fn writeAndCheck(
+        fatType: FatType,
+        write: impl FnOnce(&mut FatFileSystem<MemoryBlockDevice>, usize),
+    ) -> TestImage {
+        let mut image = TestImage::new(fatType);
+        let clusterSize = image.getClusterSize();
+        {
+            let mut fileSystem = mount(&mut image);
+            write(&mut fileSystem, clusterSize);
+            fileSystem.flush().unwrap();
+        }
+
+        image.check().unwrap();
+        image.fsck();
+        image
+    }
+
+    #[test]
+    fn writesFilesAcrossClusters() {
+        for fatType in TYPES {
+            let mut data = Vec::new();
+            let image = writeAndCheck(fatType, |fileSystem, clusterSize| {
+                data = pattern(clusterSize * 2 + 100, 3);
+                let mut file = fileSystem.create("/DATA.BIN").unwrap();
+                fileSystem
+                    .write(&mut file, &data[..clusterSize + 1])
+                    .unwrap();
+
+                // Append from the end, which is where it already is
+                fileSystem.seek(&mut file, SeekFrom::End(0)).unwrap();
+                fileSystem
+                    .write(&mut file, &data[clusterSize + 1..])
+                    .unwrap();
+
+                // Overwriting the middle doesn't change the size
+                fileSystem.seek(&mut file, SeekFrom::Start(10)).unwrap();
+                fileSystem.write(&mut file, &[0xFF; 5]).unwrap();
+                data[10..15].fill(0xFF);
+                assert_eq!(file.getInfo().size as usize, data.len());
+            });
+
+            assert_eq!(image.readFile("/DATA.BIN").unwrap(), data);
+            assert_eq!(image.find("/DATA.BIN").unwrap().size as usize, data.len());
+        }
+    }
+
+    #[test]
+    fn truncatesAndFreesClusters() {
+        for fatType in TYPES {
+            let mut data = Vec::new();
+            let image = writeAndCheck(fatType, |fileSystem, clusterSize| {
+                data = pattern(clusterSize * 3, 4);
+                let mut file = fileSystem.create("/SHRINK.BIN").unwrap();
+                fileSystem.write(&mut file, &data).unwrap();
+                fileSystem.truncate(&mut file, 10).unwrap();
+                assert_eq!(fileSystem.stat("/SHRINK.BIN").unwrap().size, 10);
+                assert_eq!(
+                    fileSystem.truncate(&mut file, 20).err(),
+                    Some("Truncate can only shrink files")
+                );
+
+                let mut file = fileSystem.create("/GONE.BIN").unwrap();
+                fileSystem.write(&mut file, &data).unwrap();
+                fileSystem.truncate(&mut file, 0).unwrap();
+            });
+
+            assert_eq!(image.readFile("/SHRINK.BIN").unwrap(), data[..10]);
+            assert_eq!(image.find("/GONE.BIN").unwrap().firstCluster, 0);
+        }
+    }
+
+    #[test]
+    fn createsAndRemovesDirectories() {
+        for fatType in TYPES {
+            let image = writeAndCheck(fatType, |fileSystem, _| {
+                fileSystem.createDirectory("/DANOSTMP").unwrap();
+                fileSystem.createDirectory("/DANOSTMP/INNER").unwrap();
+                let mut file = fileSystem.create("/DANOSTMP/INNER/WRITE.TST").unwrap();
+                fileSystem.write(&mut file, b"Scratch").unwrap();
+
+                assert_eq!(
+                    fileSystem.createDirectory("/DANOSTMP").err(),
+                    Some("File already exists")
+                );
+                assert_eq!(
+                    fileSystem.create("/NOPE/FILE.TXT").err(),
+                    Some("File not found")
+                );
+                assert_eq!(
+                    fileSystem.remove("/DANOSTMP/INNER").err(),
+                    Some("Directory isn't empty")
+                );
+
+                fileSystem.remove("/DANOSTMP/INNER/WRITE.TST").unwrap();
+                fileSystem.remove("/DANOSTMP/INNER").unwrap();
+                fileSystem.createDirectory("/KEPT").unwrap();
+                let mut file = fileSystem.create("/KEPT/FILE.TXT").unwrap();
+                fileSystem.write(&mut file, b"Still here").unwrap();
+            });
+
+            assert!(image.find("/DANOSTMP/INNER").is_none());
+            assert_eq!(
+                image
+                    .readDirectory(image.find("/DANOSTMP").unwrap().firstCluster)
+                    .unwrap()
+                    .len(),
+                2
+            );
+            assert_eq!(image.readFile("/KEPT/FILE.TXT").unwrap(), b"Still here");
+
+            // And everything they used is free again
+            let image = writeAndCheck(fatType, |fileSystem, _| {
+                fileSystem.createDirectory("/DANOSTMP").unwrap();
+                let mut file = fileSystem.create("/DANOSTMP/WRITE.TST").unwrap();
+                fileSystem.write(&mut file, b"Scratch").unwrap();
+                fileSystem.remove("/DANOSTMP/WRITE.TST").unwrap();
+                fileSystem.remove("/DANOSTMP").unwrap();
+            });
+
+            assert!(image.find("/DANOSTMP").is_none());
+            assert_eq!(image.getFreeCount(), TestImage::new(fatType).getFreeCount());
+        }
+    }
+
+    #[test]
+    fn writesLongNames() {
+        for fatType in TYPES {
+            let image = writeAndCheck(fatType, |fileSystem, _| {
+                for name in ["/A long file name.txt", "/A long file name 2.txt"] {
+                    let mut file = fileSystem.create(name).unwrap();
+                    fileSystem.write(&mut file, name.as_bytes()).unwrap();
+                }
+
+                let info = fileSystem.stat("/a LONG file name.TXT").unwrap();
+                assert_eq!(info.getName(), "A long file name.txt");
+            });
+
+            let first = image.find("/A long file name.txt").unwrap();
+            let second = image.find("/A long file name 2.txt").unwrap();
+            assert_eq!(first.shortName, "ALONGF~1.TXT");
+            assert_eq!(second.shortName, "ALONGF~2.TXT");
+            assert_eq!(
+                image.readFile("/ALONGF~2.TXT").unwrap(),
+                b"/A long file name 2.txt"
+            );
+        }
+    }
+
+    #[test]
+    fn growsDirectories() {
+        for fatType in TYPES {
+            // More than a cluster's worth of entries on both, with long names taking extra ones
+            let image = writeAndCheck(fatType, |fileSystem, _| {
+                fileSystem.createDirectory("/MANY").unwrap();
+                for index in 0..40 {
+                    let path = format!("/MANY/File number {index}.txt");
+                    let mut file = fileSystem.create(&path).unwrap();
+                    fileSystem.write(&mut file, path.as_bytes()).unwrap();
+                }
+            });
+
+            let directory = image.find("/MANY").unwrap();
+            assert_eq!(
+                image.readDirectory(directory.firstCluster).unwrap().len(),
+                42
+            );
+            assert_eq!(
+                image.readFile("/MANY/File number 39.txt").unwrap(),
+                b"/MANY/File number 39.txt"
+            );
+        }
+    }
+
+    #[test]
+    fn checkerSpotsBrokenVolumes() {
+        for fatType in TYPES {
+            let mut image = makeImage(fatType);
+            image.check().unwrap();
+            image.fsck();
+
+            // Allocated but not part of any file
+            image.setFatEntry(100, image.getEndOfChain());
+            assert_eq!(
+                image.check().err().unwrap(),
+                "Cluster 100 is allocated but nothing uses it"
+            );
+            image.setFatEntry(100, 0);
+
+            // KERNEL.BIN still has 4 clusters, but the last is the long named file's
+            image.setFatEntry(30, 11);
+            assert_eq!(
+                image.check().err().unwrap(),
+                "/BOOT/KERNEL.BIN shares cluster 11 with something else"
+            );
+            image.setFatEntry(30, 13);
+
+            // Long name that doesn't go with the entry after it
+            let slot = image.getDirectorySlots(0).unwrap()[2];
+            image.image[slot + 13] ^= 1;
+            assert!(image.check().is_err());
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use super::{readU32, writeU32};
+
+// 5 FAT32 FSInfo Sector Structure. Only ever a hint; anything in here can be wrong and has to be checked.
+const LEAD_SIGNATURE: u32 = 0x4161_5252;
+const STRUCTURE_SIGNATURE: u32 = 0x6141_7272;
+const TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+
+const OFFSET_LEAD_SIGNATURE: usize = 0;
+const OFFSET_STRUCTURE_SIGNATURE: usize = 484;
+const OFFSET_FREE_COUNT: usize = 488;
+const OFFSET_NEXT_FREE: usize = 492;
+const OFFSET_TRAIL_SIGNATURE: usize = 508;
+
+const UNKNOWN: u32 = 0xFFFF_FFFF;
+
+#[derive(Clone, Copy)]
+pub struct FsInfo {
+    pub freeCount: Option<u32>,
+    pub nextFree: Option<u32>,
+}
+
+impl FsInfo {
+    pub fn parse(sector: &[u8]) -> Result<FsInfo, &'static str> {
+        if sector.len() < 512
+            || readU32(sector, OFFSET_LEAD_SIGNATURE) != LEAD_SIGNATURE
+            || readU32(sector, OFFSET_STRUCTURE_SIGNATURE) != STRUCTURE_SIGNATURE
+            || readU32(sector, OFFSET_TRAIL_SIGNATURE) != TRAIL_SIGNATURE
+        {
+            return Err("Bad FSInfo signature");
+        }
+
+        Ok(FsInfo {
+            freeCount: known(readU32(sector, OFFSET_FREE_COUNT)),
+            nextFree: known(readU32(sector, OFFSET_NEXT_FREE)),
+        })
+    }
+
+    // Only the two counts change; the rest of the sector is left as it was
+    pub fn update(&self, sector: &mut [u8]) {
+        writeU32(sector, OFFSET_FREE_COUNT, self.freeCount.unwrap_or(UNKNOWN));
+        writeU32(sector, OFFSET_NEXT_FREE, self.nextFree.unwrap_or(UNKNOWN));
+    }
+}
+
+fn known(value: u32) -> Option<u32> {
+    if value == UNKNOWN { None } else { Some(value) }
+}
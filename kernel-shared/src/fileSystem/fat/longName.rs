@@ -0,0 +1,144 @@
+use super::{bootSector::DIRECTORY_ENTRY_LENGTH, directory::SHORT_NAME_LENGTH};
+
+// 7 Long File Name Implementation. A long name is stored as UCS-2 in a run of entries right before the short entry
+// it belongs to, last part first.
+pub const MAX_LONG_NAME_LENGTH: usize = 255; // In UCS-2 characters
+pub const MAX_LONG_NAME_ENTRIES: usize = MAX_LONG_NAME_LENGTH.div_ceil(CHARACTERS_PER_ENTRY);
+const CHARACTERS_PER_ENTRY: usize = 13;
+
+const OFFSET_ORDER: usize = 0;
+const OFFSET_ATTRIBUTES: usize = 11;
+const OFFSET_CHECKSUM: usize = 13;
+const ORDER_MASK: u8 = 0x1F;
+const LAST_LONG_ENTRY: u8 = 0x40; // On the first entry on disk, which holds the end of the name
+pub const ATTRIBUTE_LONG_NAME: u8 = 0x0F;
+
+// Where the 13 characters are in an entry: 5, then 6, then 2
+const CHARACTER_OFFSETS: [usize; CHARACTERS_PER_ENTRY] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+const NAME_TERMINATOR: u16 = 0x0000;
+const NAME_PADDING: u16 = 0xFFFF;
+
+// Collects long name entries as a directory is walked, handing the name over when the short entry they belong to
+// turns up. Anything out of order or with the wrong checksum is an orphan and gets dropped, same as 7.1 says.
+pub struct LongNameBuilder {
+    characters: [u16; MAX_LONG_NAME_ENTRIES * CHARACTERS_PER_ENTRY],
+    length: usize,
+    remaining: u8, // Entries still to come; 0 once the name is complete
+    checksum: u8,
+    inProgress: bool,
+}
+
+impl LongNameBuilder {
+    pub fn new() -> LongNameBuilder {
+        LongNameBuilder {
+            characters: [0; MAX_LONG_NAME_ENTRIES * CHARACTERS_PER_ENTRY],
+            length: 0,
+            remaining: 0,
+            checksum: 0,
+            inProgress: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.inProgress = false;
+        self.remaining = 0;
+        self.length = 0;
+    }
+
+    // True while a name has been started and hasn't been thrown away
+    pub fn isInProgress(&self) -> bool {
+        self.inProgress
+    }
+
+    pub fn add(&mut self, bytes: &[u8]) {
+        let order = bytes[OFFSET_ORDER] & ORDER_MASK;
+        let checksum = bytes[OFFSET_CHECKSUM];
+
+        if bytes[OFFSET_ORDER] & LAST_LONG_ENTRY != 0 {
+            if order == 0 || order as usize > MAX_LONG_NAME_ENTRIES {
+                self.reset();
+                return;
+            }
+
+            self.inProgress = true;
+            self.remaining = order;
+            self.checksum = checksum;
+            self.length = order as usize * CHARACTERS_PER_ENTRY;
+        } else if !self.inProgress || order != self.remaining || checksum != self.checksum {
+            self.reset();
+            return;
+        }
+
+        let start = (order as usize - 1) * CHARACTERS_PER_ENTRY;
+        for (index, &offset) in CHARACTER_OFFSETS.iter().enumerate() {
+            let character = u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+            self.characters[start + index] = character;
+
+            // Only the last part of the name has a terminator
+            if character == NAME_TERMINATOR && bytes[OFFSET_ORDER] & LAST_LONG_ENTRY != 0 {
+                self.length = start + index;
+                break;
+            }
+        }
+
+        self.remaining -= 1;
+    }
+
+    // The name, if everything before shortName was a complete long name for it. Either way the builder is ready for
+    // the next one afterwards.
+    pub fn finish(&mut self, shortName: &[u8]) -> Option<&[u16]> {
+        let complete = self.inProgress
+            && self.remaining == 0
+            && self.length > 0
+            && self.length <= MAX_LONG_NAME_LENGTH
+            && self.checksum == getChecksum(shortName);
+
+        self.inProgress = false;
+        if complete {
+            Some(&self.characters[..self.length])
+        } else {
+            None
+        }
+    }
+}
+
+pub fn isLongNameEntry(bytes: &[u8]) -> bool {
+    bytes[OFFSET_ATTRIBUTES] & 0x3F == ATTRIBUTE_LONG_NAME
+}
+
+// 7.2 Checksum of the 11 byte short name, so orphaned long names can be spotted
+pub fn getChecksum(shortName: &[u8]) -> u8 {
+    shortName[..SHORT_NAME_LENGTH]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+// How many entries name needs, not counting the short entry
+pub fn getEntryCount(characterCount: usize) -> usize {
+    characterCount.div_ceil(CHARACTERS_PER_ENTRY)
+}
+
+// Fills in the order'th (1 based) long entry for name
+pub fn encodeLongEntry(bytes: &mut [u8], name: &[u16], order: usize, checksum: u8) {
+    bytes[..DIRECTORY_ENTRY_LENGTH].fill(0);
+    bytes[OFFSET_ORDER] = order as u8;
+    if order == getEntryCount(name.len()) {
+        bytes[OFFSET_ORDER] |= LAST_LONG_ENTRY;
+    }
+
+    bytes[OFFSET_ATTRIBUTES] = ATTRIBUTE_LONG_NAME;
+    bytes[OFFSET_CHECKSUM] = checksum;
+
+    let start = (order - 1) * CHARACTERS_PER_ENTRY;
+    for (index, &offset) in CHARACTER_OFFSETS.iter().enumerate() {
+        let character = match name.get(start + index) {
+            Some(&character) => character,
+            None if start + index == name.len() => NAME_TERMINATOR,
+            None => NAME_PADDING,
+        };
+
+        bytes[offset..offset + 2].copy_from_slice(&character.to_le_bytes());
+    }
+}
@@ -0,0 +1,32 @@
+// Microsoft Extensible Firmware Initiative FAT32 File System Specification, 1.03
+// https://wiki.osdev.org/FAT
+pub mod bootSector;
+pub mod directory;
+pub mod fatFileSystem;
+pub mod fsInfo;
+pub mod longName;
+
+#[cfg(test)]
+mod testImage;
+
+// Everything on disk is little endian and rarely aligned, so it's read a byte at a time
+fn readU16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn readU32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn writeU16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn writeU32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
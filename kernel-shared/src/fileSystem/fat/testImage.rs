@@ -0,0 +1,747 @@
+// Small FAT volumes built straight into memory for the tests. Nothing in here goes through the driver, so what it
+// builds can be trusted to be what the spec says and not just what the driver expects.
+use std::{
+    io::{ErrorKind, Write},
+    process::Command,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use super::bootSector::FatType;
+
+const SECTOR_SIZE: usize = 512;
+const FAT_COUNT: usize = 2;
+const ENTRY_LENGTH: usize = 32;
+const MEDIA: u8 = 0xF8;
+const FS_INFO_SECTOR: usize = 1;
+const BACKUP_BOOT_SECTOR: usize = 6; // FAT32 only
+const LABEL: &[u8; 11] = b"TEST VOLUME";
+
+const ATTRIBUTE_VOLUME_ID: u8 = 0x08;
+const ATTRIBUTE_DIRECTORY: u8 = 0x10;
+const ATTRIBUTE_ARCHIVE: u8 = 0x20;
+const ATTRIBUTE_LONG_NAME: u8 = 0x0F;
+const LAST_LONG_ENTRY: u8 = 0x40;
+const LONG_NAME_OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+// A directory entry as the checker sees it
+pub struct RawEntry {
+    pub name: String,      // The long name if there is one
+    pub shortName: String, // NAME.EXT
+    pub attributes: u8,
+    pub firstCluster: u32,
+    pub size: u32,
+}
+
+impl RawEntry {
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || self.shortName.eq_ignore_ascii_case(name)
+    }
+}
+
+pub struct TestImage {
+    pub image: Vec<u8>,
+    fatType: FatType,
+    sectorsPerCluster: usize,
+    reservedSectors: usize,
+    sectorsPerFat: usize,
+    rootEntries: usize, // FAT16 only
+    clusterCount: u32,
+    rootCluster: u32, // FAT32 only
+}
+
+impl TestImage {
+    // An empty volume, just big enough to be the type asked for. FAT16 gets 2 sector clusters so files cross sectors
+    // within a cluster as well as clusters.
+    pub fn new(fatType: FatType) -> TestImage {
+        let (totalSectors, sectorsPerCluster, reservedSectors, rootEntries, entrySize) =
+            match fatType {
+                FatType::Fat32 => (70_000, 1, 32, 0, 4),
+                _ => (16_384, 2, 1, 512, 2),
+            };
+
+        // How many clusters there are depends on how big the FAT is and the other way round, which settles quickly
+        let rootSectors = rootEntries * ENTRY_LENGTH / SECTOR_SIZE;
+        let mut sectorsPerFat = 1;
+        let clusterCount = loop {
+            let dataSectors =
+                totalSectors - reservedSectors - FAT_COUNT * sectorsPerFat - rootSectors;
+            let clusters = dataSectors / sectorsPerCluster;
+            let needed = ((clusters + 2) * entrySize).div_ceil(SECTOR_SIZE);
+            if needed <= sectorsPerFat {
+                break clusters as u32;
+            }
+
+            sectorsPerFat = needed;
+        };
+
+        let mut result = TestImage {
+            image: vec![0; totalSectors * SECTOR_SIZE],
+            fatType,
+            sectorsPerCluster,
+            reservedSectors,
+            sectorsPerFat,
+            rootEntries,
+            clusterCount,
+            rootCluster: if fatType == FatType::Fat32 { 2 } else { 0 },
+        };
+
+        result.writeBootSector(totalSectors);
+        result.setFatEntry(0, 0xFFFF_FF00 | MEDIA as u32);
+        result.setFatEntry(1, result.getEndOfChain());
+
+        if fatType == FatType::Fat32 {
+            let root = result.rootCluster;
+            result.setFatEntry(root, result.getEndOfChain());
+            result.writeFsInfo(clusterCount - 1, root + 1);
+        }
+
+        // Same label in the root as in the boot sector, the way mkfs.fat leaves it
+        let slot = result.getDirectorySlots(0).unwrap()[0];
+        result.image[slot..slot + LABEL.len()].copy_from_slice(LABEL);
+        result.image[slot + 11] = ATTRIBUTE_VOLUME_ID;
+
+        result
+    }
+
+    pub fn getClusterSize(&self) -> usize {
+        self.sectorsPerCluster * SECTOR_SIZE
+    }
+
+    pub fn getEndOfChain(&self) -> u32 {
+        match self.fatType {
+            FatType::Fat32 => 0x0FFF_FFFF,
+            _ => 0xFFFF,
+        }
+    }
+
+    // From the first FAT
+    pub fn getFatEntry(&self, cluster: u32) -> u32 {
+        let offset = self.getFatOffset(0, cluster);
+        match self.fatType {
+            FatType::Fat32 => read32(&self.image, offset) & 0x0FFF_FFFF,
+            _ => read16(&self.image, offset) as u32,
+        }
+    }
+
+    // In every FAT, so they stay mirrored
+    pub fn setFatEntry(&mut self, cluster: u32, value: u32) {
+        for copy in 0..FAT_COUNT {
+            let offset = self.getFatOffset(copy, cluster);
+            match self.fatType {
+                FatType::Fat32 => write32(&mut self.image, offset, value & 0x0FFF_FFFF),
+                _ => write16(&mut self.image, offset, value as u16),
+            }
+        }
+    }
+
+    pub fn getCluster(&mut self, cluster: u32) -> &mut [u8] {
+        let start = self.getClusterOffset(cluster);
+        let length = self.getClusterSize();
+        &mut self.image[start..start + length]
+    }
+
+    // A file in directory (0 for the root) made of exactly the clusters given, in that order, so tests can lay out
+    // fragmented chains. NAME.EXT is the short name, and a long name goes in front of it if there is one.
+    pub fn addFile(
+        &mut self,
+        directory: u32,
+        shortName: &str,
+        longName: Option<&str>,
+        data: &[u8],
+        clusters: &[u32],
+    ) {
+        let clusterSize = self.getClusterSize();
+        assert_eq!(clusters.len(), data.len().div_ceil(clusterSize));
+
+        for (chunk, &cluster) in data.chunks(clusterSize).zip(clusters) {
+            self.getCluster(cluster)[..chunk.len()].copy_from_slice(chunk);
+        }
+
+        self.linkChain(clusters);
+        let firstCluster = clusters.first().copied().unwrap_or(0);
+        self.addEntry(
+            directory,
+            shortName,
+            longName,
+            ATTRIBUTE_ARCHIVE,
+            firstCluster,
+            data.len() as u32,
+        );
+    }
+
+    // An empty, one cluster directory with its . and .. entries
+    pub fn addDirectory(&mut self, parent: u32, shortName: &str, cluster: u32) {
+        self.getCluster(cluster).fill(0);
+        self.linkChain(&[cluster]);
+
+        let dotDotCluster = if parent == self.rootCluster {
+            0
+        } else {
+            parent
+        };
+        let entries = self.getCluster(cluster);
+        encodeShortEntry(
+            &mut entries[..ENTRY_LENGTH],
+            &toShortName("."),
+            ATTRIBUTE_DIRECTORY,
+            cluster,
+            0,
+        );
+        encodeShortEntry(
+            &mut entries[ENTRY_LENGTH..],
+            &toShortName(".."),
+            ATTRIBUTE_DIRECTORY,
+            dotDotCluster,
+            0,
+        );
+
+        self.addEntry(parent, shortName, None, ATTRIBUTE_DIRECTORY, cluster, 0);
+    }
+
+    // Keeps FSInfo's free count right for any clusters that weren't already in use
+    pub fn linkChain(&mut self, clusters: &[u32]) {
+        for (index, &cluster) in clusters.iter().enumerate() {
+            let next = clusters
+                .get(index + 1)
+                .copied()
+                .unwrap_or(self.getEndOfChain());
+            if self.getFatEntry(cluster) == 0 && self.fatType == FatType::Fat32 {
+                let offset = FS_INFO_SECTOR * SECTOR_SIZE + 488;
+                let freeCount = read32(&self.image, offset);
+                write32(&mut self.image, offset, freeCount - 1);
+            }
+
+            self.setFatEntry(cluster, next);
+        }
+    }
+
+    pub fn getFreeCount(&self) -> u32 {
+        (2..self.clusterCount + 2)
+            .filter(|&cluster| self.getFatEntry(cluster) == 0)
+            .count() as u32
+    }
+
+    fn addEntry(
+        &mut self,
+        directory: u32,
+        shortName: &str,
+        longName: Option<&str>,
+        attributes: u8,
+        firstCluster: u32,
+        size: u32,
+    ) {
+        let shortName = toShortName(shortName);
+        let longName: Vec<u16> = longName.unwrap_or("").encode_utf16().collect();
+        let longEntries = longName.len().div_ceil(LONG_NAME_OFFSETS.len());
+
+        // Only ever appended, so the first unused slot starts a run big enough for all of them
+        let slots = self.getDirectorySlots(directory).unwrap();
+        let start = slots
+            .iter()
+            .position(|&slot| self.image[slot] == 0)
+            .expect("Directory is full");
+        assert!(start + longEntries < slots.len(), "Directory is full");
+
+        let checksum = shortName
+            .iter()
+            .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte));
+        for (index, &slot) in slots[start..start + longEntries].iter().enumerate() {
+            let order = longEntries - index;
+            encodeLongEntry(
+                &mut self.image[slot..slot + ENTRY_LENGTH],
+                &longName,
+                order,
+                order == longEntries,
+                checksum,
+            );
+        }
+
+        let slot = slots[start + longEntries];
+        encodeShortEntry(
+            &mut self.image[slot..slot + ENTRY_LENGTH],
+            &shortName,
+            attributes,
+            firstCluster,
+            size,
+        );
+    }
+
+    // Where every entry of a directory is in the image
+    pub fn getDirectorySlots(&self, directory: u32) -> Result<Vec<usize>, String> {
+        if directory == 0 && self.fatType != FatType::Fat32 {
+            let rootStart = (self.reservedSectors + FAT_COUNT * self.sectorsPerFat) * SECTOR_SIZE;
+            return Ok((0..self.rootEntries)
+                .map(|index| rootStart + index * ENTRY_LENGTH)
+                .collect());
+        }
+
+        let first = if directory == 0 {
+            self.rootCluster
+        } else {
+            directory
+        };
+
+        let entriesPerCluster = self.getClusterSize() / ENTRY_LENGTH;
+        Ok(self
+            .getChain(first)?
+            .into_iter()
+            .flat_map(|cluster| {
+                let start = self.getClusterOffset(cluster);
+                (0..entriesPerCluster).map(move |index| start + index * ENTRY_LENGTH)
+            })
+            .collect())
+    }
+
+    // The clusters of the chain starting at first, in order, or what's wrong with it
+    fn getChain(&self, first: u32) -> Result<Vec<u32>, String> {
+        let mut chain = Vec::new();
+        let mut cluster = first;
+        loop {
+            if !(2..self.clusterCount + 2).contains(&cluster) {
+                return Err(format!(
+                    "Chain from {first} has out of range cluster {cluster}"
+                ));
+            }
+
+            if chain.len() > self.clusterCount as usize {
+                return Err(format!("Chain from {first} loops"));
+            }
+
+            chain.push(cluster);
+            let next = self.getFatEntry(cluster);
+            if next >= self.getEndOfChain() - 7 {
+                return Ok(chain);
+            }
+
+            if next == 0 {
+                return Err(format!(
+                    "Chain from {first} runs into free cluster {cluster}"
+                ));
+            }
+
+            cluster = next;
+        }
+    }
+
+    // Everything in a directory, with long names checked against the short entry they belong to
+    pub fn readDirectory(&self, directory: u32) -> Result<Vec<RawEntry>, String> {
+        let mut result = Vec::new();
+        let mut longName: Vec<u16> = Vec::new();
+        let mut longNameChecksum = 0;
+        let mut nextOrder = 0; // Of the long entry we're expecting; 0 when there isn't one in progress
+
+        for slot in self.getDirectorySlots(directory)? {
+            let bytes = &self.image[slot..slot + ENTRY_LENGTH];
+            match bytes[0] {
+                0x00 => break,
+                0xE5 if nextOrder != 0 => return Err("Long name is missing part of itself".into()),
+                0xE5 => continue,
+                _ => {}
+            }
+
+            if bytes[11] & 0x3F == ATTRIBUTE_LONG_NAME {
+                let order = bytes[0] & !LAST_LONG_ENTRY;
+                if bytes[0] & LAST_LONG_ENTRY != 0 {
+                    if nextOrder != 0 {
+                        return Err("Long name starts in the middle of another".into());
+                    }
+
+                    longName = vec![0xFFFF; order as usize * LONG_NAME_OFFSETS.len()];
+                    longNameChecksum = bytes[13];
+                } else if order != nextOrder || bytes[13] != longNameChecksum {
+                    return Err("Long name entries are out of order".into());
+                }
+
+                let start = (order as usize - 1) * LONG_NAME_OFFSETS.len();
+                for (index, &offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+                    longName[start + index] = read16(bytes, offset);
+                }
+
+                nextOrder = order - 1;
+                continue;
+            }
+
+            if nextOrder != 0 {
+                return Err("Long name is missing part of itself".into());
+            }
+
+            let shortName: [u8; 11] = bytes[..11].try_into().unwrap();
+            let hasLongName = !longName.is_empty();
+            let longNameCharacters = core::mem::take(&mut longName);
+            if bytes[11] & ATTRIBUTE_VOLUME_ID != 0 {
+                continue; // Volume label
+            }
+
+            let name = if hasLongName {
+                let checksum = shortName
+                    .iter()
+                    .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte));
+                if checksum != longNameChecksum {
+                    return Err(format!("Long name doesn't belong to {:?}", shortName));
+                }
+
+                let length = longNameCharacters
+                    .iter()
+                    .position(|&character| character == 0)
+                    .unwrap_or(longNameCharacters.len());
+                String::from_utf16(&longNameCharacters[..length])
+                    .map_err(|_| "Long name isn't valid UTF-16".to_string())?
+            } else {
+                fromShortName(&shortName)
+            };
+
+            result.push(RawEntry {
+                name,
+                shortName: fromShortName(&shortName),
+                attributes: bytes[11],
+                firstCluster: (read16(bytes, 20) as u32) << 16 | read16(bytes, 26) as u32,
+                size: read32(bytes, 28),
+            });
+        }
+
+        Ok(result)
+    }
+
+    // Follows path from the root by either name, ignoring case the way FAT does
+    pub fn find(&self, path: &str) -> Option<RawEntry> {
+        let mut current: Option<RawEntry> = None;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let directory = current.as_ref().map_or(0, |entry| entry.firstCluster);
+            current = Some(
+                self.readDirectory(directory)
+                    .ok()?
+                    .into_iter()
+                    .find(|entry| entry.matches(name))?,
+            );
+        }
+
+        current
+    }
+
+    pub fn readFile(&self, path: &str) -> Option<Vec<u8>> {
+        let entry = self.find(path)?;
+        if entry.firstCluster == 0 {
+            return Some(Vec::new());
+        }
+
+        let mut data = Vec::new();
+        for cluster in self.getChain(entry.firstCluster).ok()? {
+            let start = self.getClusterOffset(cluster);
+            data.extend_from_slice(&self.image[start..start + self.getClusterSize()]);
+        }
+
+        data.truncate(entry.size as usize);
+        Some(data)
+    }
+
+    // The outside opinion, from fsck.fat reading the image as a file. Nothing in here wrote it, so it doesn't share any
+    // of our misreadings of the spec. When it isn't installed that's said on stderr (past the test harness, which would
+    // swallow it) and the check is skipped.
+    pub fn fsck(&self) {
+        static NEXT_IMAGE: AtomicUsize = AtomicUsize::new(0);
+        static WARNED: AtomicBool = AtomicBool::new(false);
+
+        let path = std::env::temp_dir().join(format!(
+            "danos-fat-{}-{}.img",
+            std::process::id(),
+            NEXT_IMAGE.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, &self.image).unwrap();
+        let output = Command::new("fsck.fat")
+            .args(["-n", "-v"])
+            .arg(&path)
+            .output();
+        std::fs::remove_file(&path).unwrap();
+
+        match output {
+            Ok(output) => assert!(
+                output.status.success(),
+                "fsck.fat found problems:\n{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                if !WARNED.swap(true, Ordering::Relaxed) {
+                    let _ = writeln!(
+                        std::io::stderr(),
+                        "SKIPPED: fsck.fat isn't installed (dosfstools), so FAT images only get TestImage::check"
+                    );
+                }
+            }
+            Err(error) => panic!("Couldn't run fsck.fat: {error}"),
+        }
+    }
+
+    // The parts of what fsck.fat does that matter for the writer, as a second opinion: FATs that agree, every chain ending properly and
+    // as long as its file, no cluster used twice or allocated without anything using it, . and .. pointing the
+    // right way and FSInfo's free count being right
+    pub fn check(&self) -> Result<(), String> {
+        let fatLength = self.sectorsPerFat * SECTOR_SIZE;
+        let firstFat = self.getFatOffset(0, 0);
+        for copy in 1..FAT_COUNT {
+            let copyStart = self.getFatOffset(copy, 0);
+            if self.image[firstFat..firstFat + fatLength]
+                != self.image[copyStart..copyStart + fatLength]
+            {
+                return Err(format!("FAT {copy} doesn't match the first one"));
+            }
+        }
+
+        if self.getFatEntry(0) & 0xFF != MEDIA as u32
+            || self.getFatEntry(1) < self.getEndOfChain() - 7
+        {
+            return Err("Reserved FAT entries were changed".into());
+        }
+
+        let mut used = vec![false; self.clusterCount as usize + 2];
+        if self.fatType == FatType::Fat32 {
+            self.markChain(&mut used, self.rootCluster, "/")?;
+        }
+
+        self.checkDirectory(&mut used, 0, 0, "")?;
+
+        for cluster in 2..self.clusterCount + 2 {
+            match (self.getFatEntry(cluster), used[cluster as usize]) {
+                (0, _) => {}
+                (_, false) => {
+                    return Err(format!(
+                        "Cluster {cluster} is allocated but nothing uses it"
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        if self.fatType == FatType::Fat32 {
+            let freeCount = read32(&self.image, FS_INFO_SECTOR * SECTOR_SIZE + 488);
+            let free = self.getFreeCount();
+            if freeCount != 0xFFFF_FFFF && freeCount != free {
+                return Err(format!(
+                    "FSInfo says {freeCount} clusters are free but {free} are"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn checkDirectory(
+        &self,
+        used: &mut [bool],
+        directory: u32,
+        parent: u32,
+        path: &str,
+    ) -> Result<(), String> {
+        let entries = self.readDirectory(directory)?;
+        let mut names: Vec<String> = Vec::new();
+
+        for (index, entry) in entries.iter().enumerate() {
+            let entryPath = format!("{}/{}", path, entry.name);
+            match entry.name.as_str() {
+                "." | ".." if directory == 0 => return Err("Root has . or ..".into()),
+                "." if index != 0 || entry.firstCluster != directory => {
+                    return Err(format!("{entryPath} is wrong"));
+                }
+                ".." if index != 1 || entry.firstCluster != parent => {
+                    return Err(format!("{entryPath} is wrong"));
+                }
+                "." | ".." => continue,
+                _ => {}
+            }
+
+            let upperName = entry.name.to_uppercase();
+            if names.contains(&upperName) || names.contains(&entry.shortName) {
+                return Err(format!("{entryPath} is there twice"));
+            }
+
+            names.push(upperName);
+            names.push(entry.shortName.clone());
+
+            if entry.attributes & ATTRIBUTE_DIRECTORY != 0 {
+                if entry.firstCluster == 0 || entry.size != 0 {
+                    return Err(format!(
+                        "{entryPath} is a directory without clusters or with a size"
+                    ));
+                }
+
+                self.markChain(used, entry.firstCluster, &entryPath)?;
+                self.checkDirectory(used, entry.firstCluster, directory, &entryPath)?;
+            } else if entry.size == 0 {
+                if entry.firstCluster != 0 {
+                    return Err(format!("{entryPath} is empty but has clusters"));
+                }
+            } else {
+                let clusters = self.markChain(used, entry.firstCluster, &entryPath)?;
+                if clusters != (entry.size as usize).div_ceil(self.getClusterSize()) {
+                    return Err(format!(
+                        "{entryPath} has {clusters} clusters for {} bytes",
+                        entry.size
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns how long the chain is
+    fn markChain(&self, used: &mut [bool], first: u32, path: &str) -> Result<usize, String> {
+        let chain = self
+            .getChain(first)
+            .map_err(|reason| format!("{path}: {reason}"))?;
+        for &cluster in &chain {
+            if used[cluster as usize] {
+                return Err(format!(
+                    "{path} shares cluster {cluster} with something else"
+                ));
+            }
+
+            used[cluster as usize] = true;
+        }
+
+        Ok(chain.len())
+    }
+
+    fn getFatOffset(&self, copy: usize, cluster: u32) -> usize {
+        let entrySize = match self.fatType {
+            FatType::Fat32 => 4,
+            _ => 2,
+        };
+
+        (self.reservedSectors + copy * self.sectorsPerFat) * SECTOR_SIZE
+            + cluster as usize * entrySize
+    }
+
+    fn getClusterOffset(&self, cluster: u32) -> usize {
+        assert!((2..self.clusterCount + 2).contains(&cluster));
+        let rootSectors = self.rootEntries * ENTRY_LENGTH / SECTOR_SIZE;
+        let dataStart = self.reservedSectors + FAT_COUNT * self.sectorsPerFat + rootSectors;
+        (dataStart + (cluster as usize - 2) * self.sectorsPerCluster) * SECTOR_SIZE
+    }
+
+    // 3.1 / 3.2 / 3.3 BPB, with the FAT32 extras when it is one
+    fn writeBootSector(&mut self, totalSectors: usize) {
+        let fat32 = self.fatType == FatType::Fat32;
+        let sector = &mut self.image[..SECTOR_SIZE];
+        sector[..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+        sector[3..11].copy_from_slice(b"DANOS   ");
+        write16(sector, 11, SECTOR_SIZE as u16);
+        sector[13] = self.sectorsPerCluster as u8;
+        write16(sector, 14, self.reservedSectors as u16);
+        sector[16] = FAT_COUNT as u8;
+        write16(sector, 17, self.rootEntries as u16);
+        sector[21] = MEDIA;
+
+        if totalSectors < 0x1_0000 {
+            write16(sector, 19, totalSectors as u16);
+        } else {
+            write32(sector, 32, totalSectors as u32);
+        }
+
+        let extendedStart = if fat32 {
+            write32(sector, 36, self.sectorsPerFat as u32);
+            write32(sector, 44, self.rootCluster);
+            write16(sector, 48, FS_INFO_SECTOR as u16);
+            write16(sector, 50, BACKUP_BOOT_SECTOR as u16);
+            64
+        } else {
+            write16(sector, 22, self.sectorsPerFat as u16);
+            36
+        };
+
+        sector[extendedStart + 2] = 0x29;
+        write32(sector, extendedStart + 3, 0x1234_5678);
+        sector[extendedStart + 7..extendedStart + 18].copy_from_slice(LABEL);
+        sector[extendedStart + 18..extendedStart + 26].copy_from_slice(match fat32 {
+            true => b"FAT32   ",
+            false => b"FAT16   ",
+        });
+
+        sector[510..].copy_from_slice(&[0x55, 0xAA]);
+        if fat32 {
+            self.image
+                .copy_within(..SECTOR_SIZE, BACKUP_BOOT_SECTOR * SECTOR_SIZE);
+        }
+    }
+
+    // 5 FSInfo
+    fn writeFsInfo(&mut self, freeCount: u32, nextFree: u32) {
+        let sector = &mut self.image[FS_INFO_SECTOR * SECTOR_SIZE..][..SECTOR_SIZE];
+        write32(sector, 0, 0x4161_5252);
+        write32(sector, 484, 0x6141_7272);
+        write32(sector, 488, freeCount);
+        write32(sector, 492, nextFree);
+        write32(sector, 508, 0xAA55_0000);
+    }
+}
+
+// NAME.EXT to the 11 byte on disk form; . and .. are the special cases
+pub fn toShortName(name: &str) -> [u8; 11] {
+    let mut result = [b' '; 11];
+    let (base, extension) = match name {
+        "." | ".." => (name, ""),
+        _ => name.split_once('.').unwrap_or((name, "")),
+    };
+
+    assert!(base.len() <= 8 && extension.len() <= 3);
+    result[..base.len()].copy_from_slice(base.to_ascii_uppercase().as_bytes());
+    result[8..8 + extension.len()].copy_from_slice(extension.to_ascii_uppercase().as_bytes());
+    result
+}
+
+fn fromShortName(shortName: &[u8; 11]) -> String {
+    let base = String::from_utf8_lossy(&shortName[..8])
+        .trim_end()
+        .to_string();
+    let extension = String::from_utf8_lossy(&shortName[8..])
+        .trim_end()
+        .to_string();
+    match extension.is_empty() {
+        true => base,
+        false => format!("{base}.{extension}"),
+    }
+}
+
+fn encodeShortEntry(bytes: &mut [u8], name: &[u8; 11], attributes: u8, cluster: u32, size: u32) {
+    bytes[..ENTRY_LENGTH].fill(0);
+    bytes[..11].copy_from_slice(name);
+    bytes[11] = attributes;
+    write16(bytes, 20, (cluster >> 16) as u16);
+    write16(bytes, 26, cluster as u16);
+    write32(bytes, 28, size);
+}
+
+// 7 Long name entry order'th from the start of the name, padded with a 0 then 0xFFFFs after the end of it
+fn encodeLongEntry(bytes: &mut [u8], name: &[u16], order: usize, last: bool, checksum: u8) {
+    bytes.fill(0);
+    bytes[0] = order as u8 | if last { LAST_LONG_ENTRY } else { 0 };
+    bytes[11] = ATTRIBUTE_LONG_NAME;
+    bytes[13] = checksum;
+
+    let start = (order - 1) * LONG_NAME_OFFSETS.len();
+    for (index, &offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+        let character = match (start + index).cmp(&name.len()) {
+            core::cmp::Ordering::Less => name[start + index],
+            core::cmp::Ordering::Equal => 0,
+            core::cmp::Ordering::Greater => 0xFFFF,
+        };
+
+        write16(bytes, offset, character);
+    }
+}
+
+fn read16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn read32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn write16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
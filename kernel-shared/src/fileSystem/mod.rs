@@ -0,0 +1,23 @@
+pub mod fat;
+
+// Where a seek is measured from
+#[derive(Clone, Copy, Debug)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+impl SeekFrom {
+    // Works out the new position, refusing anything before the start
+    pub fn resolve(&self, position: u64, size: u64) -> Result<u64, &'static str> {
+        let (base, offset) = match *self {
+            SeekFrom::Start(offset) => return Ok(offset),
+            SeekFrom::Current(offset) => (position, offset),
+            SeekFrom::End(offset) => (size, offset),
+        };
+
+        base.checked_add_signed(offset)
+            .ok_or("Seek before the start")
+    }
+}
@@ -1,9 +1,13 @@
-#![no_std]
+// Tests run on the host, which has std
+#![cfg_attr(not(test), no_std)]
 #![allow(non_snake_case)]
 #![feature(if_let_guard)]
 
 pub mod alignment;
 pub mod assemblyStuff;
+pub mod crc32;
+pub mod disk;
+pub mod fileSystem;
 pub mod gdtStuff;
 pub mod logging;
 pub mod magicConstants;
@@ -12,6 +16,8 @@ pub mod memoryHelpers;
 pub mod memoryTypes;
 pub mod pageTable;
 pub mod physicalMemory;
+pub mod pic;
 pub mod relocation;
+pub mod ringBuffer;
 pub mod serial;
 pub mod textMode;
@@ -1,13 +1,23 @@
 use critical_section::RawRestoreState;
 
+use crate::assemblyStuff::misc::{areInterruptsEnabled, disableInterrupts, enableInterrupts};
+
 pub struct DanOSCriticalSection;
 critical_section::set_impl!(DanOSCriticalSection);
 
+// Single core, so keeping interrupt handlers out is all it takes. We hand back whether they
+// were on so nested sections don't turn them back on early.
 unsafe impl critical_section::Impl for DanOSCriticalSection {
     unsafe fn acquire() -> RawRestoreState {
-        // We're currently running with interrupts disabled so nothing to do right now
+        let wereEnabled = areInterruptsEnabled();
+        disableInterrupts();
+
+        wereEnabled
     }
 
-    unsafe fn release(_restore_state: RawRestoreState) {
+    unsafe fn release(wereEnabled: RawRestoreState) {
+        if wereEnabled {
+            enableInterrupts();
+        }
     }
 }
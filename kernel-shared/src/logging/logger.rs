@@ -22,6 +22,10 @@ impl Logger {
         Logger { serial: serial }
     }
 
+    pub fn serial(&self) -> Option<&SerialPort> {
+        self.serial.as_ref()
+    }
+
     pub fn Write(&self, msg: &[u8]) {
         if self.serial.is_some() {
             let _ = self.serial.as_ref().unwrap().Send(msg);
@@ -1,7 +1,3 @@
-pub const SATA_DRIVE_BASE_CMD_BASE_ADDRESS: u32 = 0x50_0000;
-pub const SATA_DRIVE_BASE_FIS_BASE_ADDRESS: u32 = 0x60_0000;
-pub const SATA_DRIVE_BASE_COMMAND_TABLE_BASE_ADDRESS: usize = 0x70_0000;
-
 pub const VGA_WIDTH: u16 = 80;
 pub const VGA_HEIGHT: u16 = 25;
 pub const VGA_BUFFER_ADDRESS: u32 = 0xB8000;
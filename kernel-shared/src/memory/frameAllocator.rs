@@ -0,0 +1,500 @@
+use crate::{
+    magicConstants::SIZE_OF_PAGE,
+    memoryHelpers::{alignDown, alignUp},
+};
+
+use super::{map::MemoryMap, mapEntry::MemoryMapEntryType};
+
+// Tracks every 4 KiB physical frame from address 0 up to the end of the highest usable E820 range with one bit each.
+// A set bit means the frame is in use (or isn't RAM at all), so anything the map doesn't call usable is never handed
+// out. The bitmap itself is provided by the caller; this doesn't know or care how it got mapped, which keeps it free of
+// any hardware dependencies.
+pub struct FrameAllocator {
+    bitmap: &'static mut [u64],
+    frameCount: usize,
+    usableFrames: usize,
+    freeFrames: usize,
+    nextHint: usize, // Word index to start looking for single frames at
+}
+
+#[derive(Clone, Copy)]
+pub struct FrameStats {
+    pub frameCount: usize,   // Covered by the bitmap
+    pub usableFrames: usize, // RAM according to the map
+    pub freeFrames: usize,
+    pub droppedFrames: usize, // RAM the bitmap was too small to cover
+}
+
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+fn isUsable(entryType: MemoryMapEntryType) -> bool {
+    entryType == MemoryMapEntryType::AddressRangeMemory
+}
+
+// One past the last frame of usable memory
+fn getFrameLimit(memoryMap: &MemoryMap) -> usize {
+    let mut result = 0;
+    for index in 0..(memoryMap.EntryCount as usize) {
+        let entry = memoryMap.Entries[index];
+        if !isUsable(entry.getType()) {
+            continue;
+        }
+
+        let end = (entry.BaseAddress + entry.Length) as usize;
+        result = result.max(alignDown(end, SIZE_OF_PAGE) / SIZE_OF_PAGE);
+    }
+
+    result
+}
+
+// Frame range fully inside [address, address + length)
+fn getInnerFrames(address: usize, length: usize) -> (usize, usize) {
+    let start = alignUp(address, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+    let end = alignDown(address + length, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+    (start, end.max(start))
+}
+
+// Frame range touching any part of [address, address + length)
+fn getOuterFrames(address: usize, length: usize) -> (usize, usize) {
+    let start = alignDown(address, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+    let end = alignUp(address + length, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+    (start, end)
+}
+
+impl FrameAllocator {
+    // How big of a bitmap is needed to cover everything usable in the map
+    pub fn getBitmapBytes(memoryMap: &MemoryMap) -> usize {
+        let words = getFrameLimit(memoryMap).div_ceil(BITS_PER_WORD);
+        words * size_of::<u64>()
+    }
+
+    // Finds page aligned space for the bitmap itself in usable memory, skipping anything in avoid (address, length).
+    // Whoever calls this still needs to mark it used once the allocator is created.
+    pub fn findBitmapHome(
+        memoryMap: &MemoryMap,
+        length: usize,
+        avoid: &[(usize, usize)],
+    ) -> Option<usize> {
+        for index in 0..(memoryMap.EntryCount as usize) {
+            let entry = memoryMap.Entries[index];
+            if !isUsable(entry.getType()) {
+                continue;
+            }
+
+            let entryEnd = (entry.BaseAddress + entry.Length) as usize;
+            let mut candidate = alignUp(entry.BaseAddress as usize, SIZE_OF_PAGE);
+
+            while candidate + length <= entryEnd {
+                let collision = avoid.iter().find(|(address, avoidLength)| {
+                    candidate < address + avoidLength && *address < candidate + length
+                });
+
+                match collision {
+                    Some((address, avoidLength)) => {
+                        candidate = alignUp(address + avoidLength, SIZE_OF_PAGE);
+                    }
+                    None => return Some(candidate),
+                }
+            }
+        }
+
+        None
+    }
+
+    // Everything starts out used, then the usable ranges are opened up. Anything the map says is reserved gets closed
+    // again afterwards in case the firmware handed us overlapping entries. Frame 0 is never handed out.
+    pub fn new(memoryMap: &MemoryMap, bitmap: &'static mut [u64]) -> Self {
+        bitmap.fill(u64::MAX);
+
+        let frameCount = getFrameLimit(memoryMap).min(bitmap.len() * BITS_PER_WORD);
+        let mut result = FrameAllocator {
+            bitmap,
+            frameCount,
+            usableFrames: 0,
+            freeFrames: 0,
+            nextHint: 0,
+        };
+
+        for index in 0..(memoryMap.EntryCount as usize) {
+            let entry = memoryMap.Entries[index];
+            if isUsable(entry.getType()) {
+                let (start, end) =
+                    getInnerFrames(entry.BaseAddress as usize, entry.Length as usize);
+                result.setRange(start, end.min(frameCount), false);
+            }
+        }
+
+        for index in 0..(memoryMap.EntryCount as usize) {
+            let entry = memoryMap.Entries[index];
+            if !isUsable(entry.getType()) {
+                let (start, end) =
+                    getOuterFrames(entry.BaseAddress as usize, entry.Length as usize);
+                result.setRange(start, end.min(frameCount), true);
+            }
+        }
+
+        result.setRange(0, 1.min(frameCount), true);
+        result.usableFrames = result.freeFrames;
+        result
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            frameCount: self.frameCount,
+            usableFrames: self.usableFrames,
+            freeFrames: self.freeFrames,
+            droppedFrames: 0,
+        }
+    }
+
+    // Same as stats, but also figures out how much RAM didn't fit in the bitmap
+    pub fn statsFor(&self, memoryMap: &MemoryMap) -> FrameStats {
+        let mut result = self.stats();
+        let limit = getFrameLimit(memoryMap);
+        if limit > self.frameCount {
+            for index in 0..(memoryMap.EntryCount as usize) {
+                let entry = memoryMap.Entries[index];
+                if isUsable(entry.getType()) {
+                    let (start, end) =
+                        getInnerFrames(entry.BaseAddress as usize, entry.Length as usize);
+                    let start = start.max(self.frameCount);
+                    result.droppedFrames += end.saturating_sub(start);
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn isFree(&self, address: usize) -> bool {
+        let frame = address / SIZE_OF_PAGE;
+        frame < self.frameCount && !self.isUsed(frame)
+    }
+
+    // Whether every frame touching the range is free
+    pub fn isRangeFree(&self, address: usize, length: usize) -> bool {
+        let (start, end) = getOuterFrames(address, length);
+        end <= self.frameCount && self.findUsed(start, end).is_none()
+    }
+
+    pub fn allocate(&mut self) -> Option<usize> {
+        let words = self.bitmap.len();
+        for offset in 0..words {
+            let word = (self.nextHint + offset) % words;
+            if self.bitmap[word] == u64::MAX {
+                continue;
+            }
+
+            let frame = word * BITS_PER_WORD + self.bitmap[word].trailing_ones() as usize;
+            if frame >= self.frameCount {
+                continue;
+            }
+
+            self.nextHint = word;
+            self.setRange(frame, frame + 1, true);
+            return Some(frame * SIZE_OF_PAGE);
+        }
+
+        None
+    }
+
+    // First fit for count frames starting on an alignment (bytes, at least a page) boundary
+    pub fn allocateContiguous(&mut self, count: usize, alignment: usize) -> Option<usize> {
+        if count == 0 {
+            return None;
+        }
+
+        if count == 1 && alignment <= SIZE_OF_PAGE {
+            return self.allocate();
+        }
+
+        let step = (alignment / SIZE_OF_PAGE).max(1);
+        let mut start = 0;
+
+        while start + count <= self.frameCount {
+            match self.findUsed(start, start + count) {
+                Some(used) => start = alignUp(used + 1, step),
+                None => {
+                    self.setRange(start, start + count, true);
+                    return Some(start * SIZE_OF_PAGE);
+                }
+            }
+        }
+
+        None
+    }
+
+    // Claims frames at a specific address. All or nothing; fails if any of them are already used or not RAM.
+    pub fn allocateAt(&mut self, address: usize, length: usize) -> Result<(), &'static str> {
+        let (start, end) = getOuterFrames(address, length);
+        if end > self.frameCount {
+            return Err("Range is past the end of usable memory");
+        }
+
+        if self.findUsed(start, end).is_some() {
+            return Err("Range is already in use");
+        }
+
+        self.setRange(start, end, true);
+        Ok(())
+    }
+
+    // Marks frames used no matter what state they're in now. For things like firmware and MMIO that we just need to
+    // stay away from. Anything past the end of the bitmap is ignored since we'd never hand it out anyway.
+    pub fn markUsed(&mut self, address: usize, length: usize) {
+        let (start, end) = getOuterFrames(address, length);
+        self.setRange(start.min(self.frameCount), end.min(self.frameCount), true);
+    }
+
+    pub fn free(&mut self, address: usize, count: usize) -> Result<(), &'static str> {
+        if address % SIZE_OF_PAGE != 0 {
+            return Err("Address isn't page aligned");
+        }
+
+        let start = address / SIZE_OF_PAGE;
+        let end = start + count;
+        if start == 0 || end > self.frameCount {
+            return Err("Range isn't something we hand out");
+        }
+
+        if (start..end).any(|frame| !self.isUsed(frame)) {
+            return Err("Double free");
+        }
+
+        self.setRange(start, end, false);
+        self.nextHint = self.nextHint.min(start / BITS_PER_WORD);
+        Ok(())
+    }
+
+    fn isUsed(&self, frame: usize) -> bool {
+        self.bitmap[frame / BITS_PER_WORD] & (1 << (frame % BITS_PER_WORD)) != 0
+    }
+
+    // First used frame in start..end
+    fn findUsed(&self, start: usize, end: usize) -> Option<usize> {
+        let mut frame = start;
+        while frame < end {
+            let word = self.bitmap[frame / BITS_PER_WORD];
+            let bit = frame % BITS_PER_WORD;
+
+            // Whole word is free, skip it
+            if bit == 0 && word == 0 {
+                frame += BITS_PER_WORD;
+                continue;
+            }
+
+            if word & (1 << bit) != 0 {
+                return Some(frame);
+            }
+
+            frame += 1;
+        }
+
+        None
+    }
+
+    // Keeps freeFrames honest by only counting bits that actually change
+    fn setRange(&mut self, start: usize, end: usize, used: bool) {
+        for frame in start..end {
+            let word = &mut self.bitmap[frame / BITS_PER_WORD];
+            let mask = 1 << (frame % BITS_PER_WORD);
+            let wasUsed = *word & mask != 0;
+
+            if used && !wasUsed {
+                *word |= mask;
+                self.freeFrames -= 1;
+            } else if !used && wasUsed {
+                *word &= !mask;
+                self.freeFrames += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::mapEntry::MemoryMapEntry;
+
+    const USABLE: u32 = 1;
+    const RESERVED: u32 = 2;
+
+    fn makeMap(entries: &[(u64, u64, u32)]) -> MemoryMap {
+        let mut result = MemoryMap {
+            Entries: [MemoryMapEntry {
+                BaseAddress: 0,
+                Length: 0,
+                Type: 0,
+                ExtendedAttributes: 0,
+            }; 32],
+            EntryCount: entries.len() as u8,
+        };
+
+        for (index, (address, length, entryType)) in entries.iter().enumerate() {
+            result.Entries[index].BaseAddress = *address;
+            result.Entries[index].Length = *length;
+            result.Entries[index].Type = *entryType;
+        }
+
+        result
+    }
+
+    fn makeAllocator(memoryMap: &MemoryMap) -> FrameAllocator {
+        let words = FrameAllocator::getBitmapBytes(memoryMap) / size_of::<u64>();
+        FrameAllocator::new(memoryMap, Vec::leak(vec![0; words]))
+    }
+
+    #[test]
+    fn onlyHandsOutUsableFrames() {
+        let memoryMap = makeMap(&[
+            (0, 0x9F000, USABLE),
+            (0x9F000, 0x61000, RESERVED),
+            (0x10_0000, 0x40_0000, USABLE),
+        ]);
+        let allocator = makeAllocator(&memoryMap);
+
+        let stats = allocator.stats();
+        assert_eq!(stats.frameCount, 0x500);
+        assert_eq!(stats.usableFrames, 0x9F - 1 + 0x400);
+        assert_eq!(stats.freeFrames, stats.usableFrames);
+
+        assert!(!allocator.isFree(0));
+        assert!(allocator.isFree(0x1000));
+        assert!(!allocator.isFree(0x9F000));
+        assert!(!allocator.isFree(0xFF000));
+        assert!(allocator.isFree(0x10_0000));
+        assert!(!allocator.isFree(0x50_0000));
+        assert!(!allocator.isRangeFree(0x9E000, 0x2000));
+    }
+
+    #[test]
+    fn handlesUnalignedRegions() {
+        // Usable only counts whole frames inside it, reserved takes any frame it touches
+        let memoryMap = makeMap(&[
+            (0x1800, 0x4000, USABLE),
+            (0x10000, 0x10000, USABLE),
+            (0x18800, 0x100, RESERVED),
+        ]);
+        let allocator = makeAllocator(&memoryMap);
+
+        assert!(!allocator.isFree(0x1000));
+        assert!(allocator.isFree(0x2000));
+        assert!(allocator.isFree(0x4000));
+        assert!(!allocator.isFree(0x5000));
+        assert!(allocator.isFree(0x17000));
+        assert!(!allocator.isFree(0x18000));
+        assert!(allocator.isFree(0x19000));
+        assert_eq!(allocator.stats().usableFrames, 3 + 16 - 1);
+    }
+
+    #[test]
+    fn reservedWinsOverlaps() {
+        // Firmware does hand out maps like this; the usable ones overlap each other and a reserved one
+        let memoryMap = makeMap(&[
+            (0, 0x10_0000, USABLE),
+            (0x8_0000, 0x1_0000, RESERVED),
+            (0x10_0000, 0x10_0000, USABLE),
+            (0x18_0000, 0x10_0000, USABLE),
+        ]);
+        let allocator = makeAllocator(&memoryMap);
+
+        assert_eq!(allocator.stats().frameCount, 0x280);
+        assert_eq!(allocator.stats().usableFrames, 0x280 - 1 - 0x10);
+        assert!(!allocator.isRangeFree(0x8_0000, 0x1_0000));
+        assert!(allocator.isFree(0x7_F000));
+        assert!(allocator.isFree(0x9_0000));
+        assert!(allocator.isFree(0x1F_F000));
+    }
+
+    #[test]
+    fn runsOutOfFrames() {
+        let memoryMap = makeMap(&[(0, 0x10000, USABLE)]);
+        let mut allocator = makeAllocator(&memoryMap);
+
+        let mut frames: Vec<usize> = (0..15).map(|_| allocator.allocate().unwrap()).collect();
+        frames.sort();
+        frames.dedup();
+        assert_eq!(frames.len(), 15);
+        assert!(!frames.contains(&0));
+
+        assert_eq!(allocator.allocate(), None);
+        assert_eq!(allocator.allocateContiguous(2, SIZE_OF_PAGE), None);
+        assert_eq!(allocator.stats().freeFrames, 0);
+    }
+
+    #[test]
+    fn reusesFreedFrames() {
+        let memoryMap = makeMap(&[(0, 0x10000, USABLE)]);
+        let mut allocator = makeAllocator(&memoryMap);
+
+        while allocator.allocate().is_some() {}
+        allocator.free(0x5000, 2).unwrap();
+        assert_eq!(allocator.stats().freeFrames, 2);
+        assert_eq!(allocator.allocateContiguous(2, SIZE_OF_PAGE), Some(0x5000));
+
+        allocator.free(0x3000, 1).unwrap();
+        assert_eq!(allocator.allocate(), Some(0x3000));
+
+        allocator.free(0x3000, 1).unwrap();
+        assert!(allocator.free(0x3000, 1).is_err());
+        assert!(allocator.free(0x3800, 1).is_err());
+        assert!(allocator.free(0, 1).is_err());
+        assert!(allocator.free(0xF000, 2).is_err());
+        assert_eq!(allocator.stats().freeFrames, 1);
+    }
+
+    #[test]
+    fn contiguousSkipsHoles() {
+        let memoryMap = makeMap(&[(0x1000, 0x3F000, USABLE), (0x8000, 0x1000, RESERVED)]);
+        let mut allocator = makeAllocator(&memoryMap);
+
+        assert_eq!(allocator.allocateContiguous(4, SIZE_OF_PAGE), Some(0x1000));
+        assert_eq!(allocator.allocateContiguous(4, SIZE_OF_PAGE), Some(0x9000));
+        assert_eq!(allocator.allocateContiguous(8, 0x8000), Some(0x10000));
+        assert_eq!(allocator.allocateContiguous(0, SIZE_OF_PAGE), None);
+        assert_eq!(allocator.allocateContiguous(0x40, SIZE_OF_PAGE), None);
+
+        assert!(allocator.allocateAt(0x8000, 0x1000).is_err());
+        assert!(allocator.allocateAt(0x3F000, 0x2000).is_err());
+        allocator.allocateAt(0x20800, 0x1000).unwrap();
+        assert!(!allocator.isRangeFree(0x20000, 0x2000));
+        assert!(allocator.allocateAt(0x21000, 0x1000).is_err());
+    }
+
+    #[test]
+    fn dropsWhatTheBitmapCantCover() {
+        let memoryMap = makeMap(&[(0, 0x10_0000, USABLE)]);
+        let allocator = FrameAllocator::new(&memoryMap, Vec::leak(vec![0; 2]));
+
+        let stats = allocator.statsFor(&memoryMap);
+        assert_eq!(stats.frameCount, 128);
+        assert_eq!(stats.usableFrames, 127);
+        assert_eq!(stats.droppedFrames, 0x100 - 128);
+    }
+
+    #[test]
+    fn findsBitmapHomeAroundOthers() {
+        let memoryMap = makeMap(&[
+            (0x800, 0x3800, USABLE),
+            (0x10000, 0x10000, RESERVED),
+            (0x20000, 0x10000, USABLE),
+        ]);
+
+        assert_eq!(
+            FrameAllocator::findBitmapHome(&memoryMap, 0x2000, &[]),
+            Some(0x1000)
+        );
+        assert_eq!(
+            FrameAllocator::findBitmapHome(&memoryMap, 0x2000, &[(0x2000, 0x100)]),
+            Some(0x20000)
+        );
+        assert_eq!(
+            FrameAllocator::findBitmapHome(&memoryMap, 0x2000, &[(0x2000, 0x100), (0x21000, 0x10)]),
+            Some(0x22000)
+        );
+        assert_eq!(
+            FrameAllocator::findBitmapHome(&memoryMap, 0x20000, &[]),
+            None
+        );
+    }
+}
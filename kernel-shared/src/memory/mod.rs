@@ -1,2 +1,3 @@
+pub mod frameAllocator;
 pub mod map;
 pub mod mapEntry;
@@ -34,3 +34,53 @@ pub enum UserSupervisor {
     User,
     Supervisor,
 }
+
+// Everything about a mapping other than where it points. Lets a huge page's settings be carried over to the smaller
+// pages it gets split into.
+#[derive(Clone, Copy)]
+pub struct PageAttributes {
+    pub executable: Execute,
+    pub present: Present,
+    pub writable: Writable,
+    pub cachable: Cachable,
+    pub us: UserSupervisor,
+    pub wt: WriteThrough,
+}
+
+impl PageAttributes {
+    // The bits are in the same spot at every level
+    pub fn fromEntry(entry: u64) -> Self {
+        PageAttributes {
+            executable: if entry & (1 << 63) != 0 {
+                Execute::No
+            } else {
+                Execute::Yes
+            },
+            present: if entry & (1 << 0) != 0 {
+                Present::Yes
+            } else {
+                Present::No
+            },
+            writable: if entry & (1 << 1) != 0 {
+                Writable::Yes
+            } else {
+                Writable::No
+            },
+            cachable: if entry & (1 << 4) != 0 {
+                Cachable::No
+            } else {
+                Cachable::Yes
+            },
+            us: if entry & (1 << 2) != 0 {
+                UserSupervisor::Supervisor
+            } else {
+                UserSupervisor::User
+            },
+            wt: if entry & (1 << 3) != 0 {
+                WriteThrough::WriteTrough
+            } else {
+                WriteThrough::WriteBack
+            },
+        }
+    }
+}
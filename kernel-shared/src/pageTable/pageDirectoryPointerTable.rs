@@ -1,6 +1,9 @@
 use core::ptr::addr_of;
 
-use crate::{assemblyStuff::halt::haltLoop, haltLoopWithMessage, memoryTypes::PhysicalAddress};
+use crate::{
+    assemblyStuff::halt::haltLoop, haltLoopWithMessage, magicConstants::SIZE_OF_PAGE_DIRECTORY,
+    memoryHelpers::haltOnMisaligned, memoryTypes::PhysicalAddress,
+};
 
 use super::{enums::*, pageDirectoryTable::PageDirectoryTable};
 
@@ -101,6 +104,50 @@ impl PageDirectoryPointerTable {
         PhysicalAddress::<PageDirectoryTable>::new(entry as usize)
     }
 
+    // Maps a 1 GiB page directly instead of pointing at a PD
+    pub fn setHugeEntry(
+        &mut self,
+        index: usize,
+        physicalAddress: usize,
+        executable: Execute,
+        present: Present,
+        writable: Writable,
+        cachable: Cachable,
+        us: UserSupervisor,
+        wt: WriteThrough,
+    ) {
+        haltOnMisaligned("1 GiB page", physicalAddress, SIZE_OF_PAGE_DIRECTORY);
+        let entry = Self::calculateEntry(
+            &PhysicalAddress::new(physicalAddress),
+            executable,
+            present,
+            writable,
+            cachable,
+            us,
+            wt,
+        );
+
+        // (PS)
+        self.Entries[index] = entry | (1 << 7);
+    }
+
+    pub fn isHuge(&self, index: usize) -> bool {
+        self.Entries[index] & (1 << 7) != 0
+    }
+
+    pub fn getAttributes(&self, index: usize) -> PageAttributes {
+        PageAttributes::fromEntry(self.Entries[index])
+    }
+
+    pub fn clearEntry(&mut self, index: usize) {
+        self.Entries[index] = 0;
+    }
+
+    // Nothing left pointing anywhere, so the table can be given back
+    pub fn isEmpty(&self) -> bool {
+        (0..self.getNumberOfEntries()).all(|index| self.Entries[index] == 0)
+    }
+
     pub fn getNumberOfEntries(&self) -> usize {
         let entries = addr_of!(self.Entries);
 
@@ -1,6 +1,9 @@
 use core::ptr::addr_of;
 
-use crate::{assemblyStuff::halt::haltLoop, haltLoopWithMessage, memoryTypes::PhysicalAddress};
+use crate::{
+    assemblyStuff::halt::haltLoop, haltLoopWithMessage, magicConstants::SIZE_OF_PAGE_TABLE,
+    memoryHelpers::haltOnMisaligned, memoryTypes::PhysicalAddress,
+};
 
 use super::{enums::*, pageTable::PageTable};
 
@@ -100,6 +103,50 @@ impl PageDirectoryTable {
 
         PhysicalAddress::<PageTable>::new(entry as usize)
     }
+
+    // Maps a 2 MiB page directly instead of pointing at a PT
+    pub fn setHugeEntry(
+        &mut self,
+        index: usize,
+        physicalAddress: usize,
+        executable: Execute,
+        present: Present,
+        writable: Writable,
+        cachable: Cachable,
+        us: UserSupervisor,
+        wt: WriteThrough,
+    ) {
+        haltOnMisaligned("2 MiB page", physicalAddress, SIZE_OF_PAGE_TABLE);
+        let entry = Self::calculateEntry(
+            &PhysicalAddress::new(physicalAddress),
+            executable,
+            present,
+            writable,
+            cachable,
+            us,
+            wt,
+        );
+
+        // (PS)
+        self.Entries[index] = entry | (1 << 7);
+    }
+
+    pub fn isHuge(&self, index: usize) -> bool {
+        self.Entries[index] & (1 << 7) != 0
+    }
+
+    pub fn getAttributes(&self, index: usize) -> PageAttributes {
+        PageAttributes::fromEntry(self.Entries[index])
+    }
+
+    pub fn clearEntry(&mut self, index: usize) {
+        self.Entries[index] = 0;
+    }
+
+    // Nothing left pointing anywhere, so the table can be given back
+    pub fn isEmpty(&self) -> bool {
+        (0..self.getNumberOfEntries()).all(|index| self.Entries[index] == 0)
+    }
     
     pub fn getNumberOfEntries(&self) -> usize {
         let entries = addr_of!(self.Entries);
@@ -125,6 +125,15 @@ impl PageMapLevel4Table {
         PhysicalAddress::<PageDirectoryPointerTable>::new(entry as usize)
     }
 
+    pub fn clearEntry(&mut self, index: usize) {
+        self.Entries[index] = 0;
+    }
+
+    // Nothing left pointing anywhere, so the table can be given back
+    pub fn isEmpty(&self) -> bool {
+        (0..self.getNumberOfEntries()).all(|index| self.Entries[index] == 0)
+    }
+
     pub fn getNumberOfEntries(&self) -> usize {
         let entries = addr_of!(self.Entries);
 
@@ -116,6 +116,49 @@ impl PageTable {
         PhysicalAddress::<PhysicalPage>::new(entry as usize)
     }
 
+    pub fn isPresent(&self, index: usize) -> bool {
+        self.Entries[index] & (1 << 0) != 0
+    }
+
+    pub fn clearEntry(&mut self, index: usize) {
+        self.Entries[index] = 0;
+    }
+
+    // Nothing left pointing anywhere, so the table can be given back
+    pub fn isEmpty(&self) -> bool {
+        (0..self.getNumberOfEntries()).all(|index| self.Entries[index] == 0)
+    }
+
+    // Swaps out the permission and caching bits of an existing entry, leaving where it points alone
+    pub fn protectEntry(
+        &mut self,
+        index: usize,
+        executable: Execute,
+        writable: Writable,
+        cachable: Cachable,
+        wt: WriteThrough,
+    ) {
+        let mut entry = self.Entries[index] & !((1 << 1) | (1 << 3) | (1 << 4) | (1 << 63));
+
+        if writable == Writable::Yes {
+            entry |= 1 << 1;
+        }
+
+        if wt == WriteThrough::WriteTrough {
+            entry |= 1 << 3;
+        }
+
+        if cachable == Cachable::No {
+            entry |= 1 << 4;
+        }
+
+        if executable == Execute::No {
+            entry |= 1 << 63;
+        }
+
+        self.Entries[index] = entry;
+    }
+
     pub fn getNumberOfEntries(&self) -> usize {
         let entries = addr_of!(self.Entries);
 
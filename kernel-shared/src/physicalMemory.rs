@@ -3,23 +3,20 @@ use core::any::type_name;
 use crate::{
     assemblyStuff::halt::haltLoop,
     haltLoopWithMessage, loggerWriteLine,
+    magicConstants::SIZE_OF_PAGE,
     memory::{
+        frameAllocator::{FrameAllocator, FrameStats},
         map::MemoryMap,
         mapEntry::{MemoryMapEntry, MemoryMapEntryType},
     },
     memoryHelpers::{alignUp, zeroMemory},
-    memoryTypes::PhysicalAddressPlain,
 };
 
+// Hands out physical memory a frame at a time (or contiguous runs of them) on top of a FrameAllocator built from the
+// E820 map. Whoever creates this is responsible for finding a home for the bitmap and reserving it.
 pub struct PhysicalMemoryManager {
     pub MemoryMap: MemoryMap,
-    pub Blobs: [MemoryBlob; 0x10],
-}
-
-pub struct MemoryBlob {
-    PhysicalAddress: PhysicalAddressPlain,
-    Length: usize,
-    What: [u8; 0x40], // Short description of what this is used for
+    Frames: FrameAllocator,
 }
 
 // BUGUBG: Come up with a better name
@@ -30,17 +27,29 @@ pub enum WhatDo {
     YoLo, // Allocate even if it isn't in the map. Using this for hardware IO.
 }
 
-impl Default for MemoryBlob {
-    fn default() -> Self {
-        MemoryBlob {
-            PhysicalAddress: PhysicalAddressPlain { address: 0 },
-            Length: 0,
-            What: [0; 0x40],
+impl PhysicalMemoryManager {
+    pub fn new(memoryMap: MemoryMap, bitmap: &'static mut [u64]) -> Self {
+        let frames = FrameAllocator::new(&memoryMap, bitmap);
+        let result = PhysicalMemoryManager {
+            MemoryMap: memoryMap,
+            Frames: frames,
+        };
+
+        let stats = result.Stats();
+        if stats.droppedFrames != 0 {
+            loggerWriteLine!(
+                "Frame bitmap is too small, ignoring 0x{:X} frames of RAM",
+                stats.droppedFrames
+            );
         }
+
+        result
+    }
+
+    pub fn Stats(&self) -> FrameStats {
+        self.Frames.statsFor(&self.MemoryMap)
     }
-}
 
-impl PhysicalMemoryManager {
     pub fn Reserve(
         &mut self,
         forWhat: &str,
@@ -56,8 +65,9 @@ impl PhysicalMemoryManager {
             whatDo
         );
 
+        // Not RAM as far as the map is concerned, so the frames are already marked used (or past the end of the bitmap)
         if let WhatDo::YoLo = whatDo {
-            self.ReserveInternal(requestLocation, requestAmmount, forWhat);
+            self.Frames.markUsed(requestLocation, requestAmmount);
             return;
         } else {
             for index in 0..(self.MemoryMap.EntryCount as usize) {
@@ -88,7 +98,7 @@ impl PhysicalMemoryManager {
                         MemoryMapEntryType::AddressRangeReserved
                             if let WhatDo::UseReserved = whatDo =>
                         {
-                            self.ReserveInternal(requestLocation, requestAmmount, forWhat);
+                            self.Frames.markUsed(requestLocation, requestAmmount);
                             return;
                         }
                         _ => {
@@ -116,49 +126,23 @@ impl PhysicalMemoryManager {
         }
     }
 
-    fn ReserveInternal(&mut self, requestLocation: usize, requestAmmount: usize, forWhat: &str,) {
-        // Figure out if we have room for this
-        let firstFreeIndex = self.nextFreeBlob();
-
-        if firstFreeIndex == None {
-            haltLoopWithMessage!("No more room in {}", type_name::<PhysicalMemoryManager>());
-        }
-
-        let firstFreeIndex = firstFreeIndex.unwrap();
-        let requestEnd = requestLocation + requestAmmount;
-
-        // Check them to see if we overlap
-        for blobIndex in 0..firstFreeIndex {
-            let blobLocation = self.Blobs[blobIndex].PhysicalAddress.address;
-            let blobAmmount = self.Blobs[blobIndex].Length;
-            let blobEnd = blobLocation + blobAmmount;
-
-            if requestLocation < blobEnd && blobLocation < requestEnd {
-                haltLoopWithMessage!(
-                    "0x{:X} for 0x{:X} overlaps with index {} 0x{:X} for 0x{:X}",
-                    requestLocation,
-                    requestAmmount,
-                    blobIndex,
-                    blobLocation,
-                    blobAmmount,
-                );
-            }
+    fn ReserveInternal(&mut self, requestLocation: usize, requestAmmount: usize, forWhat: &str) {
+        // Frames are 4K but requests aren't, so two small things next to each other can legitimately share one.
+        // Not worth halting over, but worth knowing about.
+        if let Err(reason) = self.Frames.allocateAt(requestLocation, requestAmmount) {
+            loggerWriteLine!(
+                "0x{:X} for 0x{:X}: {}, marking it used anyway",
+                requestLocation,
+                requestAmmount,
+                reason
+            );
+            self.Frames.markUsed(requestLocation, requestAmmount);
         }
 
-        self.Blobs[firstFreeIndex].PhysicalAddress = PhysicalAddressPlain {
-            address: requestLocation,
-        };
-        self.Blobs[firstFreeIndex].Length = requestAmmount;
-
-        let bytes = forWhat.as_bytes();
-        let copy_len = core::cmp::min(bytes.len(), self.Blobs[firstFreeIndex].What.len());
-        self.Blobs[firstFreeIndex].What[..copy_len].copy_from_slice(&bytes[..copy_len]);
-
         loggerWriteLine!(
-            "Reserved 0x{:X} bytes @ 0x{:X} index {} for {}",
+            "Reserved 0x{:X} bytes @ 0x{:X} for {}",
             requestAmmount,
             requestLocation,
-            firstFreeIndex,
             forWhat
         );
     }
@@ -190,20 +174,16 @@ impl PhysicalMemoryManager {
         }
     }
 
-    pub fn DumpReservedBlobs(&self) {
-        for index in 0..self.Blobs.len() {
-            if self.Blobs[index].Length == 0 {
-                break;
-            }
-
-            loggerWriteLine!(
-                "Blob {}: 0x{:X} for 0x{:X} for {}",
-                index,
-                self.Blobs[index].PhysicalAddress.address,
-                self.Blobs[index].Length,
-                core::str::from_utf8(&self.Blobs[index].What).unwrap_or(""),
-            );
-        }
+    pub fn DumpUsage(&self) {
+        let stats = self.Stats();
+        let used = stats.usableFrames - stats.freeFrames;
+        loggerWriteLine!(
+            "Physical memory: 0x{:X} of 0x{:X} frames free, 0x{:X} used ({} KiB free)",
+            stats.freeFrames,
+            stats.usableFrames,
+            used,
+            stats.freeFrames * SIZE_OF_PAGE / 1024
+        );
     }
 
     pub fn FindEntryForAddress(&self, address: usize) -> MemoryMapEntry {
@@ -245,115 +225,78 @@ impl PhysicalMemoryManager {
         sizeInBytes: usize,
         alignment: usize,
     ) -> usize {
-        loggerWriteLine!(
-            "Reserving and zeroing 0x{:X} bytes for {} with alignment 0x{:X}",
-            sizeInBytes,
-            forWhat,
-            alignment
-        );
+        let result = self.ReserveWherever(forWhat, sizeInBytes, alignment);
 
-        let nextBlob = self.nextFreeBlob();
-        if None == nextBlob {
-            haltLoopWithMessage!("No more blobs to store data in");
+        unsafe {
+            loggerWriteLine!("Zeroing 0x{:X} for 0x{:X}", result, sizeInBytes);
+            zeroMemory(result, sizeInBytes);
+            loggerWriteLine!("Zeroing complete");
         }
 
-        let nextBlob = nextBlob.unwrap();
-        let mut candidateAddress: usize;
-
-        for x in 0..self.MemoryMap.EntryCount {
-            // Entry isn't useful if isn't a memory range
-            if self.MemoryMap.Entries[x as usize].getType()
-                != MemoryMapEntryType::AddressRangeMemory
-            {
-                continue;
-            }
-
-            candidateAddress = alignUp(
-                self.MemoryMap.Entries[x as usize].BaseAddress as usize,
-                alignment,
-            );
-
-            loop {
-                loggerWriteLine!("Checking 0x{:X} for 0x{:X}", candidateAddress, sizeInBytes);
-
-                // Dumb check to see if it'll fit at all (nermind if might already be used)
-                if !self.MemoryMap.Entries[x as usize].fits(candidateAddress as usize, sizeInBytes)
-                {
-                    break;
-                }
-
-                if let Some(blobUsing) = self.findBlobUsing(candidateAddress as usize, sizeInBytes)
-                {
-                    candidateAddress = self.Blobs[blobUsing].PhysicalAddress.address
-                        + self.Blobs[blobUsing].Length;
-                    candidateAddress = alignUp(candidateAddress, alignment);
-                    continue;
-                }
-
-                self.Blobs[nextBlob].PhysicalAddress.address = candidateAddress as usize;
-                self.Blobs[nextBlob].Length = sizeInBytes;
-
-                let bytes = forWhat.as_bytes();
-                let copy_len = core::cmp::min(bytes.len(), self.Blobs[nextBlob].What.len());
-                self.Blobs[nextBlob].What[..copy_len].copy_from_slice(&bytes[..copy_len]);
-
-                unsafe {
-                    loggerWriteLine!("Zeroing 0x{:X} for 0x{:X}", candidateAddress, sizeInBytes);
-                    zeroMemory(candidateAddress, sizeInBytes);
-                    loggerWriteLine!("Zeroing complete");
-                }
-
-                return candidateAddress;
-            }
-        }
-
-        haltLoopWithMessage!("Can't find a free blob");
+        result
     }
 
-    fn nextFreeBlob(&self) -> Option<usize> {
-        for index in 0..self.Blobs.len() {
-            if self.Blobs[index].Length == 0 {
-                loggerWriteLine!("Next free blob is {}", index);
-                return Some(index);
-            }
-
-            loggerWriteLine!(
-                "Blob {} is using address 0x{:X}",
-                index,
-                self.Blobs[index].PhysicalAddress.address
+    // Same as above, but leaves the contents alone. Once the identity map is gone the physical address isn't something
+    // we can write to, so the caller has to map it and zero it themselves if they care.
+    pub fn ReserveWherever(
+        &mut self,
+        forWhat: &str,
+        sizeInBytes: usize,
+        alignment: usize,
+    ) -> usize {
+        let Some(result) = self.TryReserveWherever(forWhat, sizeInBytes, alignment) else {
+            self.DumpUsage();
+            haltLoopWithMessage!(
+                "Can't find 0x{:X} contiguous frames for {}",
+                alignUp(sizeInBytes, SIZE_OF_PAGE) / SIZE_OF_PAGE,
+                forWhat
             );
-        }
+        };
 
-        None
+        result
     }
 
-    fn findBlobUsing(&self, address: usize, size: usize) -> Option<usize> {
-        for index in 0..self.Blobs.len() {
-            let blobSize = self.Blobs[index].Length;
+    // Same again, but None when there isn't room instead of halting
+    pub fn TryReserveWherever(
+        &mut self,
+        forWhat: &str,
+        sizeInBytes: usize,
+        alignment: usize,
+    ) -> Option<usize> {
+        loggerWriteLine!(
+            "Reserving 0x{:X} bytes for {} with alignment 0x{:X}",
+            sizeInBytes,
+            forWhat,
+            alignment
+        );
 
-            // Once we hit an unused blob, nothing else used will follow so we know we're not overlapping anywhere
-            if blobSize == 0 {
-                loggerWriteLine!("No blobs are using address 0x{:X}", address);
-                return None;
-            }
+        let frames = alignUp(sizeInBytes, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+        let result = self.Frames.allocateContiguous(frames, alignment)?;
 
-            let blobStart = self.Blobs[index].PhysicalAddress.address;
-            let blobEnd = blobStart + blobSize - 1;
+        loggerWriteLine!(
+            "Reserved 0x{:X} bytes @ 0x{:X} for {}",
+            sizeInBytes,
+            result,
+            forWhat
+        );
+        Some(result)
+    }
 
-            let addressEnd = address + size - 1;
+    // Single frame, for when contiguous doesn't matter
+    pub fn ReserveFrame(&mut self) -> Option<usize> {
+        self.Frames.allocate()
+    }
 
-            // Check for any overlap between ranges using standard range overlap formula
-            if address <= blobEnd && blobStart <= addressEnd {
-                loggerWriteLine!(
-                    "Blob {} overlaps with address range 0x{:X}-0x{:X}",
-                    index,
-                    address,
-                    addressEnd
-                );
-                return Some(index);
-            }
+    // Gives back something from one of the ReserveWherever/Reserve family. Size is rounded up to whole frames.
+    pub fn Free(&mut self, address: usize, sizeInBytes: usize) {
+        let frames = alignUp(sizeInBytes, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+        if let Err(reason) = self.Frames.free(address, frames) {
+            haltLoopWithMessage!(
+                "Can't free 0x{:X} for 0x{:X}: {}",
+                address,
+                sizeInBytes,
+                reason
+            );
         }
-
-        None
     }
 }
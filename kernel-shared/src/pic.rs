@@ -0,0 +1,161 @@
+use crate::assemblyStuff::ports::{inB, outB};
+
+// https://wiki.osdev.org/8259_PIC
+// Legacy Programmable Interrupt Controllers. Two of them chained together with the secondary
+// hanging off of IRQ2 of the primary.
+// Shared so both kernels leave them in the same state. No logging in here, the 32-bit kernel
+// uses this before much of anything is up.
+const PIC1_COMMAND: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_COMMAND: u16 = 0xA0;
+const PIC2_DATA: u16 = 0xA1;
+
+// Initialization Command Words
+const ICW1_ICW4: u8 = 0x01; // ICW4 will be sent
+const ICW1_INIT: u8 = 0x10; // Start initialization
+const ICW4_8086: u8 = 0x01; // 8086/88 mode instead of MCS-80/85
+
+// Operation Command Words
+const OCW2_EOI: u8 = 0x20;
+const OCW3_READ_ISR: u8 = 0x0B;
+
+// BIOS leaves the primary at 0x08 which collides with the CPU exceptions, so move both past them
+pub const PIC1_VECTOR_OFFSET: u8 = 0x20;
+pub const PIC2_VECTOR_OFFSET: u8 = 0x28;
+pub const IRQ_COUNT: u8 = 16;
+
+pub const CASCADE_IRQ: u8 = 2;
+
+// Port 0x80 is the POST code port, writing to it takes long enough for the PIC to catch up
+fn ioWait() {
+    unsafe {
+        outB(0x80, 0);
+    }
+}
+
+// Moves both PICs to PIC1_VECTOR_OFFSET/PIC2_VECTOR_OFFSET. Everything is left masked; use unmask for
+// the lines you're ready to handle.
+pub fn remap() {
+    unsafe {
+        outB(PIC1_COMMAND, ICW1_INIT | ICW1_ICW4);
+        ioWait();
+        outB(PIC2_COMMAND, ICW1_INIT | ICW1_ICW4);
+        ioWait();
+
+        // ICW2: Vector offsets
+        outB(PIC1_DATA, PIC1_VECTOR_OFFSET);
+        ioWait();
+        outB(PIC2_DATA, PIC2_VECTOR_OFFSET);
+        ioWait();
+
+        // ICW3: Primary has the secondary on IRQ2 (bit mask), secondary gets its cascade identity (number)
+        outB(PIC1_DATA, 1 << CASCADE_IRQ);
+        ioWait();
+        outB(PIC2_DATA, CASCADE_IRQ);
+        ioWait();
+
+        outB(PIC1_DATA, ICW4_8086);
+        ioWait();
+        outB(PIC2_DATA, ICW4_8086);
+        ioWait();
+    }
+
+    maskAll();
+}
+
+pub fn maskAll() {
+    unsafe {
+        outB(PIC1_DATA, 0xFF);
+        outB(PIC2_DATA, 0xFF);
+    }
+}
+
+pub fn mask(irq: u8) {
+    unsafe {
+        if irq < 8 {
+            let mask = inB(PIC1_DATA) | (1 << irq);
+            outB(PIC1_DATA, mask);
+        } else {
+            let mask = inB(PIC2_DATA) | (1 << (irq - 8));
+            outB(PIC2_DATA, mask);
+        }
+    }
+}
+
+pub fn unmask(irq: u8) {
+    unsafe {
+        if irq < 8 {
+            let mask = inB(PIC1_DATA) & !(1 << irq);
+            outB(PIC1_DATA, mask);
+        } else {
+            let mask = inB(PIC2_DATA) & !(1 << (irq - 8));
+            outB(PIC2_DATA, mask);
+
+            // Nothing from the secondary makes it through unless the cascade line is open too
+            let mask = inB(PIC1_DATA) & !(1 << CASCADE_IRQ);
+            outB(PIC1_DATA, mask);
+        }
+    }
+}
+
+// Bit set = masked. Primary in the low byte.
+pub fn getMask() -> u16 {
+    unsafe { (inB(PIC2_DATA) as u16) << 8 | inB(PIC1_DATA) as u16 }
+}
+
+// Needs to be called at the end of every IRQ handler, otherwise that line (and anything lower priority) stays blocked
+pub fn sendEoi(irq: u8) {
+    unsafe {
+        if irq >= 8 {
+            outB(PIC2_COMMAND, OCW2_EOI);
+        }
+
+        outB(PIC1_COMMAND, OCW2_EOI);
+    }
+}
+
+// https://wiki.osdev.org/8259_PIC#Spurious_IRQs
+// If a line drops before the CPU acknowledges it, the PIC hands out its lowest priority IRQ (7 or 15)
+// without actually having anything in service. Those must not get an EOI from the PIC that raised them.
+pub fn isSpurious(irq: u8) -> bool {
+    match irq {
+        7 => readInService() & (1 << 7) == 0,
+        15 => readInService() & (1 << 15) == 0,
+        _ => false,
+    }
+}
+
+// The secondary doesn't know about a spurious 15, but the primary saw a real IRQ2 from it and still wants its EOI
+pub fn acknowledgeSpurious(irq: u8) {
+    if irq >= 8 {
+        unsafe {
+            outB(PIC1_COMMAND, OCW2_EOI);
+        }
+    }
+}
+
+fn readInService() -> u16 {
+    unsafe {
+        outB(PIC1_COMMAND, OCW3_READ_ISR);
+        outB(PIC2_COMMAND, OCW3_READ_ISR);
+        (inB(PIC2_COMMAND) as u16) << 8 | inB(PIC1_COMMAND) as u16
+    }
+}
+
+pub fn irqToVector(irq: u8) -> u8 {
+    if irq < 8 {
+        PIC1_VECTOR_OFFSET + irq
+    } else {
+        PIC2_VECTOR_OFFSET + irq - 8
+    }
+}
+
+pub fn vectorToIrq(vector: u8) -> Option<u8> {
+    if (PIC1_VECTOR_OFFSET..PIC1_VECTOR_OFFSET + 8).contains(&vector) {
+        Some(vector - PIC1_VECTOR_OFFSET)
+    } else if (PIC2_VECTOR_OFFSET..PIC2_VECTOR_OFFSET + 8).contains(&vector) {
+        Some(vector - PIC2_VECTOR_OFFSET + 8)
+    } else {
+        None
+    }
+}
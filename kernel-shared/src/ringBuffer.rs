@@ -0,0 +1,73 @@
+// Fixed size FIFO that doesn't need a heap. Meant for handing data from interrupt handlers
+// to whoever is consuming it, so wrap it in a critical_section::Mutex when sharing.
+pub struct RingBuffer<T: Copy, const N: usize> {
+    items: [Option<T>; N],
+    head: usize, // Next slot to read from
+    tail: usize, // Next slot to write to
+    count: usize,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> Self {
+        RingBuffer {
+            items: [None; N],
+            head: 0,
+            tail: 0,
+            count: 0,
+        }
+    }
+
+    // Returns false if there wasn't room. We drop the new item rather than overwrite the oldest
+    // as for input it's less confusing to lose the tail end of a burst than the start of it.
+    pub fn push(&mut self, item: T) -> bool {
+        if self.isFull() {
+            return false;
+        }
+
+        self.items[self.tail] = Some(item);
+        self.tail = (self.tail + 1) % N;
+        self.count += 1;
+
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.isEmpty() {
+            return None;
+        }
+
+        let result = self.items[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.count -= 1;
+
+        result
+    }
+
+    pub fn peek(&self) -> Option<T> {
+        if self.isEmpty() {
+            return None;
+        }
+
+        self.items[self.head]
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn isEmpty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn isFull(&self) -> bool {
+        self.count == N
+    }
+}
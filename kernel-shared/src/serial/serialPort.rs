@@ -58,6 +58,33 @@ impl SerialPort {
         Ok(())
     }
 
+    // Blocks until something shows up (or we give up)
+    pub fn Receive(&self) -> Result<u8, SerialFailure> {
+        unsafe { self.receiveByte() }
+    }
+
+    // Doesn't wait. Good for polling or draining the FIFO from an interrupt handler.
+    pub fn tryReceive(&self) -> Option<u8> {
+        unsafe {
+            if self.dataAvailable() {
+                Some(inB(self.port.getPortAddress() + 0))
+            } else {
+                None
+            }
+        }
+    }
+
+    // Whoever turns this on needs to have a handler hooked up for getIrq() first
+    pub fn setReceiveInterrupt(&self, enable: bool) {
+        unsafe {
+            self.enableInterrupts(enable);
+        }
+    }
+
+    pub fn getIrq(&self) -> u8 {
+        self.port.getIrq()
+    }
+
     unsafe fn sendByte(&self, b: u8) -> Result<(), SerialFailure> { unsafe {
         let mut x = 0;
         while self.isTransmitNotEmpty() {
@@ -129,7 +156,9 @@ impl SerialPort {
     // https://wiki.osdev.org/Serial_Ports#Interrupt_enable_register
     unsafe fn enableInterrupts(&self, enable: bool) { unsafe {
         if enable {
-            todo!("Enable interrupts")
+            // Only 'Received Data Available'. Sending stays polled.
+            // This still needs OUT2 in the modem control register to actually get to the PIC, disableLoopback takes care of that.
+            outB(self.port.getPortAddress() + 1, 0x01);
         } else {
             outB(self.port.getPortAddress() + 1, 0x00);
         }
@@ -186,4 +215,10 @@ impl COMPort {
             COMPort::COM1 => 0x3F8,
         }
     }
+
+    fn getIrq(&self) -> u8 {
+        match self {
+            COMPort::COM1 => 4,
+        }
+    }
 }
@@ -99,6 +99,15 @@ pub fn writeString(msg: &[u8]) {
                 } else {
                     cursorPosition.y += 1;
                 }
+            } else if byte == b'\x08' {
+                // Backspace only moves the cursor like a terminal would, callers overwrite with a space if they want it gone
+                if cursorPosition.x > 0 {
+                    cursorPosition.x -= 1;
+                } else if cursorPosition.y > 0 {
+                    // Printing wraps a column early (see below), so that's where the previous line ends
+                    cursorPosition.y -= 1;
+                    cursorPosition.x = VGA_WIDTH as u8 - 2;
+                }
             } else if byte == 0 {
                 break;
             } else {
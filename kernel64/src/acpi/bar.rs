@@ -1,30 +1,170 @@
+use core::{
+    fmt,
+    ptr::{read_volatile, write_volatile},
+};
+
 use crate::loggerWriteLine;
-use core::ptr::{read_volatile, write_volatile};
 
+use super::pciCommonHeader::PciCommonHeader;
+
+// PCI Local Bus 3.0, 6.2.5.1 Address Maps
+const FIRST_BAR: usize = 0x10;
+const BAR_IO_SPACE: u32 = 1 << 0;
+const BAR_TYPE_MASK: u32 = 0b11 << 1;
+const BAR_TYPE_32: u32 = 0b00 << 1;
+const BAR_TYPE_BELOW_1MB: u32 = 0b01 << 1; // From PCI 2.x, still 32-bit as far as we care
+const BAR_TYPE_64: u32 = 0b10 << 1;
+const BAR_PREFETCHABLE: u32 = 1 << 3;
+const BAR_MEMORY_ADDRESS_MASK: u32 = !0xF;
+const BAR_IO_ADDRESS_MASK: u32 = !0x3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarKind {
+    Io,
+    Memory32,
+    Memory64, // Takes the next BAR for its upper half too
+}
+
+#[derive(Clone, Copy)]
 pub struct Bar {
-    pub BarTarget: u32, // Where the BAR points to with metadata bits masked off
-    _Value: u32,        // Original value of the BAR value with the metadata bits intact
-    _AddressSpace: u32, // The extent of memory this points to
+    pub index: u8,
+    pub kind: BarKind,
+    pub address: u64, // Where it points with the flag bits masked off; a port number for I/O
+    pub size: u64,
+    pub prefetchable: bool,
 }
+
 impl Bar {
-    pub fn new(address: u32, barValue: u32, barAddress: *mut u32) -> Bar {
-        unsafe {
-            write_volatile(barAddress, 0xFFFFFFFF);
-            let readBack = read_volatile(barAddress);
+    // Decodes and sizes BAR index of the function. None when the BAR isn't implemented. index has to be the first
+    // half of a 64-bit pair, asking for the upper half gets nonsense.
+    pub fn read(header: &PciCommonHeader, index: u8) -> Option<Bar> {
+        let barCount = header.getBarCount();
+        if index as usize >= barCount {
+            return None;
+        }
 
-            // Restore to what it was
-            write_volatile(barAddress, barValue);
+        let config = header as *const _ as usize;
+        let register = (config + FIRST_BAR + index as usize * 4) as *mut u32;
+        let value = unsafe { read_volatile(register) };
 
-            // Since this is a memory address, clear the last 3 bits (the 'info bits')
-            let size = (!(readBack & 0xFFFFFFF8)) + 1;
+        // Has to stop decoding while the BAR holds all 1s, otherwise it'd claim whatever that lands on
+        let command = header.disableDecoding();
+        let low = unsafe { probe(register) };
 
-            loggerWriteLine!("Readback was 0x{:X} size is 0x{:X}", readBack, size);
+        let result = if value & BAR_IO_SPACE != 0 {
+            let mut mask = low & BAR_IO_ADDRESS_MASK;
 
-            return Bar {
-                BarTarget: address,
-                _AddressSpace: size,
-                _Value: barValue,
+            // The upper 16 bits are allowed to read back as 0 since x86 ports only go to 0xFFFF
+            if mask != 0 && mask & 0xFFFF_0000 == 0 {
+                mask |= 0xFFFF_0000;
+            }
+
+            (mask != 0).then(|| Bar {
+                index,
+                kind: BarKind::Io,
+                address: (value & BAR_IO_ADDRESS_MASK) as u64,
+                size: (!mask).wrapping_add(1) as u64,
+                prefetchable: false,
+            })
+        } else {
+            let prefetchable = value & BAR_PREFETCHABLE != 0;
+            match value & BAR_TYPE_MASK {
+                BAR_TYPE_32 | BAR_TYPE_BELOW_1MB => {
+                    let mask = low & BAR_MEMORY_ADDRESS_MASK;
+                    (mask != 0).then(|| Bar {
+                        index,
+                        kind: BarKind::Memory32,
+                        address: (value & BAR_MEMORY_ADDRESS_MASK) as u64,
+                        size: (!mask).wrapping_add(1) as u64,
+                        prefetchable,
+                    })
+                }
+                BAR_TYPE_64 if index as usize + 1 < barCount => {
+                    let upperRegister = unsafe { register.add(1) };
+                    let upperValue = unsafe { read_volatile(upperRegister) };
+                    let high = unsafe { probe(upperRegister) };
+
+                    let mask = ((high as u64) << 32) | (low & BAR_MEMORY_ADDRESS_MASK) as u64;
+                    (mask != 0).then(|| Bar {
+                        index,
+                        kind: BarKind::Memory64,
+                        address: ((upperValue as u64) << 32)
+                            | (value & BAR_MEMORY_ADDRESS_MASK) as u64,
+                        size: (!mask).wrapping_add(1),
+                        prefetchable,
+                    })
+                }
+                BAR_TYPE_64 => {
+                    loggerWriteLine!("BAR{} is 64-bit but there's no BAR after it", index);
+                    None
+                }
+                _ => {
+                    loggerWriteLine!("BAR{} has a type we don't know: 0x{:X}", index, value);
+                    None
+                }
+            }
+        };
+
+        header.restoreCommand(command);
+        result
+    }
+
+    // Every BAR the function has, sized. The upper half of a 64-bit pair stays None.
+    pub fn readAll(header: &PciCommonHeader) -> [Option<Bar>; 6] {
+        let mut result = [None; 6];
+        let mut index = 0;
+        while index < header.getBarCount() {
+            let bar = Self::read(header, index as u8);
+            index += match bar {
+                Some(Bar {
+                    kind: BarKind::Memory64,
+                    ..
+                }) => 2,
+                _ => 1,
             };
+
+            if let Some(bar) = bar {
+                result[bar.index as usize] = Some(bar);
+            }
+        }
+
+        result
+    }
+
+    pub fn isMemory(&self) -> bool {
+        self.kind != BarKind::Io
+    }
+
+    // Firmware didn't give it anywhere to live. BUGBUG: We don't hand out addresses ourselves.
+    pub fn isAssigned(&self) -> bool {
+        self.address != 0
+    }
+}
+
+impl fmt::Display for Bar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            BarKind::Io => write!(f, "I/O @ 0x{:X}", self.address)?,
+            BarKind::Memory32 => write!(f, "32-bit memory @ 0x{:X}", self.address)?,
+            BarKind::Memory64 => write!(f, "64-bit memory @ 0x{:X}", self.address)?,
+        }
+
+        write!(f, " size 0x{:X}", self.size)?;
+        if self.prefetchable {
+            write!(f, " prefetchable")?;
         }
+
+        Ok(())
+    }
+}
+
+// Writes all 1s and reads back which bits stuck, then puts it back the way it was
+unsafe fn probe(register: *mut u32) -> u32 {
+    unsafe {
+        let value = read_volatile(register);
+        write_volatile(register, 0xFFFF_FFFF);
+        let readBack = read_volatile(register);
+        write_volatile(register, value);
+        readBack
     }
 }
@@ -1,5 +1,8 @@
 use core::str::from_utf8;
-use crate::loggerWriteLine;
+
+use kernel_shared::pageTable::enums::*;
+
+use crate::{loggerWriteLine, memory::virtualMemory::VirtualMemoryManager};
 
 // https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#system-description-table-header
 #[repr(C, packed)]
@@ -16,6 +19,17 @@ pub struct DescriptionTable {
 }
 
 impl DescriptionTable {
+    // Every byte of the table, header and all, has to add up to 0. Needs the whole table mapped.
+    pub fn isChecksumValid(&self) -> bool {
+        let start = self as *const _ as *const u8;
+        let length = self.Length as usize;
+        let sum = (0..length).fold(0u8, |sum, index| {
+            sum.wrapping_add(unsafe { *start.add(index) })
+        });
+
+        sum == 0
+    }
+
     pub fn printSignature(&self) {
         match from_utf8(&self.Signature) {
             Ok(theString) => {
@@ -27,4 +41,30 @@ impl DescriptionTable {
             }
         };
     }
-}
\ No newline at end of file
+}
+
+// Maps just the header so we can see what a table is and how big it is
+pub fn mapHeader(vmm: &mut VirtualMemoryManager, physicalAddress: usize) -> *const DescriptionTable {
+    mapRange(vmm, physicalAddress, size_of::<DescriptionTable>()) as *const DescriptionTable
+}
+
+// Maps the whole table based on what its header says its length is
+pub fn mapTable(vmm: &mut VirtualMemoryManager, physicalAddress: usize) -> *const DescriptionTable {
+    let header = mapHeader(vmm, physicalAddress);
+    let length = unsafe { (*header).Length } as usize;
+
+    mapRange(vmm, physicalAddress, length) as *const DescriptionTable
+}
+
+fn mapRange(vmm: &mut VirtualMemoryManager, physicalAddress: usize, length: usize) -> usize {
+    vmm.mapPhysicalAnywhere(
+        physicalAddress,
+        length,
+        Execute::Yes,
+        Present::Yes,
+        Writable::No,
+        Cachable::No,
+        UserSupervisor::Supervisor,
+        WriteThrough::WriteTrough,
+    )
+}
@@ -0,0 +1,58 @@
+use crate::memory::virtualMemory::VirtualMemoryManager;
+
+use super::rsdp::findTable;
+
+// https://www.intel.com/content/dam/www/public/us/en/documents/technical-specifications/software-developers-hpet-spec-1-0a.pdf
+// Section 3.2.4, The ACPI 2.0 HPET Description Table (HPET)
+#[repr(C, packed)]
+pub struct HPET {
+    Signature: [u8; 4], // Supposed to be HPET
+    Length: u32,
+    Revision: u8,
+    Checksum: u8,
+    OEMID: [u8; 6],
+    OemTableID: [u8; 8],
+    OemRevision: [u8; 4],
+    CreateID: [u8; 4],
+    CreatorRevision: [u8; 4],
+    EventTimerBlockId: u32,
+    BaseAddress: GenericAddress,
+    HpetNumber: u8,
+    MinimumTick: u16, // In main counter ticks, the smallest periodic interrupt that won't lose interrupts
+    PageProtection: u8,
+}
+
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#generic-address-structure-gas
+#[repr(C, packed)]
+struct GenericAddress {
+    AddressSpaceId: u8, // 0 is memory, which is the only thing an HPET is allowed to be
+    RegisterBitWidth: u8,
+    RegisterBitOffset: u8,
+    Reserved: u8,
+    Address: u64,
+}
+
+const ADDRESS_SPACE_MEMORY: u8 = 0;
+
+#[derive(Clone, Copy)]
+pub struct HpetInfo {
+    pub address: u64, // Physical
+    pub number: u8,
+    pub minimumTick: u16,
+}
+
+pub fn getHpet(vmm: &mut VirtualMemoryManager) -> Option<HpetInfo> {
+    let table = findTable(vmm, b"HPET")? as *const HPET;
+
+    unsafe {
+        if (*table).BaseAddress.AddressSpaceId != ADDRESS_SPACE_MEMORY {
+            return None;
+        }
+
+        Some(HpetInfo {
+            address: (*table).BaseAddress.Address,
+            number: (*table).HpetNumber,
+            minimumTick: (*table).MinimumTick,
+        })
+    }
+}
@@ -0,0 +1,316 @@
+use core::{
+    mem::size_of,
+    ptr::{addr_of, read_unaligned},
+};
+
+use crate::{loggerWriteLine, memory::virtualMemory::VirtualMemoryManager};
+
+use super::rsdp::findTable;
+
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#multiple-apic-description-table-madt
+// Multiple APIC Description Table
+#[repr(C, packed)]
+pub struct MADT {
+    Signature: [u8; 4], // Supposed to be APIC
+    Length: u32,
+    Revision: u8,
+    Checksum: u8,
+    OEMID: [u8; 6],
+    OemTableID: [u8; 8],
+    OemRevision: [u8; 4],
+    CreateID: [u8; 4],
+    CreatorRevision: [u8; 4],
+    LocalApicAddress: u32, // 32-bit physical address of every processor's local APIC. Can be overridden by an entry.
+    Flags: u32,
+    FirstEntry: u8, // Variable length entries start here
+}
+
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#interrupt-controller-structure-types
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_NMI: u8 = 4;
+const ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+
+const FLAGS_PCAT_COMPAT: u32 = 1 << 0; // There's also a pair of 8259s that need to be disabled
+const LOCAL_APIC_ENABLED: u32 = 1 << 0;
+const LOCAL_APIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+pub const ALL_PROCESSORS: u8 = 0xFF;
+
+#[repr(C, packed)]
+struct EntryHeader {
+    Type: u8,
+    Length: u8,
+}
+
+#[repr(C, packed)]
+struct LocalApicEntry {
+    Header: EntryHeader,
+    ProcessorUid: u8,
+    ApicId: u8,
+    Flags: u32,
+}
+
+#[repr(C, packed)]
+struct IoApicEntry {
+    Header: EntryHeader,
+    IoApicId: u8,
+    Reserved: u8,
+    Address: u32,
+    GlobalSystemInterruptBase: u32,
+}
+
+#[repr(C, packed)]
+struct InterruptSourceOverrideEntry {
+    Header: EntryHeader,
+    Bus: u8, // Always 0 (ISA)
+    Source: u8,
+    GlobalSystemInterrupt: u32,
+    Flags: u16, // MPS INTI flags
+}
+
+#[repr(C, packed)]
+struct LocalApicNmiEntry {
+    Header: EntryHeader,
+    ProcessorUid: u8,
+    Flags: u16,
+    LocalApicLint: u8,
+}
+
+#[repr(C, packed)]
+struct LocalApicAddressOverrideEntry {
+    Header: EntryHeader,
+    Reserved: u16,
+    Address: u64,
+}
+
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#mps-inti-flags
+// Polarity is bits 0-1, trigger mode is 2-3. 'Conforms' means whatever the bus normally does, which for ISA is
+// active high and edge triggered.
+fn isActiveLow(flags: u16) -> bool {
+    flags & 0b11 == 0b11
+}
+
+fn isLevelTriggered(flags: u16) -> bool {
+    (flags >> 2) & 0b11 == 0b11
+}
+
+const MAX_PROCESSORS: usize = 16;
+const MAX_IO_APICS: usize = 4;
+const MAX_OVERRIDES: usize = 16;
+const MAX_NMIS: usize = 8;
+
+#[derive(Clone, Copy, Default)]
+pub struct ProcessorInfo {
+    pub processorUid: u8,
+    pub apicId: u8,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub address: u64,
+    pub gsiBase: u32,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct InterruptOverride {
+    pub source: u8, // ISA IRQ
+    pub gsi: u32,   // What it's actually wired to on the I/O APIC(s)
+    pub activeLow: bool,
+    pub levelTriggered: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct LocalNmi {
+    pub processorUid: u8, // ALL_PROCESSORS for everybody
+    pub lint: u8,
+    pub activeLow: bool,
+    pub levelTriggered: bool, // What the MADT says, though NMIs are always edge triggered
+}
+
+// Everything we care about from the MADT copied out into something that's easy to use.
+// Fixed limits keep it Copy, and they're well past what a single core DanOS needs; extras are logged and dropped.
+#[derive(Clone, Copy, Default)]
+pub struct MadtInfo {
+    pub localApicAddress: u64,
+    pub hasLegacyPics: bool,
+    processors: [ProcessorInfo; MAX_PROCESSORS],
+    processorCount: usize,
+    ioApics: [IoApicInfo; MAX_IO_APICS],
+    ioApicCount: usize,
+    overrides: [InterruptOverride; MAX_OVERRIDES],
+    overrideCount: usize,
+    nmis: [LocalNmi; MAX_NMIS],
+    nmiCount: usize,
+}
+
+impl MadtInfo {
+    pub fn processors(&self) -> &[ProcessorInfo] {
+        &self.processors[..self.processorCount]
+    }
+
+    pub fn ioApics(&self) -> &[IoApicInfo] {
+        &self.ioApics[..self.ioApicCount]
+    }
+
+    pub fn overrides(&self) -> &[InterruptOverride] {
+        &self.overrides[..self.overrideCount]
+    }
+
+    pub fn nmis(&self) -> &[LocalNmi] {
+        &self.nmis[..self.nmiCount]
+    }
+
+    pub fn dump(&self) {
+        loggerWriteLine!(
+            "MADT: Local APIC @ 0x{:X}, legacy PICs: {}",
+            self.localApicAddress,
+            self.hasLegacyPics
+        );
+
+        for processor in self.processors() {
+            loggerWriteLine!(
+                "  CPU {} APIC ID {} {}",
+                processor.processorUid,
+                processor.apicId,
+                if processor.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+        }
+
+        for ioApic in self.ioApics() {
+            loggerWriteLine!(
+                "  I/O APIC {} @ 0x{:X} GSI base {}",
+                ioApic.id,
+                ioApic.address,
+                ioApic.gsiBase
+            );
+        }
+
+        for o in self.overrides() {
+            loggerWriteLine!(
+                "  IRQ {} -> GSI {}{}{}",
+                o.source,
+                o.gsi,
+                if o.activeLow { " active low" } else { "" },
+                if o.levelTriggered { " level" } else { "" }
+            );
+        }
+
+        for nmi in self.nmis() {
+            loggerWriteLine!("  NMI on LINT{} for CPU 0x{:X}", nmi.lint, nmi.processorUid);
+        }
+    }
+}
+
+pub fn getMadt(vmm: &mut VirtualMemoryManager) -> Option<MadtInfo> {
+    let table = findTable(vmm, b"APIC")? as *const MADT;
+    Some(unsafe { (*table).parse() })
+}
+
+impl MADT {
+    pub fn parse(&self) -> MadtInfo {
+        let mut result = MadtInfo {
+            localApicAddress: self.LocalApicAddress as u64,
+            hasLegacyPics: self.Flags & FLAGS_PCAT_COMPAT != 0,
+            ..Default::default()
+        };
+
+        let length = self.Length as usize;
+        let start = addr_of!(self.FirstEntry) as usize;
+        let end = self as *const _ as usize + length;
+        let mut current = start;
+
+        while current + size_of::<EntryHeader>() <= end {
+            unsafe {
+                let header = read_unaligned(current as *const EntryHeader);
+                if header.Length < 2 || current + header.Length as usize > end {
+                    loggerWriteLine!("MADT entry at 0x{:X} is bogus, stopping", current);
+                    break;
+                }
+
+                match header.Type {
+                    ENTRY_LOCAL_APIC => {
+                        let entry = read_unaligned(current as *const LocalApicEntry);
+                        let flags = entry.Flags;
+
+                        // Neither enabled nor able to be, so it's not really there
+                        if flags & (LOCAL_APIC_ENABLED | LOCAL_APIC_ONLINE_CAPABLE) != 0 {
+                            if result.processorCount < MAX_PROCESSORS {
+                                result.processors[result.processorCount] = ProcessorInfo {
+                                    processorUid: entry.ProcessorUid,
+                                    apicId: entry.ApicId,
+                                    enabled: flags & LOCAL_APIC_ENABLED != 0,
+                                };
+                                result.processorCount += 1;
+                            } else {
+                                loggerWriteLine!(
+                                    "Too many CPUs, ignoring APIC ID {}",
+                                    entry.ApicId
+                                );
+                            }
+                        }
+                    }
+                    ENTRY_IO_APIC => {
+                        let entry = read_unaligned(current as *const IoApicEntry);
+                        if result.ioApicCount < MAX_IO_APICS {
+                            result.ioApics[result.ioApicCount] = IoApicInfo {
+                                id: entry.IoApicId,
+                                address: entry.Address as u64,
+                                gsiBase: entry.GlobalSystemInterruptBase,
+                            };
+                            result.ioApicCount += 1;
+                        } else {
+                            loggerWriteLine!("Too many I/O APICs, ignoring ID {}", entry.IoApicId);
+                        }
+                    }
+                    ENTRY_INTERRUPT_SOURCE_OVERRIDE => {
+                        let entry = read_unaligned(current as *const InterruptSourceOverrideEntry);
+                        if result.overrideCount < MAX_OVERRIDES {
+                            result.overrides[result.overrideCount] = InterruptOverride {
+                                source: entry.Source,
+                                gsi: entry.GlobalSystemInterrupt,
+                                activeLow: isActiveLow(entry.Flags),
+                                levelTriggered: isLevelTriggered(entry.Flags),
+                            };
+                            result.overrideCount += 1;
+                        } else {
+                            loggerWriteLine!("Too many overrides, ignoring IRQ {}", entry.Source);
+                        }
+                    }
+                    ENTRY_LOCAL_APIC_NMI => {
+                        let entry = read_unaligned(current as *const LocalApicNmiEntry);
+                        if result.nmiCount < MAX_NMIS {
+                            result.nmis[result.nmiCount] = LocalNmi {
+                                processorUid: entry.ProcessorUid,
+                                lint: entry.LocalApicLint,
+                                activeLow: isActiveLow(entry.Flags),
+                                levelTriggered: isLevelTriggered(entry.Flags),
+                            };
+                            result.nmiCount += 1;
+                        } else {
+                            loggerWriteLine!("Too many NMIs, ignoring LINT{}", entry.LocalApicLint);
+                        }
+                    }
+                    ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE => {
+                        let entry = read_unaligned(current as *const LocalApicAddressOverrideEntry);
+                        result.localApicAddress = entry.Address;
+                    }
+                    _ => {
+                        // x2APIC, GIC and friends. Nothing we use.
+                    }
+                }
+
+                current += header.Length as usize;
+            }
+        }
+
+        result
+    }
+}
@@ -1,11 +1,13 @@
-use core::{mem::size_of, ptr::addr_of};
+use alloc::vec::Vec;
+use core::{
+    mem::size_of,
+    ptr::{addr_of, read_unaligned},
+};
 
 use kernel_shared::assemblyStuff::halt::haltLoop;
 
 use crate::{acpi::mcfgEntry::McfgEntry, loggerWriteLine};
 
-use super::pciGeneralDevice::PciGeneralDevice;
-
 // UEFI doesn't doc this and links to something you need to register for, so OSDev it is
 // https://wiki.osdev.org/PCI_Express#Enhanced_Configuration_Mechanism
 // Memory-mapped ConFiGuration space
@@ -26,7 +28,8 @@ pub struct MCFG {
 }
 
 impl MCFG {
-    pub fn printSomeInfo(&self) -> Option<*const PciGeneralDevice>{
+    // Every segment group / bus range that has configuration space
+    pub fn getEntries(&self) -> Vec<McfgEntry> {
         let length = self.Length;
         let lengthForEntries = length - size_of::<MCFG>() as u32 + 1; // +1 as FirstConfigEntryis the first byte of the first entry, so shouldn't count as the base size of this table
         let size = size_of::<McfgEntry>() as u32;
@@ -49,14 +52,12 @@ impl MCFG {
         );
 
         let base = addr_of!(self.FirstConfigEntry) as *const McfgEntry;
-        let mut result = None;
+        let mut result = Vec::new();
         unsafe {
             for index in 0..numOfEntries as isize {
-                let entry = base.offset(index);
-                let maybeResult = (*entry).printSomeInfo();
-                if result == None && maybeResult != None{
-                    result = maybeResult;
-                }
+                let entry = read_unaligned(base.offset(index));
+                entry.printSomeInfo();
+                result.push(entry);
             }
         }
 
@@ -1,13 +1,12 @@
-use crate::{
-    acpi::{
-        pciCommonHeader::{PciCommonHeader, PciHeaderType},
-        pciGeneralDevice::PciGeneralDevice,
-    }, loggerWriteLine
-};
+use crate::loggerWriteLine;
+
+// Each bus gets 32 devices * 8 functions * 4K of configuration space
+pub const CONFIG_SPACE_PER_BUS: usize = 0x10_0000;
 
 // Same no UEFI docs story as MCFG
 // https://wiki.osdev.org/PCI_Express#Enhanced_Configuration_Mechanism
 // Also useful https://wiki.qemu.org/images/f/f6/PCIvsPCIe.pdf.
+#[derive(Clone, Copy)]
 #[repr(C, packed)]
 pub struct McfgEntry {
     pub BaseAddress: u64,
@@ -18,14 +17,22 @@ pub struct McfgEntry {
 }
 
 impl McfgEntry {
-    pub fn printSomeInfo(&self) -> Option<*const PciGeneralDevice> {
+    pub fn hasBus(&self, bus: u8) -> bool {
+        bus >= self.StartBus && bus <= self.EndBus
+    }
+
+    // Physical address of a whole bus's configuration space. BaseAddress is where bus 0 would be even when the range
+    // starts later, which is how Linux reads it too.
+    pub fn getBusAddress(&self, bus: u8) -> u64 {
+        self.BaseAddress + (bus as u64) * CONFIG_SPACE_PER_BUS as u64
+    }
+
+    pub fn printSomeInfo(&self) {
         let base = self.BaseAddress;
         let seg = self.SegmentGroup;
         let start = self.StartBus;
         let end = self.EndBus;
 
-        let mut ahciController: Option<*const PciGeneralDevice> = None;
-
         loggerWriteLine!(
             "    Base 0x{:X} for group {} busses {}..={}",
             base,
@@ -33,91 +40,5 @@ impl McfgEntry {
             start,
             end
         );
-
-        for device in 0..32 {
-            match PciCommonHeader::tryGetEntry(&self, 0, device, 0) {
-                Some(header) => unsafe {
-                    let headerType = (*header).getType();
-                    let result =
-                        self.printDetails(header, &headerType, device, 0, (*header).ProgIF);
-
-                    // First one wins
-                    if ahciController == None {
-                        if let Some(result) = result {
-                            ahciController = Some(result);
-                        }
-                    }
-
-                    if headerType == PciHeaderType::MultiFunctionGeneral {
-                        for remainingFunction in 1..=7 {
-                            match PciCommonHeader::tryGetEntry(&self, 0, device, remainingFunction)
-                            {
-                                Some(innerHeader) => {
-                                    let headerType = (*innerHeader).getType();
-                                    let result = self.printDetails(
-                                        innerHeader,
-                                        &headerType,
-                                        device,
-                                        remainingFunction,
-                                        (*header).ProgIF,
-                                    );
-                                    
-                                    // First one wins
-                                    if ahciController == None {
-                                        if let Some(result) = result {
-                                            ahciController = Some(result);
-                                        }
-                                    }
-                                }
-                                None => {}
-                            }
-                        }
-                    }
-                },
-                None => (),
-            }
-        }
-
-        return ahciController;
     }
-
-    unsafe fn printDetails(
-        &self,
-        header: *const PciCommonHeader,
-        headerType: &PciHeaderType,
-        device: u8,
-        function: u8,
-        progIF: u8,
-    ) -> Option<*const PciGeneralDevice> { unsafe {
-        let cc = (*header).ClassCode;
-        let sc = (*header).Subclass;
-
-        if *headerType == PciHeaderType::Dunno {
-            loggerWriteLine!("    Dunno is 0x{:X}", (*header).HeaderType);
-        }
-
-        loggerWriteLine!(
-            "    Device {} Function {} exists as a {:?} 0x{:X}:0x{:X}:0x{:X}",
-            device,
-            function,
-            headerType,
-            cc,
-            sc,
-            progIF
-        );
-
-        let maybeDevice = PciGeneralDevice::tryGet(&*header);
-        if let Some(device) = maybeDevice {
-            (*device).printBars();
-
-            // Class - Mass Storage Controller
-            // Subclass - Serial ATA Controller
-            // Interface - AHCI
-            if cc == 0x1 && sc == 0x6 && progIF == 0x1 {
-                return Some(device);
-            }
-        }
-
-        return None;
-    }}
 }
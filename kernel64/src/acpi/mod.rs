@@ -4,7 +4,10 @@ pub mod pciGeneralDevice;
 pub mod descriptionTable;
 pub mod dsdt;
 pub mod fadt;
+pub mod hpet;
+pub mod madt;
 pub mod rsdp;
 pub mod rsdt;
+pub mod xsdt;
 pub mod mcfg;
 pub mod mcfgEntry;
@@ -1,19 +1,32 @@
-use kernel_shared::assemblyStuff::halt::haltLoop;
+use core::ptr::{addr_of, read_volatile, write_volatile};
 
-use crate::loggerWriteLine;
-
-use super::mcfgEntry::McfgEntry;
-
-#[derive(Debug)]
-#[derive(PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PciHeaderType {
     General,
     NormalBridge, // PCI to PCI
     CardBridge,   // PCI to CardBus
-    MultiFunctionGeneral,
     Dunno,
 }
 
+// Bit 7 of the header type says the device has functions past 0, the rest is the actual layout
+const HEADER_TYPE_MULTI_FUNCTION: u8 = 1 << 7;
+const HEADER_TYPE_MASK: u8 = 0x7F;
+
+// Reading a function that isn't there gives all 1s
+const VENDOR_NONE: u16 = 0xFFFF;
+
+// PCI-to-PCI Bridge 1.2, 3.2.5.4. CardBus bridges have it in the same spot.
+const BRIDGE_SECONDARY_BUS: usize = 0x19;
+
+// Command register
+const COMMAND_IO_SPACE: u16 = 1 << 0;
+const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+const COMMAND_BUS_MASTER: u16 = 1 << 2;
+const COMMAND_INTERRUPT_DISABLE: u16 = 1 << 10;
+
+// Status register, set when CapabilitiesOffset points at something
+const STATUS_CAPABILITIES_LIST: u16 = 1 << 4;
+
 #[repr(C, packed)]
 pub struct PciCommonHeader {
     VendorID: u16,
@@ -31,70 +44,116 @@ pub struct PciCommonHeader {
 }
 
 impl PciCommonHeader {
-    pub fn tryGetEntry(
-        entry: &McfgEntry,
-        bus: u8,
-        device: u8,
-        function: u8,
-    ) -> Option<*const PciCommonHeader> {
-        let targetAddress = Self::calculateAddress(entry, bus, device, function);
+    // address is the start of a function's config space. None when nothing answers there.
+    pub fn tryGet(address: usize) -> Option<*const PciCommonHeader> {
+        let header = address as *const PciCommonHeader;
 
-        unsafe {
-            // Note if you look at this memory directly in Bochs it doesn't reflect what it actually is
-            // You have to read it
-            let data = *(targetAddress as *const u32);
-            if data == 0xFFFFFFFF {
-                return None;
-            } else {
-                return Some(targetAddress as *const PciCommonHeader);
-            }
+        // Note if you look at this memory directly in Bochs it doesn't reflect what it actually is
+        // You have to read it
+        let vendor = unsafe { read_volatile(addr_of!((*header).VendorID)) };
+        if vendor == VENDOR_NONE {
+            return None;
         }
+
+        Some(header)
     }
 
     pub fn getType(&self) -> PciHeaderType {
-        match self.HeaderType {
+        match self.HeaderType & HEADER_TYPE_MASK {
             0 => PciHeaderType::General,
             1 => PciHeaderType::NormalBridge,
             2 => PciHeaderType::CardBridge,
-            0x80 => PciHeaderType::MultiFunctionGeneral, // BUGBUG: I'm pretty sure this should be a bit flag on the other types
             _ => PciHeaderType::Dunno,
         }
     }
 
-    fn calculateAddress(entry: &McfgEntry, bus: u8, device: u8, function: u8) -> usize {
-        if device >= 32 {
-            loggerWriteLine!("Device # {} >= 32", device);
-            haltLoop();
+    // Only means anything on function 0; the others can say whatever they like
+    pub fn isMultiFunction(&self) -> bool {
+        self.HeaderType & HEADER_TYPE_MULTI_FUNCTION != 0
+    }
+
+    pub fn getVendorId(&self) -> u16 {
+        unsafe { read_volatile(addr_of!(self.VendorID)) }
+    }
+
+    pub fn getDeviceId(&self) -> u16 {
+        unsafe { read_volatile(addr_of!(self.DeviceID)) }
+    }
+
+    pub fn getRevision(&self) -> u8 {
+        unsafe { read_volatile(addr_of!(self.RevisionID)) }
+    }
+
+    // The bus right behind a bridge, as firmware numbered it. None for anything that isn't a bridge.
+    pub fn getSecondaryBus(&self) -> Option<u8> {
+        match self.getType() {
+            PciHeaderType::NormalBridge | PciHeaderType::CardBridge => {
+                let address = self as *const _ as usize + BRIDGE_SECONDARY_BUS;
+                Some(unsafe { read_volatile(address as *const u8) })
+            }
+            _ => None,
         }
+    }
 
-        if function >= 8 {
-            loggerWriteLine!("Function # {} >= 8", function);
-            haltLoop();
+    // General devices have 6, PCI bridges have 2, CardBus bridges have their windows somewhere else entirely
+    pub fn getBarCount(&self) -> usize {
+        match self.getType() {
+            PciHeaderType::General => 6,
+            PciHeaderType::NormalBridge => 2,
+            PciHeaderType::CardBridge | PciHeaderType::Dunno => 0,
         }
+    }
 
-        let base = entry.BaseAddress;
+    // Stops the device answering to its BARs and hands back the command register so restoreCommand can put it back
+    pub fn disableDecoding(&self) -> u16 {
+        let command = unsafe { read_volatile(addr_of!(self.Command)) };
+        self.updateCommand(0, COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE);
+        command
+    }
 
-        if base > usize::MAX as u64 {
-            loggerWriteLine!("    Base is too big for this platform");
-            haltLoop();
-        }
+    pub fn restoreCommand(&self, command: u16) {
+        let address = addr_of!(self.Command) as *mut u16;
+        unsafe { write_volatile(address, command) };
+    }
 
-        let bigStart = entry.StartBus as u64;
-        let bigBus = bus as u64;
-        let bigDevice = device as u64;
-        let bigFunction = function as u64;
+    pub fn hasCapabilities(&self) -> bool {
+        let status = unsafe { read_volatile(addr_of!(self.Status)) };
+        status & STATUS_CAPABILITIES_LIST != 0
+    }
 
-        let a = (bigBus - bigStart) << 20;
-        let b = bigDevice << 15;
-        let c = bigFunction << 12;
-        let d = a | b | c;
-        let e = base + d;
+    // Lets the device respond to its memory BARs and do DMA. Firmware usually does this for anything it booted
+    // from, but nothing says it has to.
+    pub fn enableBusMastering(&self) {
+        self.updateCommand(COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER, 0);
+    }
 
-        if e > usize::MAX as u64 {
-            loggerWriteLine!("    Calculated is too big for this platform");
-            haltLoop();
-        }
+    // Stops the device asserting INTx, for when it's been told to use MSI instead
+    pub fn disableLegacyInterrupts(&self) {
+        self.updateCommand(COMMAND_INTERRUPT_DISABLE, 0);
+    }
+
+    // Lets INTx back in, for when MSI couldn't be set up after all
+    pub fn enableLegacyInterrupts(&self) {
+        self.updateCommand(0, COMMAND_INTERRUPT_DISABLE);
+    }
+
+    // offset is from the start of this function's config space
+    pub fn readConfig<T>(&self, offset: usize) -> T {
+        let address = self as *const _ as usize + offset;
+        unsafe { read_volatile(address as *const T) }
+    }
 
-        return e as usize;
+    pub fn writeConfig<T>(&self, offset: usize, value: T) {
+        let address = self as *const _ as usize + offset;
+        unsafe { write_volatile(address as *mut T, value) }
+    }
+
+    fn updateCommand(&self, set: u16, clear: u16) {
+        // BUGBUG: Casting mutable again; config space is only ever reached through these pointers
+        let command = addr_of!(self.Command) as *mut u16;
+        unsafe {
+            let value = read_volatile(command);
+            write_volatile(command, (value & !clear) | set);
+        }
     }
 }
@@ -1,6 +1,4 @@
-use core::ptr::{addr_of, read_volatile};
-
-use kernel_shared::{assemblyStuff::halt::haltLoop, loggerWrite, loggerWriteLine};
+use kernel_shared::loggerWriteLine;
 
 
 use super::{
@@ -34,7 +32,7 @@ impl PciGeneralDevice {
     pub fn tryGet(commonHeader: &PciCommonHeader) -> Option<*const PciGeneralDevice> {
         let headerType = commonHeader.getType();
         match headerType {
-            PciHeaderType::General | PciHeaderType::MultiFunctionGeneral => {
+            PciHeaderType::General => {
                 return Some(commonHeader as *const _ as *const PciGeneralDevice)
             }
             _ => return None,
@@ -42,79 +40,17 @@ impl PciGeneralDevice {
     }
 
     pub fn printBars(&self) {
-        Self::printBarDetails(0, self.BAR0);
-        Self::printBarDetails(1, self.BAR1);
-        Self::printBarDetails(2, self.BAR2);
-        Self::printBarDetails(3, self.BAR3);
-        Self::printBarDetails(4, self.BAR4);
-        Self::printBarDetails(5, self.BAR5);
-    }
-
-    pub fn tryGetBarAddress(&self, barNumber: u8) -> Option<Bar> {
-        if barNumber >= 6 {
-            return None;
+        for bar in Bar::readAll(&self.PciCommonHeader).iter().flatten() {
+            loggerWriteLine!("      BAR{}: {}", bar.index, bar);
         }
+    }
 
-        let barAddress = match barNumber {
-            0 => addr_of!(self.BAR0),
-            1 => addr_of!(self.BAR1),
-            2 => addr_of!(self.BAR2),
-            3 => addr_of!(self.BAR3),
-            4 => addr_of!(self.BAR4),
-            5 => addr_of!(self.BAR5),
-            _ => return None,
-        };
-
-        let barAddress = barAddress as *mut u32;
-
-        unsafe {
-            let barValue = read_volatile(barAddress);
-            if barValue == 0 {
-                return None;
-            }
-
-            if barValue & 1 == 1 {
-                // I/O port
-                return None;
-            }
-
-            let memoryType = (barValue >> 1) & 0x3;
-            if memoryType != 0 {
-                // BUGBUG: Only supporting 32-bit for now
-                loggerWriteLine!("Don't know how to handle memory type {}", memoryType);
-                haltLoop();
-            }
-
-            let address = barValue & 0xFFFFFFF0;
-            let result = Bar::new(address, barValue, barAddress);
-
-            return Some(result);
-        }
+    // Decoded and sized. For a 64-bit BAR, ask for the first of the pair.
+    pub fn getBar(&self, index: u8) -> Option<Bar> {
+        Bar::read(&self.PciCommonHeader, index)
     }
 
-    fn printBarDetails(barNumber: u8, barValue: u32) {
-        if barValue != 0 {
-            loggerWrite!("      BAR{}: 0x{:X}", barNumber, barValue);
-            if barValue & 1 == 1 {
-                loggerWriteLine!(" I/O @ 0x{:X}", barValue & 0xFFFFFFFC);
-            } else {
-                let memoryType = (barValue >> 1) & 0x3;
-                match memoryType {
-                    0 => {
-                        loggerWriteLine!(" 32-bit memory @ 0x{:X}", barValue & 0xFFFFFFF0);
-                    }
-                    1 => {
-                        loggerWriteLine!(" (reserved-type)");
-                    }
-                    2 => {
-                        loggerWriteLine!(" 64-bit memory; dunno how to handle that...");
-                        haltLoop();
-                    }
-                    _ => {
-                        loggerWriteLine!(" ({}-type memory)", memoryType);
-                    }
-                }
-            }
-        }
+    pub fn getCommonHeader(&self) -> &PciCommonHeader {
+        &self.PciCommonHeader
     }
 }
@@ -1,116 +1,261 @@
-use core::str::from_utf8;
+use alloc::vec::Vec;
+use core::{
+    cell::RefCell,
+    mem::{offset_of, size_of},
+    ptr::{copy_nonoverlapping, read_unaligned},
+    str::from_utf8,
+};
 
-use kernel_shared::{assemblyStuff::halt::haltLoop, memoryHelpers::alignDown, pageTable::enums::*};
+use critical_section::Mutex;
+use kernel_shared::pageTable::enums::*;
 
 use crate::{loggerWriteLine, memory::virtualMemory::VirtualMemoryManager};
 
-use super::{pciGeneralDevice::PciGeneralDevice, rsdt::RSDT};
+use super::{
+    descriptionTable::{DescriptionTable, mapTable},
+    fadt::FADT,
+    rsdt::RSDT,
+    xsdt::XSDT,
+};
 
 // https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#root-system-description-pointer-rsdp-structure
-// Version 1 (Revsion 0) defintion
-// Root System Description Pointer
+// Root System Description Pointer. Revision 0 (ACPI 1.0) stops after RsdtAddress, 2 and up have the rest.
+#[derive(Clone, Copy)]
 #[repr(C, packed)]
 pub struct RSDP {
     Signature: [u8; 8],
-    Checksum: u8,
+    Checksum: u8, // First 20 bytes should sum to 0
     OEMID: [u8; 6],
     Revision: u8,
     RsdtAddress: u32,
+    Length: u32, // Of the whole thing
+    XsdtAddress: u64,
+    ExtendedChecksum: u8, // All Length bytes should sum to 0
+    Reserved: [u8; 3],
 }
 
-pub fn getRsdp(vmm: &mut VirtualMemoryManager) -> Option<*const PciGeneralDevice> {
-    // https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#finding-the-rsdp-on-ia-pc-systems
-    // Going to assume this isn't in the Extended BIOS Data Area (EBDA) and search directly in the BIOS read-only memory
+const RSDP_SIGNATURE: [u8; 8] = *b"RSD PTR ";
+const RSDP_V1_LENGTH: usize = 20;
+const RSDP_ALIGNMENT: usize = 16;
+const RSDP_XSDT_REVISION: u8 = 2;
 
-    let physicalAddress: usize = 0xE_0000;
-    let length: usize = 0x2_0000;
-    let mut virtualAddress: usize = 0xD00_0000;
-    vmm.map(
-        physicalAddress,
-        virtualAddress,
-        length,
-        Execute::Yes,
-        Present::Yes,
-        Writable::Yes,
-        Cachable::No,
-        UserSupervisor::Supervisor,
-        WriteThrough::WriteTrough,
-    );
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#finding-the-rsdp-on-ia-pc-systems
+// The first KB of the EBDA, whose real mode segment is in the BIOS Data Area, then the BIOS read-only memory
+const EBDA_SEGMENT_POINTER: usize = 0x40E;
+const EBDA_SEARCH_LENGTH: usize = 0x400;
+const BIOS_AREA: usize = 0xE_0000;
+const BIOS_AREA_LENGTH: usize = 0x2_0000;
 
-    loggerWriteLine!("Mapped BIOS");
+// Virtual address of every table the XSDT (or RSDT) lists, skipping any with a bad checksum. Found the first time
+// someone looks and mapped for good, so looking again doesn't map them again.
+static TABLES: Mutex<RefCell<Option<Vec<usize>>>> = Mutex::new(RefCell::new(None));
 
-    loop {
-        let ptr = virtualAddress as *const RSDP;
-        let checkMe = checkSignature(ptr, vmm);
-        if let Ok(xxx) = checkMe {
-            return xxx;
-        }
+// Looks up a table by its signature (e.g. b"APIC") and hands back a pointer to it, fully mapped
+pub fn findTable(
+    vmm: &mut VirtualMemoryManager,
+    signature: &[u8; 4],
+) -> Option<*const DescriptionTable> {
+    findTables(vmm, signature).first().copied()
+}
+
+// Same, but every one of them; some, like SSDT, can show up more than once
+pub fn findTables(
+    vmm: &mut VirtualMemoryManager,
+    signature: &[u8; 4],
+) -> Vec<*const DescriptionTable> {
+    getTables(vmm)
+        .into_iter()
+        .map(|table| table as *const DescriptionTable)
+        .filter(|table| unsafe { &(**table).Signature } == signature)
+        .collect()
+}
 
-        virtualAddress = virtualAddress + 16;
-        if virtualAddress >= (virtualAddress + length) {
-            loggerWriteLine!("Didn't find RSDP. Halting.");
-            haltLoop();
+// Lists every table and what a few of them say
+pub fn dumpTables(vmm: &mut VirtualMemoryManager) {
+    for table in getTables(vmm) {
+        let table = table as *const DescriptionTable;
+        unsafe {
+            (*table).printSignature();
+            if &(*table).Signature == b"FACP" {
+                (*(table as *const FADT)).printSomeInfo();
+            }
         }
     }
 }
 
-fn checkSignature(ptr: *const RSDP, vmm: &mut VirtualMemoryManager) -> Result<Option<*const PciGeneralDevice>, u8> {
-    let expected = *b"RSD PTR ";
-    unsafe {
-        let toCheck = (*ptr).Signature;
+fn getTables(vmm: &mut VirtualMemoryManager) -> Vec<usize> {
+    if let Some(tables) = critical_section::with(|cs| TABLES.borrow_ref(cs).clone()) {
+        return tables;
+    }
 
-        if toCheck == expected {
-            loggerWriteLine!("Potential ACPI info at: 0x{:X}", ptr as usize);
+    let tables = loadTables(vmm);
+    critical_section::with(|cs| *TABLES.borrow_ref_mut(cs) = Some(tables.clone()));
+    tables
+}
 
-            let mut calculated: u8 = 0;
-            let asBytes = ptr as *const u8;
-            for index in 0..20 {
-                let byte = *asBytes.offset(index);
-                calculated = calculated.wrapping_add(byte);
-            }
+fn loadTables(vmm: &mut VirtualMemoryManager) -> Vec<usize> {
+    let Some(rsdp) = findRsdp(vmm) else {
+        loggerWriteLine!("Didn't find the RSDP, so no ACPI");
+        return Vec::new();
+    };
 
-            if calculated != 0 {
-                loggerWriteLine!("Checksum fail (should be 0): {calculated}");
-                return Err(1);
-            }
-
-            match from_utf8(&(*ptr).OEMID) {
-                Ok(theString) => {
-                    loggerWriteLine!("ACPI by {}", theString);
-
-                    // Spec says this is always a 32 bit address
-                    loggerWriteLine!("RSDT is at 0x{:X}", (*ptr).RsdtAddress as u32);
-                }
-                _ => {
-                    loggerWriteLine!("Couldn't read ACPI OEM: {:?}", (*ptr).OEMID);
-                }
-            };
-
-            // BUGBUG: Delete after debugging
-            let rsdt :usize= (*ptr).RsdtAddress.try_into().unwrap();
-            let aligned = alignDown(rsdt.try_into().unwrap(), 0x1000);
-            let diff = rsdt - aligned;
-
-            let length: usize = 0x1_0000;
-            let virtualAddress = vmm.mapPhysicalAnywhere(
-                aligned,
-                length,
-                Execute::Yes,
-                Present::Yes,
-                Writable::Yes,
-                Cachable::No,
-                UserSupervisor::Supervisor,
-                WriteThrough::WriteTrough,
+    let mut result = Vec::new();
+    for (index, physicalAddress) in getRootEntries(vmm, &rsdp).into_iter().enumerate() {
+        let table = mapTable(vmm, physicalAddress);
+        if unsafe { (*table).isChecksumValid() } {
+            result.push(table as usize);
+        } else {
+            let signature = unsafe { (*table).Signature };
+            loggerWriteLine!(
+                "Entry {} @ 0x{:X} ({}) has a bad checksum, ignoring it",
+                index,
+                physicalAddress,
+                from_utf8(&signature).unwrap_or("????")
             );
+        }
+    }
+
+    loggerWriteLine!("{} ACPI table(s)", result.len());
+    result
+}
 
-            let rsdt = (virtualAddress + diff) as *const RSDT;
+// Where every table is, from the XSDT when there is one. 64-bit firmware can put tables above 4GB and the RSDT has
+// no way to point at them.
+fn getRootEntries(vmm: &mut VirtualMemoryManager, rsdp: &RSDP) -> Vec<usize> {
+    let xsdtAddress = rsdp.XsdtAddress;
+    if rsdp.Revision >= RSDP_XSDT_REVISION && xsdtAddress != 0 {
+        loggerWriteLine!("XSDT is at 0x{:X}", xsdtAddress);
+        let xsdt = mapTable(vmm, xsdtAddress as usize);
+        if isRootValid(xsdt, b"XSDT") {
+            return unsafe { (*(xsdt as *const XSDT)).getEntries() };
+        }
 
+        loggerWriteLine!("XSDT is no good, trying the RSDT");
+    }
 
+    // Spec says this is always a 32 bit address
+    let rsdtAddress = rsdp.RsdtAddress;
+    loggerWriteLine!("RSDT is at 0x{:X}", rsdtAddress);
+    let rsdt = mapTable(vmm, rsdtAddress as usize);
+    if isRootValid(rsdt, b"RSDT") {
+        return unsafe { (*(rsdt as *const RSDT)).getEntries() };
+    }
 
-            let result = (*rsdt).walkEntries();
-            return Ok(result);
+    loggerWriteLine!("RSDT is no good either");
+    Vec::new()
+}
+
+fn isRootValid(table: *const DescriptionTable, signature: &[u8; 4]) -> bool {
+    unsafe { &(*table).Signature == signature && (*table).isChecksumValid() }
+}
+
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#finding-the-rsdp-on-ia-pc-systems
+fn findRsdp(vmm: &mut VirtualMemoryManager) -> Option<RSDP> {
+    let pointer = mapLow(vmm, EBDA_SEGMENT_POINTER, size_of::<u16>());
+    let segment = unsafe { read_unaligned(pointer as *const u16) };
+    vmm.unmapPhysicalAnywhere(pointer, size_of::<u16>());
+
+    // Some firmware doesn't have one and leaves 0 here
+    let ebda = (segment as usize) << 4;
+    if ebda != 0 {
+        if let Some(rsdp) = searchArea(vmm, ebda, EBDA_SEARCH_LENGTH) {
+            return Some(rsdp);
         }
     }
 
-    return Err(2);
+    searchArea(vmm, BIOS_AREA, BIOS_AREA_LENGTH)
+}
+
+fn searchArea(
+    vmm: &mut VirtualMemoryManager,
+    physicalAddress: usize,
+    length: usize,
+) -> Option<RSDP> {
+    let area = mapLow(vmm, physicalAddress, length);
+    let result = (0..length)
+        .step_by(RSDP_ALIGNMENT)
+        .find_map(|offset| checkRsdp(area + offset, length - offset, physicalAddress + offset));
+
+    vmm.unmapPhysicalAnywhere(area, length);
+    result
+}
+
+// available is how much of the mapping is left from address on, so a revision 2 one right at the end can't have us
+// reading off it
+fn checkRsdp(address: usize, available: usize, physicalAddress: usize) -> Option<RSDP> {
+    if available < RSDP_V1_LENGTH {
+        return None;
+    }
+
+    let signature = unsafe { read_unaligned(address as *const [u8; 8]) };
+    if signature != RSDP_SIGNATURE {
+        return None;
+    }
+
+    loggerWriteLine!("Potential ACPI info at: 0x{:X}", physicalAddress);
+    let calculated = checksum(address, RSDP_V1_LENGTH);
+    if calculated != 0 {
+        loggerWriteLine!("Checksum fail (should be 0): {calculated}");
+        return None;
+    }
+
+    let revision = unsafe { *((address + offset_of!(RSDP, Revision)) as *const u8) };
+    let rsdp = if revision < RSDP_XSDT_REVISION {
+        // Nothing past RsdtAddress is there, so don't pretend it is
+        let mut bytes = [0u8; size_of::<RSDP>()];
+        unsafe { copy_nonoverlapping(address as *const u8, bytes.as_mut_ptr(), RSDP_V1_LENGTH) };
+
+        unsafe { read_unaligned(bytes.as_ptr() as *const RSDP) }
+    } else {
+        if available < size_of::<RSDP>() {
+            return None;
+        }
+
+        let rsdp = unsafe { read_unaligned(address as *const RSDP) };
+        let length = rsdp.Length as usize;
+        if length < size_of::<RSDP>() || length > available {
+            loggerWriteLine!("RSDP length of {} doesn't make sense", length);
+            return None;
+        }
+
+        let calculated = checksum(address, length);
+        if calculated != 0 {
+            loggerWriteLine!("Extended checksum fail (should be 0): {calculated}");
+            return None;
+        }
+
+        rsdp
+    };
+
+    match from_utf8(&rsdp.OEMID) {
+        Ok(theString) => {
+            loggerWriteLine!("ACPI revision {} by {}", revision, theString);
+        }
+        _ => {
+            let oemId = rsdp.OEMID;
+            loggerWriteLine!("Couldn't read ACPI OEM: {:?}", oemId);
+        }
+    };
+
+    Some(rsdp)
+}
+
+fn checksum(address: usize, length: usize) -> u8 {
+    (0..length).fold(0u8, |sum, index| {
+        sum.wrapping_add(unsafe { *((address + index) as *const u8) })
+    })
+}
+
+// The real mode stuff below 1MB; read only, since we're only looking
+fn mapLow(vmm: &mut VirtualMemoryManager, physicalAddress: usize, length: usize) -> usize {
+    vmm.mapPhysicalAnywhere(
+        physicalAddress,
+        length,
+        Execute::Yes,
+        Present::Yes,
+        Writable::No,
+        Cachable::No,
+        UserSupervisor::Supervisor,
+        WriteThrough::WriteTrough,
+    )
 }
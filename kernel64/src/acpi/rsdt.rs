@@ -1,11 +1,10 @@
-use kernel_shared::{assemblyStuff::halt::haltLoop, loggerWrite, loggerWriteLine};
-
-use crate::{
-    acpi::{descriptionTable::DescriptionTable, fadt::FADT, mcfg::MCFG}
+use alloc::vec::Vec;
+use core::{
+    mem::size_of,
+    ptr::{addr_of, read_unaligned},
 };
-use core::{mem::size_of, ptr::{addr_of, read_unaligned}};
 
-use super::pciGeneralDevice::PciGeneralDevice;
+use super::descriptionTable::DescriptionTable;
 
 // https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#root-system-description-table-rsdt
 // Root System Description Table
@@ -24,52 +23,14 @@ pub struct RSDT {
 }
 
 impl RSDT {
-    pub fn walkEntries(&self)-> Option<*const PciGeneralDevice> {
+    // Physical address of every table it points at
+    pub fn getEntries(&self) -> Vec<usize> {
         let length = self.Length as usize;
-        let extraLength = length - size_of::<RSDT>();
-        let remainder = extraLength % 4;
-        if remainder != 0 {
-            loggerWriteLine!("Remaining space is not a multiple of 4");
-            haltLoop();
-        }
-
-        let firstEntryAddress = addr_of!(self.FirstEntry);
-        let mut totalEntries = extraLength / 4;
-
-        // You get one entry for free in the size of the struct
-        totalEntries = totalEntries + 1;
-        loggerWriteLine!(
-            "Length of {} implies there's {} toal entries, pointer to first is: 0x{:X}",
-            length,
-            totalEntries,
-            firstEntryAddress as usize,
-        );
-
-        let mut result = None;
-
-        for x in 0..totalEntries {
-            let address = firstEntryAddress as usize + x * size_of::<u32>();
-            
-            unsafe {
-                let ptr = address as *const u32;
-                let dtAddress = read_unaligned(ptr);
-                loggerWrite!("Entry {} @ 0x{:X} is a ", x, dtAddress);
-                let ptr = dtAddress as *const DescriptionTable;
-                (*ptr).printSignature();
-
-                if &(*ptr).Signature == b"FACP" {
-                    let ptr = ptr as *const FADT;
-                    (*ptr).printSomeInfo();
-                } else if &(*ptr).Signature == b"MCFG" {
-                    let ptr = ptr as *const MCFG;
-                    let maybeResult = (*ptr).printSomeInfo();
-                    if result == None && maybeResult != None {
-                        result = maybeResult;
-                    }
-                }
-            }
-        }
+        let count = length.saturating_sub(size_of::<DescriptionTable>()) / size_of::<u32>();
+        let first = addr_of!(self.FirstEntry) as *const u32;
 
-        return  result;
+        (0..count)
+            .map(|index| unsafe { read_unaligned(first.add(index)) } as usize)
+            .collect()
     }
 }
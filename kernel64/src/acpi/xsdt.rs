@@ -0,0 +1,36 @@
+use alloc::vec::Vec;
+use core::{
+    mem::size_of,
+    ptr::{addr_of, read_unaligned},
+};
+
+use super::descriptionTable::DescriptionTable;
+
+// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#extended-system-description-table-xsdt
+// Extended System Description Table. The RSDT with 64-bit pointers, which are only 4 byte aligned.
+#[repr(C, packed)]
+pub struct XSDT {
+    Signature: [u8; 4],
+    Length: u32,
+    Revision: u8,
+    Checksum: u8,
+    OEMID: [u8; 6],
+    OemTableID: [u8; 8],
+    OemRevision: [u8; 4],
+    CreateID: [u8; 4],
+    CreatorRevision: [u8; 4],
+    FirstEntry: u64,
+}
+
+impl XSDT {
+    // Physical address of every table it points at
+    pub fn getEntries(&self) -> Vec<usize> {
+        let length = self.Length as usize;
+        let count = length.saturating_sub(size_of::<DescriptionTable>()) / size_of::<u64>();
+        let first = addr_of!(self.FirstEntry) as *const u64;
+
+        (0..count)
+            .map(|index| unsafe { read_unaligned(first.add(index)) } as usize)
+            .collect()
+    }
+}
@@ -1,15 +1,23 @@
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+
 use kernel_shared::assemblyStuff::halt::haltLoop;
 
 use crate::{
-    acpi::{bar::Bar, pciGeneralDevice::PciGeneralDevice}, loggerWriteLine
+    loggerWriteLine,
+    pci::driver::{BoundDevice, MappedBar},
 };
 
 // AHCI Base Address Register
 pub struct ABar {
-    _Bar: Bar,
+    pub Bar: MappedBar,
     pub HBA: *const HbaData,
 }
 
+// HBA points at registers that are mapped for good and only ever touched with volatile accesses, so it's fine to
+// share across whoever ends up holding a drive
+unsafe impl Send for ABar {}
+unsafe impl Sync for ABar {}
+
 // Host Bus Adaptor
 // 3 HBA Memory Registers
 #[repr(C, packed)]
@@ -92,32 +100,76 @@ pub struct PortRegister {
     Reserved2: [u8; 0x28],
     pub VS: u32, // Vendor Specific
 }
+impl GenericHostControl {
+    // CAP.NCS is 0's based
+    pub fn getCommandSlots(&self) -> u8 {
+        let cap = unsafe { read_volatile(addr_of!(self.Cap)) };
+        (((cap >> 8) & 0x1F) + 1) as u8
+    }
+
+    // CAP.S64A, whether anything the controller reads or writes can be above 4G
+    pub fn supports64BitAddresses(&self) -> bool {
+        let cap = unsafe { read_volatile(addr_of!(self.Cap)) };
+        cap & (1 << 31) != 0
+    }
+
+    // CAP.SNCQ, whether the HBA knows about SACT and FPDMA QUEUED commands
+    pub fn supportsNcq(&self) -> bool {
+        let cap = unsafe { read_volatile(addr_of!(self.Cap)) };
+        cap & (1 << 30) != 0
+    }
+
+    // GHC.IE; nothing gets through from any port without it
+    pub fn enableInterrupts(&mut self) {
+        unsafe {
+            let ghc = read_volatile(addr_of!(self.Ghc));
+            write_volatile(addr_of_mut!(self.Ghc), ghc | (1 << 1));
+        }
+    }
+
+    // IS has a bit per port that's pending, write 1 to clear. It has to go after the port's own PxIS is cleared or
+    // it comes straight back.
+    pub fn clearPortInterrupt(&mut self, port: u8) {
+        unsafe { write_volatile(addr_of_mut!(self.IS), 1 << port) }
+    }
+}
+
 impl PortRegister {
-    pub fn setClb(&mut self, address: u32) {
+    pub fn setClb(&mut self, address: usize) {
         if address & 0b11_1111_1111 != 0 {
             loggerWriteLine!("0x{:X} is not 1K-byte aligned", address);
             haltLoop();
         }
 
-        self.CLB = address;
+        self.CLB = address as u32;
+        self.CLBU = (address >> 32) as u32;
+    }
 
-        // In 32-bit space
-        self.CLBU = 0;
+    pub fn setFb(&mut self, address: usize) {
+        if address & 0b1111_1111 != 0 {
+            loggerWriteLine!("0x{:X} is not 256-byte aligned", address);
+            haltLoop();
+        }
+
+        self.FB = address as u32;
+        self.FBU = (address >> 32) as u32;
     }
 }
 
 impl ABar {
-    pub fn tryGet(device: &PciGeneralDevice) -> Option<ABar> {
-        // Docs say BAR 5 is always the one we need
-        if let Some(bar) = device.tryGetBarAddress(5) {
-            let addr = bar.BarTarget as *const HbaData;
-            loggerWriteLine!("Got BAR 5 @ 0x{:X}", addr as usize);
-            return Some(ABar {
-                _Bar: bar,
-                HBA: addr as *const HbaData,
-            });
-        }
+    pub fn tryGet(device: &BoundDevice) -> Option<ABar> {
+        // Docs say BAR 5 is always the one we need. BUGBUG: QEMU's BAR is only 0x1000, but all 32 ports would run past
+        // that; we just never touch ports that PI says aren't there.
+        let bar = device.getBar(5)?;
+        loggerWriteLine!(
+            "Got BAR 5 @ 0x{:X} / 0x{:X} (P/V)",
+            bar.physical,
+            bar.address
+        );
 
-        return None;
+        Some(ABar {
+            Bar: bar,
+            HBA: bar.address as *const HbaData,
+        })
     }
 }
@@ -1,52 +1,101 @@
 use kernel_shared::assemblyStuff::halt::haltLoop;
 
 use crate::{
-    acpi::pciGeneralDevice::PciGeneralDevice, loggerWriteLine
+    interupts::InteruptDescriptorTable::InterruptHandler,
+    loggerWriteLine,
+    pci::{driver::BoundDevice, msi},
 };
 use core::ptr::{addr_of, read_volatile};
 
-use super::{abar::{ABar, PortRegister}, sataDrive::SataDrive};
+use super::abar::{ABar, HbaData, PortRegister};
 
 pub struct Controller {
     ABar: ABar,
+    Interrupts: bool, // Ports can rely on an interrupt when something finishes, otherwise they poll
 }
 
 impl Controller {
-    pub fn tryGet(header: *const PciGeneralDevice) -> Option<SataDrive> {
-        unsafe {
-            if let Some(abar) = ABar::tryGet(&*header) {
-                let controller = Controller { ABar: abar };
-                if let Some(port) = controller.enumeratePorts() {
-                    let drive = SataDrive::new(controller, port);
-
-                    return Some(drive);
-                } else {
-                    loggerWriteLine!("Didn't find a SATA port");
-                    return None;
-                }
-            } else {
-                loggerWriteLine!("ABar returned None");
-                return None;
-            }
+    pub fn tryGet(device: &BoundDevice) -> Option<Controller> {
+        if let Some(abar) = ABar::tryGet(device) {
+            device.device.getCommonHeader().enableBusMastering();
+            return Some(Controller {
+                ABar: abar,
+                Interrupts: false,
+            });
+        } else {
+            loggerWriteLine!("ABar returned None");
+            return None;
         }
     }
 
-    pub fn enumeratePorts(&self) -> Option<u8> {
+    // Physical address of the registers, good enough to tell controllers apart
+    pub fn getAddress(&self) -> u64 {
+        self.ABar.Bar.physical
+    }
+
+    // Bit N is set when port N has a SATA drive we can use
+    pub fn enumeratePorts(&self) -> u32 {
+        let mut result = 0;
         unsafe {
-            let hba = read_volatile(self.ABar.HBA);
-            let mut pi = hba.GHC.PI;
+            let pi = read_volatile(addr_of!((*self.ABar.HBA).GHC.PI));
             for index in 0..32 {
-                if (pi & 1) != 0 {
+                if (pi & (1 << index)) != 0 {
                     loggerWriteLine!("Something at port {index}");
                     if self.isSATA(index) == Some(true) {
-                        return Some(index);
+                        result |= 1 << index;
                     }
-                    pi >>= 1;
                 }
             }
         }
 
-        return None;
+        return result;
+    }
+
+    // Has the controller's MSI call handler on this processor. Only works once the local APIC is up; before that,
+    // or when the device can't do it, every port just polls.
+    // BUGBUG: No INTx fallback; that needs the _PRT out of the DSDT to know where the pin ends up
+    pub fn enableInterrupts(&mut self, device: &BoundDevice, handler: InterruptHandler) {
+        // Every port shares the one message, AHCI only asks for more so it can tell ports apart
+        let interrupts = match msi::enableInterrupts(device, 1, handler) {
+            Ok(interrupts) => interrupts,
+            Err(reason) => {
+                loggerWriteLine!("AHCI will poll: {}", reason);
+                return;
+            }
+        };
+
+        unsafe { (*(self.ABar.HBA as *mut HbaData)).GHC.enableInterrupts() };
+        self.Interrupts = true;
+        loggerWriteLine!(
+            "AHCI @ 0x{:X} interrupts on vector 0x{:X} via {:?}",
+            self.getAddress(),
+            interrupts.vectors[0],
+            interrupts.kind
+        );
+    }
+
+    pub fn usesInterrupts(&self) -> bool {
+        self.Interrupts
+    }
+
+    pub fn clearPortInterrupt(&self, port: u8) {
+        unsafe {
+            (*(self.ABar.HBA as *mut HbaData))
+                .GHC
+                .clearPortInterrupt(port)
+        }
+    }
+
+    pub fn supportsNcq(&self) -> bool {
+        unsafe { (*self.ABar.HBA).GHC.supportsNcq() }
+    }
+
+    pub fn getCommandSlots(&self) -> u8 {
+        unsafe { (*self.ABar.HBA).GHC.getCommandSlots() }
+    }
+
+    pub fn supports64BitAddresses(&self) -> bool {
+        unsafe { (*self.ABar.HBA).GHC.supports64BitAddresses() }
     }
 
     pub fn getPort(&self, index: u8) -> *mut PortRegister {
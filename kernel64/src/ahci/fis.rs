@@ -0,0 +1,95 @@
+// Serial ATA Revision 3.0, 10.3 FIS Types
+// https://wiki.osdev.org/AHCI#SATA_basic
+
+pub const FIS_TYPE_REGISTER_H2D: u8 = 0x27;
+
+// ATA8-ACS 7.22 / 7.63 / 7.11
+pub const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
+pub const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35;
+pub const ATA_CMD_FLUSH_CACHE_EXT: u8 = 0xEA;
+
+// SATA 3.0 13.6.4 / 13.6.5, Native Command Queuing
+pub const ATA_CMD_READ_FPDMA_QUEUED: u8 = 0x60;
+pub const ATA_CMD_WRITE_FPDMA_QUEUED: u8 = 0x61;
+
+// Bit 7 of byte 1; set means this is a new command rather than a Device Control register update
+const REGISTER_H2D_COMMAND: u8 = 1 << 7;
+
+// Bit 6 of Device; LBA addressing instead of CHS
+const DEVICE_LBA: u8 = 1 << 6;
+
+// 10.3.4 Register - Host to Device FIS
+#[repr(C, packed)]
+pub struct FisRegisterH2D {
+    FisType: u8,
+    PortMultiplierAndC: u8, // Port multiplier port in 3:0, C in 7
+    Command: u8,
+    FeatureLow: u8,
+    Lba0: u8,
+    Lba1: u8,
+    Lba2: u8,
+    Device: u8,
+    Lba3: u8,
+    Lba4: u8,
+    Lba5: u8,
+    FeatureHigh: u8,
+    CountLow: u8,
+    CountHigh: u8,
+    Icc: u8, // Isochronous Command Completion
+    Control: u8,
+    Reserved: [u8; 4],
+}
+
+impl FisRegisterH2D {
+    // Something like IDENTIFY or FLUSH that doesn't take an LBA or count
+    pub fn new(command: u8) -> FisRegisterH2D {
+        let mut result = Self::newCommand(command, 0, 0);
+        result.Device = 0;
+        result
+    }
+
+    // A 48-bit LBA command. count is in sectors where 0 means 65536.
+    pub fn newCommand(command: u8, lba: u64, count: u16) -> FisRegisterH2D {
+        FisRegisterH2D {
+            FisType: FIS_TYPE_REGISTER_H2D,
+            PortMultiplierAndC: REGISTER_H2D_COMMAND,
+            Command: command,
+            FeatureLow: 0,
+            Lba0: lba as u8,
+            Lba1: (lba >> 8) as u8,
+            Lba2: (lba >> 16) as u8,
+            Device: DEVICE_LBA,
+            Lba3: (lba >> 24) as u8,
+            Lba4: (lba >> 32) as u8,
+            Lba5: (lba >> 40) as u8,
+            FeatureHigh: 0,
+            CountLow: count as u8,
+            CountHigh: (count >> 8) as u8,
+            Icc: 0,
+            Control: 0,
+            Reserved: [0; 4],
+        }
+    }
+
+    // An NCQ command. These move the sector count into Feature so Count can carry the tag, see setTag.
+    pub fn newQueued(command: u8, lba: u64, count: u16) -> FisRegisterH2D {
+        let mut result = Self::newCommand(command, lba, 0);
+        result.FeatureLow = count as u8;
+        result.FeatureHigh = (count >> 8) as u8;
+        result
+    }
+
+    // NCQ tag in Count 7:3. It has to match the command slot the FIS is issued in.
+    pub fn setTag(&mut self, tag: u8) {
+        self.CountLow = (tag & 0x1F) << 3;
+    }
+
+    pub fn getCommand(&self) -> u8 {
+        self.Command
+    }
+
+    // Command FIS Length (CFL) in the command header is in dwords
+    pub fn getLengthInDwords() -> u32 {
+        (size_of::<FisRegisterH2D>() / size_of::<u32>()) as u32
+    }
+}
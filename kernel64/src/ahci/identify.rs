@@ -0,0 +1,162 @@
+use core::str::from_utf8;
+
+use crate::loggerWriteLine;
+
+// ATA8-ACS 7.16 IDENTIFY DEVICE - ECh, PIO Data-In
+// The device hands back 256 words; these are the ones we care about.
+pub const ATA_CMD_IDENTIFY_DEVICE: u8 = 0xEC;
+pub const IDENTIFY_LENGTH: usize = 512;
+
+const WORD_SERIAL: usize = 10; // 10 words
+const WORD_FIRMWARE: usize = 23; // 4 words
+const WORD_MODEL: usize = 27; // 20 words
+const WORD_CAPABILITIES: usize = 49;
+const WORD_LBA28_SECTORS: usize = 60; // 2 words
+const WORD_QUEUE_DEPTH: usize = 75;
+const WORD_SATA_CAPABILITIES: usize = 76;
+const WORD_COMMAND_SET_SUPPORTED: usize = 82; // 82 and 83
+const WORD_COMMAND_SET_ENABLED: usize = 85;
+const WORD_LBA48_SECTORS: usize = 100; // 4 words
+const WORD_SECTOR_SIZE: usize = 106;
+const WORD_LOGICAL_SECTOR_SIZE: usize = 117; // 2 words, in words
+const WORD_DATA_SET_MANAGEMENT: usize = 169;
+
+const CAPABILITIES_LBA: u16 = 1 << 9;
+const SATA_CAPABILITIES_NCQ: u16 = 1 << 8;
+const COMMAND_SET_WRITE_CACHE: u16 = 1 << 5; // Word 82 and 85
+const COMMAND_SET_LBA48: u16 = 1 << 10; // Word 83
+const COMMAND_SET_FLUSH_CACHE_EXT: u16 = 1 << 13; // Word 83
+const DATA_SET_MANAGEMENT_TRIM: u16 = 1 << 0;
+
+// Word 106 is only meaningful when bit 14 is set and 15 is clear
+const SECTOR_SIZE_VALID_MASK: u16 = 0b11 << 14;
+const SECTOR_SIZE_VALID: u16 = 0b01 << 14;
+const SECTOR_SIZE_MULTIPLE_PER_PHYSICAL: u16 = 1 << 13;
+const SECTOR_SIZE_LONG_LOGICAL: u16 = 1 << 12;
+
+// Everything worth knowing about a drive, copied out of the raw IDENTIFY data
+#[derive(Clone, Copy)]
+pub struct IdentifyData {
+    model: [u8; 40],
+    serial: [u8; 20],
+    firmware: [u8; 8],
+    pub sectorCount: u64, // Logical sectors reachable with the largest LBA mode supported
+    pub logicalSectorSize: usize,
+    pub physicalSectorSize: usize,
+    pub supportsLba: bool,
+    pub supportsLba48: bool,
+    pub supportsNcq: bool,
+    pub queueDepth: u8, // Only meaningful with NCQ
+    pub supportsTrim: bool,
+    pub supportsWriteCache: bool,
+    pub writeCacheEnabled: bool,
+    pub supportsFlushCacheExt: bool,
+}
+
+impl IdentifyData {
+    pub fn parse(raw: &[u16; IDENTIFY_LENGTH / 2]) -> IdentifyData {
+        let capabilities = raw[WORD_CAPABILITIES];
+        let supported82 = raw[WORD_COMMAND_SET_SUPPORTED];
+        let supported83 = raw[WORD_COMMAND_SET_SUPPORTED + 1];
+        let supportsLba48 = supported83 & COMMAND_SET_LBA48 != 0;
+
+        let sectorCount = if supportsLba48 {
+            (0..4).fold(0u64, |total, index| {
+                total | (raw[WORD_LBA48_SECTORS + index] as u64) << (16 * index)
+            })
+        } else {
+            raw[WORD_LBA28_SECTORS] as u64 | (raw[WORD_LBA28_SECTORS + 1] as u64) << 16
+        };
+
+        let mut logicalSectorSize = 512;
+        let mut physicalSectorSize = 512;
+        let sectorSize = raw[WORD_SECTOR_SIZE];
+        if sectorSize & SECTOR_SIZE_VALID_MASK == SECTOR_SIZE_VALID {
+            if sectorSize & SECTOR_SIZE_LONG_LOGICAL != 0 {
+                let words = raw[WORD_LOGICAL_SECTOR_SIZE] as usize
+                    | (raw[WORD_LOGICAL_SECTOR_SIZE + 1] as usize) << 16;
+                logicalSectorSize = words * 2;
+            }
+
+            physicalSectorSize = logicalSectorSize;
+            if sectorSize & SECTOR_SIZE_MULTIPLE_PER_PHYSICAL != 0 {
+                physicalSectorSize <<= sectorSize & 0xF;
+            }
+        }
+
+        IdentifyData {
+            model: getString(raw, WORD_MODEL),
+            serial: getString(raw, WORD_SERIAL),
+            firmware: getString(raw, WORD_FIRMWARE),
+            sectorCount,
+            logicalSectorSize,
+            physicalSectorSize,
+            supportsLba: capabilities & CAPABILITIES_LBA != 0,
+            supportsLba48,
+            supportsNcq: raw[WORD_SATA_CAPABILITIES] & SATA_CAPABILITIES_NCQ != 0,
+            queueDepth: (raw[WORD_QUEUE_DEPTH] & 0x1F) as u8 + 1,
+            supportsTrim: raw[WORD_DATA_SET_MANAGEMENT] & DATA_SET_MANAGEMENT_TRIM != 0,
+            supportsWriteCache: supported82 & COMMAND_SET_WRITE_CACHE != 0,
+            writeCacheEnabled: raw[WORD_COMMAND_SET_ENABLED] & COMMAND_SET_WRITE_CACHE != 0,
+            supportsFlushCacheExt: supported83 & COMMAND_SET_FLUSH_CACHE_EXT != 0,
+        }
+    }
+
+    pub fn getModel(&self) -> &str {
+        trimString(&self.model)
+    }
+
+    pub fn getSerial(&self) -> &str {
+        trimString(&self.serial)
+    }
+
+    pub fn getFirmware(&self) -> &str {
+        trimString(&self.firmware)
+    }
+
+    pub fn getCapacity(&self) -> u64 {
+        self.sectorCount * self.logicalSectorSize as u64
+    }
+
+    pub fn dump(&self) {
+        loggerWriteLine!(
+            "    {} serial {} firmware {}",
+            self.getModel(),
+            self.getSerial(),
+            self.getFirmware()
+        );
+        loggerWriteLine!(
+            "    {} sectors of {} bytes ({} physical), {} MiB",
+            self.sectorCount,
+            self.logicalSectorSize,
+            self.physicalSectorSize,
+            self.getCapacity() / (1024 * 1024)
+        );
+        loggerWriteLine!(
+            "    LBA48: {} NCQ: {} (depth {}) TRIM: {} Write cache: {} (enabled: {}) Flush: {}",
+            self.supportsLba48,
+            self.supportsNcq,
+            self.queueDepth,
+            self.supportsTrim,
+            self.supportsWriteCache,
+            self.writeCacheEnabled,
+            self.supportsFlushCacheExt
+        );
+    }
+}
+
+// ATA strings are space padded with the two bytes in every word swapped
+fn getString<const N: usize>(raw: &[u16; IDENTIFY_LENGTH / 2], firstWord: usize) -> [u8; N] {
+    let mut result = [b' '; N];
+    for index in 0..N / 2 {
+        let word = raw[firstWord + index];
+        result[index * 2] = (word >> 8) as u8;
+        result[index * 2 + 1] = word as u8;
+    }
+
+    result
+}
+
+fn trimString(bytes: &[u8]) -> &str {
+    from_utf8(bytes).unwrap_or("???").trim()
+}
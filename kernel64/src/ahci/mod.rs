@@ -3,4 +3,7 @@
 
 pub mod abar;
 pub mod controller;
+pub mod fis;
+pub mod identify;
+pub mod sataBlockDevice;
 pub mod sataDrive;
@@ -0,0 +1,112 @@
+use core::ptr::copy_nonoverlapping;
+
+use kernel_shared::{
+    disk::blockDevice::{BlockDevice, checkRequest},
+    magicConstants::SIZE_OF_PAGE,
+};
+
+use crate::memory::virtualMemory::VirtualMemoryManager;
+
+use super::sataDrive::SataDrive;
+
+// Most that goes through the bounce buffer in one command
+const BOUNCE_PAGES: usize = 16;
+
+// A SataDrive as a BlockDevice. Everything goes through a bounce buffer that's physically contiguous with a known
+// address, so unlike SataDrive::read nothing needs the vmm after new. That's what lets a filesystem hang on to one.
+pub struct SataBlockDevice {
+    drive: &'static SataDrive,
+    bounceVirtual: usize,
+    bouncePhysical: usize,
+    bounceSectors: usize,
+}
+
+impl SataBlockDevice {
+    pub fn new(
+        vmm: &mut VirtualMemoryManager,
+        drive: &'static SataDrive,
+    ) -> Result<SataBlockDevice, &'static str> {
+        let bounceSectors = BOUNCE_PAGES * SIZE_OF_PAGE / drive.getSectorSize();
+        if bounceSectors == 0 {
+            return Err("Sectors are bigger than the bounce buffer");
+        }
+
+        let bounce = vmm.allocateDeviceMemory("SATA bounce buffer", BOUNCE_PAGES);
+        Ok(SataBlockDevice {
+            drive,
+            bounceVirtual: bounce.r#virtual.address,
+            bouncePhysical: bounce.physical.address,
+            bounceSectors,
+        })
+    }
+
+    // Gives the bounce buffer back. Whatever was on top should have flushed already.
+    pub fn free(self, vmm: &mut VirtualMemoryManager) {
+        vmm.freePages(self.bounceVirtual, BOUNCE_PAGES);
+    }
+
+    pub fn getDrive(&self) -> &'static SataDrive {
+        self.drive
+    }
+}
+
+impl BlockDevice for SataBlockDevice {
+    fn getSectorSize(&self) -> usize {
+        self.drive.getSectorSize()
+    }
+
+    fn getSectorCount(&self) -> u64 {
+        self.drive.getSectorCount()
+    }
+
+    fn read(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+        checkRequest(self, lba, buffer.len())?;
+
+        let sectorSize = self.getSectorSize();
+        let mut lba = lba;
+        for chunk in buffer.chunks_mut(self.bounceSectors * sectorSize) {
+            let sectors = chunk.len() / sectorSize;
+            self.drive.readPhysical(lba, sectors, self.bouncePhysical)?;
+            unsafe {
+                copy_nonoverlapping(
+                    self.bounceVirtual as *const u8,
+                    chunk.as_mut_ptr(),
+                    chunk.len(),
+                );
+            }
+
+            lba += sectors as u64;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, lba: u64, buffer: &[u8]) -> Result<(), &'static str> {
+        checkRequest(self, lba, buffer.len())?;
+
+        let sectorSize = self.getSectorSize();
+        let mut lba = lba;
+        for chunk in buffer.chunks(self.bounceSectors * sectorSize) {
+            let sectors = chunk.len() / sectorSize;
+            unsafe {
+                copy_nonoverlapping(chunk.as_ptr(), self.bounceVirtual as *mut u8, chunk.len());
+            }
+
+            self.drive
+                .writePhysical(lba, sectors, self.bouncePhysical)?;
+            lba += sectors as u64;
+        }
+
+        Ok(())
+    }
+
+    // A drive with no write cache to flush is already done
+    fn flush(&mut self) -> Result<(), &'static str> {
+        match self.drive.getIdentity() {
+            Some(identity) if !identity.supportsFlushCacheExt && !identity.writeCacheEnabled => {
+                Ok(())
+            }
+            _ => self.drive.flush(),
+        }
+    }
+}
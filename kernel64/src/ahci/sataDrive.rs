@@ -1,16 +1,70 @@
-use kernel_shared::{assemblyStuff::halt::haltLoop, magicConstants::{SATA_DRIVE_BASE_CMD_BASE_ADDRESS, SATA_DRIVE_BASE_COMMAND_TABLE_BASE_ADDRESS, SATA_DRIVE_BASE_FIS_BASE_ADDRESS}, memoryHelpers::zeroMemory2};
+use kernel_shared::{
+    assemblyStuff::halt::haltLoop, haltLoopWithMessage, magicConstants::SIZE_OF_PAGE,
+    memoryHelpers::zeroMemory2,
+};
 
-use crate::loggerWriteLine;
+use crate::{loggerWriteLine, memory::virtualMemory::VirtualMemoryManager, time::clock::Deadline};
 
-use super::controller::Controller;
+use super::{
+    controller::Controller,
+    fis::{
+        ATA_CMD_FLUSH_CACHE_EXT, ATA_CMD_READ_DMA_EXT, ATA_CMD_READ_FPDMA_QUEUED,
+        ATA_CMD_WRITE_DMA_EXT, ATA_CMD_WRITE_FPDMA_QUEUED, FisRegisterH2D,
+    },
+    identify::{ATA_CMD_IDENTIFY_DEVICE, IDENTIFY_LENGTH, IdentifyData},
+};
 use core::{
+    cell::Cell,
+    hint::spin_loop,
     mem::size_of,
-    ptr::{addr_of, read_volatile},
+    ptr::{addr_of, addr_of_mut, read_volatile, write_bytes, write_volatile},
 };
+use critical_section::Mutex;
+
+// What sectors are until IDENTIFY DEVICE says otherwise
+pub const SECTOR_SIZE: usize = 512;
+
+// Sector count in the FIS is 16 bits and 0 means 65536. Stay clear of that.
+const MAX_SECTORS_PER_COMMAND: usize = 0xFFFF;
+
+// Data Byte Count is 22 bits of bytes - 1
+const MAX_BYTES_PER_PRDT: usize = 0x40_0000;
+
+// How long a single command gets before we give up on it
+const COMMAND_TIMEOUT_MS: u64 = 5_000;
+
+// Each port gets one allocation laid out as:
+//   0x0000 CommandList (1K, needs 1K alignment)
+//   0x0400 Received FIS (256 bytes, needs 256 byte alignment)
+//   0x1000 CommandTable * 32 (needs 128 byte alignment)
+const RECEIVED_FIS_OFFSET: usize = 0x400;
+const COMMAND_TABLES_OFFSET: usize = 0x1000;
+const PORT_MEMORY_PAGES: usize = 3;
+
+// Our side of the command slots, bit N is slot N. The HBA's side is SACT and CI.
+#[derive(Clone, Copy)]
+struct SlotState {
+    allocated: u32,           // Handed out and not given back by wait yet
+    outstanding: u32,         // Issued and the HBA isn't done with it
+    queued: u32,              // The outstanding ones that are NCQ
+    failed: u32,              // Done, but it didn't work
+    expectedBytes: [u32; 32], // What PRDBC should say when it's done. NCQ doesn't promise to update it.
+}
+
+// A command that's been issued. Hand it to wait to find out how it went and get the slot back.
+#[must_use]
+pub struct PendingCommand {
+    slot: u8,
+}
 
 pub struct SataDrive {
-    Controller: Controller,
+    Controller: &'static Controller, // Shared with every other drive on the same controller
     Port: u8,
+    CommandList: usize,   // Virtual address of the CommandList, 0 until remapStuff
+    CommandTables: usize, // Virtual address of the first CommandTable, the rest follow
+    Identity: Option<IdentifyData>, // None until identify
+    Slots: Mutex<Cell<SlotState>>, // Shared with the interrupt handler
+    Interrupts: bool,     // Whether completions show up by interrupt or we have to go looking
 }
 impl SataDrive {
     pub fn stopCommands(&self) {
@@ -53,11 +107,125 @@ impl SataDrive {
         }
     }
 
-    pub(crate) fn new(controller: Controller, port: u8) -> SataDrive {
+    pub(crate) fn new(controller: &'static Controller, port: u8) -> SataDrive {
         SataDrive {
             Controller: controller,
             Port: port,
+            CommandList: 0,
+            CommandTables: 0,
+            Identity: None,
+            Slots: Mutex::new(Cell::new(SlotState {
+                allocated: 0,
+                outstanding: 0,
+                queued: 0,
+                failed: 0,
+                expectedBytes: [0; 32],
+            })),
+            Interrupts: false,
+        }
+    }
+
+    pub fn getController(&self) -> &'static Controller {
+        self.Controller
+    }
+
+    pub fn getPort(&self) -> u8 {
+        self.Port
+    }
+
+    pub fn getIdentity(&self) -> Option<&IdentifyData> {
+        self.Identity.as_ref()
+    }
+
+    pub fn getSectorSize(&self) -> usize {
+        self.Identity
+            .map_or(SECTOR_SIZE, |identity| identity.logicalSectorSize)
+    }
+
+    pub fn getSectorCount(&self) -> u64 {
+        self.Identity.map_or(0, |identity| identity.sectorCount)
+    }
+
+    // Reads and writes go out as FPDMA QUEUED when both the HBA and the drive can do it
+    pub fn usesNcq(&self) -> bool {
+        self.Controller.supportsNcq() && self.Identity.is_some_and(|identity| identity.supportsNcq)
+    }
+
+    // How many commands can be outstanding at once. Without NCQ the HBA still takes a slot's worth, it just hands
+    // them to the drive one at a time.
+    pub fn getQueueDepth(&self) -> u8 {
+        let slots = self.Controller.getCommandSlots();
+        match self.Identity {
+            Some(identity) if self.usesNcq() => slots.min(identity.queueDepth),
+            _ => slots,
+        }
+    }
+
+    pub fn usesInterrupts(&self) -> bool {
+        self.Interrupts
+    }
+
+    // 3.3.6 PxIE. Until this is called completions are found by polling, which is what bring-up wants since nothing
+    // would route the interrupt here before the drive is registered.
+    pub fn enableInterrupts(&mut self) {
+        if !self.Controller.usesInterrupts() {
+            return;
+        }
+
+        let port = self.Controller.getPort(self.Port);
+        unsafe { write_volatile(addr_of_mut!((*port).IE), IE_MASK) };
+        self.Interrupts = true;
+    }
+
+    // Asks the drive what it is and what it can do. Reads and writes are refused until this succeeds.
+    pub fn identify(&mut self, vmm: &mut VirtualMemoryManager) -> Result<(), &'static str> {
+        if self.CommandList == 0 {
+            return Err("Drive isn't set up");
+        }
+
+        let buffer = vmm.allocatePages("IDENTIFY buffer", 1);
+        let mut regions = [Region {
+            physical: 0,
+            length: 0,
+        }; COUNT_OF_PRDT as usize];
+
+        let result = Self::buildRegions(vmm, buffer, IDENTIFY_LENGTH, SECTOR_SIZE, &mut regions)
+            .and_then(|_| {
+                self.issue(
+                    FisRegisterH2D::new(ATA_CMD_IDENTIFY_DEVICE),
+                    &regions,
+                    false,
+                    IDENTIFY_LENGTH,
+                )
+            })
+            .and_then(|_| {
+                let raw = unsafe { &*(buffer as *const [u16; IDENTIFY_LENGTH / 2]) };
+                let identity = IdentifyData::parse(raw);
+
+                // Everything else is sized in sectors, one that can't be split into PRDT entries would be trouble
+                if identity.logicalSectorSize == 0 || identity.logicalSectorSize % 2 != 0 {
+                    return Err("Drive reported a bogus sector size");
+                }
+
+                Ok(identity)
+            });
+
+        vmm.freePages(buffer, 1);
+        self.Identity = Some(result?);
+        Ok(())
+    }
+
+    // Pushes anything in the drive's write cache out to the media
+    pub fn flush(&self) -> Result<(), &'static str> {
+        let Some(identity) = self.Identity else {
+            return Err("Drive hasn't been identified");
+        };
+
+        if !identity.supportsFlushCacheExt {
+            return Err("Drive doesn't support FLUSH CACHE EXT");
         }
+
+        self.issue(FisRegisterH2D::new(ATA_CMD_FLUSH_CACHE_EXT), &[], false, 0)
     }
 
     // Port (0 .. 32)
@@ -65,21 +233,38 @@ impl SataDrive {
     //     CommandHeader (0 .. 32)
     //       CommandTable
     //         PRDT (0 .. COUNT_OF_PRDT)
-    // BUGBUG: This all breaks if we attempt to use this for multiple ports, we assume only one
-    pub fn remapStuff(&self) {
+    // The controller gets the physical addresses, we go through an uncached mapping of them so both sides see every
+    // write right away. Commands need to be stopped first.
+    pub fn remapStuff(&mut self, vmm: &mut VirtualMemoryManager) {
         let port = self.Controller.getPort(self.Port);
-        unsafe {
+        let memory = vmm.allocateDeviceMemory("AHCI port structures", PORT_MEMORY_PAGES);
+        let physical = memory.physical.address;
+        let virtualAddress = memory.r#virtual.address;
 
-            (*port).setClb(SATA_DRIVE_BASE_CMD_BASE_ADDRESS);
-            
+        if physical + PORT_MEMORY_PAGES * SIZE_OF_PAGE > u32::MAX as usize
+            && !self.Controller.supports64BitAddresses()
+        {
+            haltLoopWithMessage!(
+                "Port {} structures @ 0x{:X} are above 4G and the controller can't reach them",
+                self.Port,
+                physical
+            );
+        }
 
-            let bytePointer = (*port).CLB as *mut u8;
+        self.CommandList = virtualAddress;
+        self.CommandTables = virtualAddress + COMMAND_TABLES_OFFSET;
 
-            for offset in 0..size_of::<CommandList>() {
-                *(bytePointer.offset(offset as isize)) = 0;
-            }
+        loggerWriteLine!(
+            "Port {} structures @ 0x{:X} / 0x{:X} (P/V)",
+            self.Port,
+            physical,
+            virtualAddress
+        );
+
+        unsafe {
+            (*port).setClb(physical);
 
-            let cl = (*port).CLB as *mut CommandList;
+            let cl = self.CommandList as *mut CommandList;
             for index in 0..32 {
                 let header = (*cl).getHeader(index);
                 zeroMemory2(header);
@@ -87,23 +272,579 @@ impl SataDrive {
                 (*header).setPrdtl(COUNT_OF_PRDT);
 
                 let offset = CommandTable::getFullLength() * index as usize;
-                (*header).setCommandTable(SATA_DRIVE_BASE_COMMAND_TABLE_BASE_ADDRESS + offset)
+                (*header).setCommandTable(physical + COMMAND_TABLES_OFFSET + offset)
+            }
+
+            (*port).setFb(physical + RECEIVED_FIS_OFFSET);
+
+            // Whatever happened before we got here isn't our problem. Both are write 1 to clear.
+            write_volatile(addr_of_mut!((*port).SERR), u32::MAX);
+            write_volatile(addr_of_mut!((*port).IS), u32::MAX);
+        }
+    }
+
+    // Reads sectorCount sectors starting at lba into buffer, which needs to be at least that big and mapped
+    pub fn read(
+        &self,
+        vmm: &mut VirtualMemoryManager,
+        lba: u64,
+        sectorCount: usize,
+        buffer: *mut u8,
+    ) -> Result<(), &'static str> {
+        self.transfer(vmm, lba, sectorCount, buffer as usize, false)
+    }
+
+    pub fn write(
+        &self,
+        vmm: &mut VirtualMemoryManager,
+        lba: u64,
+        sectorCount: usize,
+        buffer: *const u8,
+    ) -> Result<(), &'static str> {
+        self.transfer(vmm, lba, sectorCount, buffer as usize, true)
+    }
+
+    // Same as read, but into physically contiguous memory the caller already knows the address of, so there's nothing
+    // to translate and no vmm needed
+    pub fn readPhysical(
+        &self,
+        lba: u64,
+        sectorCount: usize,
+        physical: usize,
+    ) -> Result<(), &'static str> {
+        self.transferPhysical(lba, sectorCount, physical, false)
+    }
+
+    pub fn writePhysical(
+        &self,
+        lba: u64,
+        sectorCount: usize,
+        physical: usize,
+    ) -> Result<(), &'static str> {
+        self.transferPhysical(lba, sectorCount, physical, true)
+    }
+
+    // Same as read, but returns as soon as the command is issued. The whole request has to fit in one command and
+    // buffer has to stay put until wait says it's done.
+    pub fn submitRead(
+        &self,
+        vmm: &mut VirtualMemoryManager,
+        lba: u64,
+        sectorCount: usize,
+        buffer: *mut u8,
+    ) -> Result<PendingCommand, &'static str> {
+        let (pending, _) =
+            self.startTransfer(vmm, lba, sectorCount, buffer as usize, false, true)?;
+        Ok(pending)
+    }
+
+    pub fn submitWrite(
+        &self,
+        vmm: &mut VirtualMemoryManager,
+        lba: u64,
+        sectorCount: usize,
+        buffer: *const u8,
+    ) -> Result<PendingCommand, &'static str> {
+        let (pending, _) =
+            self.startTransfer(vmm, lba, sectorCount, buffer as usize, true, true)?;
+        Ok(pending)
+    }
+
+    // Whether wait would return right away
+    pub fn isDone(&self, pending: &PendingCommand) -> bool {
+        if !self.Interrupts {
+            self.poll();
+        }
+
+        self.getSlotState().outstanding & (1 << pending.slot) == 0
+    }
+
+    // Blocks until the command is finished, then frees its slot
+    pub fn wait(&self, pending: PendingCommand) -> Result<(), &'static str> {
+        let bit = 1 << pending.slot;
+        let deadline = Deadline::afterMilliseconds(COMMAND_TIMEOUT_MS);
+        while self.getSlotState().outstanding & bit != 0 {
+            if deadline.hasExpired() {
+                // Could be the interrupt went missing, so one last look before giving up on it
+                self.poll();
+                if self.getSlotState().outstanding & bit != 0 {
+                    let port = self.Controller.getPort(self.Port);
+                    self.recover(unsafe { read_volatile(addr_of!((*port).IS)) }, "Timed out");
+                }
+
+                break;
+            }
+
+            self.waitForProgress();
+        }
+
+        let failed = critical_section::with(|cs| {
+            let slots = self.Slots.borrow(cs);
+            let mut state = slots.get();
+            let failed = state.failed & bit != 0;
+            state.allocated &= !bit;
+            state.failed &= !bit;
+            slots.set(state);
+            failed
+        });
+
+        if failed {
+            return Err("Command failed");
+        }
+
+        Ok(())
+    }
+
+    // Works out which outstanding commands the HBA is done with. Called from the interrupt handler, or by whoever is
+    // waiting when the port doesn't have one. Non-queued commands are done when their CI bit clears, NCQ ones when
+    // the drive's Set Device Bits FIS clears their SACT bit.
+    pub fn poll(&self) {
+        let port = self.Controller.getPort(self.Port);
+        critical_section::with(|cs| unsafe {
+            // Write 1 to clear, then the controller's summary bit for this port
+            let is = read_volatile(addr_of!((*port).IS));
+            write_volatile(addr_of_mut!((*port).IS), is);
+            self.Controller.clearPortInterrupt(self.Port);
+
+            if is & IS_FATAL_MASK != 0 {
+                self.recover(is, "Fatal error");
+                return;
+            }
+
+            let slots = self.Slots.borrow(cs);
+            let mut state = slots.get();
+            let busy = read_volatile(addr_of!((*port).SACT)) | read_volatile(addr_of!((*port).CI));
+            let done = state.outstanding & !busy;
+
+            for slot in 0..32u8 {
+                let bit = 1 << slot;
+                if done & bit == 0 || state.queued & bit != 0 {
+                    continue;
+                }
+
+                let header = (*(self.CommandList as *mut CommandList)).getHeader(slot);
+                let transferred = (*header).getPrdbc();
+                let expected = state.expectedBytes[slot as usize];
+                if transferred != expected {
+                    loggerWriteLine!(
+                        "Port {} slot {} moved 0x{:X} bytes, wanted 0x{:X}",
+                        self.Port,
+                        slot,
+                        transferred,
+                        expected
+                    );
+                    state.failed |= bit;
+                }
+            }
+
+            state.outstanding &= !done;
+            state.queued &= !done;
+            slots.set(state);
+        });
+    }
+
+    // Issues one command for as much of the request as fits and returns how many sectors that was. mustFit turns a
+    // partial fit into an error instead.
+    fn startTransfer(
+        &self,
+        vmm: &mut VirtualMemoryManager,
+        lba: u64,
+        sectorCount: usize,
+        buffer: usize,
+        isWrite: bool,
+        mustFit: bool,
+    ) -> Result<(PendingCommand, usize), &'static str> {
+        let sectorSize = self.checkTransfer(lba, sectorCount, buffer)?;
+        let maxBytes = sectorCount.min(MAX_SECTORS_PER_COMMAND) * sectorSize;
+        let mut regions = [Region {
+            physical: 0,
+            length: 0,
+        }; COUNT_OF_PRDT as usize];
+        let bytes = Self::buildRegions(vmm, buffer, maxBytes, sectorSize, &mut regions)?;
+        if mustFit && bytes != sectorCount * sectorSize {
+            return Err("Request doesn't fit in one command");
+        }
+
+        let sectors = bytes / sectorSize;
+        let pending = self.startRegions(lba, sectors, &regions, bytes, isWrite)?;
+        Ok((pending, sectors))
+    }
+
+    // Everything a read or write has to get past before it's worth building a command. Returns the sector size.
+    fn checkTransfer(
+        &self,
+        lba: u64,
+        sectorCount: usize,
+        buffer: usize,
+    ) -> Result<usize, &'static str> {
+        let Some(identity) = self.Identity else {
+            return Err("Drive hasn't been identified");
+        };
+
+        // Everything here is a 48-bit command
+        if !identity.supportsLba48 {
+            return Err("Drive doesn't support 48-bit LBA");
+        }
+
+        // Data Base Address bit 0 is reserved
+        if buffer & 1 != 0 {
+            return Err("Buffer isn't word aligned");
+        }
+
+        if sectorCount == 0 {
+            return Err("Nothing to transfer");
+        }
+
+        if lba + sectorCount as u64 > identity.sectorCount {
+            return Err("Past the end of the drive");
+        }
+
+        Ok(identity.logicalSectorSize)
+    }
+
+    // Reads or writes sectors at lba to or from regions, which have to add up to bytes
+    fn startRegions(
+        &self,
+        lba: u64,
+        sectors: usize,
+        regions: &[Region],
+        bytes: usize,
+        isWrite: bool,
+    ) -> Result<PendingCommand, &'static str> {
+        if !self.Controller.supports64BitAddresses()
+            && regions
+                .iter()
+                .any(|region| region.physical + region.length > u32::MAX as usize + 1)
+        {
+            return Err("Buffer is above 4G and the controller can't reach it");
+        }
+
+        let queued = self.usesNcq();
+        let fis = match (queued, isWrite) {
+            (true, false) => {
+                FisRegisterH2D::newQueued(ATA_CMD_READ_FPDMA_QUEUED, lba, sectors as u16)
+            }
+            (true, true) => {
+                FisRegisterH2D::newQueued(ATA_CMD_WRITE_FPDMA_QUEUED, lba, sectors as u16)
+            }
+            (false, false) => FisRegisterH2D::newCommand(ATA_CMD_READ_DMA_EXT, lba, sectors as u16),
+            (false, true) => FisRegisterH2D::newCommand(ATA_CMD_WRITE_DMA_EXT, lba, sectors as u16),
+        };
+
+        self.start(fis, regions, isWrite, bytes, queued)
+    }
+
+    // Physically contiguous memory only needs splitting where a single PRDT entry runs out
+    fn transferPhysical(
+        &self,
+        mut lba: u64,
+        sectorCount: usize,
+        mut physical: usize,
+        isWrite: bool,
+    ) -> Result<(), &'static str> {
+        let sectorSize = self.checkTransfer(lba, sectorCount, physical)?;
+        let sectorsPerCommand =
+            MAX_SECTORS_PER_COMMAND.min(COUNT_OF_PRDT as usize * MAX_BYTES_PER_PRDT / sectorSize);
+
+        let mut remaining = sectorCount;
+        while remaining > 0 {
+            let sectors = remaining.min(sectorsPerCommand);
+            let bytes = sectors * sectorSize;
+            let mut regions = [Region {
+                physical: 0,
+                length: 0,
+            }; COUNT_OF_PRDT as usize];
+
+            for (index, region) in regions.iter_mut().enumerate() {
+                let offset = index * MAX_BYTES_PER_PRDT;
+                if offset >= bytes {
+                    break;
+                }
+
+                *region = Region {
+                    physical: physical + offset,
+                    length: (bytes - offset).min(MAX_BYTES_PER_PRDT),
+                };
+            }
+
+            let pending = self.startRegions(lba, sectors, &regions, bytes, isWrite)?;
+            self.wait(pending)?;
+
+            lba += sectors as u64;
+            physical += bytes;
+            remaining -= sectors;
+        }
+
+        Ok(())
+    }
+
+    // Splits the request up into as many commands as it takes to fit in the PRDT, one at a time
+    fn transfer(
+        &self,
+        vmm: &mut VirtualMemoryManager,
+        mut lba: u64,
+        sectorCount: usize,
+        mut buffer: usize,
+        isWrite: bool,
+    ) -> Result<(), &'static str> {
+        let sectorSize = self.getSectorSize();
+        let mut remaining = sectorCount;
+        while remaining > 0 {
+            let (pending, sectors) =
+                self.startTransfer(vmm, lba, remaining, buffer, isWrite, false)?;
+            self.wait(pending)?;
+
+            lba += sectors as u64;
+            buffer += sectors * sectorSize;
+            remaining -= sectors;
+        }
+
+        Ok(())
+    }
+
+    // Walks the buffer a page at a time since that's the most we know is physically contiguous. Pages that happen to
+    // line up are merged. Returns how many bytes got described, always a whole number of sectors.
+    fn buildRegions(
+        vmm: &mut VirtualMemoryManager,
+        buffer: usize,
+        maxBytes: usize,
+        sectorSize: usize,
+        regions: &mut [Region; COUNT_OF_PRDT as usize],
+    ) -> Result<usize, &'static str> {
+        let mut used = 0;
+        let mut bytes = 0;
+
+        while bytes < maxBytes {
+            let virtualAddress = buffer + bytes;
+            let Some(physical) = vmm.translate(virtualAddress) else {
+                return Err("Buffer isn't mapped");
+            };
+
+            let length = (SIZE_OF_PAGE - virtualAddress % SIZE_OF_PAGE).min(maxBytes - bytes);
+
+            if used > 0 {
+                let last = &mut regions[used - 1];
+                if last.physical + last.length == physical
+                    && last.length + length <= MAX_BYTES_PER_PRDT
+                {
+                    last.length += length;
+                    bytes += length;
+                    continue;
+                }
+            }
+
+            if used == regions.len() {
+                break;
+            }
+
+            regions[used] = Region { physical, length };
+            used += 1;
+            bytes += length;
+        }
+
+        // Ran out of entries partway through a sector, so leave that sector for the next command
+        let mut excess = bytes % sectorSize;
+        bytes -= excess;
+        while excess > 0 {
+            used -= 1;
+            let trim = excess.min(regions[used].length);
+            regions[used].length -= trim;
+            excess -= trim;
+            if regions[used].length != 0 {
+                used += 1;
+            }
+        }
+
+        if bytes == 0 {
+            return Err("Buffer is too fragmented");
+        }
+
+        Ok(bytes)
+    }
+
+    // Issues a non-queued command and waits for it. expectedBytes is how much the data phase should move, 0 for
+    // commands that don't have one.
+    fn issue(
+        &self,
+        fis: FisRegisterH2D,
+        regions: &[Region],
+        isWrite: bool,
+        expectedBytes: usize,
+    ) -> Result<(), &'static str> {
+        let pending = self.start(fis, regions, isWrite, expectedBytes, false)?;
+        self.wait(pending)
+    }
+
+    // 5.5 System Software Rules: build the command in a free slot and hand it to the HBA. Doesn't wait for it.
+    fn start(
+        &self,
+        mut fis: FisRegisterH2D,
+        regions: &[Region],
+        isWrite: bool,
+        expectedBytes: usize,
+        queued: bool,
+    ) -> Result<PendingCommand, &'static str> {
+        let port = self.Controller.getPort(self.Port);
+        let slot = self.allocateSlot(queued)?;
+        if queued {
+            fis.setTag(slot);
+        }
+
+        unsafe {
+            // 5.5.1 Software shouldn't issue while the device is still busy with something. With other commands
+            // outstanding it's allowed to be, the HBA holds on to this one until it can send it.
+            if self.getSlotState().outstanding == 0 {
+                let deadline = Deadline::afterMilliseconds(COMMAND_TIMEOUT_MS);
+                while read_volatile(addr_of!((*port).TFD)) & (TFD_STS_BSY | TFD_STS_DRQ) != 0 {
+                    if deadline.hasExpired() {
+                        self.recover(read_volatile(addr_of!((*port).IS)), "Device stayed busy");
+                        self.freeSlot(slot);
+                        return Err("Device is busy");
+                    }
+
+                    spin_loop();
+                }
+            }
+
+            let table = (self.CommandTables + CommandTable::getFullLength() * slot as usize)
+                as *mut CommandTable;
+            write_bytes(table as *mut u8, 0, CommandTable::getFullLength());
+
+            write_volatile(addr_of_mut!((*table).CFIS) as *mut FisRegisterH2D, fis);
+
+            let mut prdtCount = 0;
+            for region in regions.iter().filter(|region| region.length != 0) {
+                (*(*table).getPrdt(prdtCount)).set(region.physical, region.length);
+                prdtCount += 1;
             }
 
-            (*port).FB = SATA_DRIVE_BASE_FIS_BASE_ADDRESS;
+            let header = (*(self.CommandList as *mut CommandList)).getHeader(slot);
+            (*header).setCommand(FisRegisterH2D::getLengthInDwords(), isWrite, prdtCount);
 
-            // In 32-bit space
-            (*port).FBU = 0;
+            // Same critical section so the interrupt handler never sees it outstanding before the HBA does
+            critical_section::with(|cs| {
+                let slots = self.Slots.borrow(cs);
+                let mut state = slots.get();
+                state.outstanding |= 1 << slot;
+                if queued {
+                    state.queued |= 1 << slot;
+                }
+
+                state.expectedBytes[slot as usize] = expectedBytes as u32;
+                slots.set(state);
+
+                // 3.3.13 PxSACT: NCQ commands need their bit set there before CI
+                if queued {
+                    write_volatile(addr_of_mut!((*port).SACT), 1 << slot);
+                }
+
+                write_volatile(addr_of_mut!((*port).CI), 1 << slot);
+            });
+        }
+
+        Ok(PendingCommand { slot })
+    }
+
+    // Waits for a slot that's free and within the queue depth. NCQ and non-queued commands can't be outstanding at the
+    // same time, so it also waits for the other kind to drain.
+    fn allocateSlot(&self, queued: bool) -> Result<u8, &'static str> {
+        let depth = self.getQueueDepth();
+        let deadline = Deadline::afterMilliseconds(COMMAND_TIMEOUT_MS);
+        loop {
+            let slot = critical_section::with(|cs| {
+                let slots = self.Slots.borrow(cs);
+                let mut state = slots.get();
+                let conflicting = if queued {
+                    state.outstanding & !state.queued
+                } else {
+                    state.queued
+                };
+
+                let slot = state.allocated.trailing_ones() as u8;
+                if conflicting != 0 || slot >= depth {
+                    return None;
+                }
 
-            let bytePointer = (*port).FB as *mut u8;
+                state.allocated |= 1 << slot;
+                slots.set(state);
+                Some(slot)
+            });
 
-            // BUGBUG: Is this the right ammount?
-            // BUGBUG: Create a 0-memory function
-            for offset in 0..256 {
-                *(bytePointer.offset(offset)) = 0;
+            if let Some(slot) = slot {
+                return Ok(slot);
             }
+
+            if deadline.hasExpired() {
+                return Err("No free command slots");
+            }
+
+            self.waitForProgress();
         }
     }
+
+    fn freeSlot(&self, slot: u8) {
+        critical_section::with(|cs| {
+            let slots = self.Slots.borrow(cs);
+            let mut state = slots.get();
+            state.allocated &= !(1 << slot);
+            slots.set(state);
+        });
+    }
+
+    fn getSlotState(&self) -> SlotState {
+        critical_section::with(|cs| self.Slots.borrow(cs).get())
+    }
+
+    // Leaves it to the interrupt handler, or goes and looks when there isn't one
+    fn waitForProgress(&self) {
+        if self.Interrupts {
+            spin_loop();
+        } else {
+            self.poll();
+        }
+    }
+
+    // PxTFD has the ATA status in 7:0 and error in 15:8. After a fatal error the port stops processing commands, so
+    // 6.2.2 Software Error Recovery: restart it to get things moving again. That throws away SACT and CI, so
+    // everything that was outstanding has failed.
+    // BUGBUG: After an NCQ error the drive wants READ LOG EXT page 10h before it'll take more queued commands
+    fn recover(&self, is: u32, reason: &str) {
+        let port = self.Controller.getPort(self.Port);
+        critical_section::with(|cs| unsafe {
+            let slots = self.Slots.borrow(cs);
+            let mut state = slots.get();
+
+            let tfd = read_volatile(addr_of!((*port).TFD));
+            let serr = read_volatile(addr_of!((*port).SERR));
+            loggerWriteLine!(
+                "Port {} failed ({}): status 0x{:X} error 0x{:X} IS 0x{:X} SERR 0x{:X} outstanding 0x{:X}",
+                self.Port,
+                reason,
+                tfd & 0xFF,
+                (tfd >> 8) & 0xFF,
+                is,
+                serr,
+                state.outstanding
+            );
+
+            self.stopCommands();
+            write_volatile(addr_of_mut!((*port).SERR), u32::MAX);
+            write_volatile(addr_of_mut!((*port).IS), u32::MAX);
+            self.startCommands();
+
+            state.failed |= state.outstanding;
+            state.outstanding = 0;
+            state.queued = 0;
+            slots.set(state);
+        });
+    }
+}
+
+// One physically contiguous piece of a buffer
+#[derive(Clone, Copy)]
+struct Region {
+    physical: usize,
+    length: usize,
 }
 
 impl CommandTable {
@@ -111,6 +852,34 @@ impl CommandTable {
         // -1 as the table defintion already alocates the first entry
         return size_of::<CommandTable>() + size_of::<PRDT>() * ((COUNT_OF_PRDT - 1) as usize);
     }
+
+    pub fn getPrdt(&mut self, index: u16) -> *mut PRDT {
+        if index >= COUNT_OF_PRDT {
+            loggerWriteLine!("{index} is a bogus PRDT");
+            haltLoop();
+        }
+
+        let first = addr_of_mut!(self.PRDT0);
+        unsafe { first.add(index as usize) }
+    }
+}
+
+impl PRDT {
+    // Data Base Address needs to be word aligned and the byte count has to be even
+    pub fn set(&mut self, physicalAddress: usize, length: usize) {
+        if physicalAddress & 1 != 0 || length & 1 != 0 || length == 0 || length > MAX_BYTES_PER_PRDT
+        {
+            loggerWriteLine!("PRDT 0x{:X} for 0x{:X} is bogus", physicalAddress, length);
+            haltLoop();
+        }
+
+        self.DW0 = physicalAddress as u32;
+        self.DW1 = (physicalAddress >> 32) as u32;
+        self.DW2 = 0;
+
+        // Data Byte Count (DBC), 0's based. Bit 31 would ask for an interrupt, which we don't use.
+        self.DW3 = (length - 1) as u32;
+    }
 }
 
 impl CommandList {
@@ -160,6 +929,27 @@ impl CommandList {
 }
 
 impl CommandHeader {
+    // Everything in DW0 for a new command and clears the byte count from the last one
+    pub fn setCommand(&mut self, fisLengthInDwords: u32, isWrite: bool, prdtl: u16) {
+        // Command FIS Length (CFL)
+        let mut value = fisLengthInDwords & 0x1F;
+
+        // Write (W), direction is host to device
+        if isWrite {
+            value |= 1 << 6;
+        }
+
+        self.DW0 = value;
+        self.setPrdtl(prdtl);
+
+        // Physical Region Descriptor Byte Count (PRDBC)
+        self.DW1 = 0;
+    }
+
+    pub fn getPrdbc(&self) -> u32 {
+        unsafe { read_volatile(addr_of!(self.DW1)) }
+    }
+
     // Physical Region Descriptor Table Length
     pub fn setPrdtl(&mut self, value: u16) {
         let mut value = value as u32;
@@ -175,17 +965,11 @@ impl CommandHeader {
             haltLoop();
         }
 
-        if address > u32::MAX as usize {
-            loggerWriteLine!("Address it out of range");
-            haltLoop();
-        }
-
         // Command Table Descriptor Base Address (CTBA)
         self.DW2 = address as u32;
 
-        // We're only doing 32 bit addresses for now
         // Command Table Descriptor Base Address Upper 32-bits (CTBAU)
-        self.DW3 = 0;
+        self.DW3 = (address >> 32) as u32;
     }
 }
 
@@ -207,6 +991,23 @@ const CMD_FR_MASK: u32 = 1 << 14;
 // RO
 const CMD_CR_MASK: u32 = 1 << 15;
 
+// 3.3.5 Offset 10h: PxIS – Port x Interrupt Status
+// Task File Error Status (TFES)
+const IS_TFES_MASK: u32 = 1 << 30;
+
+// Host Bus Fatal Error (HBFS), Host Bus Data Error (HBDS) and Interface Fatal Error (IFS). Along with TFES, any of
+// these stop the port until it's restarted.
+const IS_FATAL_MASK: u32 = IS_TFES_MASK | (1 << 29) | (1 << 28) | (1 << 27);
+
+// 3.3.6 Offset 14h: PxIE – Port x Interrupt Enable
+// Device to Host Register FIS (DHRE), PIO Setup FIS (PSE) and Set Device Bits FIS (SDBE), which is how NCQ
+// commands finish, plus everything fatal
+const IE_MASK: u32 = (1 << 0) | (1 << 1) | (1 << 3) | IS_FATAL_MASK;
+
+// 3.3.8 Offset 20h: PxTFD – Port x Task File Data, status half
+const TFD_STS_DRQ: u32 = 1 << 3;
+const TFD_STS_BSY: u32 = 1 << 7;
+
 // Physical Region Descriptor Table
 // BUGBUG: This is the value OSDev went with, should figure out why...
 const COUNT_OF_PRDT: u16 = 8;
@@ -1,4 +1,4 @@
-use core::arch::asm;
+use core::arch::{asm, x86_64::__cpuid};
 
 pub fn getCR2() -> u64 {
     let cr2Value : u64;
@@ -12,3 +12,51 @@ pub fn getCR2() -> u64 {
 
     cr2Value
 }
+
+// Model Specific Registers. Intel Volume 4 has the list.
+pub fn readMsr(msr: u32) -> u64 {
+    let low: u32;
+    let high: u32;
+
+    unsafe {
+        asm!(
+            "rdmsr",
+            in("ecx") msr,
+            out("eax") low,
+            out("edx") high,
+        );
+    }
+
+    (high as u64) << 32 | low as u64
+}
+
+pub fn writeMsr(msr: u32, value: u64) {
+    unsafe {
+        asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+        );
+    }
+}
+
+// Drops whatever the TLB has for the page containing address, plus any cached paging structures
+pub fn invlpg(address: usize) {
+    unsafe {
+        asm!(
+            "invlpg [{0}]",
+            in(reg) address,
+        );
+    }
+}
+
+// CPUID 0x80000001 EDX bit 26 (Page1GB)
+pub fn supports1GiBPages() -> bool {
+    let maxExtended = __cpuid(0x8000_0000).eax;
+    if maxExtended < 0x8000_0001 {
+        return false;
+    }
+
+    __cpuid(0x8000_0001).edx & (1 << 26) != 0
+}
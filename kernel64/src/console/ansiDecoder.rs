@@ -0,0 +1,235 @@
+use crate::keyboard::keyEvent::{KeyCode, KeyEvent, Modifiers};
+
+// Turns what a terminal emulator sends down the wire into the same KeyEvents the PS/2 keyboard makes,
+// so the shell doesn't care where its input came from.
+// https://en.wikipedia.org/wiki/ANSI_escape_code#Terminal_input_sequences
+// Only presses are produced; terminals don't tell us about releases.
+
+const ESCAPE: u8 = 0x1B;
+const DELETE: u8 = 0x7F;
+const BACKSPACE: u8 = 0x08;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DecoderState {
+    Ground,
+    Escape,                     // Saw ESC
+    ControlSequence(u16, bool), // Saw ESC [, first parameter so far and whether we're past it
+    SingleShift,                // Saw ESC O (what xterm sends for arrows in application mode)
+}
+
+// (key, unshifted, shifted) for everything printable on a US keyboard
+const US_LAYOUT: [(KeyCode, u8, u8); 48] = [
+    (KeyCode::A, b'a', b'A'),
+    (KeyCode::B, b'b', b'B'),
+    (KeyCode::C, b'c', b'C'),
+    (KeyCode::D, b'd', b'D'),
+    (KeyCode::E, b'e', b'E'),
+    (KeyCode::F, b'f', b'F'),
+    (KeyCode::G, b'g', b'G'),
+    (KeyCode::H, b'h', b'H'),
+    (KeyCode::I, b'i', b'I'),
+    (KeyCode::J, b'j', b'J'),
+    (KeyCode::K, b'k', b'K'),
+    (KeyCode::L, b'l', b'L'),
+    (KeyCode::M, b'm', b'M'),
+    (KeyCode::N, b'n', b'N'),
+    (KeyCode::O, b'o', b'O'),
+    (KeyCode::P, b'p', b'P'),
+    (KeyCode::Q, b'q', b'Q'),
+    (KeyCode::R, b'r', b'R'),
+    (KeyCode::S, b's', b'S'),
+    (KeyCode::T, b't', b'T'),
+    (KeyCode::U, b'u', b'U'),
+    (KeyCode::V, b'v', b'V'),
+    (KeyCode::W, b'w', b'W'),
+    (KeyCode::X, b'x', b'X'),
+    (KeyCode::Y, b'y', b'Y'),
+    (KeyCode::Z, b'z', b'Z'),
+    (KeyCode::Digit1, b'1', b'!'),
+    (KeyCode::Digit2, b'2', b'@'),
+    (KeyCode::Digit3, b'3', b'#'),
+    (KeyCode::Digit4, b'4', b'$'),
+    (KeyCode::Digit5, b'5', b'%'),
+    (KeyCode::Digit6, b'6', b'^'),
+    (KeyCode::Digit7, b'7', b'&'),
+    (KeyCode::Digit8, b'8', b'*'),
+    (KeyCode::Digit9, b'9', b'('),
+    (KeyCode::Digit0, b'0', b')'),
+    (KeyCode::Backtick, b'`', b'~'),
+    (KeyCode::Minus, b'-', b'_'),
+    (KeyCode::Equals, b'=', b'+'),
+    (KeyCode::LeftBracket, b'[', b'{'),
+    (KeyCode::RightBracket, b']', b'}'),
+    (KeyCode::Backslash, b'\\', b'|'),
+    (KeyCode::Semicolon, b';', b':'),
+    (KeyCode::Quote, b'\'', b'"'),
+    (KeyCode::Comma, b',', b'<'),
+    (KeyCode::Period, b'.', b'>'),
+    (KeyCode::Slash, b'/', b'?'),
+    (KeyCode::Space, b' ', b' '),
+];
+
+pub struct AnsiDecoder {
+    state: DecoderState,
+    // Terminals send CR for Enter, piped input tends to be LF, some send both. Only count CR LF once.
+    lastWasCarriageReturn: bool,
+}
+
+impl AnsiDecoder {
+    pub const fn new() -> Self {
+        AnsiDecoder {
+            state: DecoderState::Ground,
+            lastWasCarriageReturn: false,
+        }
+    }
+
+    pub fn decode(&mut self, byte: u8) -> Option<KeyEvent> {
+        match self.state {
+            DecoderState::Ground => self.decodeGround(byte),
+            DecoderState::Escape => match byte {
+                b'[' => {
+                    self.state = DecoderState::ControlSequence(0, false);
+                    None
+                }
+                b'O' => {
+                    self.state = DecoderState::SingleShift;
+                    None
+                }
+                _ => {
+                    // Alt+key or a lone Escape followed by typing. Either way the Escape isn't interesting to us.
+                    self.state = DecoderState::Ground;
+                    self.decodeGround(byte)
+                }
+            },
+            DecoderState::ControlSequence(parameter, pastFirst) => match byte {
+                b'0'..=b'9' => {
+                    if !pastFirst {
+                        let parameter = parameter
+                            .saturating_mul(10)
+                            .saturating_add((byte - b'0') as u16);
+                        self.state = DecoderState::ControlSequence(parameter, false);
+                    }
+
+                    None
+                }
+                // Anything after the first parameter is modifiers, which we don't bother with
+                b';' => {
+                    self.state = DecoderState::ControlSequence(parameter, true);
+                    None
+                }
+                // Final byte
+                0x40..=0x7E => {
+                    self.state = DecoderState::Ground;
+                    let code = match byte {
+                        b'A' => KeyCode::ArrowUp,
+                        b'B' => KeyCode::ArrowDown,
+                        b'C' => KeyCode::ArrowRight,
+                        b'D' => KeyCode::ArrowLeft,
+                        b'H' => KeyCode::Home,
+                        b'F' => KeyCode::End,
+                        b'~' => match parameter {
+                            1 | 7 => KeyCode::Home,
+                            2 => KeyCode::Insert,
+                            3 => KeyCode::Delete,
+                            4 | 8 => KeyCode::End,
+                            5 => KeyCode::PageUp,
+                            6 => KeyCode::PageDown,
+                            _ => return None,
+                        },
+                        _ => return None,
+                    };
+
+                    Some(Self::makeEvent(code, Modifiers::new(), None))
+                }
+                // Intermediate bytes, keep swallowing
+                _ => None,
+            },
+            DecoderState::SingleShift => {
+                self.state = DecoderState::Ground;
+                let code = match byte {
+                    b'A' => KeyCode::ArrowUp,
+                    b'B' => KeyCode::ArrowDown,
+                    b'C' => KeyCode::ArrowRight,
+                    b'D' => KeyCode::ArrowLeft,
+                    b'H' => KeyCode::Home,
+                    b'F' => KeyCode::End,
+                    _ => return None,
+                };
+
+                Some(Self::makeEvent(code, Modifiers::new(), None))
+            }
+        }
+    }
+
+    fn decodeGround(&mut self, byte: u8) -> Option<KeyEvent> {
+        let afterCarriageReturn = self.lastWasCarriageReturn;
+        self.lastWasCarriageReturn = false;
+
+        match byte {
+            ESCAPE => {
+                self.state = DecoderState::Escape;
+                None
+            }
+            b'\r' => {
+                self.lastWasCarriageReturn = true;
+                Some(Self::makeEvent(
+                    KeyCode::Enter,
+                    Modifiers::new(),
+                    Some('\n'),
+                ))
+            }
+            b'\n' if afterCarriageReturn => None,
+            b'\n' => Some(Self::makeEvent(
+                KeyCode::Enter,
+                Modifiers::new(),
+                Some('\n'),
+            )),
+            b'\t' => Some(Self::makeEvent(KeyCode::Tab, Modifiers::new(), Some('\t'))),
+            // Most terminals send DEL for the backspace key, a few still send BS
+            DELETE | BACKSPACE => Some(Self::makeEvent(
+                KeyCode::Backspace,
+                Modifiers::new(),
+                Some('\x08'),
+            )),
+            // Ctrl+A through Ctrl+Z, same characters the keyboard decoder hands out for these
+            0x01..=0x1A => {
+                let letter = byte - 1 + b'a';
+                let (code, _) = Self::lookup(letter)?;
+                let mut modifiers = Modifiers::new();
+                modifiers.leftControl = true;
+                Some(Self::makeEvent(code, modifiers, Some(byte as char)))
+            }
+            0x20..=0x7E => {
+                let (code, shifted) = Self::lookup(byte)?;
+                let mut modifiers = Modifiers::new();
+                modifiers.leftShift = shifted;
+                Some(Self::makeEvent(code, modifiers, Some(byte as char)))
+            }
+            _ => None,
+        }
+    }
+
+    fn lookup(byte: u8) -> Option<(KeyCode, bool)> {
+        for (code, normal, shifted) in US_LAYOUT {
+            if byte == normal {
+                return Some((code, false));
+            }
+
+            if byte == shifted {
+                return Some((code, true));
+            }
+        }
+
+        None
+    }
+
+    fn makeEvent(code: KeyCode, modifiers: Modifiers, character: Option<char>) -> KeyEvent {
+        KeyEvent {
+            code,
+            pressed: true,
+            repeat: false,
+            modifiers,
+            character,
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use core::hint::spin_loop;
+
+use kernel_shared::assemblyStuff::misc::{
+    disableInterrupts, enableInterrupts, enableInterruptsAndHalt,
+};
+
+use crate::keyboard::{keyEvent::KeyEvent, ps2Keyboard};
+
+use super::{ansiDecoder::AnsiDecoder, serialInput};
+
+// Merges the PS/2 keyboard and the serial port into one stream of key events.
+// Whichever one has something first wins; there's no attempt to keep the two in any order.
+pub struct ConsoleInput {
+    serialDecoder: AnsiDecoder,
+}
+
+impl ConsoleInput {
+    pub const fn new() -> Self {
+        ConsoleInput {
+            serialDecoder: AnsiDecoder::new(),
+        }
+    }
+
+    pub fn tryReadKeyEvent(&mut self) -> Option<KeyEvent> {
+        if let Some(event) = ps2Keyboard::tryReadKeyEvent() {
+            return Some(event);
+        }
+
+        // Escape sequences take several bytes to make one event, so keep going until one comes out
+        while let Some(byte) = serialInput::tryReadByte() {
+            if let Some(event) = self.serialDecoder.decode(byte) {
+                return Some(event);
+            }
+        }
+
+        None
+    }
+
+    pub fn readKeyEvent(&mut self) -> KeyEvent {
+        loop {
+            // Same deal as ps2Keyboard::readKeyEvent, don't let input land between checking and halting
+            disableInterrupts();
+            if let Some(event) = self.tryReadKeyEvent() {
+                enableInterrupts();
+                return event;
+            }
+
+            if serialInput::isInterruptDriven() {
+                enableInterruptsAndHalt();
+            } else {
+                // Polled serial won't wake us up, so we can't sleep
+                enableInterrupts();
+                spin_loop();
+            }
+        }
+    }
+}
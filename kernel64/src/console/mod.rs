@@ -0,0 +1,3 @@
+pub mod ansiDecoder;
+pub mod consoleInput;
+pub mod serialInput;
@@ -0,0 +1,86 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use kernel_shared::{logging::logger::SYSTEM_LOGGER, ringBuffer::RingBuffer};
+
+use crate::{interupts::irq::registerIrqHandler, loggerWriteLine};
+
+// Input side of the serial port the logger already owns. The logger keeps doing the sending.
+
+struct SerialInputState {
+    bytes: RingBuffer<u8, 256>,
+    interruptDriven: bool,
+    dropped: usize,
+}
+
+static SERIAL_INPUT: Mutex<RefCell<SerialInputState>> =
+    Mutex::new(RefCell::new(SerialInputState {
+        bytes: RingBuffer::new(),
+        interruptDriven: false,
+        dropped: 0,
+    }));
+
+// With useInterrupts false nothing gets hooked up and tryReadByte polls the port directly
+pub fn init(useInterrupts: bool) {
+    let Some(serial) = SYSTEM_LOGGER.serial() else {
+        loggerWriteLine!("No serial port, no serial input");
+        return;
+    };
+
+    if !useInterrupts {
+        loggerWriteLine!("Serial input is polled");
+        return;
+    }
+
+    let irq = serial.getIrq();
+
+    // Anything that showed up before now has nowhere to go
+    while serial.tryReceive().is_some() {}
+
+    critical_section::with(|cs| {
+        SERIAL_INPUT.borrow_ref_mut(cs).interruptDriven = true;
+    });
+
+    registerIrqHandler(irq, handleInterrupt);
+    serial.setReceiveInterrupt(true);
+
+    loggerWriteLine!("Serial input on IRQ {}", irq);
+}
+
+fn handleInterrupt(_irq: u8) {
+    let Some(serial) = SYSTEM_LOGGER.serial() else {
+        return;
+    };
+
+    critical_section::with(|cs| {
+        let mut state = SERIAL_INPUT.borrow_ref_mut(cs);
+
+        // FIFO might have more than one byte in it, drain it all so we don't leave the interrupt pending
+        while let Some(byte) = serial.tryReceive() {
+            if !state.bytes.push(byte) {
+                state.dropped += 1;
+            }
+        }
+    });
+}
+
+pub fn isInterruptDriven() -> bool {
+    critical_section::with(|cs| SERIAL_INPUT.borrow_ref(cs).interruptDriven)
+}
+
+pub fn tryReadByte() -> Option<u8> {
+    let (byte, interruptDriven) = critical_section::with(|cs| {
+        let mut state = SERIAL_INPUT.borrow_ref_mut(cs);
+        (state.bytes.pop(), state.interruptDriven)
+    });
+
+    if byte.is_some() || interruptDriven {
+        return byte;
+    }
+
+    SYSTEM_LOGGER.serial()?.tryReceive()
+}
+
+pub fn droppedCount() -> usize {
+    critical_section::with(|cs| SERIAL_INPUT.borrow_ref(cs).dropped)
+}
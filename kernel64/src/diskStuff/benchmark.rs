@@ -0,0 +1,117 @@
+use alloc::{collections::VecDeque, vec::Vec};
+
+use kernel_shared::magicConstants::SIZE_OF_PAGE;
+
+use crate::{
+    ahci::sataDrive::SataDrive, loggerWriteLine, memory::virtualMemory::VirtualMemoryManager,
+    time::clock,
+};
+
+// Each read is this many pages. Worst case none of them are physically next to each other, which still fits in one
+// command's PRDT.
+const PAGES_PER_REQUEST: usize = 8;
+
+// Reads the start of the drive twice, once a request at a time and once keeping as many outstanding as the drive
+// takes. Only reads, so it's fine to point at anything.
+pub fn run(
+    vmm: &mut VirtualMemoryManager,
+    drive: &SataDrive,
+    megabytes: usize,
+) -> Result<(), &'static str> {
+    let requestBytes = PAGES_PER_REQUEST * SIZE_OF_PAGE;
+    let sectorSize = drive.getSectorSize();
+    if requestBytes % sectorSize != 0 {
+        return Err("Sector size doesn't fit evenly in a request");
+    }
+
+    let sectorsPerRequest = requestBytes / sectorSize;
+    let fitsOnDrive = (drive.getSectorCount() / sectorsPerRequest as u64) as usize;
+    let requests = (megabytes * 1024 * 1024 / requestBytes).min(fitsOnDrive);
+    if requests == 0 {
+        return Err("Nothing to read");
+    }
+
+    let depth = drive.getQueueDepth() as usize;
+    let buffers: Vec<usize> = (0..depth)
+        .map(|_| vmm.allocatePages("Benchmark buffer", PAGES_PER_REQUEST))
+        .collect();
+
+    loggerWriteLine!(
+        "Reading {} KiB in {} KiB requests, NCQ: {}",
+        requests * requestBytes / 1024,
+        requestBytes / 1024,
+        drive.usesNcq()
+    );
+
+    let result = measure(vmm, drive, &buffers[..1], requests, sectorsPerRequest)
+        .and_then(|_| measure(vmm, drive, &buffers, requests, sectorsPerRequest));
+
+    for buffer in buffers {
+        vmm.freePages(buffer, PAGES_PER_REQUEST);
+    }
+
+    result
+}
+
+// Keeps up to one read per buffer outstanding until requests of them are done. Waits on them oldest first; anything
+// that finished out of order is just picked up when its turn comes.
+fn measure(
+    vmm: &mut VirtualMemoryManager,
+    drive: &SataDrive,
+    buffers: &[usize],
+    requests: usize,
+    sectorsPerRequest: usize,
+) -> Result<(), &'static str> {
+    let mut free: Vec<usize> = buffers.to_vec();
+    let mut inFlight = VecDeque::new();
+    let mut submitted = 0;
+    let mut failure = None;
+    let start = clock::now();
+
+    loop {
+        while failure.is_none() && submitted < requests {
+            let Some(buffer) = free.pop() else {
+                break;
+            };
+
+            let lba = (submitted * sectorsPerRequest) as u64;
+            match drive.submitRead(vmm, lba, sectorsPerRequest, buffer as *mut u8) {
+                Ok(pending) => {
+                    inFlight.push_back((pending, buffer));
+                    submitted += 1;
+                }
+                Err(reason) => {
+                    free.push(buffer);
+                    failure = Some(reason);
+                }
+            }
+        }
+
+        // Even after a failure, everything outstanding has to finish before its buffer and slot can go
+        let Some((pending, buffer)) = inFlight.pop_front() else {
+            break;
+        };
+
+        if let Err(reason) = drive.wait(pending) {
+            failure.get_or_insert(reason);
+        }
+
+        free.push(buffer);
+    }
+
+    let elapsed = clock::now() - start;
+    if let Some(reason) = failure {
+        return Err(reason);
+    }
+
+    let bytes = (requests * sectorsPerRequest * drive.getSectorSize()) as u64;
+    let microseconds = (elapsed / 1_000).max(1);
+    loggerWriteLine!(
+        "  Depth {:>2}: {} ms, {} KiB/s",
+        buffers.len(),
+        elapsed / 1_000_000,
+        bytes * 1_000_000 / 1024 / microseconds
+    );
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+use crate::{
+    ahci::{controller::Controller, sataDrive::SataDrive},
+    interupts::localApic,
+    loggerWriteLine,
+    memory::virtualMemory::VirtualMemoryManager,
+    pci::driver::{BoundDevice, PciDriver, PciId, registerDriver},
+};
+
+// Every drive we found, in discovery order; the index is how everyone else refers to them.
+// Drives and their controllers live forever once found, so they're leaked and handed out by reference. The only thing
+// in a SataDrive that changes after setup is its slot bookkeeping, which has its own lock.
+static DRIVES: Mutex<RefCell<Vec<&'static SataDrive>>> = Mutex::new(RefCell::new(Vec::new()));
+
+// Class - Mass Storage Controller
+// Subclass - Serial ATA Controller
+// Interface - AHCI
+const AHCI_IDS: &[PciId] = &[PciId::class(0x1, 0x6, Some(0x1))];
+
+// Has the PCI enumerator hand us every AHCI controller it finds
+pub fn register() {
+    registerDriver(PciDriver {
+        name: "ahci",
+        ids: AHCI_IDS,
+        probe: probeController,
+    });
+}
+
+// Brings up every SATA drive on the controller
+fn probeController(
+    vmm: &mut VirtualMemoryManager,
+    device: &BoundDevice,
+) -> Result<(), &'static str> {
+    let Some(mut controller) = Controller::tryGet(device) else {
+        return Err("No ABAR");
+    };
+
+    // Each controller gets its own vector, but they all go to the same place. Checking a port with nothing
+    // outstanding is cheap, so there's no need to keep track of who's who.
+    controller.enableInterrupts(device, handleInterrupt);
+
+    let controller: &'static Controller = Box::leak(Box::new(controller));
+    let ports = controller.enumeratePorts();
+    if ports == 0 {
+        loggerWriteLine!(
+            "Controller @ 0x{:X} doesn't have any SATA drives",
+            controller.getAddress()
+        );
+        return Ok(());
+    }
+
+    for port in 0..32 {
+        if ports & (1 << port) == 0 {
+            continue;
+        }
+
+        let mut drive = SataDrive::new(controller, port);
+        drive.stopCommands();
+        drive.remapStuff(vmm);
+        drive.startCommands();
+
+        if let Err(reason) = drive.identify(vmm) {
+            loggerWriteLine!("Port {} didn't IDENTIFY: {}", port, reason);
+            drive.stopCommands();
+            continue;
+        }
+
+        // Identified by polling, everything after this can use interrupts
+        drive.enableInterrupts();
+
+        let drive: &'static SataDrive = Box::leak(Box::new(drive));
+        let index = critical_section::with(|cs| {
+            let mut drives = DRIVES.borrow_ref_mut(cs);
+            drives.push(drive);
+            drives.len() - 1
+        });
+
+        loggerWriteLine!(
+            "Drive {} is controller 0x{:X} port {}",
+            index,
+            controller.getAddress(),
+            port
+        );
+
+        if let Some(identity) = drive.getIdentity() {
+            identity.dump();
+        }
+    }
+
+    loggerWriteLine!("Found {} drive(s)", getDriveCount());
+    Ok(())
+}
+
+// Whichever port it was, it'll have cleared its own status by the time poll is done
+fn handleInterrupt(_vector: u8) {
+    for index in 0..getDriveCount() {
+        if let Some(drive) = getDrive(index) {
+            drive.poll();
+        }
+    }
+
+    localApic::sendEoi();
+}
+
+pub fn getDriveCount() -> usize {
+    critical_section::with(|cs| DRIVES.borrow_ref(cs).len())
+}
+
+pub fn getDrive(index: usize) -> Option<&'static SataDrive> {
+    critical_section::with(|cs| DRIVES.borrow_ref(cs).get(index).copied())
+}
+
+pub fn dump() {
+    for index in 0..getDriveCount() {
+        if let Some(drive) = getDrive(index) {
+            loggerWriteLine!(
+                "  {}: controller 0x{:X} port {}, {} deep, NCQ: {} interrupts: {}",
+                index,
+                drive.getController().getAddress(),
+                drive.getPort(),
+                drive.getQueueDepth(),
+                drive.usesNcq(),
+                drive.usesInterrupts()
+            );
+
+            if let Some(identity) = drive.getIdentity() {
+                identity.dump();
+            }
+        }
+    }
+}
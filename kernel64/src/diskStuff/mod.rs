@@ -1 +1,4 @@
-pub mod read;
\ No newline at end of file
+pub mod benchmark;
+pub mod drives;
+pub mod read;
+pub mod volumes;
@@ -1,21 +1,183 @@
-use kernel_shared::assemblyStuff::halt::haltLoop;
+use alloc::vec;
+use core::slice::from_raw_parts;
 
-use crate::{acpi::rsdp::getRsdp, ahci::controller::Controller, loggerWriteLine, memory::virtualMemory::VirtualMemoryManager};
+use kernel_shared::{
+    disk::{
+        blockCache::BlockCache,
+        blockDevice::BlockDevice,
+        partitionTable::{PartitionInfo, readPartitions},
+    },
+    fileSystem::{SeekFrom, fat::fatFileSystem::FatFileSystem},
+    magicConstants::SIZE_OF_PAGE,
+    memoryHelpers::alignUp,
+};
 
+use crate::{
+    ahci::{sataBlockDevice::SataBlockDevice, sataDrive::SataDrive},
+    loggerWriteLine,
+    memory::virtualMemory::VirtualMemoryManager,
+};
+
+use super::drives::{getDrive, getDriveCount};
+
+// Sectors worth of storage the cache check gets
+const CACHE_SECTORS: usize = 4;
+
+// And the file system check, which rereads the FAT and directories a lot more
+const FILE_SYSTEM_CACHE_SECTORS: usize = 32;
+
+// GPT allows 128 out of the box; more than that and the rest just aren't looked at
+const MAX_PARTITIONS: usize = 128;
+
+// Something stage 2 already needs in the root of the boot volume
+const TEST_FILE: &str = "/HI.TXT";
+
+// Proves every drive works by reading the boot sector twice. Then the same through the block cache, and finally by
+// reading a file off the first FAT partition.
 pub fn readBytes(vmm: &mut VirtualMemoryManager) {
-    let rdsp = getRsdp(vmm);
-    if let Some(pciGeneralDevice) = rdsp {
-        loggerWriteLine!("Maybe can read some bytes from 0x{:X}", pciGeneralDevice as usize);
-        if let Some(drive) = Controller::tryGet(pciGeneralDevice) {
-            drive.stopCommands();
-            drive.remapStuff();
-            drive.startCommands();
-            loggerWriteLine!("Drive is partially configured");
-        } else {
-            loggerWriteLine!("No drive found");    
+    for index in 0..getDriveCount() {
+        let Some(drive) = getDrive(index) else {
+            continue;
+        };
+
+        // Room for two sectors, the original and the read back
+        let pages = alignUp(drive.getSectorSize() * 2, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+        let buffer = vmm.allocatePages("Sector buffer", pages);
+        if let Err(reason) = checkBootSector(vmm, drive, buffer) {
+            loggerWriteLine!("Drive {} doesn't work: {}", index, reason);
         }
-    } else {
-        loggerWriteLine!("Didn't find an AHCI controller");
+
+        vmm.freePages(buffer, pages);
+
+        match SataBlockDevice::new(vmm, drive) {
+            Ok(mut device) => {
+                if let Err(reason) = checkCache(&mut device) {
+                    loggerWriteLine!("Drive {} doesn't work through the cache: {}", index, reason);
+                }
+
+                if let Err(reason) = checkFileSystem(&mut device) {
+                    loggerWriteLine!("Drive {} has no readable FAT volume: {}", index, reason);
+                }
+
+                device.free(vmm);
+            }
+            Err(reason) => {
+                loggerWriteLine!("Drive {} isn't a block device: {}", index, reason);
+            }
+        }
+    }
+}
+
+fn checkBootSector(
+    vmm: &mut VirtualMemoryManager,
+    drive: &SataDrive,
+    buffer: usize,
+) -> Result<(), &'static str> {
+    let sectorSize = drive.getSectorSize();
+    let first = buffer as *mut u8;
+    let second = (buffer + sectorSize) as *mut u8;
+
+    drive.read(vmm, 0, 1, first)?;
+
+    let bytes = unsafe { from_raw_parts(first, sectorSize) };
+    loggerWriteLine!("LBA 0 starts with {:02X?}", &bytes[..16]);
+    loggerWriteLine!(
+        "Boot signature is 0x{:02X}{:02X}",
+        bytes[sectorSize - 2],
+        bytes[sectorSize - 1]
+    );
+
+    // Read only; nothing at boot should be writing to disks it knows nothing about
+    drive.read(vmm, 0, 1, second)?;
+
+    let again = unsafe { from_raw_parts(second, sectorSize) };
+    if bytes != again {
+        return Err("LBA 0 read differently the second time");
+    }
+
+    loggerWriteLine!("LBA 0 reads the same twice");
+    Ok(())
+}
+
+// Reading LBA 0 twice should only touch the drive once. Read only, like the rest of the boot checks.
+fn checkCache(device: &mut SataBlockDevice) -> Result<(), &'static str> {
+    let sectorSize = device.getSectorSize();
+    let mut storage = vec![0u8; sectorSize * CACHE_SECTORS];
+    let mut first = vec![0u8; sectorSize];
+    let mut second = vec![0u8; sectorSize];
+
+    let mut cache = BlockCache::new(device, &mut storage)?;
+    cache.read(0, &mut first)?;
+    cache.read(0, &mut second)?;
+    if first != second {
+        return Err("Cached copy of LBA 0 doesn't match");
     }
-    haltLoop();
+
+    let (hits, misses) = cache.getStats();
+    if hits != 1 || misses != 1 {
+        return Err("Second read didn't come from the cache");
+    }
+
+    loggerWriteLine!("LBA 0 came back from the block cache");
+    Ok(())
+}
+
+// Mounts the first FAT partition, lists the root and reads the test file twice to make sure seek works
+fn checkFileSystem(device: &mut SataBlockDevice) -> Result<(), &'static str> {
+    let mut partitions = vec![PartitionInfo::EMPTY; MAX_PARTITIONS];
+    let (scheme, count) = readPartitions(device, &mut partitions)?;
+    partitions.truncate(count);
+
+    loggerWriteLine!("{:?} partition table with {} partition(s)", scheme, count);
+    for partition in &partitions {
+        partition.dump();
+    }
+
+    let Some(fatPartition) = partitions.iter().find(|partition| partition.isFat()) else {
+        return Err("No FAT partitions");
+    };
+
+    let mut storage = vec![0u8; device.getSectorSize() * FILE_SYSTEM_CACHE_SECTORS];
+    let cache = BlockCache::new(device, &mut storage)?;
+    let mut fileSystem = FatFileSystem::mount(fatPartition.open(cache)?)?;
+    loggerWriteLine!(
+        "FAT partition {} @ LBA {}",
+        fatPartition.number,
+        fatPartition.startLba
+    );
+    fileSystem.dump();
+
+    let mut directory = fileSystem.openDirectory("/")?;
+    while let Some(info) = fileSystem.readDirectory(&mut directory)? {
+        loggerWriteLine!(
+            "  {:12} {:>10} {}",
+            info.getName(),
+            info.size,
+            if info.isDirectory() { "<DIR>" } else { "" }
+        );
+    }
+
+    let mut file = fileSystem.open(TEST_FILE)?;
+    let size = file.getInfo().size as usize;
+    let mut first = vec![0u8; size];
+    let mut second = vec![0u8; size];
+
+    if fileSystem.read(&mut file, &mut first)? != size {
+        return Err("Short read");
+    }
+
+    fileSystem.seek(&mut file, SeekFrom::Start(0))?;
+    if fileSystem.read(&mut file, &mut second)? != size || first != second {
+        return Err("Reading again after seeking back didn't match");
+    }
+
+    let preview = &first[..size.min(32)];
+    loggerWriteLine!(
+        "{} is {} bytes: {}",
+        TEST_FILE,
+        size,
+        core::str::from_utf8(preview).unwrap_or("(not text)")
+    );
+
+    Ok(())
 }
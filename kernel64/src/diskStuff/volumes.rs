@@ -0,0 +1,67 @@
+use alloc::{boxed::Box, format, vec, vec::Vec};
+
+use kernel_shared::{
+    disk::{
+        blockCache::BlockCache,
+        blockDevice::BlockDevice,
+        partitionTable::{PartitionInfo, readPartitions},
+    },
+    fileSystem::fat::fatFileSystem::FatFileSystem,
+};
+
+use crate::{
+    ahci::sataBlockDevice::SataBlockDevice,
+    loggerWriteLine,
+    memory::virtualMemory::VirtualMemoryManager,
+    vfs::{fatFs::FatFs, virtualFileSystem},
+};
+
+use super::drives::{getDrive, getDriveCount};
+
+// Sectors of cache each mounted volume gets
+const VOLUME_CACHE_SECTORS: usize = 64;
+
+// Same as the partition check in read; a GPT can have 128 of them
+const MAX_PARTITIONS: usize = 128;
+
+// Mounts the first FAT partition on every drive at /diskN, N being the drive index
+// BUGBUG: Any other FAT partitions on the drive don't get mounted
+pub fn mountVolumes(vmm: &mut VirtualMemoryManager) {
+    for index in 0..getDriveCount() {
+        let Some(drive) = getDrive(index) else {
+            continue;
+        };
+
+        let device = match SataBlockDevice::new(vmm, drive) {
+            Ok(device) => device,
+            Err(reason) => {
+                loggerWriteLine!("Drive {} isn't a block device: {}", index, reason);
+                continue;
+            }
+        };
+
+        let path = format!("/disk{}", index);
+        if let Err(reason) = mountVolume(device, &path) {
+            loggerWriteLine!("Nothing mounted from drive {}: {}", index, reason);
+        }
+    }
+}
+
+fn mountVolume(mut device: SataBlockDevice, path: &str) -> Result<(), &'static str> {
+    let mut partitions = vec![PartitionInfo::EMPTY; MAX_PARTITIONS];
+    let (_, count) = readPartitions(&mut device, &mut partitions)?;
+    partitions.truncate(count);
+
+    let Some(partition) = partitions.into_iter().find(|partition| partition.isFat()) else {
+        return Err("No FAT partitions");
+    };
+
+    // Mounted for good, so the drive's cache lives as long as the drive does
+    let storage: &'static mut [u8] =
+        Vec::leak(vec![0u8; device.getSectorSize() * VOLUME_CACHE_SECTORS]);
+    let cache = BlockCache::new(device, storage)?;
+    let fat = FatFileSystem::mount(partition.open(cache)?)?;
+
+    virtualFileSystem::createDirectory(path)?;
+    virtualFileSystem::mount(path, Box::new(FatFs::new(fat)))
+}
@@ -1,3 +1,6 @@
 pub mod InteruptDescriptorTable;
+pub mod ioApic;
+pub mod irq;
+pub mod localApic;
 pub mod setup;
 pub mod table;
\ No newline at end of file
@@ -1,6 +1,9 @@
 use core::arch::asm;
+use core::cell::Cell;
 use core::mem::size_of;
 
+use critical_section::Mutex;
+
 use kernel_shared::assemblyStuff::halt::haltLoop;
 use kernel_shared::memoryHelpers::zeroMemory2;
 use kernel_shared::physicalMemory::PhysicalMemoryManager;
@@ -64,6 +67,71 @@ pub struct ExceptionStackFrame {
     StackSegment: usize,
 }
 
+// Rust side handler for a vector. Gets the vector number so one function can service several.
+pub type InterruptHandler = fn(vector: u8);
+
+static HANDLERS: Mutex<[Cell<Option<InterruptHandler>>; 256]> =
+    Mutex::new([const { Cell::new(None) }; 256]);
+
+// Routes the given vector to handler instead of the default log (and usually halt) path
+pub fn registerHandler(vector: u8, handler: InterruptHandler) {
+    critical_section::with(|cs| {
+        let previous = HANDLERS.borrow(cs)[vector as usize].replace(Some(handler));
+        if previous.is_some() {
+            loggerWriteLine!("Replacing existing handler for vector 0x{:X}", vector);
+        }
+    });
+}
+
+// Vectors nobody has a fixed use for, handed out to whoever asks. Above the IRQs and the timer, below the APIC's own.
+const DYNAMIC_VECTOR_FIRST: u8 = 0x50;
+const DYNAMIC_VECTOR_LAST: u8 = 0xEF;
+
+// Finds count free vectors in a row, starting on a multiple of count, and routes them all to handler. MSI needs the
+// alignment since the device ORs the message number into the low bits, so count has to be a power of 2.
+pub fn allocateVectors(count: usize, handler: InterruptHandler) -> Option<u8> {
+    let start = (DYNAMIC_VECTOR_FIRST as usize).next_multiple_of(count.max(1));
+    let end = DYNAMIC_VECTOR_LAST as usize + 1;
+    if count == 0 || !count.is_power_of_two() || start + count > end {
+        return None;
+    }
+
+    let first = critical_section::with(|cs| {
+        let handlers = HANDLERS.borrow(cs);
+        let first = (start..=end - count).step_by(count).find(|first| {
+            handlers[*first..*first + count]
+                .iter()
+                .all(|slot| slot.get().is_none())
+        })?;
+
+        for slot in handlers[first..first + count].iter() {
+            slot.set(Some(handler));
+        }
+
+        Some(first as u8)
+    });
+
+    if first.is_none() {
+        loggerWriteLine!("No room for {} more vector(s)", count);
+    }
+
+    first
+}
+
+// Gives back what allocateVectors handed out
+pub fn freeVectors(first: u8, count: usize) {
+    critical_section::with(|cs| {
+        let handlers = HANDLERS.borrow(cs);
+        for slot in handlers[first as usize..first as usize + count].iter() {
+            slot.set(None);
+        }
+    });
+}
+
+fn getHandler(vector: u8) -> Option<InterruptHandler> {
+    critical_section::with(|cs| HANDLERS.borrow(cs)[vector as usize].get())
+}
+
 // Interupt Descriptor Table
 pub struct IDT {
     idtr: *mut IDTR,
@@ -105,11 +173,11 @@ impl IDT {
                 "lidt [{0}]",
                 //"ljmp $2f", // BUGBUG? OS Dev says do a long jump after loading the table
                 "2:",
-                "sti",
                 in(reg) idtr,
             );
         }
 
+        // Interrupts are left alone. Whoever is setting things up turns them on once the PICs and handlers are ready.
         return IDT { idtr };
     }
 }
@@ -117,6 +185,11 @@ impl IDT {
 #[inline(never)]
 #[unsafe(no_mangle)]
 pub fn InterruptHandlerIntImpl(vector: u8, stackFrame: ExceptionStackFrame) {
+    if let Some(handler) = getHandler(vector) {
+        handler(vector);
+        return;
+    }
+
     let cs = stackFrame.CodeSegment;
     let ip = stackFrame.InstructionPointer;
     let flags = stackFrame.CpuFlags;
@@ -204,7 +277,6 @@ pub unsafe fn SetIDT(memoryManager: &mut PhysicalMemoryManager) -> usize {
             "lidt [{0}]",
             //"ljmp $2f", // BUGBUG? OS Dev says do a long jump after loading the table
             "2:",
-            "sti",
             in(reg) idtr,
         );
     }
@@ -0,0 +1,256 @@
+use core::{
+    cell::RefCell,
+    ptr::{read_volatile, write_volatile},
+};
+
+use critical_section::Mutex;
+use kernel_shared::{pageTable::enums::*, pic::IRQ_COUNT};
+
+use crate::{acpi::madt::MadtInfo, loggerWriteLine, memory::virtualMemory::VirtualMemoryManager};
+
+// https://wiki.osdev.org/IOAPIC
+// 82093AA I/O Advanced Programmable Interrupt Controller datasheet
+// Everything is accessed indirectly: write the register number to IOREGSEL, then read/write IOWIN.
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+
+const REGISTER_ID: u32 = 0x00;
+const REGISTER_VERSION: u32 = 0x01;
+const REGISTER_REDIRECTION_TABLE: u32 = 0x10; // Two registers per entry, low dword first
+
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL_TRIGGERED: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+const REDIRECTION_DESTINATION_SHIFT: u64 = 56;
+
+const MAX_IO_APICS: usize = 4;
+
+#[derive(Clone, Copy)]
+struct IoApic {
+    base: usize, // Virtual
+    id: u8,
+    gsiBase: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    fn read(&self, register: u32) -> u32 {
+        unsafe {
+            write_volatile((self.base + IOREGSEL) as *mut u32, register);
+            read_volatile((self.base + IOWIN) as *const u32)
+        }
+    }
+
+    fn write(&self, register: u32, value: u32) {
+        unsafe {
+            write_volatile((self.base + IOREGSEL) as *mut u32, register);
+            write_volatile((self.base + IOWIN) as *mut u32, value);
+        }
+    }
+
+    fn readRedirection(&self, index: u32) -> u64 {
+        let low = self.read(REGISTER_REDIRECTION_TABLE + index * 2) as u64;
+        let high = self.read(REGISTER_REDIRECTION_TABLE + index * 2 + 1) as u64;
+        high << 32 | low
+    }
+
+    fn writeRedirection(&self, index: u32, value: u64) {
+        // Low has the mask bit, so write high first to avoid it firing with a half written entry
+        self.write(
+            REGISTER_REDIRECTION_TABLE + index * 2 + 1,
+            (value >> 32) as u32,
+        );
+        self.write(REGISTER_REDIRECTION_TABLE + index * 2, value as u32);
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsiBase && gsi < self.gsiBase + self.entries
+    }
+}
+
+// Where an ISA IRQ ended up. Identity mapped, active high, edge triggered unless the MADT says otherwise.
+#[derive(Clone, Copy)]
+struct IsaRoute {
+    gsi: u32,
+    activeLow: bool,
+    levelTriggered: bool,
+}
+
+struct IoApicState {
+    ioApics: [Option<IoApic>; MAX_IO_APICS],
+    isaRoutes: [IsaRoute; IRQ_COUNT as usize],
+}
+
+static IO_APICS: Mutex<RefCell<IoApicState>> = Mutex::new(RefCell::new(IoApicState {
+    ioApics: [None; MAX_IO_APICS],
+    isaRoutes: [IsaRoute {
+        gsi: 0,
+        activeLow: false,
+        levelTriggered: false,
+    }; IRQ_COUNT as usize],
+}));
+
+#[derive(Clone, Copy)]
+pub struct Redirection {
+    pub vector: u8,
+    pub destination: u8, // Local APIC ID
+    pub activeLow: bool,
+    pub levelTriggered: bool,
+    pub masked: bool,
+}
+
+impl Redirection {
+    // Fixed delivery, physical destination mode
+    fn getBits(&self) -> u64 {
+        let mut result = self.vector as u64;
+        if self.activeLow {
+            result |= REDIRECTION_ACTIVE_LOW;
+        }
+
+        if self.levelTriggered {
+            result |= REDIRECTION_LEVEL_TRIGGERED;
+        }
+
+        if self.masked {
+            result |= REDIRECTION_MASKED;
+        }
+
+        result | (self.destination as u64) << REDIRECTION_DESTINATION_SHIFT
+    }
+}
+
+// Maps every I/O APIC the MADT knows about and masks all of their inputs
+pub fn init(vmm: &mut VirtualMemoryManager, madt: &MadtInfo) -> bool {
+    let mut ioApics = [None; MAX_IO_APICS];
+    let mut count = 0;
+
+    for info in madt.ioApics() {
+        if count == MAX_IO_APICS {
+            break;
+        }
+
+        let virtualAddress = vmm.mapPhysicalAnywhere(
+            info.address as usize,
+            0x1000,
+            Execute::Yes,
+            Present::Yes,
+            Writable::Yes,
+            Cachable::No,
+            UserSupervisor::Supervisor,
+            WriteThrough::WriteTrough,
+        );
+
+        let mut ioApic = IoApic {
+            base: virtualAddress,
+            id: info.id,
+            gsiBase: info.gsiBase,
+            entries: 0,
+        };
+
+        // Bits 16-23 are the index of the last entry
+        let version = ioApic.read(REGISTER_VERSION);
+        ioApic.entries = ((version >> 16) & 0xFF) + 1;
+
+        for index in 0..ioApic.entries {
+            ioApic.writeRedirection(index, REDIRECTION_MASKED);
+        }
+
+        loggerWriteLine!(
+            "I/O APIC {} (ID reg 0x{:X}) @ 0x{:X} / 0x{:X} (P/V) GSIs {}..{}",
+            ioApic.id,
+            ioApic.read(REGISTER_ID) >> 24,
+            info.address,
+            virtualAddress,
+            ioApic.gsiBase,
+            ioApic.gsiBase + ioApic.entries
+        );
+
+        ioApics[count] = Some(ioApic);
+        count += 1;
+    }
+
+    if count == 0 {
+        loggerWriteLine!("No I/O APICs");
+        return false;
+    }
+
+    let mut isaRoutes = [IsaRoute {
+        gsi: 0,
+        activeLow: false,
+        levelTriggered: false,
+    }; IRQ_COUNT as usize];
+
+    for (irq, route) in isaRoutes.iter_mut().enumerate() {
+        route.gsi = irq as u32;
+    }
+
+    for o in madt.overrides() {
+        if (o.source as usize) < isaRoutes.len() {
+            isaRoutes[o.source as usize] = IsaRoute {
+                gsi: o.gsi,
+                activeLow: o.activeLow,
+                levelTriggered: o.levelTriggered,
+            };
+        }
+    }
+
+    critical_section::with(|cs| {
+        let mut state = IO_APICS.borrow_ref_mut(cs);
+        state.ioApics = ioApics;
+        state.isaRoutes = isaRoutes;
+    });
+
+    true
+}
+
+pub fn setRedirection(gsi: u32, redirection: &Redirection) -> bool {
+    critical_section::with(|cs| {
+        let state = IO_APICS.borrow_ref(cs);
+        let Some(ioApic) = state.ioApics.iter().flatten().find(|i| i.handles(gsi)) else {
+            return false;
+        };
+
+        ioApic.writeRedirection(gsi - ioApic.gsiBase, redirection.getBits());
+        true
+    })
+}
+
+pub fn setMasked(gsi: u32, masked: bool) -> bool {
+    critical_section::with(|cs| {
+        let state = IO_APICS.borrow_ref(cs);
+        let Some(ioApic) = state.ioApics.iter().flatten().find(|i| i.handles(gsi)) else {
+            return false;
+        };
+
+        let index = gsi - ioApic.gsiBase;
+        let mut value = ioApic.readRedirection(index);
+        if masked {
+            value |= REDIRECTION_MASKED;
+        } else {
+            value &= !REDIRECTION_MASKED;
+        }
+
+        ioApic.writeRedirection(index, value);
+        true
+    })
+}
+
+pub fn getIsaGsi(irq: u8) -> u32 {
+    critical_section::with(|cs| IO_APICS.borrow_ref(cs).isaRoutes[irq as usize].gsi)
+}
+
+// Points an ISA IRQ at vector on the given CPU, following any interrupt source overrides. Left unmasked.
+pub fn routeIsaIrq(irq: u8, vector: u8, destination: u8) -> bool {
+    let route = critical_section::with(|cs| IO_APICS.borrow_ref(cs).isaRoutes[irq as usize]);
+
+    setRedirection(
+        route.gsi,
+        &Redirection {
+            vector,
+            destination,
+            activeLow: route.activeLow,
+            levelTriggered: route.levelTriggered,
+            masked: false,
+        },
+    )
+}
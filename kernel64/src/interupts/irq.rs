@@ -0,0 +1,213 @@
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use kernel_shared::{
+    assemblyStuff::misc::{areInterruptsEnabled, disableInterrupts, enableInterrupts},
+    pic::{self, IRQ_COUNT},
+};
+
+use crate::{
+    acpi::madt::{MadtInfo, getMadt},
+    loggerWriteLine,
+    memory::virtualMemory::VirtualMemoryManager,
+};
+
+use super::{InteruptDescriptorTable::registerHandler, ioApic, localApic};
+
+// Hardware IRQ dispatch. Starts out on the legacy PICs and can be moved over to the APICs once ACPI is
+// reachable. Drivers register for an ISA IRQ number and get called with it; masking, routing, spurious
+// IRQs and EOI are all taken care of here.
+pub type IrqHandler = fn(irq: u8);
+
+// ISA IRQs go here once the I/O APIC is in charge. Above the PIC vectors so a stray one from them is
+// still recognizable.
+pub const APIC_IRQ_VECTOR_OFFSET: u8 = 0x30;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Controller {
+    Pic,
+    Apic,
+}
+
+static CONTROLLER: Mutex<Cell<Controller>> = Mutex::new(Cell::new(Controller::Pic));
+
+static IRQ_HANDLERS: Mutex<[Cell<Option<IrqHandler>>; IRQ_COUNT as usize]> =
+    Mutex::new([const { Cell::new(None) }; IRQ_COUNT as usize]);
+
+static SPURIOUS_COUNT: Mutex<Cell<usize>> = Mutex::new(Cell::new(0));
+
+// Remaps the PICs and takes over their vectors. Everything stays masked until someone registers.
+// Interrupts are left however they were; enable them once the drivers you care about are registered.
+pub fn init() {
+    pic::remap();
+
+    for irq in 0..IRQ_COUNT {
+        registerHandler(pic::irqToVector(irq), dispatch);
+    }
+
+    loggerWriteLine!(
+        "IRQs 0-{} on vectors 0x{:X}-0x{:X}",
+        IRQ_COUNT - 1,
+        pic::irqToVector(0),
+        pic::irqToVector(IRQ_COUNT - 1)
+    );
+}
+
+pub fn getController() -> Controller {
+    critical_section::with(|cs| CONTROLLER.borrow(cs).get())
+}
+
+// Moves IRQ delivery from the PICs to the local + I/O APICs described by the MADT. Anything already
+// registered gets rerouted. If there's no MADT (or no I/O APIC) we stay on the PICs and return false.
+pub fn switchToApic(vmm: &mut VirtualMemoryManager) -> bool {
+    let Some(madt) = getMadt(vmm) else {
+        loggerWriteLine!("No MADT, staying on the PICs");
+        return false;
+    };
+
+    madt.dump();
+
+    let wereEnabled = areInterruptsEnabled();
+    disableInterrupts();
+
+    let result = switchToApicInternal(vmm, &madt);
+
+    if wereEnabled {
+        enableInterrupts();
+    }
+
+    result
+}
+
+fn switchToApicInternal(vmm: &mut VirtualMemoryManager, madt: &MadtInfo) -> bool {
+    if !localApic::init(vmm, madt) {
+        return false;
+    }
+
+    if !ioApic::init(vmm, madt) {
+        loggerWriteLine!("Staying on the PICs");
+        return false;
+    }
+
+    for irq in 0..IRQ_COUNT {
+        registerHandler(APIC_IRQ_VECTOR_OFFSET + irq, dispatch);
+    }
+
+    // The PIC vectors keep pointing at dispatch so anything already latched in them gets cleaned up
+    pic::maskAll();
+    critical_section::with(|cs| CONTROLLER.borrow(cs).set(Controller::Apic));
+
+    let destination = localApic::getId();
+    for irq in 0..IRQ_COUNT {
+        let handler = critical_section::with(|cs| IRQ_HANDLERS.borrow(cs)[irq as usize].get());
+        if handler.is_some() {
+            ioApic::routeIsaIrq(irq, APIC_IRQ_VECTOR_OFFSET + irq, destination);
+        }
+    }
+
+    loggerWriteLine!(
+        "IRQs 0-{} now on vectors 0x{:X}-0x{:X} via the I/O APIC",
+        IRQ_COUNT - 1,
+        APIC_IRQ_VECTOR_OFFSET,
+        APIC_IRQ_VECTOR_OFFSET + IRQ_COUNT - 1
+    );
+
+    true
+}
+
+// Also unmasks the line, so have the device ready (or at least quiet) before calling this
+pub fn registerIrqHandler(irq: u8, handler: IrqHandler) {
+    if irq >= IRQ_COUNT || irq == pic::CASCADE_IRQ {
+        loggerWriteLine!("Can't register for IRQ {}", irq);
+        return;
+    }
+
+    critical_section::with(|cs| {
+        let previous = IRQ_HANDLERS.borrow(cs)[irq as usize].replace(Some(handler));
+        if previous.is_some() {
+            loggerWriteLine!("Replacing existing handler for IRQ {}", irq);
+        }
+    });
+
+    match getController() {
+        Controller::Pic => pic::unmask(irq),
+        Controller::Apic => {
+            ioApic::routeIsaIrq(irq, APIC_IRQ_VECTOR_OFFSET + irq, localApic::getId());
+        }
+    }
+}
+
+pub fn unregisterIrqHandler(irq: u8) {
+    if irq >= IRQ_COUNT {
+        return;
+    }
+
+    mask(irq);
+    critical_section::with(|cs| {
+        IRQ_HANDLERS.borrow(cs)[irq as usize].set(None);
+    });
+}
+
+pub fn spuriousCount() -> usize {
+    critical_section::with(|cs| SPURIOUS_COUNT.borrow(cs).get())
+}
+
+fn mask(irq: u8) {
+    match getController() {
+        Controller::Pic => pic::mask(irq),
+        Controller::Apic => {
+            ioApic::setMasked(ioApic::getIsaGsi(irq), true);
+        }
+    }
+}
+
+fn countSpurious() {
+    critical_section::with(|cs| {
+        let count = SPURIOUS_COUNT.borrow(cs);
+        count.set(count.get() + 1);
+    });
+}
+
+fn dispatch(vector: u8) {
+    if vector >= APIC_IRQ_VECTOR_OFFSET && vector < APIC_IRQ_VECTOR_OFFSET + IRQ_COUNT {
+        dispatchApic(vector - APIC_IRQ_VECTOR_OFFSET);
+        return;
+    }
+
+    let Some(irq) = pic::vectorToIrq(vector) else {
+        return;
+    };
+
+    if pic::isSpurious(irq) {
+        pic::acknowledgeSpurious(irq);
+        countSpurious();
+        return;
+    }
+
+    if getController() == Controller::Apic {
+        // Was latched in the PIC before it got masked. The device will come around again via the I/O APIC.
+        pic::sendEoi(irq);
+        countSpurious();
+        return;
+    }
+
+    callHandler(irq);
+    pic::sendEoi(irq);
+}
+
+fn dispatchApic(irq: u8) {
+    callHandler(irq);
+    localApic::sendEoi();
+}
+
+fn callHandler(irq: u8) {
+    let handler = critical_section::with(|cs| IRQ_HANDLERS.borrow(cs)[irq as usize].get());
+    match handler {
+        Some(handler) => handler(irq),
+        None => {
+            // Nobody asked for this, so something unmasked it behind our back. Shut it up rather than halt.
+            loggerWriteLine!("Unhandled IRQ {}, masking it", irq);
+            mask(irq);
+        }
+    }
+}
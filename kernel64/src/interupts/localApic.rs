@@ -0,0 +1,259 @@
+use core::{
+    cell::Cell,
+    ptr::{read_volatile, write_volatile},
+};
+
+use critical_section::Mutex;
+use kernel_shared::pageTable::enums::*;
+
+use crate::{
+    acpi::madt::{ALL_PROCESSORS, MadtInfo},
+    assemblyHelpers::{readMsr, writeMsr},
+    loggerWriteLine,
+    memory::virtualMemory::VirtualMemoryManager,
+};
+
+use super::InteruptDescriptorTable::registerHandler;
+
+// Intel Volume 3A, Chapter 11: Advanced Programmable Interrupt Controller (APIC)
+// https://wiki.osdev.org/APIC
+const IA32_APIC_BASE_MSR: u32 = 0x1B;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0xF_FFFF_F000;
+
+// Register offsets from the base. Every one of these is 32 bits on a 16 byte boundary.
+const REGISTER_ID: usize = 0x20;
+const REGISTER_VERSION: usize = 0x30;
+const REGISTER_TASK_PRIORITY: usize = 0x80;
+const REGISTER_EOI: usize = 0xB0;
+const REGISTER_SPURIOUS: usize = 0xF0;
+const REGISTER_ERROR_STATUS: usize = 0x280;
+const REGISTER_LVT_TIMER: usize = 0x320;
+const REGISTER_LVT_LINT0: usize = 0x350;
+const REGISTER_LVT_LINT1: usize = 0x360;
+const REGISTER_LVT_ERROR: usize = 0x370;
+const REGISTER_TIMER_INITIAL_COUNT: usize = 0x380;
+const REGISTER_TIMER_CURRENT_COUNT: usize = 0x390;
+const REGISTER_TIMER_DIVIDE: usize = 0x3E0;
+
+// Where a device writes to interrupt a processor
+const MSI_ADDRESS_BASE: u64 = 0xFEE0_0000;
+
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+
+// Local Vector Table bits
+const LVT_DELIVERY_NMI: u32 = 0b100 << 8;
+const LVT_ACTIVE_LOW: u32 = 1 << 13;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+
+// Spurious has to have the low 4 bits set on older parts, so 0xFF it is
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+pub const ERROR_VECTOR: u8 = 0xFE;
+pub const TIMER_VECTOR: u8 = 0x40;
+
+#[derive(Clone, Copy)]
+pub enum TimerDivide {
+    By1,
+    By2,
+    By4,
+    By8,
+    By16,
+    By32,
+    By64,
+    By128,
+}
+
+impl TimerDivide {
+    // Bits 0, 1 and 3 of the divide configuration register. Yes, bit 2 is skipped.
+    fn getBits(&self) -> u32 {
+        match self {
+            TimerDivide::By1 => 0b1011,
+            TimerDivide::By2 => 0b0000,
+            TimerDivide::By4 => 0b0001,
+            TimerDivide::By8 => 0b0010,
+            TimerDivide::By16 => 0b0011,
+            TimerDivide::By32 => 0b1000,
+            TimerDivide::By64 => 0b1001,
+            TimerDivide::By128 => 0b1010,
+        }
+    }
+
+    pub fn getValue(&self) -> u32 {
+        match self {
+            TimerDivide::By1 => 1,
+            TimerDivide::By2 => 2,
+            TimerDivide::By4 => 4,
+            TimerDivide::By8 => 8,
+            TimerDivide::By16 => 16,
+            TimerDivide::By32 => 32,
+            TimerDivide::By64 => 64,
+            TimerDivide::By128 => 128,
+        }
+    }
+}
+
+// Virtual address of this CPU's APIC registers, 0 until init
+static LOCAL_APIC_BASE: Mutex<Cell<usize>> = Mutex::new(Cell::new(0));
+
+fn getBase() -> usize {
+    critical_section::with(|cs| LOCAL_APIC_BASE.borrow(cs).get())
+}
+
+fn readRegister(offset: usize) -> u32 {
+    unsafe { read_volatile((getBase() + offset) as *const u32) }
+}
+
+fn writeRegister(offset: usize, value: u32) {
+    unsafe { write_volatile((getBase() + offset) as *mut u32, value) }
+}
+
+pub fn isEnabled() -> bool {
+    getBase() != 0
+}
+
+pub fn init(vmm: &mut VirtualMemoryManager, madt: &MadtInfo) -> bool {
+    let mut apicBase = readMsr(IA32_APIC_BASE_MSR);
+    let msrAddress = apicBase & APIC_BASE_ADDRESS_MASK;
+    let physicalAddress = madt.localApicAddress;
+
+    if msrAddress != physicalAddress {
+        loggerWriteLine!(
+            "Local APIC is @ 0x{:X} but MADT says 0x{:X}, moving it",
+            msrAddress,
+            physicalAddress
+        );
+        apicBase =
+            (apicBase & !APIC_BASE_ADDRESS_MASK) | (physicalAddress & APIC_BASE_ADDRESS_MASK);
+    }
+
+    writeMsr(IA32_APIC_BASE_MSR, apicBase | APIC_BASE_ENABLE);
+
+    // MMIO, so no caching
+    // BUGBUG: Execute::No faults because nobody turns on NXE, same mystery as the other mappings
+    let virtualAddress = vmm.mapPhysicalAnywhere(
+        physicalAddress as usize,
+        0x1000,
+        Execute::Yes,
+        Present::Yes,
+        Writable::Yes,
+        Cachable::No,
+        UserSupervisor::Supervisor,
+        WriteThrough::WriteTrough,
+    );
+
+    critical_section::with(|cs| LOCAL_APIC_BASE.borrow(cs).set(virtualAddress));
+
+    registerHandler(SPURIOUS_VECTOR, handleSpurious);
+    registerHandler(ERROR_VECTOR, handleError);
+
+    // Accept everything
+    writeRegister(REGISTER_TASK_PRIORITY, 0);
+
+    // Nothing on the timer until someone asks. LINT0 is where the 8259s would come in as ExtINT, but
+    // everything is going through the I/O APIC now.
+    writeRegister(REGISTER_LVT_TIMER, LVT_MASKED | TIMER_VECTOR as u32);
+    writeRegister(REGISTER_LVT_LINT0, LVT_MASKED);
+    writeRegister(REGISTER_LVT_LINT1, LVT_MASKED);
+
+    let id = getId();
+    let processorUid = madt
+        .processors()
+        .iter()
+        .find(|p| p.apicId == id)
+        .map(|p| p.processorUid);
+
+    for nmi in madt.nmis() {
+        if nmi.processorUid != ALL_PROCESSORS && Some(nmi.processorUid) != processorUid {
+            continue;
+        }
+
+        // SDM Vol 3A, Local Vector Table: NMI delivery is always edge sensitive, so whatever the MADT says about the trigger mode is
+        // ignored and only the polarity is used
+        let mut value = LVT_DELIVERY_NMI;
+        if nmi.activeLow {
+            value |= LVT_ACTIVE_LOW;
+        }
+
+        match nmi.lint {
+            0 => writeRegister(REGISTER_LVT_LINT0, value),
+            1 => writeRegister(REGISTER_LVT_LINT1, value),
+            _ => {
+                loggerWriteLine!("NMI on LINT{}? There's only 2.", nmi.lint);
+            }
+        }
+    }
+
+    writeRegister(REGISTER_LVT_ERROR, ERROR_VECTOR as u32);
+
+    // Error status has to be written before it'll update, do it twice to clear anything from back when
+    writeRegister(REGISTER_ERROR_STATUS, 0);
+    writeRegister(REGISTER_ERROR_STATUS, 0);
+
+    writeRegister(
+        REGISTER_SPURIOUS,
+        SPURIOUS_APIC_ENABLE | SPURIOUS_VECTOR as u32,
+    );
+
+    // In case something was left in service
+    sendEoi();
+
+    let version = readRegister(REGISTER_VERSION);
+    loggerWriteLine!(
+        "Local APIC {} @ 0x{:X} / 0x{:X} (P/V) version 0x{:X} with {} LVT entries",
+        id,
+        physicalAddress,
+        virtualAddress,
+        version & 0xFF,
+        ((version >> 16) & 0xFF) + 1
+    );
+
+    true
+}
+
+pub fn getId() -> u8 {
+    (readRegister(REGISTER_ID) >> 24) as u8
+}
+
+// 11.11.1 Message Address Register Format: fixed delivery, physical destination. The data is just the vector.
+pub fn getMsiAddress(destination: u8) -> u64 {
+    MSI_ADDRESS_BASE | (destination as u64) << 12
+}
+
+pub fn sendEoi() {
+    writeRegister(REGISTER_EOI, 0);
+}
+
+// Timer counts down from initialCount at the bus/core crystal clock divided by divide. It doesn't say what that
+// frequency is, so someone has to calibrate it against a clock that does.
+pub fn startTimer(vector: u8, initialCount: u32, divide: TimerDivide, periodic: bool) {
+    let mut lvt = vector as u32;
+    if periodic {
+        lvt |= LVT_TIMER_PERIODIC;
+    }
+
+    writeRegister(REGISTER_TIMER_DIVIDE, divide.getBits());
+    writeRegister(REGISTER_LVT_TIMER, lvt);
+
+    // Writing the count is what starts it
+    writeRegister(REGISTER_TIMER_INITIAL_COUNT, initialCount);
+}
+
+pub fn stopTimer() {
+    writeRegister(REGISTER_TIMER_INITIAL_COUNT, 0);
+    writeRegister(REGISTER_LVT_TIMER, LVT_MASKED | TIMER_VECTOR as u32);
+}
+
+pub fn getTimerCurrentCount() -> u32 {
+    readRegister(REGISTER_TIMER_CURRENT_COUNT)
+}
+
+// Spurious interrupts don't get an EOI
+fn handleSpurious(_vector: u8) {}
+
+fn handleError(_vector: u8) {
+    writeRegister(REGISTER_ERROR_STATUS, 0);
+    let status = readRegister(REGISTER_ERROR_STATUS);
+    loggerWriteLine!("Local APIC error 0x{:X}", status);
+    sendEoi();
+}
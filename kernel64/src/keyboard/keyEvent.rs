@@ -0,0 +1,176 @@
+// Physical keys on a US 104 key keyboard. Named for what's printed on the key, not what it types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+
+    Backtick,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Digit0,
+    Minus,
+    Equals,
+    Backspace,
+
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+
+    CapsLock,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Quote,
+    Enter,
+
+    LeftShift,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    RightShift,
+
+    LeftControl,
+    LeftGui,
+    LeftAlt,
+    Space,
+    RightAlt,
+    RightGui,
+    Menu,
+    RightControl,
+
+    Insert,
+    Home,
+    PageUp,
+    Delete,
+    End,
+    PageDown,
+
+    ArrowUp,
+    ArrowLeft,
+    ArrowDown,
+    ArrowRight,
+
+    NumLock,
+    NumpadDivide,
+    NumpadMultiply,
+    NumpadSubtract,
+    NumpadAdd,
+    NumpadEnter,
+    NumpadDecimal,
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub leftShift: bool,
+    pub rightShift: bool,
+    pub leftControl: bool,
+    pub rightControl: bool,
+    pub leftAlt: bool,
+    pub rightAlt: bool,
+    pub leftGui: bool,
+    pub rightGui: bool,
+
+    // Lock keys are toggled on press, these are the current states (matching the LEDs)
+    pub capsLock: bool,
+    pub numLock: bool,
+    pub scrollLock: bool,
+}
+
+impl Modifiers {
+    pub const fn new() -> Self {
+        Modifiers {
+            leftShift: false,
+            rightShift: false,
+            leftControl: false,
+            rightControl: false,
+            leftAlt: false,
+            rightAlt: false,
+            leftGui: false,
+            rightGui: false,
+            capsLock: false,
+            numLock: false,
+            scrollLock: false,
+        }
+    }
+
+    pub fn shift(&self) -> bool {
+        self.leftShift || self.rightShift
+    }
+
+    pub fn control(&self) -> bool {
+        self.leftControl || self.rightControl
+    }
+
+    pub fn alt(&self) -> bool {
+        self.leftAlt || self.rightAlt
+    }
+
+    pub fn gui(&self) -> bool {
+        self.leftGui || self.rightGui
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub pressed: bool, // false for release
+    pub repeat: bool,  // Key was already down and this is the typematic repeat
+    pub modifiers: Modifiers, // State after this event was applied
+    pub character: Option<char>, // What this types, if anything. Only set on presses.
+}
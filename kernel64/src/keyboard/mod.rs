@@ -0,0 +1,3 @@
+pub mod keyEvent;
+pub mod ps2Keyboard;
+pub mod scanCodeSet1;
@@ -0,0 +1,291 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use kernel_shared::{
+    assemblyStuff::{
+        misc::{disableInterrupts, enableInterrupts, enableInterruptsAndHalt},
+        ports::{inB, outB},
+    },
+    ringBuffer::RingBuffer,
+};
+
+use crate::{interupts::irq::registerIrqHandler, loggerWriteLine};
+
+use super::{keyEvent::KeyCode, keyEvent::KeyEvent, scanCodeSet1::ScanCodeDecoder};
+
+// https://wiki.osdev.org/I8042_PS/2_Controller
+// https://wiki.osdev.org/PS/2_Keyboard
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64; // Read
+const COMMAND_PORT: u16 = 0x64; // Write
+
+const STATUS_OUTPUT_FULL: u8 = 0x01; // Something for us to read from DATA_PORT
+const STATUS_INPUT_FULL: u8 = 0x02; // Controller hasn't consumed what we last wrote yet
+
+const CONTROLLER_READ_CONFIG: u8 = 0x20;
+const CONTROLLER_WRITE_CONFIG: u8 = 0x60;
+
+const CONFIG_PORT1_INTERRUPT: u8 = 0x01;
+const CONFIG_PORT1_CLOCK_DISABLED: u8 = 0x10;
+const CONFIG_PORT1_TRANSLATION: u8 = 0x40;
+
+const KEYBOARD_SET_LEDS: u8 = 0xED;
+const KEYBOARD_SET_TYPEMATIC: u8 = 0xF3;
+
+const RESPONSE_ACK: u8 = 0xFA;
+const RESPONSE_RESEND: u8 = 0xFE;
+const RESPONSE_ERROR_1: u8 = 0x00; // Key detection error / buffer overrun
+const RESPONSE_ERROR_2: u8 = 0xFF;
+
+// Bits 0-4 rate (0 = 30/s), bits 5-6 delay (0 = 250ms). 250ms / ~10.9/s is close to what everyone is used to.
+const TYPEMATIC_DEFAULT: u8 = 0b0_01_01011;
+
+const LED_SCROLL_LOCK: u8 = 0x01;
+const LED_NUM_LOCK: u8 = 0x02;
+const LED_CAPS_LOCK: u8 = 0x04;
+
+const KEYBOARD_IRQ: u8 = 1;
+
+// Polling limit while talking to the controller during init; real hardware answers way before this
+const MAX_LOOP_VALUE: usize = 100_000;
+const MAX_RESENDS: u8 = 3;
+
+struct KeyboardState {
+    decoder: ScanCodeDecoder,
+    events: RingBuffer<KeyEvent, 128>,
+    // Bytes waiting to go to the keyboard. Only one is in flight at a time since the keyboard
+    // needs to ACK each before it'll take the next.
+    commands: RingBuffer<u8, 8>,
+    inFlight: Option<u8>,
+    resends: u8,
+    dropped: usize,
+}
+
+static KEYBOARD: Mutex<RefCell<KeyboardState>> = Mutex::new(RefCell::new(KeyboardState {
+    decoder: ScanCodeDecoder::new(),
+    events: RingBuffer::new(),
+    commands: RingBuffer::new(),
+    inFlight: None,
+    resends: 0,
+    dropped: 0,
+}));
+
+fn waitToWrite() -> bool {
+    for _ in 0..MAX_LOOP_VALUE {
+        if unsafe { inB(STATUS_PORT) } & STATUS_INPUT_FULL == 0 {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn waitToRead() -> bool {
+    for _ in 0..MAX_LOOP_VALUE {
+        if unsafe { inB(STATUS_PORT) } & STATUS_OUTPUT_FULL != 0 {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn writeController(command: u8) {
+    if waitToWrite() {
+        unsafe {
+            outB(COMMAND_PORT, command);
+        }
+    }
+}
+
+fn writeData(value: u8) {
+    if waitToWrite() {
+        unsafe {
+            outB(DATA_PORT, value);
+        }
+    }
+}
+
+fn readData() -> Option<u8> {
+    if waitToRead() {
+        unsafe { Some(inB(DATA_PORT)) }
+    } else {
+        None
+    }
+}
+
+fn flushOutput() {
+    for _ in 0..MAX_LOOP_VALUE {
+        unsafe {
+            if inB(STATUS_PORT) & STATUS_OUTPUT_FULL == 0 {
+                return;
+            }
+
+            inB(DATA_PORT);
+        }
+    }
+}
+
+// Only valid before the IRQ is hooked up, otherwise the handler would eat the ACK
+fn sendPolled(byte: u8) -> bool {
+    for _ in 0..MAX_RESENDS {
+        writeData(byte);
+        match readData() {
+            Some(RESPONSE_ACK) => return true,
+            Some(RESPONSE_RESEND) => continue,
+            _ => return false,
+        }
+    }
+
+    false
+}
+
+fn getLedBits(state: &KeyboardState) -> u8 {
+    let modifiers = state.decoder.modifiers();
+    let mut leds = 0;
+    if modifiers.scrollLock {
+        leds |= LED_SCROLL_LOCK;
+    }
+
+    if modifiers.numLock {
+        leds |= LED_NUM_LOCK;
+    }
+
+    if modifiers.capsLock {
+        leds |= LED_CAPS_LOCK;
+    }
+
+    leds
+}
+
+// IRQ1 stays masked until the very end, so everything before that can be polled
+pub fn init() {
+    // Whatever the BIOS left lying around isn't ours
+    flushOutput();
+
+    writeController(CONTROLLER_READ_CONFIG);
+    let Some(mut config) = readData() else {
+        loggerWriteLine!("PS/2 controller didn't return its config, no keyboard for you");
+        return;
+    };
+
+    config |= CONFIG_PORT1_INTERRUPT | CONFIG_PORT1_TRANSLATION;
+    config &= !CONFIG_PORT1_CLOCK_DISABLED;
+    writeController(CONTROLLER_WRITE_CONFIG);
+    writeData(config);
+
+    // Start with NumLock on like every BIOS out there seems to
+    let leds = critical_section::with(|cs| {
+        let mut state = KEYBOARD.borrow_ref_mut(cs);
+        state.decoder.setLocks(false, true, false);
+        getLedBits(&state)
+    });
+
+    if !(sendPolled(KEYBOARD_SET_TYPEMATIC) && sendPolled(TYPEMATIC_DEFAULT)) {
+        loggerWriteLine!("Keyboard didn't accept typematic settings");
+    }
+
+    if !(sendPolled(KEYBOARD_SET_LEDS) && sendPolled(leds)) {
+        loggerWriteLine!("Keyboard didn't accept LED settings");
+    }
+
+    flushOutput();
+
+    registerIrqHandler(KEYBOARD_IRQ, handleInterrupt);
+
+    loggerWriteLine!("PS/2 keyboard ready (config 0x{:X})", config);
+}
+
+fn handleInterrupt(_irq: u8) {
+    let byte = unsafe { inB(DATA_PORT) };
+
+    critical_section::with(|cs| {
+        let mut state = KEYBOARD.borrow_ref_mut(cs);
+
+        match byte {
+            RESPONSE_ACK => {
+                state.inFlight = None;
+                state.resends = 0;
+                sendNextCommand(&mut state);
+            }
+            RESPONSE_RESEND => {
+                if let Some(command) = state.inFlight {
+                    if state.resends < MAX_RESENDS {
+                        state.resends += 1;
+                        unsafe {
+                            outB(DATA_PORT, command);
+                        }
+                    } else {
+                        // Keyboard doesn't like it, give up on this one and move along
+                        state.inFlight = None;
+                        state.resends = 0;
+                        sendNextCommand(&mut state);
+                    }
+                }
+            }
+            RESPONSE_ERROR_1 | RESPONSE_ERROR_2 => {
+                state.dropped += 1;
+            }
+            _ => {
+                if let Some(event) = state.decoder.decode(byte) {
+                    let isLockPress = event.pressed
+                        && !event.repeat
+                        && matches!(
+                            event.code,
+                            KeyCode::CapsLock | KeyCode::NumLock | KeyCode::ScrollLock
+                        );
+
+                    if !state.events.push(event) {
+                        state.dropped += 1;
+                    }
+
+                    if isLockPress {
+                        let leds = getLedBits(&state);
+                        queueCommand(&mut state, KEYBOARD_SET_LEDS);
+                        queueCommand(&mut state, leds);
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn queueCommand(state: &mut KeyboardState, byte: u8) {
+    state.commands.push(byte);
+    if state.inFlight.is_none() {
+        sendNextCommand(state);
+    }
+}
+
+fn sendNextCommand(state: &mut KeyboardState) {
+    if let Some(command) = state.commands.pop() {
+        state.inFlight = Some(command);
+        unsafe {
+            outB(DATA_PORT, command);
+        }
+    }
+}
+
+pub fn tryReadKeyEvent() -> Option<KeyEvent> {
+    critical_section::with(|cs| KEYBOARD.borrow_ref_mut(cs).events.pop())
+}
+
+// Sleeps until there's something to hand back
+pub fn readKeyEvent() -> KeyEvent {
+    loop {
+        // Interrupts have to be off between checking and halting, otherwise the key could
+        // land in that gap and we'd sleep through it.
+        disableInterrupts();
+        let event = critical_section::with(|cs| KEYBOARD.borrow_ref_mut(cs).events.pop());
+        if let Some(event) = event {
+            enableInterrupts();
+            return event;
+        }
+
+        enableInterruptsAndHalt();
+    }
+}
+
+pub fn droppedCount() -> usize {
+    critical_section::with(|cs| KEYBOARD.borrow_ref(cs).dropped)
+}
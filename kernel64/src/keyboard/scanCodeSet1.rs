@@ -0,0 +1,390 @@
+use super::keyEvent::{KeyCode, KeyEvent, Modifiers};
+
+// https://wiki.osdev.org/PS/2_Keyboard#Scan_Code_Set_1
+// The controller translates whatever the keyboard speaks into set 1 for us (as long as translation is on),
+// so this is the only set we bother decoding.
+
+const PREFIX_EXTENDED: u8 = 0xE0;
+const PREFIX_PAUSE: u8 = 0xE1;
+const BREAK_BIT: u8 = 0x80;
+
+// Pause is E1 1D 45 E1 9D C5 and never sends a release, this is how many bytes follow the E1
+const PAUSE_SEQUENCE_REMAINING: u8 = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DecoderState {
+    Normal,
+    Extended,            // Saw E0, next byte is from the extended table
+    Pause(u8),           // Saw E1, swallowing the rest of the sequence
+}
+
+pub struct ScanCodeDecoder {
+    state: DecoderState,
+    modifiers: Modifiers,
+    // One bit per (extended, scancode) pair so we can tell typematic repeats from fresh presses
+    keysDown: [u64; 4],
+}
+
+impl ScanCodeDecoder {
+    pub const fn new() -> Self {
+        ScanCodeDecoder {
+            state: DecoderState::Normal,
+            modifiers: Modifiers::new(),
+            keysDown: [0; 4],
+        }
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    // Lets whoever owns the keyboard seed lock state (e.g. what the BIOS left on)
+    pub fn setLocks(&mut self, capsLock: bool, numLock: bool, scrollLock: bool) {
+        self.modifiers.capsLock = capsLock;
+        self.modifiers.numLock = numLock;
+        self.modifiers.scrollLock = scrollLock;
+    }
+
+    // Feed one byte from the controller. Returns an event once a full key sequence has been seen.
+    pub fn decode(&mut self, byte: u8) -> Option<KeyEvent> {
+        match self.state {
+            DecoderState::Normal => match byte {
+                PREFIX_EXTENDED => {
+                    self.state = DecoderState::Extended;
+                    None
+                }
+                PREFIX_PAUSE => {
+                    self.state = DecoderState::Pause(PAUSE_SEQUENCE_REMAINING);
+                    None
+                }
+                _ => self.decodeKey(false, byte),
+            },
+            DecoderState::Extended => {
+                self.state = DecoderState::Normal;
+                self.decodeKey(true, byte)
+            }
+            DecoderState::Pause(remaining) => {
+                if remaining > 1 {
+                    self.state = DecoderState::Pause(remaining - 1);
+                    return None;
+                }
+
+                self.state = DecoderState::Normal;
+                Some(KeyEvent {
+                    code: KeyCode::Pause,
+                    pressed: true,
+                    repeat: false,
+                    modifiers: self.modifiers,
+                    character: None,
+                })
+            }
+        }
+    }
+
+    fn decodeKey(&mut self, extended: bool, byte: u8) -> Option<KeyEvent> {
+        let pressed = byte & BREAK_BIT == 0;
+        let scanCode = byte & !BREAK_BIT;
+
+        // Print Screen (and a few others on older boards) wrap themselves in fake shift presses
+        // so that the key looks unshifted to old software. They aren't real keys, so drop them.
+        if extended && (scanCode == 0x2A || scanCode == 0x36) {
+            return None;
+        }
+
+        let physicalCode = Self::lookup(extended, scanCode)?;
+
+        let bit = ((extended as usize) << 7) | scanCode as usize;
+        let wasDown = self.keysDown[bit / 64] & (1 << (bit % 64)) != 0;
+        if pressed {
+            self.keysDown[bit / 64] |= 1 << (bit % 64);
+        } else {
+            self.keysDown[bit / 64] &= !(1 << (bit % 64));
+        }
+
+        let repeat = pressed && wasDown;
+        self.updateModifiers(physicalCode, pressed, repeat);
+
+        let code = self.applyNumpadMode(physicalCode);
+        let character = if pressed {
+            Self::getCharacter(code, &self.modifiers)
+        } else {
+            None
+        };
+
+        Some(KeyEvent {
+            code,
+            pressed,
+            repeat,
+            modifiers: self.modifiers,
+            character,
+        })
+    }
+
+    fn updateModifiers(&mut self, code: KeyCode, pressed: bool, repeat: bool) {
+        let m = &mut self.modifiers;
+        match code {
+            KeyCode::LeftShift => m.leftShift = pressed,
+            KeyCode::RightShift => m.rightShift = pressed,
+            KeyCode::LeftControl => m.leftControl = pressed,
+            KeyCode::RightControl => m.rightControl = pressed,
+            KeyCode::LeftAlt => m.leftAlt = pressed,
+            KeyCode::RightAlt => m.rightAlt = pressed,
+            KeyCode::LeftGui => m.leftGui = pressed,
+            KeyCode::RightGui => m.rightGui = pressed,
+
+            // Locks flip on the initial press only; holding them shouldn't strobe the state
+            KeyCode::CapsLock if pressed && !repeat => m.capsLock = !m.capsLock,
+            KeyCode::NumLock if pressed && !repeat => m.numLock = !m.numLock,
+            KeyCode::ScrollLock if pressed && !repeat => m.scrollLock = !m.scrollLock,
+            _ => {}
+        }
+    }
+
+    // The keypad doubles as a nav cluster. NumLock picks digits, and shift temporarily flips whatever NumLock says.
+    fn applyNumpadMode(&self, code: KeyCode) -> KeyCode {
+        if Self::isNumpadDigits(&self.modifiers) {
+            return code;
+        }
+
+        match code {
+            KeyCode::Numpad0 => KeyCode::Insert,
+            KeyCode::Numpad1 => KeyCode::End,
+            KeyCode::Numpad2 => KeyCode::ArrowDown,
+            KeyCode::Numpad3 => KeyCode::PageDown,
+            KeyCode::Numpad4 => KeyCode::ArrowLeft,
+            KeyCode::Numpad6 => KeyCode::ArrowRight,
+            KeyCode::Numpad7 => KeyCode::Home,
+            KeyCode::Numpad8 => KeyCode::ArrowUp,
+            KeyCode::Numpad9 => KeyCode::PageUp,
+            KeyCode::NumpadDecimal => KeyCode::Delete,
+            // Numpad5 has no nav meaning, it just stops typing a digit
+            _ => code,
+        }
+    }
+
+    fn isNumpadDigits(modifiers: &Modifiers) -> bool {
+        modifiers.numLock != modifiers.shift()
+    }
+
+    fn getCharacter(code: KeyCode, modifiers: &Modifiers) -> Option<char> {
+        if let Some(letter) = Self::getLetter(code) {
+            if modifiers.control() {
+                // Ctrl+A = 0x01 ... Ctrl+Z = 0x1A
+                return Some(((letter as u8) & 0x1F) as char);
+            }
+
+            let upper = modifiers.shift() != modifiers.capsLock;
+            return Some(if upper {
+                letter.to_ascii_uppercase()
+            } else {
+                letter
+            });
+        }
+
+        let (normal, shifted) = match code {
+            KeyCode::Backtick => ('`', '~'),
+            KeyCode::Digit1 => ('1', '!'),
+            KeyCode::Digit2 => ('2', '@'),
+            KeyCode::Digit3 => ('3', '#'),
+            KeyCode::Digit4 => ('4', '$'),
+            KeyCode::Digit5 => ('5', '%'),
+            KeyCode::Digit6 => ('6', '^'),
+            KeyCode::Digit7 => ('7', '&'),
+            KeyCode::Digit8 => ('8', '*'),
+            KeyCode::Digit9 => ('9', '('),
+            KeyCode::Digit0 => ('0', ')'),
+            KeyCode::Minus => ('-', '_'),
+            KeyCode::Equals => ('=', '+'),
+            KeyCode::LeftBracket => ('[', '{'),
+            KeyCode::RightBracket => (']', '}'),
+            KeyCode::Backslash => ('\\', '|'),
+            KeyCode::Semicolon => (';', ':'),
+            KeyCode::Quote => ('\'', '"'),
+            KeyCode::Comma => (',', '<'),
+            KeyCode::Period => ('.', '>'),
+            KeyCode::Slash => ('/', '?'),
+
+            // Numpad digits only get here when we're in digit mode, see applyNumpadMode
+            KeyCode::Numpad0 => ('0', '0'),
+            KeyCode::Numpad1 => ('1', '1'),
+            KeyCode::Numpad2 => ('2', '2'),
+            KeyCode::Numpad3 => ('3', '3'),
+            KeyCode::Numpad4 => ('4', '4'),
+            // Nav mode leaves Numpad5 as it is, and there it doesn't type anything
+            KeyCode::Numpad5 if !Self::isNumpadDigits(modifiers) => return None,
+            KeyCode::Numpad5 => ('5', '5'),
+            KeyCode::Numpad6 => ('6', '6'),
+            KeyCode::Numpad7 => ('7', '7'),
+            KeyCode::Numpad8 => ('8', '8'),
+            KeyCode::Numpad9 => ('9', '9'),
+            KeyCode::NumpadDecimal => ('.', '.'),
+            KeyCode::NumpadDivide => ('/', '/'),
+            KeyCode::NumpadMultiply => ('*', '*'),
+            KeyCode::NumpadSubtract => ('-', '-'),
+            KeyCode::NumpadAdd => ('+', '+'),
+
+            KeyCode::Space => (' ', ' '),
+            KeyCode::Tab => ('\t', '\t'),
+            KeyCode::Enter | KeyCode::NumpadEnter => ('\n', '\n'),
+            KeyCode::Backspace => ('\x08', '\x08'),
+            KeyCode::Escape => ('\x1B', '\x1B'),
+            _ => return None,
+        };
+
+        if modifiers.shift() {
+            Some(shifted)
+        } else {
+            Some(normal)
+        }
+    }
+
+    fn getLetter(code: KeyCode) -> Option<char> {
+        let letter = match code {
+            KeyCode::A => 'a',
+            KeyCode::B => 'b',
+            KeyCode::C => 'c',
+            KeyCode::D => 'd',
+            KeyCode::E => 'e',
+            KeyCode::F => 'f',
+            KeyCode::G => 'g',
+            KeyCode::H => 'h',
+            KeyCode::I => 'i',
+            KeyCode::J => 'j',
+            KeyCode::K => 'k',
+            KeyCode::L => 'l',
+            KeyCode::M => 'm',
+            KeyCode::N => 'n',
+            KeyCode::O => 'o',
+            KeyCode::P => 'p',
+            KeyCode::Q => 'q',
+            KeyCode::R => 'r',
+            KeyCode::S => 's',
+            KeyCode::T => 't',
+            KeyCode::U => 'u',
+            KeyCode::V => 'v',
+            KeyCode::W => 'w',
+            KeyCode::X => 'x',
+            KeyCode::Y => 'y',
+            KeyCode::Z => 'z',
+            _ => return None,
+        };
+
+        Some(letter)
+    }
+
+    fn lookup(extended: bool, scanCode: u8) -> Option<KeyCode> {
+        if extended {
+            return match scanCode {
+                0x1C => Some(KeyCode::NumpadEnter),
+                0x1D => Some(KeyCode::RightControl),
+                0x35 => Some(KeyCode::NumpadDivide),
+                0x37 => Some(KeyCode::PrintScreen),
+                0x38 => Some(KeyCode::RightAlt),
+                0x47 => Some(KeyCode::Home),
+                0x48 => Some(KeyCode::ArrowUp),
+                0x49 => Some(KeyCode::PageUp),
+                0x4B => Some(KeyCode::ArrowLeft),
+                0x4D => Some(KeyCode::ArrowRight),
+                0x4F => Some(KeyCode::End),
+                0x50 => Some(KeyCode::ArrowDown),
+                0x51 => Some(KeyCode::PageDown),
+                0x52 => Some(KeyCode::Insert),
+                0x53 => Some(KeyCode::Delete),
+                0x5B => Some(KeyCode::LeftGui),
+                0x5C => Some(KeyCode::RightGui),
+                0x5D => Some(KeyCode::Menu),
+                // Multimedia and ACPI keys, we don't care about those
+                _ => None,
+            };
+        }
+
+        match scanCode {
+            0x01 => Some(KeyCode::Escape),
+            0x02 => Some(KeyCode::Digit1),
+            0x03 => Some(KeyCode::Digit2),
+            0x04 => Some(KeyCode::Digit3),
+            0x05 => Some(KeyCode::Digit4),
+            0x06 => Some(KeyCode::Digit5),
+            0x07 => Some(KeyCode::Digit6),
+            0x08 => Some(KeyCode::Digit7),
+            0x09 => Some(KeyCode::Digit8),
+            0x0A => Some(KeyCode::Digit9),
+            0x0B => Some(KeyCode::Digit0),
+            0x0C => Some(KeyCode::Minus),
+            0x0D => Some(KeyCode::Equals),
+            0x0E => Some(KeyCode::Backspace),
+            0x0F => Some(KeyCode::Tab),
+            0x10 => Some(KeyCode::Q),
+            0x11 => Some(KeyCode::W),
+            0x12 => Some(KeyCode::E),
+            0x13 => Some(KeyCode::R),
+            0x14 => Some(KeyCode::T),
+            0x15 => Some(KeyCode::Y),
+            0x16 => Some(KeyCode::U),
+            0x17 => Some(KeyCode::I),
+            0x18 => Some(KeyCode::O),
+            0x19 => Some(KeyCode::P),
+            0x1A => Some(KeyCode::LeftBracket),
+            0x1B => Some(KeyCode::RightBracket),
+            0x1C => Some(KeyCode::Enter),
+            0x1D => Some(KeyCode::LeftControl),
+            0x1E => Some(KeyCode::A),
+            0x1F => Some(KeyCode::S),
+            0x20 => Some(KeyCode::D),
+            0x21 => Some(KeyCode::F),
+            0x22 => Some(KeyCode::G),
+            0x23 => Some(KeyCode::H),
+            0x24 => Some(KeyCode::J),
+            0x25 => Some(KeyCode::K),
+            0x26 => Some(KeyCode::L),
+            0x27 => Some(KeyCode::Semicolon),
+            0x28 => Some(KeyCode::Quote),
+            0x29 => Some(KeyCode::Backtick),
+            0x2A => Some(KeyCode::LeftShift),
+            0x2B => Some(KeyCode::Backslash),
+            0x2C => Some(KeyCode::Z),
+            0x2D => Some(KeyCode::X),
+            0x2E => Some(KeyCode::C),
+            0x2F => Some(KeyCode::V),
+            0x30 => Some(KeyCode::B),
+            0x31 => Some(KeyCode::N),
+            0x32 => Some(KeyCode::M),
+            0x33 => Some(KeyCode::Comma),
+            0x34 => Some(KeyCode::Period),
+            0x35 => Some(KeyCode::Slash),
+            0x36 => Some(KeyCode::RightShift),
+            0x37 => Some(KeyCode::NumpadMultiply),
+            0x38 => Some(KeyCode::LeftAlt),
+            0x39 => Some(KeyCode::Space),
+            0x3A => Some(KeyCode::CapsLock),
+            0x3B => Some(KeyCode::F1),
+            0x3C => Some(KeyCode::F2),
+            0x3D => Some(KeyCode::F3),
+            0x3E => Some(KeyCode::F4),
+            0x3F => Some(KeyCode::F5),
+            0x40 => Some(KeyCode::F6),
+            0x41 => Some(KeyCode::F7),
+            0x42 => Some(KeyCode::F8),
+            0x43 => Some(KeyCode::F9),
+            0x44 => Some(KeyCode::F10),
+            0x45 => Some(KeyCode::NumLock),
+            0x46 => Some(KeyCode::ScrollLock),
+            0x47 => Some(KeyCode::Numpad7),
+            0x48 => Some(KeyCode::Numpad8),
+            0x49 => Some(KeyCode::Numpad9),
+            0x4A => Some(KeyCode::NumpadSubtract),
+            0x4B => Some(KeyCode::Numpad4),
+            0x4C => Some(KeyCode::Numpad5),
+            0x4D => Some(KeyCode::Numpad6),
+            0x4E => Some(KeyCode::NumpadAdd),
+            0x4F => Some(KeyCode::Numpad1),
+            0x50 => Some(KeyCode::Numpad2),
+            0x51 => Some(KeyCode::Numpad3),
+            0x52 => Some(KeyCode::Numpad0),
+            0x53 => Some(KeyCode::NumpadDecimal),
+            0x57 => Some(KeyCode::F11),
+            0x58 => Some(KeyCode::F12),
+            _ => None,
+        }
+    }
+}
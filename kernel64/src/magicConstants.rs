@@ -10,4 +10,18 @@ pub const VM_KERNEL64_DATA: usize = 0x4000_0000;
 pub const VM_KERNEL64_STACK_LENGTH: usize = 0x10_0000;
 pub const VM_KERNEL64_DATA_LENGTH: usize = 0x20_0000;
 
-pub const DUMB_HEAP_SIZE: usize = 0x5_0000;
\ No newline at end of file
+pub const DUMB_HEAP_SIZE: usize = 0x5_0000;
+
+// Physical frame bitmap lives after the dumb heap and gets the rest of the data space. 1 bit per 4K, so this covers
+// a bit over 22GiB; anything past that is ignored.
+pub const VM_KERNEL64_FRAME_BITMAP: usize =
+    VM_KERNEL64_DATA + VM_KERNEL64_STACK_LENGTH + DUMB_HEAP_SIZE;
+pub const FRAME_BITMAP_MAX_LENGTH: usize =
+    VM_KERNEL64_DATA_LENGTH - VM_KERNEL64_STACK_LENGTH - DUMB_HEAP_SIZE;
+
+// Where mapPhysicalAnywhere and friends carve space from (MMIO, ACPI tables, etc.)
+pub const VM_KERNEL64_DYNAMIC: usize = 0x8000_0000;
+pub const VM_KERNEL64_DYNAMIC_LENGTH: usize = 0x4000_0000;
+
+// Page tables that came from the physical allocator get mapped here while they're being looked at
+pub const VM_KERNEL64_PAGE_TABLE_WINDOW: usize = 0xC000_0000;
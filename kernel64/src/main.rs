@@ -8,35 +8,46 @@
 #![feature(const_trait_impl)]
 #![feature(if_let_guard)]
 
+extern crate alloc;
+
 mod acpi;
 mod ahci;
 mod assemblyHelpers;
+mod console;
 mod diskStuff;
 mod interupts;
+mod keyboard;
 mod magicConstants;
 mod memory;
+mod pci;
 mod shell;
+mod time;
+mod vfs;
 
 use core::arch::asm;
-use core::array::from_fn;
 use core::panic::PanicInfo;
+use core::slice::from_raw_parts_mut;
 
 use interupts::InteruptDescriptorTable::{IDT, SetIDT};
 
 use kernel_shared::gdtStuff::{GDTR, Gdt};
+use kernel_shared::memory::frameAllocator::FrameAllocator;
 use kernel_shared::memory::map::MemoryMap;
 use kernel_shared::memoryTypes::{
     MemoryAddress, PhysicalAddress, PhysicalAddressPlain, VirtualAddress, VirtualAddressPlain,
 };
 use kernel_shared::pageTable::enums::*;
 use kernel_shared::pageTable::pageMapLevel4Table::PageMapLevel4Table;
-use kernel_shared::physicalMemory::{MemoryBlob, PhysicalMemoryManager, WhatDo};
+use kernel_shared::physicalMemory::{PhysicalMemoryManager, WhatDo};
 use kernel_shared::relocation::relocateKernel64Ex;
 use kernel_shared::{
-    assemblyStuff::{halt::haltLoop, misc::Breakpoint},
+    assemblyStuff::{
+        halt::haltLoop,
+        misc::{Breakpoint, enableInterrupts},
+    },
     pageTable::pageBook::PageBook,
 };
-use kernel_shared::{loggerWriteLine, magicConstants::*};
+use kernel_shared::{haltLoopWithMessage, loggerWriteLine, magicConstants::*};
 use magicConstants::*;
 use memory::dumbHeap::BootstrapDumbHeap;
 use memory::virtualMemory::VirtualMemoryManager;
@@ -50,16 +61,6 @@ fn panic(info: &PanicInfo) -> ! {
     haltLoop();
 }
 
-fn reloadCR3() {
-    unsafe {
-        asm!(
-            "mov rax, cr3",
-            "mov cr3, rax",
-            out("rax") _,
-        );
-    }
-}
-
 #[inline(always)]
 fn getBP() -> usize {
     unsafe {
@@ -115,8 +116,6 @@ fn mapKernelCode(
         UserSupervisor::Supervisor,
         WriteThrough::WriteTrough,
     );
-
-    reloadCR3();
 }
 
 fn mapKernelData(
@@ -134,8 +133,6 @@ fn mapKernelData(
         UserSupervisor::Supervisor,
         WriteThrough::WriteTrough,
     );
-
-    reloadCR3();
 }
 
 // Arguments 1-6 are passed via registers RDI, RSI, RDX, RCX, R8, R9 respectively;
@@ -162,11 +159,31 @@ pub extern "sysv64" fn DanMain(
 
     memoryMap.dumpEx(true);
 
-    let mut physicalMemoryManager = PhysicalMemoryManager {
-        MemoryMap: memoryMap,
-        Blobs: from_fn(|_| MemoryBlob::default()),
+    // Everything is still identity mapped, so the frame bitmap can go anywhere that isn't already in use. Real mode
+    // leftovers, the stack, page tables and the GDT are all in low memory; we're the only other thing we know about.
+    let bitmapLength = FrameAllocator::getBitmapBytes(&memoryMap);
+    let avoid = [
+        (0, 0x10_0000),
+        (kernelElfLocation, kernelElfSize),
+        (memoryMapLocation, size_of::<MemoryMap>()),
+    ];
+
+    let Some(bitmapAddress) = FrameAllocator::findBitmapHome(&memoryMap, bitmapLength, &avoid)
+    else {
+        haltLoopWithMessage!("No room for a 0x{:X} byte frame bitmap", bitmapLength);
     };
 
+    loggerWriteLine!(
+        "Frame bitmap @ 0x{:X} for 0x{:X}",
+        bitmapAddress,
+        bitmapLength
+    );
+    let bitmap =
+        unsafe { from_raw_parts_mut(bitmapAddress as *mut u64, bitmapLength / size_of::<u64>()) };
+
+    let mut physicalMemoryManager = PhysicalMemoryManager::new(memoryMap, bitmap);
+    physicalMemoryManager.Reserve("Frame bitmap", bitmapAddress, bitmapLength, WhatDo::Normal);
+
     loggerWriteLine!(
         "PMM is at 0x{:X}",
         &physicalMemoryManager as *const _ as usize
@@ -228,10 +245,11 @@ pub extern "sysv64" fn DanMain(
         physicalMemoryManager.ReserveWhereverZeroed("Relocated kernel code", kernelElfSize, 0x1000)
             as *mut u8;
 
+    // Aligned so the whole thing can be a single 2 MiB page
     let kernelStackPhysicalAddress: *mut u8 = physicalMemoryManager.ReserveWhereverZeroed(
         "Relocated kernel data",
         VM_KERNEL64_DATA_LENGTH,
-        0x1000,
+        SIZE_OF_PAGE_TABLE,
     ) as *mut u8;
 
     loggerWriteLine!(
@@ -423,12 +441,21 @@ extern "sysv64" fn newStackHome(
         &memoryMap as *const _ as usize
     );
 
-    let mut physicalMemoryManager = PhysicalMemoryManager {
-        MemoryMap: memoryMap,
-        Blobs: from_fn(|_| MemoryBlob::default()),
+    // The old bitmap is somewhere we're about to stop mapping, so start fresh with one in our data space. Anything
+    // from before that isn't reserved again below is free for the taking.
+    let bitmapLength = FrameAllocator::getBitmapBytes(&memoryMap).min(FRAME_BITMAP_MAX_LENGTH);
+    let bitmap = unsafe {
+        from_raw_parts_mut(
+            VM_KERNEL64_FRAME_BITMAP as *mut u64,
+            bitmapLength / size_of::<u64>(),
+        )
     };
 
+    let mut physicalMemoryManager = PhysicalMemoryManager::new(memoryMap, bitmap);
     physicalMemoryManager.DumpMemoryMap();
+
+    // Real mode leftovers (IVT, BDA, EBDA, ACPI tables, etc.) all live down here. Keep the heap and friends out of it.
+    physicalMemoryManager.Reserve("Low memory", 0, 0x10_0000, WhatDo::YoLo);
     physicalMemoryManager.Reserve(
         "The kernel",
         kernelImageAddress.physical.address,
@@ -442,7 +469,7 @@ extern "sysv64" fn newStackHome(
         WhatDo::Normal,
     );
 
-    physicalMemoryManager.DumpReservedBlobs();
+    physicalMemoryManager.DumpUsage();
 
     // We're in the course of setting up a new virtual memory manager. We're currently executing in non-identity mapped space
     // so we cannot just ask the physical manager for unused space. We know nothing has used the kernel data space yet aside
@@ -459,6 +486,13 @@ extern "sysv64" fn newStackHome(
     Breakpoint();
     loggerWriteLine!("We handled the new breakpoint!");
 
+    interupts::irq::init();
+    keyboard::ps2Keyboard::init();
+    console::serialInput::init(true);
+
+    loggerWriteLine!("Enabling interrupts...");
+    enableInterrupts();
+
     // BUGBUG: This is on the stack, we should probably allocate from BDH
     let gdt = Gdt::new();
     let mut gdtr = GDTR::new();
@@ -514,19 +548,24 @@ extern "sysv64" fn newStackHome(
         cr3V.address
     );
 
-    unsafe {
-        asm!(
-            "mov cr3, rax",
-            in("rax") cr3,
-        );
-    }
+    virtualMemoryManager.activate();
 
     loggerWriteLine!("We're fully remapped!");
     virtualMemoryManager.dumpPhysical();
 
+    memory::heap::init(&virtualMemoryManager);
+
+    // Page tables are final, so the APICs can be mapped and take over from the PICs
+    interupts::irq::switchToApic(&mut virtualMemoryManager);
+    time::clock::init(&mut virtualMemoryManager);
+
     //virtualMemoryManager.getFreeVirtualAddress(1);
-    //readBytes(&mut virtualMemoryManager);
-    let shell = shell::kernelShell::KernelShell::new(&mut virtualMemoryManager);
+    diskStuff::drives::register();
+    pci::enumeration::enumerate(&mut virtualMemoryManager);
+    diskStuff::read::readBytes(&mut virtualMemoryManager);
+    vfs::virtualFileSystem::init();
+    diskStuff::volumes::mountVolumes(&mut virtualMemoryManager);
+    let mut shell = shell::kernelShell::KernelShell::new(&mut virtualMemoryManager);
     shell.run();
 
     haltLoop();
@@ -7,8 +7,12 @@ use core::{array::from_fn, mem::{size_of, align_of}, ptr::null_mut};
 
 use super::memoryStuff::MemoryStuff;
 
+// Page tables still come from here, one entry each, so this needs to be a fair bit bigger than the handful of
+// things allocated before the real heap is up
+const BOOTSTRAP_ENTRY_COUNT: usize = 64;
+
 pub struct BootstrapDumbHeap {
-    Entries: [BootstrapDumbHeapEntry; 10],
+    Entries: [BootstrapDumbHeapEntry; BOOTSTRAP_ENTRY_COUNT],
     StartAddress: usize,
     Length: usize,
     VirtualIsGreaterThanPhysical: bool,
@@ -39,8 +43,11 @@ impl MemoryStuff for BootstrapDumbHeap {
         return address as *mut T;
     }
 
-    fn free(&mut self, _address: usize) {
-        todo!()
+    // Everything from here (page tables, the VMM's state) lives until the machine goes down and the entries only ever
+    // fill up, so there's nothing to give back to. Getting here means something bootstrap handed out is being
+    // treated like a heap allocation.
+    fn free(&mut self, address: usize) {
+        haltLoopWithMessage!("Bootstrap allocations are never freed, 0x{:X} was", address);
     }
 }
 
@@ -144,6 +151,14 @@ impl BootstrapDumbHeap {
         PhysicalAddress::new(result)
     }
 
+    // Whether the physical address is something this heap handed out
+    pub fn ownsPhysical<T>(&self, address: &PhysicalAddress<T>) -> bool {
+        let start = self
+            .vToP(&VirtualAddress::<T>::new(self.StartAddress))
+            .address;
+        address.address >= start && address.address < start + self.Length
+    }
+
     pub fn pToV<T>(&self, address: &PhysicalAddress<T>) -> VirtualAddress<T> {
         let result;
         if self.VirtualIsGreaterThanPhysical {
@@ -0,0 +1,362 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::RefCell,
+    ptr::{copy_nonoverlapping, null_mut},
+};
+
+use critical_section::Mutex;
+use kernel_shared::{magicConstants::SIZE_OF_PAGE, memoryHelpers::alignUp};
+
+use crate::loggerWriteLine;
+
+use super::virtualMemory::VirtualMemoryManager;
+
+// General purpose kernel heap behind alloc::{Box, Vec, String, ...}
+//
+// Small requests come out of slabs: a page carved up into equal sized objects, one free list per size class.
+// Anything bigger than the largest class goes straight to the page pool, which is also where the slabs get their
+// pages from. The page pool is an address ordered list of free runs that coalesce when given back. When it can't
+// satisfy a request it grows by asking the virtual memory manager for more.
+//
+// Everything happens inside a critical section, so allocating from an interrupt handler is fine (if not fast).
+const SIZE_CLASSES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+const SIZE_CLASS_COUNT: usize = SIZE_CLASSES.len();
+
+// Growing means mapping, which is slow and chatty, so do it in big chunks
+const GROWTH_PAGES: usize = 0x100;
+
+// Lives in the first bytes of a free slab object
+#[repr(C)]
+struct FreeObject {
+    next: usize,
+}
+
+// Lives in the first bytes of a free run of pages
+#[repr(C)]
+struct FreeRun {
+    pages: usize,
+    next: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct HeapStats {
+    pub pagesOwned: usize,    // Everything we've ever gotten from the VMM
+    pub pagesFree: usize,     // Sitting in the page pool
+    pub slabPages: usize,     // Handed out to slabs (never given back)
+    pub bytesInUse: usize,    // Sum of the sizes callers asked for
+    pub allocations: usize,   // Outstanding
+    pub allocationCount: u64, // Lifetime
+    pub failedCount: u64,
+}
+
+struct HeapState {
+    vmm: Option<VirtualMemoryManager>, // Our own handle, None until init
+    freeObjects: [usize; SIZE_CLASS_COUNT],
+    freeRuns: usize,
+    stats: HeapStats,
+}
+
+pub struct KernelHeap {
+    state: Mutex<RefCell<HeapState>>,
+}
+
+#[global_allocator]
+static KERNEL_HEAP: KernelHeap = KernelHeap {
+    state: Mutex::new(RefCell::new(HeapState {
+        vmm: None,
+        freeObjects: [0; SIZE_CLASS_COUNT],
+        freeRuns: 0,
+        stats: HeapStats {
+            pagesOwned: 0,
+            pagesFree: 0,
+            slabPages: 0,
+            bytesInUse: 0,
+            allocations: 0,
+            allocationCount: 0,
+            failedCount: 0,
+        },
+    })),
+};
+
+// The heap keeps a handle of its own, so growing doesn't need whoever else has the VMM to hand theirs over. The VMM
+// never allocates, so it can't be in the middle of something when we grow.
+pub fn init(vmm: &VirtualMemoryManager) {
+    critical_section::with(|cs| {
+        let mut state = KERNEL_HEAP.state.borrow_ref_mut(cs);
+        state.vmm = Some(vmm.share());
+        state.grow(GROWTH_PAGES);
+    });
+
+    loggerWriteLine!("Kernel heap is up with {} pages", stats().pagesOwned);
+}
+
+pub fn stats() -> HeapStats {
+    critical_section::with(|cs| KERNEL_HEAP.state.borrow_ref(cs).stats)
+}
+
+pub fn dump() {
+    let stats = stats();
+    loggerWriteLine!(
+        "Heap: {} pages owned, {} free, {} in slabs",
+        stats.pagesOwned,
+        stats.pagesFree,
+        stats.slabPages
+    );
+    loggerWriteLine!(
+        "  {} bytes in {} allocations, {} allocations total, {} failed",
+        stats.bytesInUse,
+        stats.allocations,
+        stats.allocationCount,
+        stats.failedCount
+    );
+
+    critical_section::with(|cs| {
+        let state = KERNEL_HEAP.state.borrow_ref(cs);
+        for (index, size) in SIZE_CLASSES.iter().enumerate() {
+            let mut free = 0;
+            let mut current = state.freeObjects[index];
+            while current != 0 {
+                free += 1;
+                current = unsafe { (*(current as *const FreeObject)).next };
+            }
+
+            if free != 0 {
+                loggerWriteLine!("  {:>4} byte objects: {} free", size, free);
+            }
+        }
+
+        let mut current = state.freeRuns;
+        while current != 0 {
+            let run = unsafe { &*(current as *const FreeRun) };
+            loggerWriteLine!("  Free run 0x{:X} for {} pages", current, run.pages);
+            current = run.next;
+        }
+    });
+}
+
+// Objects in a slab sit at multiples of their size from a page boundary, so any alignment up to the size is free
+fn getSizeClass(layout: &Layout) -> Option<usize> {
+    let needed = layout.size().max(layout.align());
+    SIZE_CLASSES.iter().position(|size| *size >= needed)
+}
+
+fn getPageCount(layout: &Layout) -> usize {
+    alignUp(layout.size(), SIZE_OF_PAGE) / SIZE_OF_PAGE
+}
+
+impl HeapState {
+    fn allocate(&mut self, layout: &Layout) -> usize {
+        let result = match getSizeClass(layout) {
+            Some(sizeClass) => self.allocateObject(sizeClass),
+            None => self.allocatePages(getPageCount(layout), layout.align().max(SIZE_OF_PAGE)),
+        };
+
+        if result == 0 {
+            self.stats.failedCount += 1;
+        } else {
+            self.stats.bytesInUse += layout.size();
+            self.stats.allocations += 1;
+            self.stats.allocationCount += 1;
+        }
+
+        result
+    }
+
+    fn free(&mut self, address: usize, layout: &Layout) {
+        match getSizeClass(layout) {
+            Some(sizeClass) => self.freeObject(address, sizeClass),
+            None => self.freePages(address, getPageCount(layout)),
+        }
+
+        self.stats.bytesInUse -= layout.size();
+        self.stats.allocations -= 1;
+    }
+
+    fn allocateObject(&mut self, sizeClass: usize) -> usize {
+        if self.freeObjects[sizeClass] == 0 && !self.refillSlab(sizeClass) {
+            return 0;
+        }
+
+        let result = self.freeObjects[sizeClass];
+        self.freeObjects[sizeClass] = unsafe { (*(result as *const FreeObject)).next };
+        result
+    }
+
+    fn freeObject(&mut self, address: usize, sizeClass: usize) {
+        unsafe {
+            (*(address as *mut FreeObject)).next = self.freeObjects[sizeClass];
+        }
+
+        self.freeObjects[sizeClass] = address;
+    }
+
+    // BUGBUG: Slab pages never go back to the pool, even once every object in them is free
+    fn refillSlab(&mut self, sizeClass: usize) -> bool {
+        let page = self.allocatePages(1, SIZE_OF_PAGE);
+        if page == 0 {
+            return false;
+        }
+
+        self.stats.slabPages += 1;
+
+        let size = SIZE_CLASSES[sizeClass];
+        let mut offset = SIZE_OF_PAGE;
+        while offset >= size {
+            offset -= size;
+            self.freeObject(page + offset, sizeClass);
+        }
+
+        true
+    }
+
+    fn allocatePages(&mut self, pages: usize, alignment: usize) -> usize {
+        if let Some(result) = self.carve(pages, alignment) {
+            return result;
+        }
+
+        // Worst case the new space starts just past an alignment boundary
+        let extra = (alignment / SIZE_OF_PAGE).saturating_sub(1);
+        if !self.grow(pages + extra) {
+            return 0;
+        }
+
+        self.carve(pages, alignment).unwrap_or(0)
+    }
+
+    // First fit. Whatever is left in front of or behind the allocation stays in the list.
+    fn carve(&mut self, pages: usize, alignment: usize) -> Option<usize> {
+        let length = pages * SIZE_OF_PAGE;
+        let mut link = &mut self.freeRuns as *mut usize;
+
+        unsafe {
+            while *link != 0 {
+                let runAddress = *link;
+                let run = runAddress as *mut FreeRun;
+                let runEnd = runAddress + (*run).pages * SIZE_OF_PAGE;
+                let start = alignUp(runAddress, alignment);
+
+                if start + length > runEnd {
+                    link = &mut (*run).next;
+                    continue;
+                }
+
+                let end = start + length;
+                let mut next = (*run).next;
+
+                if end != runEnd {
+                    let trailing = end as *mut FreeRun;
+                    (*trailing).pages = (runEnd - end) / SIZE_OF_PAGE;
+                    (*trailing).next = next;
+                    next = end;
+                }
+
+                if start != runAddress {
+                    (*run).pages = (start - runAddress) / SIZE_OF_PAGE;
+                    (*run).next = next;
+                } else {
+                    *link = next;
+                }
+
+                self.stats.pagesFree -= pages;
+                return Some(start);
+            }
+        }
+
+        None
+    }
+
+    fn freePages(&mut self, address: usize, pages: usize) {
+        let mut previous = 0;
+        let mut link = &mut self.freeRuns as *mut usize;
+
+        unsafe {
+            while *link != 0 && *link < address {
+                previous = *link;
+                link = &mut (*(previous as *mut FreeRun)).next;
+            }
+
+            let run = address as *mut FreeRun;
+            (*run).pages = pages;
+            (*run).next = *link;
+            *link = address;
+
+            // Merge with whatever follows
+            let next = (*run).next;
+            if next != 0 && address + pages * SIZE_OF_PAGE == next {
+                let nextRun = next as *const FreeRun;
+                (*run).pages += (*nextRun).pages;
+                (*run).next = (*nextRun).next;
+            }
+
+            // And whatever precedes
+            if previous != 0 {
+                let previousRun = previous as *mut FreeRun;
+                if previous + (*previousRun).pages * SIZE_OF_PAGE == address {
+                    (*previousRun).pages += (*run).pages;
+                    (*previousRun).next = (*run).next;
+                }
+            }
+        }
+
+        self.stats.pagesFree += pages;
+    }
+
+    // False when there's no VMM yet or it's out of RAM or virtual space, which makes the allocation fail
+    fn grow(&mut self, pages: usize) -> bool {
+        let pages = pages.max(GROWTH_PAGES);
+        let Some(vmm) = self.vmm.as_mut() else {
+            return false;
+        };
+
+        let Some(address) = vmm.tryAllocatePages("Kernel heap", pages) else {
+            loggerWriteLine!("Heap couldn't grow by {} pages", pages);
+            return false;
+        };
+
+        loggerWriteLine!("Heap grew by {} pages @ 0x{:X}", pages, address);
+
+        self.stats.pagesOwned += pages;
+        self.freePages(address, pages);
+        true
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        critical_section::with(|cs| self.state.borrow_ref_mut(cs).allocate(&layout)) as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        critical_section::with(|cs| self.state.borrow_ref_mut(cs).free(ptr as usize, &layout));
+    }
+
+    // Staying in the same size class (or page count) means there's nothing to do
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        unsafe {
+            let newLayout = Layout::from_size_align_unchecked(new_size, layout.align());
+            let sameSlot = match (getSizeClass(&layout), getSizeClass(&newLayout)) {
+                (Some(old), Some(new)) => old == new,
+                (None, None) => getPageCount(&layout) == getPageCount(&newLayout),
+                _ => false,
+            };
+
+            if sameSlot {
+                critical_section::with(|cs| {
+                    let mut state = self.state.borrow_ref_mut(cs);
+                    state.stats.bytesInUse = state.stats.bytesInUse - layout.size() + new_size;
+                });
+
+                return ptr;
+            }
+
+            let result = self.alloc(newLayout);
+            if result.is_null() {
+                return null_mut();
+            }
+
+            copy_nonoverlapping(ptr, result, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+            result
+        }
+    }
+}
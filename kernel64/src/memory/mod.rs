@@ -1,3 +1,5 @@
 pub mod dumbHeap;
+pub mod heap;
 pub mod memoryStuff;
 pub mod virtualMemory;
+pub mod virtualRanges;
@@ -1,10 +1,14 @@
-use core::array::from_fn;
+use core::{arch::asm, array::from_fn, cell::RefCell};
+use critical_section::Mutex;
 use kernel_shared::{
     assemblyStuff::halt::haltLoop,
     haltLoopWithMessage, loggerWrite,
-    magicConstants::{PAGES_PER_TABLE, SIZE_OF_PAGE},
-    memoryHelpers::{alignUp, haltOnMisaligned, zeroMemory2},
-    memoryTypes::{PhysicalAddress, SomeSortOfIndex, VirtualAddress},
+    magicConstants::{PAGES_PER_TABLE, SIZE_OF_PAGE, SIZE_OF_PAGE_DIRECTORY, SIZE_OF_PAGE_TABLE},
+    memoryHelpers::{alignDown, alignUp, haltOnMisaligned, zeroMemory2},
+    memoryTypes::{
+        MemoryAddress, PhysicalAddress, PhysicalAddressPlain, SomeSortOfIndex, VirtualAddress,
+        VirtualAddressPlain,
+    },
     pageTable::{
         enums::*, pageBook::PageBook, pageDirectoryPointerTable::PageDirectoryPointerTable,
         pageDirectoryTable::PageDirectoryTable,
@@ -13,19 +17,67 @@ use kernel_shared::{
     physicalMemory::PhysicalMemoryManager,
 };
 
-use crate::loggerWriteLine;
+use crate::{
+    assemblyHelpers::{invlpg, supports1GiBPages},
+    loggerWriteLine,
+    magicConstants::{
+        VM_KERNEL64_DYNAMIC, VM_KERNEL64_DYNAMIC_LENGTH, VM_KERNEL64_PAGE_TABLE_WINDOW,
+    },
+};
 
-use super::dumbHeap::BootstrapDumbHeap;
+use super::{dumbHeap::BootstrapDumbHeap, virtualRanges::VirtualRangeAllocator};
 
+// Everything that gets handed a VMM, the heap included, only has a handle. What they share lives in the BDH behind a
+// critical section and each call has it to itself, so the heap can grow in the middle of someone else's work without
+// the two of them stepping on each other.
 pub struct VirtualMemoryManager {
+    state: &'static Mutex<RefCell<VirtualMemoryState>>,
+}
+
+struct VirtualMemoryState {
     physical: PhysicalMemoryManager,
     pageBook: PageBook,
     bdh: BootstrapDumbHeap,
     virtualAddresses: [usize; 100],
     nextVirtualAddressIndex: u8,
+    virtualRanges: VirtualRangeAllocator,
+    tableWindow: usize, // Virtual address of the PT behind VM_KERNEL64_PAGE_TABLE_WINDOW, 0 until activate
+    gibPagesSupported: bool,
+}
+
+// Which page of the table window each level gets, so a walk can hold on to one of each at the same time. The PML4
+// doesn't need one, the page book already knows where it is.
+const WINDOW_SLOT_PDPT: usize = 0;
+const WINDOW_SLOT_PD: usize = 1;
+const WINDOW_SLOT_PT: usize = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum PageSize {
+    Size4KiB,
+    Size2MiB,
+    Size1GiB,
+}
+
+impl PageSize {
+    fn getLength(&self) -> usize {
+        match self {
+            PageSize::Size4KiB => SIZE_OF_PAGE,
+            PageSize::Size2MiB => SIZE_OF_PAGE_TABLE,
+            PageSize::Size1GiB => SIZE_OF_PAGE_DIRECTORY,
+        }
+    }
+}
+
+// What's behind an address. The tables might be through the table window, so they're only good until the next look.
+enum Mapping {
+    Nothing(PageSize), // Missing at the level that covers this much
+    Small(VirtualAddress<PageTable>),
+    Large(VirtualAddress<PageDirectoryTable>), // 2 MiB page in the PD
+    Huge(VirtualAddress<PageDirectoryPointerTable>), // 1 GiB page in the PDPT
 }
 
 struct VirtualMemoryIndex {
+    pub Address: usize,
     pub PML4: usize,
     pub PDPT: usize,
     pub PD: usize,
@@ -44,28 +96,47 @@ impl VirtualMemoryIndex {
     }
 }
 
+// Worst case for mapping numberOfPages somewhere new: a fresh table at every level for each one the range touches
+fn getWorstCaseTables(numberOfPages: usize) -> usize {
+    let mut result = 0;
+    let mut covered = numberOfPages;
+    for _ in 0..3 {
+        covered = covered.div_ceil(PAGES_PER_TABLE);
+        result += covered + 1;
+    }
+
+    result
+}
+
 impl VirtualMemoryManager {
+    // The state goes in the BDH, since that's the only memory around this early that stays put for good
     pub fn new(
         physical: PhysicalMemoryManager,
         pageBook: PageBook,
-        bdh: BootstrapDumbHeap,
+        mut bdh: BootstrapDumbHeap,
     ) -> Self {
-        VirtualMemoryManager {
-            pageBook: pageBook,
-            physical: physical,
-            bdh: bdh,
-            virtualAddresses: from_fn(|_| 0),
-            nextVirtualAddressIndex: 0,
+        let home = bdh.allocate::<Mutex<RefCell<VirtualMemoryState>>>(
+            size_of::<Mutex<RefCell<VirtualMemoryState>>>(),
+            align_of::<Mutex<RefCell<VirtualMemoryState>>>(),
+        );
+
+        unsafe {
+            let state = VirtualMemoryState::new(physical, pageBook, bdh);
+            home.ptr().write(Mutex::new(RefCell::new(state)));
+
+            VirtualMemoryManager {
+                state: &*home.ptr(),
+            }
         }
     }
 
-    pub fn dumpPhysical(&self) {
-        self.physical.DumpReservedBlobs();
+    // Another handle on the same VMM, for the heap to grow with
+    pub(crate) fn share(&self) -> Self {
+        VirtualMemoryManager { state: self.state }
     }
 
-    fn is_canonical_address(virtual_address: usize) -> bool {
-        let upper_bits = virtual_address >> 48;
-        upper_bits == 0 || upper_bits == 0xFFFF
+    fn with<R>(&self, f: impl FnOnce(&mut VirtualMemoryState) -> R) -> R {
+        critical_section::with(|cs| f(&mut self.state.borrow_ref_mut(cs)))
     }
 
     pub const fn canonicalize(address: usize) -> usize {
@@ -77,20 +148,222 @@ impl VirtualMemoryManager {
         }
     }
 
+    pub fn dumpPhysical(&self) {
+        self.with(|state| state.dumpPhysical());
+    }
+
+    pub fn dumpVirtual(&self) {
+        self.with(|state| state.dumpVirtual());
+    }
+
+    pub fn activate(&mut self) {
+        self.with(|state| state.activate());
+    }
+
+    pub fn map(
+        &mut self,
+        physicalAddress: usize,
+        virtualAddress: usize,
+        length: usize,
+        executable: Execute,
+        present: Present,
+        writable: Writable,
+        cachable: Cachable,
+        us: UserSupervisor,
+        wt: WriteThrough,
+    ) {
+        self.with(|state| {
+            state.map(
+                physicalAddress,
+                virtualAddress,
+                length,
+                executable,
+                present,
+                writable,
+                cachable,
+                us,
+                wt,
+            )
+        });
+    }
+
+    pub fn unmap(&mut self, virtualAddress: usize, length: usize) {
+        self.with(|state| state.unmap(virtualAddress, length));
+    }
+
+    pub fn protect(
+        &mut self,
+        virtualAddress: usize,
+        length: usize,
+        executable: Execute,
+        writable: Writable,
+        cachable: Cachable,
+        wt: WriteThrough,
+    ) {
+        self.with(|state| {
+            state.protect(virtualAddress, length, executable, writable, cachable, wt)
+        });
+    }
+
+    pub fn translate(&mut self, virtualAddress: usize) -> Option<usize> {
+        self.with(|state| state.translate(virtualAddress))
+    }
+
+    pub fn identityMap(
+        &mut self,
+        physicalAddress: usize,
+        length: usize,
+        executable: Execute,
+        present: Present,
+        writable: Writable,
+        cachable: Cachable,
+        us: UserSupervisor,
+        wt: WriteThrough,
+    ) {
+        self.with(|state| {
+            state.identityMap(
+                physicalAddress,
+                length,
+                executable,
+                present,
+                writable,
+                cachable,
+                us,
+                wt,
+            )
+        });
+    }
+
+    pub(crate) fn mapPhysicalAnywhere(
+        &mut self,
+        physicalAddress: usize,
+        length: usize,
+        execute: Execute,
+        present: Present,
+        writable: Writable,
+        cachable: Cachable,
+        supervisor: UserSupervisor,
+        writeTrough: WriteThrough,
+    ) -> usize {
+        self.with(|state| {
+            state.mapPhysicalAnywhere(
+                physicalAddress,
+                length,
+                execute,
+                present,
+                writable,
+                cachable,
+                supervisor,
+                writeTrough,
+            )
+        })
+    }
+
+    pub(crate) fn unmapPhysicalAnywhere(&mut self, virtualAddress: usize, length: usize) {
+        self.with(|state| state.unmapPhysicalAnywhere(virtualAddress, length));
+    }
+
+    pub fn allocatePages(&mut self, forWhat: &str, numberOfPages: usize) -> usize {
+        self.with(|state| state.allocatePages(forWhat, numberOfPages))
+    }
+
+    pub(crate) fn tryAllocatePages(
+        &mut self,
+        forWhat: &str,
+        numberOfPages: usize,
+    ) -> Option<usize> {
+        self.with(|state| state.tryAllocatePages(forWhat, numberOfPages))
+    }
+
+    pub fn allocateDeviceMemory(&mut self, forWhat: &str, numberOfPages: usize) -> MemoryAddress {
+        self.with(|state| state.allocateDeviceMemory(forWhat, numberOfPages))
+    }
+
+    pub fn freePages(&mut self, virtualAddress: usize, numberOfPages: usize) {
+        self.with(|state| state.freePages(virtualAddress, numberOfPages));
+    }
+
+    pub fn getFreeVirtualAddress(&mut self, numberOfPages: usize) -> usize {
+        self.with(|state| state.getFreeVirtualAddress(numberOfPages))
+    }
+
+    pub fn freeVirtualAddress(&mut self, virtualAddress: usize, numberOfPages: usize) {
+        self.with(|state| state.freeVirtualAddress(virtualAddress, numberOfPages));
+    }
+}
+
+impl VirtualMemoryState {
+    fn new(physical: PhysicalMemoryManager, pageBook: PageBook, bdh: BootstrapDumbHeap) -> Self {
+        VirtualMemoryState {
+            pageBook: pageBook,
+            physical: physical,
+            bdh: bdh,
+            virtualAddresses: from_fn(|_| 0),
+            nextVirtualAddressIndex: 0,
+            virtualRanges: VirtualRangeAllocator::new(
+                VM_KERNEL64_DYNAMIC,
+                VM_KERNEL64_DYNAMIC_LENGTH,
+            ),
+            tableWindow: 0,
+            gibPagesSupported: supports1GiBPages(),
+        }
+    }
+
+    pub fn dumpPhysical(&self) {
+        self.physical.DumpUsage();
+    }
+
+    pub fn dumpVirtual(&self) {
+        self.virtualRanges.dump();
+    }
+
+    // Points the CPU at this page book. Until now every page table came from the BDH since that's the only memory
+    // we could get at; from here on they come from the physical allocator and get looked at through the table window,
+    // which means they can be given back once they're empty.
+    pub fn activate(&mut self) {
+        let vmi = Self::getVmi(VM_KERNEL64_PAGE_TABLE_WINDOW);
+        if vmi.PT != 0 {
+            haltLoopWithMessage!("Table window needs to start a PT");
+        }
+
+        unsafe {
+            let Some(virtualPageTable) = self.walk(&vmi, Some(UserSupervisor::Supervisor)) else {
+                haltLoopWithMessage!("Couldn't create the table window");
+            };
+
+            self.tableWindow = virtualPageTable.address;
+            loggerWriteLine!(
+                "Table window @ 0x{:X} with its PT @ 0x{:X}, 1 GiB pages: {}",
+                VM_KERNEL64_PAGE_TABLE_WINDOW,
+                virtualPageTable.address,
+                self.gibPagesSupported
+            );
+
+            asm!(
+                "mov cr3, rax",
+                in("rax") self.pageBook.getCR3Value(),
+            );
+        }
+    }
+
+    fn is_canonical_address(virtual_address: usize) -> bool {
+        let upper_bits = virtual_address >> 48;
+        upper_bits == 0 || upper_bits == 0xFFFF
+    }
+
     fn getVmi(address: usize) -> VirtualMemoryIndex {
         if !Self::is_canonical_address(address) {
             haltLoopWithMessage!("0x{:X} is not canonical", address);
         }
 
         let result = VirtualMemoryIndex {
+            Address: address,
             PML4: (address >> 39) & 0x1FF,
             PDPT: (address >> 30) & 0x1FF,
             PD: (address >> 21) & 0x1FF,
             PT: (address >> 12) & 0x1FF,
         };
 
-        result.dump();
-
         result
     }
 
@@ -121,7 +394,7 @@ impl VirtualMemoryManager {
         );
 
         while numberOfPages != 0 {
-            let pagesMapped = self.mapInternal(
+            let pagesMapped = match self.mapHuge(
                 physicalAddress,
                 virtualAddress,
                 numberOfPages,
@@ -131,7 +404,20 @@ impl VirtualMemoryManager {
                 cachable,
                 us,
                 wt,
-            );
+            ) {
+                Some(pagesMapped) => pagesMapped,
+                None => self.mapInternal(
+                    physicalAddress,
+                    virtualAddress,
+                    numberOfPages,
+                    executable,
+                    present,
+                    writable,
+                    cachable,
+                    us,
+                    wt,
+                ),
+            };
 
             if numberOfPages == pagesMapped {
                 break;
@@ -153,6 +439,97 @@ impl VirtualMemoryManager {
         loggerWriteLine!("Mapping complete");
     }
 
+    // Maps the front of the range with a single 1 GiB or 2 MiB page if the alignment and length allow it, returning how
+    // many 4 KiB pages worth that was. None means it needs to be done the small way, including when there's already a
+    // table where the big page would go.
+    // BUGBUG: An empty table in the way could be given back instead of giving up
+    fn mapHuge(
+        &mut self,
+        physicalAddress: usize,
+        virtualAddress: usize,
+        numberOfPages: usize,
+        executable: Execute,
+        present: Present,
+        writable: Writable,
+        cachable: Cachable,
+        us: UserSupervisor,
+        wt: WriteThrough,
+    ) -> Option<usize> {
+        let length = numberOfPages * SIZE_OF_PAGE;
+        let fits = |size: PageSize| {
+            physicalAddress % size.getLength() == 0 && Self::covers(virtualAddress, length, size)
+        };
+
+        let vmi = Self::getVmi(virtualAddress);
+
+        unsafe {
+            if self.gibPagesSupported && fits(PageSize::Size1GiB) {
+                let virtualPdpt = self.getPdpt(&vmi, Some(us))?;
+                let pdpt = virtualPdpt.ptr();
+                let wasHuge = (*pdpt).isHuge(vmi.PDPT);
+
+                if wasHuge || (*pdpt).getAddressForEntry(vmi.PDPT).is_null() {
+                    (*pdpt).setHugeEntry(
+                        vmi.PDPT,
+                        physicalAddress,
+                        executable,
+                        present,
+                        writable,
+                        cachable,
+                        us,
+                        wt,
+                    );
+
+                    if wasHuge {
+                        invlpg(virtualAddress);
+                    }
+
+                    loggerWriteLine!(
+                        "Mapped 0x{:X} / 0x{:X} (P/V) as a 1 GiB page",
+                        physicalAddress,
+                        virtualAddress
+                    );
+
+                    return Some(SIZE_OF_PAGE_DIRECTORY / SIZE_OF_PAGE);
+                }
+            }
+
+            if fits(PageSize::Size2MiB) {
+                let virtualPdpt = self.getPdpt(&vmi, Some(us))?;
+                let virtualPdt = self.getPdt(&vmi, &virtualPdpt, Some(us))?;
+                let pdt = virtualPdt.ptr();
+                let wasHuge = (*pdt).isHuge(vmi.PD);
+
+                if wasHuge || (*pdt).getAddressForEntry(vmi.PD).is_null() {
+                    (*pdt).setHugeEntry(
+                        vmi.PD,
+                        physicalAddress,
+                        executable,
+                        present,
+                        writable,
+                        cachable,
+                        us,
+                        wt,
+                    );
+
+                    if wasHuge {
+                        invlpg(virtualAddress);
+                    }
+
+                    loggerWriteLine!(
+                        "Mapped 0x{:X} / 0x{:X} (P/V) as a 2 MiB page",
+                        physicalAddress,
+                        virtualAddress
+                    );
+
+                    return Some(SIZE_OF_PAGE_TABLE / SIZE_OF_PAGE);
+                }
+            }
+        }
+
+        None
+    }
+
     // Requests to map the inputs
     // This function may end up mapping less, in which case you'll need to call it again adjusted for what it has already mapped
     // This function will return the number of pages mapped, so on reinvocation the address should be offset by the number of pages mapped
@@ -168,11 +545,11 @@ impl VirtualMemoryManager {
         us: UserSupervisor,
         wt: WriteThrough,
     ) -> usize {
-        // BUGBUG: Need to handle the case when a data structure already exists with conflicting enum bits
         haltOnMisaligned("Map - Physical", physicalAddress, SIZE_OF_PAGE);
         haltOnMisaligned("Map - Virtual", virtualAddress, SIZE_OF_PAGE);
 
         let vmi = Self::getVmi(virtualAddress);
+        vmi.dump();
 
         if vmi.PT + numberOfPages > PAGES_PER_TABLE {
             loggerWrite!("Mapping {} pages, but only ", numberOfPages);
@@ -199,6 +576,300 @@ impl VirtualMemoryManager {
             vmi.PT + numberOfPages
         );
 
+        unsafe {
+            let Some(virtualPageTable) = self.walk(&vmi, Some(us)) else {
+                haltLoopWithMessage!("Walk didn't create the tables");
+            };
+
+            let virtualPageTable = virtualPageTable.ptr();
+            for pageOffset in 0..numberOfPages {
+                let pageAddress = physicalAddress + (pageOffset * SIZE_OF_PAGE);
+                let pageAddress = PhysicalAddress::<PhysicalPage>::new(pageAddress);
+                let wasPresent = (*virtualPageTable).isPresent(vmi.PT + pageOffset);
+
+                (*virtualPageTable).setEntry(
+                    vmi.PT + pageOffset,
+                    &pageAddress,
+                    executable,
+                    present,
+                    writable,
+                    cachable,
+                    us,
+                    wt,
+                );
+
+                // Nothing gets cached for a page that wasn't there, so only a remap needs a flush
+                if wasPresent {
+                    invlpg(virtualAddress + (pageOffset * SIZE_OF_PAGE));
+                }
+            }
+
+            numberOfPages
+        }
+    }
+
+    // Removes the mappings for the range. Whatever they pointed at is left alone, as is the virtual space; see
+    // freePages and unmapPhysicalAnywhere for that. Huge pages only partly in the range get split, and page tables
+    // that end up empty are given back.
+    pub fn unmap(&mut self, mut virtualAddress: usize, length: usize) {
+        haltOnMisaligned("Unmap", virtualAddress, SIZE_OF_PAGE);
+        let mut numberOfPages = alignUp(length, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+
+        while numberOfPages != 0 {
+            let vmi = Self::getVmi(virtualAddress);
+            let length = numberOfPages * SIZE_OF_PAGE;
+            let pages;
+
+            unsafe {
+                match self.findMapping(&vmi) {
+                    Mapping::Nothing(size) if size != PageSize::Size4KiB => {
+                        pages = Self::pagesToBoundary(virtualAddress, size).min(numberOfPages);
+                    }
+                    Mapping::Huge(virtualPdpt)
+                        if Self::covers(virtualAddress, length, PageSize::Size1GiB) =>
+                    {
+                        (*virtualPdpt.ptr()).clearEntry(vmi.PDPT);
+                        invlpg(virtualAddress);
+                        pages = SIZE_OF_PAGE_DIRECTORY / SIZE_OF_PAGE;
+                    }
+                    Mapping::Large(virtualPdt)
+                        if Self::covers(virtualAddress, length, PageSize::Size2MiB) =>
+                    {
+                        (*virtualPdt.ptr()).clearEntry(vmi.PD);
+                        invlpg(virtualAddress);
+                        pages = SIZE_OF_PAGE_TABLE / SIZE_OF_PAGE;
+                    }
+                    _ => {
+                        pages = numberOfPages.min(PAGES_PER_TABLE - vmi.PT);
+                        let Some(virtualPageTable) = self.walk(&vmi, None) else {
+                            haltLoopWithMessage!("Tables for 0x{:X} disappeared", virtualAddress);
+                        };
+
+                        let virtualPageTable = virtualPageTable.ptr();
+                        for pageOffset in 0..pages {
+                            if (*virtualPageTable).isPresent(vmi.PT + pageOffset) {
+                                (*virtualPageTable).clearEntry(vmi.PT + pageOffset);
+                                invlpg(virtualAddress + (pageOffset * SIZE_OF_PAGE));
+                            }
+                        }
+                    }
+                }
+
+                // invlpg also throws out every cached paging structure, so the flushes above already took care of
+                // anything released here
+                self.releaseEmptyTables(&vmi);
+            }
+
+            numberOfPages -= pages;
+            virtualAddress += pages * SIZE_OF_PAGE;
+        }
+    }
+
+    // Changes the permissions and caching of something that's already mapped. Huge pages only partly in the range get
+    // split first.
+    pub fn protect(
+        &mut self,
+        mut virtualAddress: usize,
+        length: usize,
+        executable: Execute,
+        writable: Writable,
+        cachable: Cachable,
+        wt: WriteThrough,
+    ) {
+        haltOnMisaligned("Protect", virtualAddress, SIZE_OF_PAGE);
+        let mut numberOfPages = alignUp(length, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+
+        while numberOfPages != 0 {
+            let vmi = Self::getVmi(virtualAddress);
+            let length = numberOfPages * SIZE_OF_PAGE;
+            let pages;
+
+            unsafe {
+                match self.findMapping(&vmi) {
+                    Mapping::Nothing(_) => {
+                        haltLoopWithMessage!(
+                            "Can't protect 0x{:X}, it isn't mapped",
+                            virtualAddress
+                        );
+                    }
+                    Mapping::Huge(virtualPdpt)
+                        if Self::covers(virtualAddress, length, PageSize::Size1GiB) =>
+                    {
+                        let pdpt = virtualPdpt.ptr();
+                        let attributes = (*pdpt).getAttributes(vmi.PDPT);
+                        let address = (*pdpt).getAddressForEntry(vmi.PDPT).address;
+                        (*pdpt).setHugeEntry(
+                            vmi.PDPT,
+                            alignDown(address, SIZE_OF_PAGE_DIRECTORY),
+                            executable,
+                            attributes.present,
+                            writable,
+                            cachable,
+                            attributes.us,
+                            wt,
+                        );
+
+                        invlpg(virtualAddress);
+                        pages = SIZE_OF_PAGE_DIRECTORY / SIZE_OF_PAGE;
+                    }
+                    Mapping::Large(virtualPdt)
+                        if Self::covers(virtualAddress, length, PageSize::Size2MiB) =>
+                    {
+                        let pdt = virtualPdt.ptr();
+                        let attributes = (*pdt).getAttributes(vmi.PD);
+                        let address = (*pdt).getAddressForEntry(vmi.PD).address;
+                        (*pdt).setHugeEntry(
+                            vmi.PD,
+                            alignDown(address, SIZE_OF_PAGE_TABLE),
+                            executable,
+                            attributes.present,
+                            writable,
+                            cachable,
+                            attributes.us,
+                            wt,
+                        );
+
+                        invlpg(virtualAddress);
+                        pages = SIZE_OF_PAGE_TABLE / SIZE_OF_PAGE;
+                    }
+                    _ => {
+                        pages = numberOfPages.min(PAGES_PER_TABLE - vmi.PT);
+                        let Some(virtualPageTable) = self.walk(&vmi, None) else {
+                            haltLoopWithMessage!("Tables for 0x{:X} disappeared", virtualAddress);
+                        };
+
+                        let virtualPageTable = virtualPageTable.ptr();
+                        for pageOffset in 0..pages {
+                            let pageAddress = virtualAddress + (pageOffset * SIZE_OF_PAGE);
+                            if !(*virtualPageTable).isPresent(vmi.PT + pageOffset) {
+                                haltLoopWithMessage!(
+                                    "Can't protect 0x{:X}, it isn't mapped",
+                                    pageAddress
+                                );
+                            }
+
+                            (*virtualPageTable).protectEntry(
+                                vmi.PT + pageOffset,
+                                executable,
+                                writable,
+                                cachable,
+                                wt,
+                            );
+                            invlpg(pageAddress);
+                        }
+                    }
+                }
+            }
+
+            numberOfPages -= pages;
+            virtualAddress += pages * SIZE_OF_PAGE;
+        }
+    }
+
+    // Where virtualAddress currently points, if anywhere
+    pub fn translate(&mut self, virtualAddress: usize) -> Option<usize> {
+        if !Self::is_canonical_address(virtualAddress) {
+            return None;
+        }
+
+        let vmi = Self::getVmi(virtualAddress);
+
+        unsafe {
+            match self.findMapping(&vmi) {
+                Mapping::Nothing(_) => None,
+                Mapping::Small(virtualPageTable) => {
+                    let page = (*virtualPageTable.ptr()).getAddressForEntry(vmi.PT);
+                    Some(page.address + (virtualAddress % SIZE_OF_PAGE))
+                }
+                Mapping::Large(virtualPdt) => {
+                    let page = (*virtualPdt.ptr()).getAddressForEntry(vmi.PD);
+                    Some(
+                        alignDown(page.address, SIZE_OF_PAGE_TABLE)
+                            + (virtualAddress % SIZE_OF_PAGE_TABLE),
+                    )
+                }
+                Mapping::Huge(virtualPdpt) => {
+                    let page = (*virtualPdpt.ptr()).getAddressForEntry(vmi.PDPT);
+                    Some(
+                        alignDown(page.address, SIZE_OF_PAGE_DIRECTORY)
+                            + (virtualAddress % SIZE_OF_PAGE_DIRECTORY),
+                    )
+                }
+            }
+        }
+    }
+
+    // Whether the range starts with a whole page of the given size
+    fn covers(virtualAddress: usize, length: usize, size: PageSize) -> bool {
+        virtualAddress % size.getLength() == 0 && length >= size.getLength()
+    }
+
+    // How many 4 KiB pages until the next boundary of the given size
+    fn pagesToBoundary(virtualAddress: usize, size: PageSize) -> usize {
+        let next = alignDown(virtualAddress, size.getLength()) + size.getLength();
+        (next - virtualAddress) / SIZE_OF_PAGE
+    }
+
+    // Looks up what's behind an address without creating or splitting anything
+    unsafe fn findMapping(&mut self, vmi: &VirtualMemoryIndex) -> Mapping {
+        unsafe {
+            let virtualPml4 = self.pageBook.getVirtual();
+            let physicalPdpt = (*virtualPml4.ptr()).getAddressForEntry(vmi.PML4);
+            if physicalPdpt.is_null() {
+                // BUGBUG: This could skip a lot more, but nothing maps that much yet
+                return Mapping::Nothing(PageSize::Size1GiB);
+            }
+
+            let virtualPdpt = self.getTable(&physicalPdpt, WINDOW_SLOT_PDPT);
+            if (*virtualPdpt.ptr()).isHuge(vmi.PDPT) {
+                return Mapping::Huge(virtualPdpt);
+            }
+
+            let physicalPdt = (*virtualPdpt.ptr()).getAddressForEntry(vmi.PDPT);
+            if physicalPdt.is_null() {
+                return Mapping::Nothing(PageSize::Size1GiB);
+            }
+
+            let virtualPdt = self.getTable(&physicalPdt, WINDOW_SLOT_PD);
+            if (*virtualPdt.ptr()).isHuge(vmi.PD) {
+                return Mapping::Large(virtualPdt);
+            }
+
+            let physicalPageTable = (*virtualPdt.ptr()).getAddressForEntry(vmi.PD);
+            if physicalPageTable.is_null() {
+                return Mapping::Nothing(PageSize::Size2MiB);
+            }
+
+            let virtualPageTable = self.getTable(&physicalPageTable, WINDOW_SLOT_PT);
+            if !(*virtualPageTable.ptr()).isPresent(vmi.PT) {
+                return Mapping::Nothing(PageSize::Size4KiB);
+            }
+
+            Mapping::Small(virtualPageTable)
+        }
+    }
+
+    // Finds the PT behind vmi, splitting any huge pages in the way. Anything missing along the way is created if
+    // there's a UserSupervisor to create it with, otherwise the walk gives up.
+    // Permissions are only enforced at the last level; everything above is writable and executable so a later mapping
+    // that shares tables with an earlier one isn't stuck with whatever the earlier one asked for.
+    unsafe fn walk(
+        &mut self,
+        vmi: &VirtualMemoryIndex,
+        create: Option<UserSupervisor>,
+    ) -> Option<VirtualAddress<PageTable>> {
+        unsafe {
+            let virtualPdpt = self.getPdpt(vmi, create)?;
+            let virtualPdt = self.getPdt(vmi, &virtualPdpt, create)?;
+            self.getPageTable(vmi, &virtualPdt, create)
+        }
+    }
+
+    unsafe fn getPdpt(
+        &mut self,
+        vmi: &VirtualMemoryIndex,
+        create: Option<UserSupervisor>,
+    ) -> Option<VirtualAddress<PageDirectoryPointerTable>> {
         unsafe {
             let virtualPml4 = self.pageBook.getVirtual();
             if virtualPml4.is_null() {
@@ -207,141 +878,303 @@ impl VirtualMemoryManager {
                 haltLoopWithMessage!("No PML4 in PageBook!");
             }
 
-            let mut physicalPdpt = (*virtualPml4.ptr()).getAddressForEntry(vmi.PML4);
-            let virtualPdpt: VirtualAddress<PageDirectoryPointerTable>;
+            let physicalPdpt = (*virtualPml4.ptr()).getAddressForEntry(vmi.PML4);
+            if !physicalPdpt.is_null() {
+                return Some(self.getTable(&physicalPdpt, WINDOW_SLOT_PDPT));
+            }
 
-            if physicalPdpt.is_null() {
-                virtualPdpt = self
-                    .bdh
-                    .allocate(size_of::<PageDirectoryPointerTable>(), 0x1000);
+            let us = create?;
+            let (physicalPdpt, virtualPdpt) = self.allocateTable(WINDOW_SLOT_PDPT);
+
+            loggerWriteLine!(
+                "Allocated a new PDPT @ 0x{:X} / 0x{:X} (P/V)",
+                physicalPdpt.address,
+                virtualPdpt.address
+            );
 
-                zeroMemory2(virtualPdpt.ptr());
+            (*virtualPml4.ptr()).setEntry(
+                vmi.PML4,
+                &physicalPdpt,
+                Execute::Yes,
+                Present::Yes,
+                Writable::Yes,
+                Cachable::No,
+                us,
+                WriteThrough::WriteTrough,
+                SomeSortOfIndex { value: u8::MAX },
+            );
 
-                physicalPdpt = self.bdh.vToP(&virtualPdpt);
+            Some(virtualPdpt)
+        }
+    }
 
-                loggerWriteLine!(
-                    "Allocated a new PDPT @ 0x{:X} / 0x{:X} (P/V)",
-                    physicalPdpt.address,
-                    virtualPdpt.address
-                );
+    unsafe fn getPdt(
+        &mut self,
+        vmi: &VirtualMemoryIndex,
+        virtualPdpt: &VirtualAddress<PageDirectoryPointerTable>,
+        create: Option<UserSupervisor>,
+    ) -> Option<VirtualAddress<PageDirectoryTable>> {
+        unsafe {
+            let pdpt = virtualPdpt.ptr();
+            if (*pdpt).isHuge(vmi.PDPT) {
+                return Some(self.splitHugePage(vmi, virtualPdpt));
+            }
 
-                (*virtualPml4.ptr()).setEntry(
-                    vmi.PML4,
-                    &physicalPdpt,
-                    executable,
-                    present,
-                    writable,
-                    cachable,
-                    us,
-                    wt,
-                    SomeSortOfIndex { value: u8::MAX },
-                );
-            } else {
-                virtualPdpt = self.bdh.pToV(&physicalPdpt);
+            let physicalPdt = (*pdpt).getAddressForEntry(vmi.PDPT);
+            if !physicalPdt.is_null() {
+                return Some(self.getTable(&physicalPdt, WINDOW_SLOT_PD));
+            }
 
-                loggerWriteLine!(
-                    "PDPT exists @ 0x{:X} / 0x{:X} (P/V)",
-                    physicalPdpt.address,
-                    virtualPdpt.address
-                );
+            let us = create?;
+            let (physicalPdt, virtualPdt) = self.allocateTable(WINDOW_SLOT_PD);
+
+            (*pdpt).setEntry(
+                vmi.PDPT,
+                &physicalPdt,
+                Execute::Yes,
+                Present::Yes,
+                Writable::Yes,
+                Cachable::No,
+                us,
+                WriteThrough::WriteTrough,
+            );
+
+            loggerWriteLine!(
+                "Allocated a new PDT @ 0x{:X} / 0x{:X} (P/V)",
+                physicalPdt.address,
+                virtualPdt.address
+            );
+
+            Some(virtualPdt)
+        }
+    }
+
+    unsafe fn getPageTable(
+        &mut self,
+        vmi: &VirtualMemoryIndex,
+        virtualPdt: &VirtualAddress<PageDirectoryTable>,
+        create: Option<UserSupervisor>,
+    ) -> Option<VirtualAddress<PageTable>> {
+        unsafe {
+            let pdt = virtualPdt.ptr();
+            if (*pdt).isHuge(vmi.PD) {
+                return Some(self.splitLargePage(vmi, virtualPdt));
             }
 
-            if physicalPdpt.address == virtualPdpt.address {
-                loggerWriteLine!("PDPT is identity mapped");
-            } else {
-                loggerWriteLine!("PDPT is not identity mapped");
+            let physicalPageTable = (*pdt).getAddressForEntry(vmi.PD);
+            if !physicalPageTable.is_null() {
+                return Some(self.getTable(&physicalPageTable, WINDOW_SLOT_PT));
             }
 
-            let mut physicalPdt = (*virtualPdpt.ptr()).getAddressForEntry(vmi.PDPT);
-            let virtualPdt: VirtualAddress<PageDirectoryTable>;
+            let us = create?;
+            let (physicalPageTable, virtualPageTable) = self.allocateTable(WINDOW_SLOT_PT);
 
-            if physicalPdt.is_null() {
-                virtualPdt = self
-                    .bdh
-                    .allocate::<PageDirectoryTable>(size_of::<PageDirectoryTable>(), 0x1000);
-                zeroMemory2(virtualPdt.ptr());
+            (*pdt).setEntry(
+                vmi.PD,
+                &physicalPageTable,
+                Execute::Yes,
+                Present::Yes,
+                Writable::Yes,
+                Cachable::No,
+                us,
+                WriteThrough::WriteTrough,
+            );
 
-                physicalPdt = self.bdh.vToP(&virtualPdt);
+            loggerWriteLine!(
+                "Allocated a new PT @ 0x{:X} / 0x{:X} (P/V)",
+                physicalPageTable.address,
+                virtualPageTable.address
+            );
 
-                (*virtualPdpt.ptr()).setEntry(
-                    vmi.PDPT,
-                    &physicalPdt,
-                    executable,
-                    present,
-                    writable,
-                    cachable,
-                    us,
-                    wt,
-                );
+            Some(virtualPageTable)
+        }
+    }
 
-                loggerWriteLine!(
-                    "Allocated a new PDT @ 0x{:X} / 0x{:X} (P/V)",
-                    physicalPdt.address,
-                    virtualPdt.address
-                );
-            } else {
-                virtualPdt = self.bdh.pToV(&physicalPdt);
+    // Swaps a 1 GiB page for a PD full of 2 MiB pages that map the same memory the same way
+    unsafe fn splitHugePage(
+        &mut self,
+        vmi: &VirtualMemoryIndex,
+        virtualPdpt: &VirtualAddress<PageDirectoryPointerTable>,
+    ) -> VirtualAddress<PageDirectoryTable> {
+        unsafe {
+            let pdpt = virtualPdpt.ptr();
+            let attributes = (*pdpt).getAttributes(vmi.PDPT);
+            let address = alignDown(
+                (*pdpt).getAddressForEntry(vmi.PDPT).address,
+                SIZE_OF_PAGE_DIRECTORY,
+            );
 
-                loggerWriteLine!(
-                    "PDT exists @ 0x{:X} / 0x{:X} (P/V)",
-                    physicalPdt.address,
-                    virtualPdt.address
+            let (physicalPdt, virtualPdt) = self.allocateTable(WINDOW_SLOT_PD);
+            let pdt: *mut PageDirectoryTable = virtualPdt.ptr();
+            for index in 0..(*pdt).getNumberOfEntries() {
+                (*pdt).setHugeEntry(
+                    index,
+                    address + (index * SIZE_OF_PAGE_TABLE),
+                    attributes.executable,
+                    attributes.present,
+                    attributes.writable,
+                    attributes.cachable,
+                    attributes.us,
+                    attributes.wt,
                 );
             }
 
-            let mut physicalPageTable = (*virtualPdt.ptr()).getAddressForEntry(vmi.PD);
-            let virtualPageTable: VirtualAddress<PageTable>;
+            (*pdpt).setEntry(
+                vmi.PDPT,
+                &physicalPdt,
+                Execute::Yes,
+                Present::Yes,
+                Writable::Yes,
+                Cachable::No,
+                attributes.us,
+                WriteThrough::WriteTrough,
+            );
 
-            if physicalPageTable.is_null() {
-                virtualPageTable = self
-                    .bdh
-                    .allocate::<PageTable>(size_of::<PageTable>(), 0x1000);
-                zeroMemory2(virtualPageTable.ptr());
+            // Same translations, but the TLB could still be holding the old size
+            invlpg(vmi.Address);
+            loggerWriteLine!("Split the 1 GiB page at 0x{:X}", vmi.Address);
 
-                physicalPageTable = self.bdh.vToP(&virtualPageTable);
+            virtualPdt
+        }
+    }
 
-                (*virtualPdt.ptr()).setEntry(
-                    vmi.PD,
-                    &physicalPageTable,
-                    executable,
-                    present,
-                    writable,
-                    cachable,
-                    us,
-                    wt,
-                );
+    // Swaps a 2 MiB page for a PT full of 4 KiB pages that map the same memory the same way
+    unsafe fn splitLargePage(
+        &mut self,
+        vmi: &VirtualMemoryIndex,
+        virtualPdt: &VirtualAddress<PageDirectoryTable>,
+    ) -> VirtualAddress<PageTable> {
+        unsafe {
+            let pdt = virtualPdt.ptr();
+            let attributes = (*pdt).getAttributes(vmi.PD);
+            let address = alignDown(
+                (*pdt).getAddressForEntry(vmi.PD).address,
+                SIZE_OF_PAGE_TABLE,
+            );
 
-                loggerWriteLine!(
-                    "Allocated a new PT @ 0x{:X} / 0x{:X} (P/V)",
-                    physicalPageTable.address,
-                    virtualPageTable.address
+            let (physicalPageTable, virtualPageTable) = self.allocateTable(WINDOW_SLOT_PT);
+            let pageTable: *mut PageTable = virtualPageTable.ptr();
+            for index in 0..(*pageTable).getNumberOfEntries() {
+                let pageAddress = address + (index * SIZE_OF_PAGE);
+                (*pageTable).setEntry(
+                    index,
+                    &PhysicalAddress::<PhysicalPage>::new(pageAddress),
+                    attributes.executable,
+                    attributes.present,
+                    attributes.writable,
+                    attributes.cachable,
+                    attributes.us,
+                    attributes.wt,
                 );
-            } else {
-                virtualPageTable = self.bdh.pToV(&physicalPageTable);
+            }
 
-                loggerWriteLine!(
-                    "PT exists @ 0x{:X} / 0x{:X} (P/V)",
-                    physicalPageTable.address,
-                    virtualPageTable.address
-                );
+            (*pdt).setEntry(
+                vmi.PD,
+                &physicalPageTable,
+                Execute::Yes,
+                Present::Yes,
+                Writable::Yes,
+                Cachable::No,
+                attributes.us,
+                WriteThrough::WriteTrough,
+            );
+
+            invlpg(vmi.Address);
+            loggerWriteLine!("Split the 2 MiB page at 0x{:X}", vmi.Address);
+
+            virtualPageTable
+        }
+    }
+
+    // Somewhere we can look at the table. BDH tables (and everything before activate) are reachable with the BDH's
+    // adjustment; anything else gets mapped into its slot of the table window.
+    unsafe fn getTable<T>(
+        &mut self,
+        physicalAddress: &PhysicalAddress<T>,
+        slot: usize,
+    ) -> VirtualAddress<T> {
+        unsafe {
+            if self.tableWindow == 0 || self.bdh.ownsPhysical(physicalAddress) {
+                return self.bdh.pToV(physicalAddress);
             }
 
-            for pageOffset in 0..numberOfPages {
-                let pageAddress = physicalAddress + (pageOffset * SIZE_OF_PAGE);
-                let pageAddress = PhysicalAddress::<PhysicalPage>::new(pageAddress);
-                (*virtualPageTable.ptr()).setEntry(
-                    vmi.PT + pageOffset,
-                    &pageAddress,
-                    executable,
-                    present,
-                    writable,
-                    cachable,
-                    us,
-                    wt,
-                );
+            // BUGBUG: Uncached like every other table until we've sorted out what the rest of the mappings need
+            let windowAddress = VM_KERNEL64_PAGE_TABLE_WINDOW + (slot * SIZE_OF_PAGE);
+            (*(self.tableWindow as *mut PageTable)).setEntry(
+                slot,
+                &PhysicalAddress::<PhysicalPage>::new(physicalAddress.address),
+                Execute::Yes,
+                Present::Yes,
+                Writable::Yes,
+                Cachable::No,
+                UserSupervisor::Supervisor,
+                WriteThrough::WriteTrough,
+            );
+
+            invlpg(windowAddress);
+            VirtualAddress::new(windowAddress)
+        }
+    }
+
+    // A zeroed table, ready to be pointed at
+    unsafe fn allocateTable<T>(&mut self, slot: usize) -> (PhysicalAddress<T>, VirtualAddress<T>) {
+        unsafe {
+            if self.tableWindow == 0 {
+                let virtualAddress = self.bdh.allocate::<T>(SIZE_OF_PAGE, SIZE_OF_PAGE);
+                zeroMemory2(virtualAddress.ptr());
+                return (self.bdh.vToP(&virtualAddress), virtualAddress);
             }
 
-            numberOfPages
+            let Some(frame) = self.physical.ReserveFrame() else {
+                haltLoopWithMessage!("Out of memory for page tables");
+            };
+
+            let physicalAddress = PhysicalAddress::<T>::new(frame);
+            let virtualAddress = self.getTable(&physicalAddress, slot);
+            zeroMemory2(virtualAddress.ptr());
+
+            (physicalAddress, virtualAddress)
+        }
+    }
+
+    // Only tables from the physical allocator can go back. Everything from the BDH is there for good.
+    fn canFreeTable<T>(&self, physicalAddress: &PhysicalAddress<T>) -> bool {
+        self.tableWindow != 0 && !self.bdh.ownsPhysical(physicalAddress)
+    }
+
+    // Works up from the PT behind vmi giving back anything that no longer points anywhere. The PML4 stays no matter
+    // what.
+    unsafe fn releaseEmptyTables(&mut self, vmi: &VirtualMemoryIndex) {
+        unsafe {
+            let virtualPml4 = self.pageBook.getVirtual();
+            let physicalPdpt = (*virtualPml4.ptr()).getAddressForEntry(vmi.PML4);
+            if physicalPdpt.is_null() {
+                return;
+            }
+
+            let pdpt = self.getTable(&physicalPdpt, WINDOW_SLOT_PDPT).ptr();
+            let physicalPdt = (*pdpt).getAddressForEntry(vmi.PDPT);
+            if !(*pdpt).isHuge(vmi.PDPT) && !physicalPdt.is_null() {
+                let pdt = self.getTable(&physicalPdt, WINDOW_SLOT_PD).ptr();
+                let physicalPageTable = (*pdt).getAddressForEntry(vmi.PD);
+                if !(*pdt).isHuge(vmi.PD) && !physicalPageTable.is_null() {
+                    let pageTable = self.getTable(&physicalPageTable, WINDOW_SLOT_PT).ptr();
+                    if (*pageTable).isEmpty() && self.canFreeTable(&physicalPageTable) {
+                        (*pdt).clearEntry(vmi.PD);
+                        self.physical.Free(physicalPageTable.address, SIZE_OF_PAGE);
+                    }
+                }
+
+                if (*pdt).isEmpty() && self.canFreeTable(&physicalPdt) {
+                    (*pdpt).clearEntry(vmi.PDPT);
+                    self.physical.Free(physicalPdt.address, SIZE_OF_PAGE);
+                }
+            }
+
+            if (*pdpt).isEmpty() && self.canFreeTable(&physicalPdpt) {
+                (*virtualPml4.ptr()).clearEntry(vmi.PML4);
+                self.physical.Free(physicalPdpt.address, SIZE_OF_PAGE);
+            }
         }
     }
 
@@ -370,18 +1203,139 @@ impl VirtualMemoryManager {
         );
     }
 
+    // Maps the physical range somewhere in the dynamic window and returns the virtual address that lines up with
+    // physicalAddress. It doesn't need to be page aligned, whatever surrounds it in the page gets mapped too.
     pub(crate) fn mapPhysicalAnywhere(
-        &self,
-        _physicalAddress: usize,
-        _length: usize,
-        _execute: Execute,
-        _present: Present,
-        _writable: Writable,
-        _cachable: Cachable,
-        _supervisor: UserSupervisor,
-        _writeTrough: WriteThrough,
+        &mut self,
+        physicalAddress: usize,
+        length: usize,
+        execute: Execute,
+        present: Present,
+        writable: Writable,
+        cachable: Cachable,
+        supervisor: UserSupervisor,
+        writeTrough: WriteThrough,
     ) -> usize {
-        todo!()
+        let alignedPhysical = alignDown(physicalAddress, SIZE_OF_PAGE);
+        let offset = physicalAddress - alignedPhysical;
+        let numberOfPages = alignUp(offset + length, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+
+        let virtualAddress = self.getFreeVirtualAddress(numberOfPages);
+        self.map(
+            alignedPhysical,
+            virtualAddress,
+            numberOfPages * SIZE_OF_PAGE,
+            execute,
+            present,
+            writable,
+            cachable,
+            supervisor,
+            writeTrough,
+        );
+
+        virtualAddress + offset
+    }
+
+    // Undoes mapPhysicalAnywhere. Takes what it returned, offset and all, along with the same length.
+    pub(crate) fn unmapPhysicalAnywhere(&mut self, virtualAddress: usize, length: usize) {
+        let alignedVirtual = alignDown(virtualAddress, SIZE_OF_PAGE);
+        let offset = virtualAddress - alignedVirtual;
+        let numberOfPages = alignUp(offset + length, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+
+        self.unmap(alignedVirtual, numberOfPages * SIZE_OF_PAGE);
+        self.freeVirtualAddress(alignedVirtual, numberOfPages);
+    }
+
+    // Backs numberOfPages of fresh virtual space with RAM and returns where it landed. Contents are whatever was
+    // there before.
+    pub fn allocatePages(&mut self, forWhat: &str, numberOfPages: usize) -> usize {
+        let Some(result) = self.tryAllocatePages(forWhat, numberOfPages) else {
+            self.physical.DumpUsage();
+            haltLoopWithMessage!("Can't allocate {} pages for {}", numberOfPages, forWhat);
+        };
+
+        result
+    }
+
+    // Same, but None instead of halting when there isn't the RAM or virtual space for it
+    fn tryAllocatePages(&mut self, forWhat: &str, numberOfPages: usize) -> Option<usize> {
+        let length = numberOfPages * SIZE_OF_PAGE;
+        let physicalAddress = self
+            .physical
+            .TryReserveWherever(forWhat, length, SIZE_OF_PAGE)?;
+
+        // Mapping gets its page tables from the same frames and halts if they run out, so make sure they won't
+        if self.physical.Stats().freeFrames < getWorstCaseTables(numberOfPages) {
+            self.physical.Free(physicalAddress, length);
+            return None;
+        }
+
+        let Some(virtualAddress) = self.virtualRanges.allocate(numberOfPages) else {
+            self.physical.Free(physicalAddress, length);
+            return None;
+        };
+
+        self.map(
+            physicalAddress,
+            virtualAddress,
+            length,
+            Execute::Yes, // BUGBUG: Same NX mystery as the kernel data
+            Present::Yes,
+            Writable::Yes,
+            Cachable::Yes,
+            UserSupervisor::Supervisor,
+            WriteThrough::WriteBack,
+        );
+
+        Some(virtualAddress)
+    }
+
+    // Physically contiguous, zeroed and uncached; for structures a device reads and writes on its own. The device
+    // gets the physical half, we get the virtual half. freePages gives it back.
+    pub fn allocateDeviceMemory(&mut self, forWhat: &str, numberOfPages: usize) -> MemoryAddress {
+        let length = numberOfPages * SIZE_OF_PAGE;
+        let physicalAddress = self.physical.ReserveWherever(forWhat, length, SIZE_OF_PAGE);
+        let virtualAddress = self.getFreeVirtualAddress(numberOfPages);
+
+        self.map(
+            physicalAddress,
+            virtualAddress,
+            length,
+            Execute::Yes,
+            Present::Yes,
+            Writable::Yes,
+            Cachable::No,
+            UserSupervisor::Supervisor,
+            WriteThrough::WriteTrough,
+        );
+
+        unsafe {
+            core::ptr::write_bytes(virtualAddress as *mut u8, 0, length);
+        }
+
+        MemoryAddress {
+            r#virtual: VirtualAddressPlain {
+                address: virtualAddress,
+            },
+            physical: PhysicalAddressPlain {
+                address: physicalAddress,
+            },
+        }
+    }
+
+    // Undoes allocatePages: the RAM, the mapping and the virtual space all go back
+    pub fn freePages(&mut self, virtualAddress: usize, numberOfPages: usize) {
+        for page in 0..numberOfPages {
+            let pageAddress = virtualAddress + (page * SIZE_OF_PAGE);
+            let Some(physicalAddress) = self.translate(pageAddress) else {
+                haltLoopWithMessage!("Freeing 0x{:X}, but it isn't mapped", pageAddress);
+            };
+
+            self.physical.Free(physicalAddress, SIZE_OF_PAGE);
+        }
+
+        self.unmap(virtualAddress, numberOfPages * SIZE_OF_PAGE);
+        self.freeVirtualAddress(virtualAddress, numberOfPages);
     }
 
     fn getVirtualAddress<T>(&self, xxx: SomeSortOfIndex) -> VirtualAddress<T> {
@@ -406,7 +1360,27 @@ impl VirtualMemoryManager {
         result
     }
 
-    pub fn getFreeVirtualAddress(&self, _numberOfPages: usize) -> usize {
-        todo!()
+    // Hands out unused virtual space from the dynamic window. Nothing is mapped there yet.
+    pub fn getFreeVirtualAddress(&mut self, numberOfPages: usize) -> usize {
+        let Some(result) = self.virtualRanges.allocate(numberOfPages) else {
+            haltLoopWithMessage!(
+                "Out of dynamic virtual space asking for {} pages",
+                numberOfPages
+            );
+        };
+
+        result
+    }
+
+    // Makes space from getFreeVirtualAddress available again. Anything still mapped there needs to be unmapped first.
+    pub fn freeVirtualAddress(&mut self, virtualAddress: usize, numberOfPages: usize) {
+        if let Err(reason) = self.virtualRanges.free(virtualAddress, numberOfPages) {
+            haltLoopWithMessage!(
+                "Can't free virtual 0x{:X} for {} pages: {}",
+                virtualAddress,
+                numberOfPages,
+                reason
+            );
+        }
     }
 }
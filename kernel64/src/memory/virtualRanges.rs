@@ -0,0 +1,162 @@
+use kernel_shared::magicConstants::SIZE_OF_PAGE;
+
+use crate::loggerWriteLine;
+
+// Hands out page aligned ranges of virtual address space. Just the addresses; nothing here knows or cares whether
+// anything is mapped there.
+//
+// Free space is kept as an address ordered array of runs that coalesce when given back. It can't use the heap since
+// the heap gets its space from here, so the number of runs is fixed. First fit.
+const MAX_FREE_RANGES: usize = 64;
+
+#[derive(Clone, Copy)]
+struct FreeRange {
+    address: usize,
+    pages: usize,
+}
+
+pub struct VirtualRangeAllocator {
+    ranges: [FreeRange; MAX_FREE_RANGES],
+    rangeCount: usize,
+    start: usize,
+    end: usize,
+    freePages: usize,
+    leakedPages: usize, // Given back when there was nowhere to put it
+}
+
+impl VirtualRangeAllocator {
+    pub fn new(address: usize, length: usize) -> Self {
+        let mut result = VirtualRangeAllocator {
+            ranges: [FreeRange {
+                address: 0,
+                pages: 0,
+            }; MAX_FREE_RANGES],
+            rangeCount: 1,
+            start: address,
+            end: address + length,
+            freePages: length / SIZE_OF_PAGE,
+            leakedPages: 0,
+        };
+
+        result.ranges[0] = FreeRange {
+            address,
+            pages: length / SIZE_OF_PAGE,
+        };
+
+        result
+    }
+
+    pub fn getFreePages(&self) -> usize {
+        self.freePages
+    }
+
+    pub fn allocate(&mut self, numberOfPages: usize) -> Option<usize> {
+        if numberOfPages == 0 {
+            return None;
+        }
+
+        let index = self.ranges[..self.rangeCount]
+            .iter()
+            .position(|range| range.pages >= numberOfPages)?;
+
+        let range = &mut self.ranges[index];
+        let result = range.address;
+        range.address += numberOfPages * SIZE_OF_PAGE;
+        range.pages -= numberOfPages;
+
+        if range.pages == 0 {
+            self.remove(index);
+        }
+
+        self.freePages -= numberOfPages;
+        Some(result)
+    }
+
+    pub fn free(&mut self, address: usize, numberOfPages: usize) -> Result<(), &'static str> {
+        let end = address + numberOfPages * SIZE_OF_PAGE;
+        if address % SIZE_OF_PAGE != 0 {
+            return Err("Address isn't page aligned");
+        }
+
+        if address < self.start || end > self.end {
+            return Err("Range isn't something we hand out");
+        }
+
+        // First run that starts after what's being freed
+        let index = self.ranges[..self.rangeCount]
+            .iter()
+            .position(|range| range.address >= address)
+            .unwrap_or(self.rangeCount);
+
+        if index < self.rangeCount && self.ranges[index].address < end {
+            return Err("Double free");
+        }
+
+        if index > 0 {
+            let previous = self.ranges[index - 1];
+            if previous.address + previous.pages * SIZE_OF_PAGE > address {
+                return Err("Double free");
+            }
+        }
+
+        let joinsPrevious = index > 0 && {
+            let previous = self.ranges[index - 1];
+            previous.address + previous.pages * SIZE_OF_PAGE == address
+        };
+        let joinsNext = index < self.rangeCount && self.ranges[index].address == end;
+
+        match (joinsPrevious, joinsNext) {
+            (true, true) => {
+                self.ranges[index - 1].pages += numberOfPages + self.ranges[index].pages;
+                self.remove(index);
+            }
+            (true, false) => self.ranges[index - 1].pages += numberOfPages,
+            (false, true) => {
+                self.ranges[index].address = address;
+                self.ranges[index].pages += numberOfPages;
+            }
+            (false, false) => {
+                // BUGBUG: Fragmented enough to run out of slots, so this space is just gone
+                if self.rangeCount == MAX_FREE_RANGES {
+                    loggerWriteLine!(
+                        "Out of free virtual ranges, leaking 0x{:X} for {} pages",
+                        address,
+                        numberOfPages
+                    );
+                    self.leakedPages += numberOfPages;
+                    return Ok(());
+                }
+
+                self.ranges.copy_within(index..self.rangeCount, index + 1);
+                self.ranges[index] = FreeRange {
+                    address,
+                    pages: numberOfPages,
+                };
+                self.rangeCount += 1;
+            }
+        }
+
+        self.freePages += numberOfPages;
+        Ok(())
+    }
+
+    pub fn dump(&self) {
+        loggerWriteLine!(
+            "Virtual 0x{:X}..0x{:X}: {} pages free in {} ranges, {} leaked",
+            self.start,
+            self.end,
+            self.freePages,
+            self.rangeCount,
+            self.leakedPages
+        );
+
+        for range in &self.ranges[..self.rangeCount] {
+            loggerWriteLine!("  0x{:X} for {} pages", range.address, range.pages);
+        }
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.ranges.copy_within(index + 1..self.rangeCount, index);
+        self.rangeCount -= 1;
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    acpi::pciCommonHeader::{PciCommonHeader, PciHeaderType},
+    loggerWriteLine,
+};
+
+// PCI Local Bus 3.0, 6.7 Capabilities List, and the IDs from the PCI Code and ID Assignment spec
+pub const CAPABILITY_POWER_MANAGEMENT: u8 = 0x01;
+pub const CAPABILITY_MSI: u8 = 0x05;
+pub const CAPABILITY_VENDOR_SPECIFIC: u8 = 0x09;
+pub const CAPABILITY_PCI_EXPRESS: u8 = 0x10;
+pub const CAPABILITY_MSI_X: u8 = 0x11;
+pub const CAPABILITY_SATA: u8 = 0x12;
+
+// Where the first pointer lives. CardBus bridges moved it to make room for their windows.
+const CAPABILITIES_POINTER: usize = 0x34;
+const CARDBUS_CAPABILITIES_POINTER: usize = 0x14;
+
+// The bottom 2 bits of every pointer are reserved
+const CAPABILITIES_POINTER_MASK: u8 = 0xFC;
+
+// Everything before this is the header, so a pointer into it is garbage
+const FIRST_CAPABILITY: usize = 0x40;
+
+// A list longer than this has to be looping; there's only 256 bytes of config space to put them in
+const MAX_CAPABILITIES: usize = 48;
+
+#[derive(Clone, Copy)]
+pub struct Capability {
+    pub id: u8,
+    pub offset: usize, // Into the function's config space
+}
+
+pub struct Capabilities<'a> {
+    header: &'a PciCommonHeader,
+    next: usize,
+    remaining: usize,
+}
+
+impl Iterator for Capabilities<'_> {
+    type Item = Capability;
+
+    fn next(&mut self) -> Option<Capability> {
+        if self.next == 0 {
+            return None;
+        }
+
+        if self.next < FIRST_CAPABILITY {
+            loggerWriteLine!("Capability pointer 0x{:X} is inside the header", self.next);
+            return None;
+        }
+
+        if self.remaining == 0 {
+            loggerWriteLine!("Capabilities list doesn't end");
+            return None;
+        }
+
+        let offset = self.next;
+        self.remaining -= 1;
+        self.next = (self.header.readConfig::<u8>(offset + 1) & CAPABILITIES_POINTER_MASK) as usize;
+
+        Some(Capability {
+            id: self.header.readConfig::<u8>(offset),
+            offset,
+        })
+    }
+}
+
+// Walks the function's capabilities list, in the order the device has them
+pub fn getCapabilities(header: &PciCommonHeader) -> Capabilities<'_> {
+    let pointer = match header.getType() {
+        _ if !header.hasCapabilities() => None,
+        PciHeaderType::General | PciHeaderType::NormalBridge => Some(CAPABILITIES_POINTER),
+        PciHeaderType::CardBridge => Some(CARDBUS_CAPABILITIES_POINTER),
+        PciHeaderType::Dunno => None,
+    };
+
+    let first = pointer.map_or(0, |pointer| header.readConfig::<u8>(pointer));
+    Capabilities {
+        header,
+        next: (first & CAPABILITIES_POINTER_MASK) as usize,
+        remaining: MAX_CAPABILITIES,
+    }
+}
+
+// Offset of the first capability with id
+pub fn findCapability(header: &PciCommonHeader, id: u8) -> Option<usize> {
+    getCapabilities(header)
+        .find(|capability| capability.id == id)
+        .map(|capability| capability.offset)
+}
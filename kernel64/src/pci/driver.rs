@@ -0,0 +1,203 @@
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use kernel_shared::pageTable::enums::*;
+
+use crate::{loggerWriteLine, memory::virtualMemory::VirtualMemoryManager};
+
+use super::registry::{self, PciAddress, PciDevice};
+
+// What a driver says it can handle. Anything left as None matches whatever the device has.
+#[derive(Clone, Copy)]
+pub struct PciId {
+    pub vendorId: Option<u16>,
+    pub deviceId: Option<u16>,
+    pub classCode: Option<u8>,
+    pub subclass: Option<u8>,
+    pub progIf: Option<u8>,
+}
+
+impl PciId {
+    // One specific part
+    pub const fn device(vendorId: u16, deviceId: u16) -> PciId {
+        PciId {
+            vendorId: Some(vendorId),
+            deviceId: Some(deviceId),
+            classCode: None,
+            subclass: None,
+            progIf: None,
+        }
+    }
+
+    // Anything that says it's this kind of device; progIf of None takes every interface
+    pub const fn class(classCode: u8, subclass: u8, progIf: Option<u8>) -> PciId {
+        PciId {
+            vendorId: None,
+            deviceId: None,
+            classCode: Some(classCode),
+            subclass: Some(subclass),
+            progIf,
+        }
+    }
+
+    pub fn matches(&self, device: &PciDevice) -> bool {
+        self.vendorId.is_none_or(|id| id == device.vendorId)
+            && self.deviceId.is_none_or(|id| id == device.deviceId)
+            && self.classCode.is_none_or(|id| id == device.classCode)
+            && self.subclass.is_none_or(|id| id == device.subclass)
+            && self.progIf.is_none_or(|id| id == device.progIf)
+    }
+}
+
+// A memory BAR that's been mapped for the driver. Registers, so it's never cached, even when it's prefetchable.
+#[derive(Clone, Copy)]
+pub struct MappedBar {
+    pub physical: u64,
+    pub size: usize,
+    pub address: usize, // Virtual
+}
+
+// What a driver's probe gets handed: the device and every memory BAR it has, already mapped
+#[derive(Clone, Copy)]
+pub struct BoundDevice {
+    pub device: PciDevice,
+    pub bars: [Option<MappedBar>; 6],
+}
+
+impl BoundDevice {
+    pub fn getBar(&self, index: usize) -> Option<MappedBar> {
+        self.bars.get(index).copied().flatten()
+    }
+
+    // Port I/O BARs don't need mapping, just where they start
+    pub fn getIoPort(&self, index: usize) -> Option<u16> {
+        match self.device.bars.get(index).copied().flatten() {
+            Some(bar) if !bar.isMemory() => Some(bar.address as u16),
+            _ => None,
+        }
+    }
+
+    fn new(vmm: &mut VirtualMemoryManager, device: PciDevice) -> BoundDevice {
+        let mut bars = [None; 6];
+        for bar in device.bars.iter().flatten() {
+            if !bar.isMemory() {
+                continue;
+            }
+
+            if !bar.isAssigned() {
+                loggerWriteLine!(
+                    "{} BAR{} was never given an address",
+                    device.address,
+                    bar.index
+                );
+                continue;
+            }
+
+            let size = bar.size as usize;
+            let address = vmm.mapPhysicalAnywhere(
+                bar.address as usize,
+                size,
+                Execute::Yes,
+                Present::Yes,
+                Writable::Yes,
+                Cachable::No,
+                UserSupervisor::Supervisor,
+                WriteThrough::WriteTrough,
+            );
+
+            bars[bar.index as usize] = Some(MappedBar {
+                physical: bar.address,
+                size,
+                address,
+            });
+        }
+
+        BoundDevice { device, bars }
+    }
+
+    // For when nobody wanted it after all
+    fn unmap(&self, vmm: &mut VirtualMemoryManager) {
+        for bar in self.bars.iter().flatten() {
+            vmm.unmapPhysicalAnywhere(bar.address, bar.size);
+        }
+    }
+}
+
+// The first ID that matches gets the driver probed. probe returning an error means it's not taking the device after
+// all, and the next driver gets a go.
+pub type ProbeHandler =
+    fn(vmm: &mut VirtualMemoryManager, device: &BoundDevice) -> Result<(), &'static str>;
+
+#[derive(Clone, Copy)]
+pub struct PciDriver {
+    pub name: &'static str,
+    pub ids: &'static [PciId],
+    pub probe: ProbeHandler,
+}
+
+static DRIVERS: Mutex<RefCell<Vec<PciDriver>>> = Mutex::new(RefCell::new(Vec::new()));
+
+// Devices a driver took, and which driver. Once bound, always bound.
+static BOUND: Mutex<RefCell<Vec<(BoundDevice, &'static str)>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+
+// Drivers registered before enumeration get probed as part of it. Anyone later has to call bindDrivers themselves.
+pub fn registerDriver(driver: PciDriver) {
+    critical_section::with(|cs| DRIVERS.borrow_ref_mut(cs).push(driver));
+}
+
+// Offers every device nobody has yet to every driver that matches it, in the order they registered
+pub fn bindDrivers(vmm: &mut VirtualMemoryManager) {
+    // Copied out so probes don't run with interrupts off; AHCI needs them to identify its drives
+    let drivers = critical_section::with(|cs| DRIVERS.borrow_ref(cs).clone());
+
+    for index in 0..registry::getDeviceCount() {
+        let Some(device) = registry::getDevice(index) else {
+            continue;
+        };
+
+        if getDriverFor(device.address).is_some() {
+            continue;
+        }
+
+        for driver in drivers.iter() {
+            if !driver.ids.iter().any(|id| id.matches(&device)) {
+                continue;
+            }
+
+            let bound = BoundDevice::new(vmm, device);
+            match (driver.probe)(vmm, &bound) {
+                Ok(()) => {
+                    loggerWriteLine!("{} bound to {}", device.address, driver.name);
+                    critical_section::with(|cs| {
+                        BOUND.borrow_ref_mut(cs).push((bound, driver.name))
+                    });
+                    break;
+                }
+                Err(reason) => {
+                    loggerWriteLine!("{} didn't take {}: {}", driver.name, device.address, reason);
+                    bound.unmap(vmm);
+                }
+            }
+        }
+    }
+}
+
+pub fn getDriverFor(address: PciAddress) -> Option<&'static str> {
+    critical_section::with(|cs| {
+        BOUND
+            .borrow_ref(cs)
+            .iter()
+            .find(|(bound, _)| bound.device.address == address)
+            .map(|(_, name)| *name)
+    })
+}
+
+pub fn dump() {
+    critical_section::with(|cs| {
+        for (bound, name) in BOUND.borrow_ref(cs).iter() {
+            loggerWriteLine!("  {} -> {}", bound.device.address, name);
+        }
+    });
+}
@@ -0,0 +1,211 @@
+use alloc::{vec, vec::Vec};
+
+use kernel_shared::pageTable::enums::*;
+
+use crate::{
+    acpi::{
+        bar::Bar,
+        mcfg::MCFG,
+        mcfgEntry::{CONFIG_SPACE_PER_BUS, McfgEntry},
+        pciCommonHeader::PciCommonHeader,
+        rsdp::findTable,
+    },
+    loggerWriteLine,
+    memory::virtualMemory::VirtualMemoryManager,
+};
+
+use super::{
+    driver,
+    registry::{self, PciAddress, PciDevice},
+};
+
+const DEVICES_PER_BUS: u8 = 32;
+const FUNCTIONS_PER_DEVICE: u8 = 8;
+const BUSES_PER_SEGMENT: usize = 256;
+
+// Where a function's config space starts within its bus
+const DEVICE_SHIFT: usize = 15;
+const FUNCTION_SHIFT: usize = 12;
+
+// One MCFG entry's worth of config space. A bus only gets mapped the first time something looks at it, so a segment
+// claiming 256 buses doesn't cost 256MB of address space when only a couple are in use. Mappings are never undone;
+// the registry hands out pointers into them.
+struct Segment {
+    entry: McfgEntry,
+    buses: Vec<usize>,  // Virtual address each bus is mapped at, 0 until it is
+    visited: Vec<bool>, // So a bridge firmware numbered badly can't have us scan a bus twice or loop
+}
+
+impl Segment {
+    fn new(entry: McfgEntry) -> Segment {
+        Segment {
+            entry,
+            buses: vec![0; BUSES_PER_SEGMENT],
+            visited: vec![false; BUSES_PER_SEGMENT],
+        }
+    }
+
+    fn getFunction(
+        &mut self,
+        vmm: &mut VirtualMemoryManager,
+        bus: u8,
+        device: u8,
+        function: u8,
+    ) -> Option<*const PciCommonHeader> {
+        let base = self.mapBus(vmm, bus);
+        PciCommonHeader::tryGet(
+            base + ((device as usize) << DEVICE_SHIFT) + ((function as usize) << FUNCTION_SHIFT),
+        )
+    }
+
+    fn mapBus(&mut self, vmm: &mut VirtualMemoryManager, bus: u8) -> usize {
+        let index = bus as usize;
+        if self.buses[index] == 0 {
+            // Configuration space is MMIO, so no caching
+            self.buses[index] = vmm.mapPhysicalAnywhere(
+                self.entry.getBusAddress(bus) as usize,
+                CONFIG_SPACE_PER_BUS,
+                Execute::Yes,
+                Present::Yes,
+                Writable::Yes,
+                Cachable::No,
+                UserSupervisor::Supervisor,
+                WriteThrough::WriteTrough,
+            );
+        }
+
+        self.buses[index]
+    }
+
+    // https://wiki.osdev.org/PCI#Recursive_Scan
+    fn scanRoot(&mut self, vmm: &mut VirtualMemoryManager) {
+        let start = self.entry.StartBus;
+        let Some(host) = self.getFunction(vmm, start, 0, 0) else {
+            loggerWriteLine!("  Nothing at {}:00.0", start);
+            return;
+        };
+
+        if !unsafe { (*host).isMultiFunction() } {
+            self.scanBus(vmm, start);
+            return;
+        }
+
+        // Several host bridges; each function is the one for the bus that many past the first
+        for function in 0..FUNCTIONS_PER_DEVICE {
+            let Some(bus) = start.checked_add(function) else {
+                break;
+            };
+
+            if self.getFunction(vmm, start, 0, function).is_some() {
+                self.scanBus(vmm, bus);
+            }
+        }
+    }
+
+    fn scanBus(&mut self, vmm: &mut VirtualMemoryManager, bus: u8) {
+        if !self.entry.hasBus(bus) {
+            let start = self.entry.StartBus;
+            let end = self.entry.EndBus;
+            loggerWriteLine!("  Bus {} is outside {}..={}", bus, start, end);
+            return;
+        }
+
+        if self.visited[bus as usize] {
+            loggerWriteLine!("  Bus {} was already scanned", bus);
+            return;
+        }
+
+        self.visited[bus as usize] = true;
+        for device in 0..DEVICES_PER_BUS {
+            self.scanDevice(vmm, bus, device);
+        }
+    }
+
+    fn scanDevice(&mut self, vmm: &mut VirtualMemoryManager, bus: u8, device: u8) {
+        let Some(header) = self.getFunction(vmm, bus, device, 0) else {
+            return;
+        };
+
+        self.scanFunction(vmm, header, bus, device, 0);
+
+        // Functions past 0 only exist when 0 says so. Some single function devices answer on all 8 with copies of
+        // function 0, so looking anyway would find ghosts.
+        if !unsafe { (*header).isMultiFunction() } {
+            return;
+        }
+
+        for function in 1..FUNCTIONS_PER_DEVICE {
+            if let Some(header) = self.getFunction(vmm, bus, device, function) {
+                self.scanFunction(vmm, header, bus, device, function);
+            }
+        }
+    }
+
+    fn scanFunction(
+        &mut self,
+        vmm: &mut VirtualMemoryManager,
+        header: *const PciCommonHeader,
+        bus: u8,
+        device: u8,
+        function: u8,
+    ) {
+        let address = PciAddress {
+            segment: self.entry.SegmentGroup,
+            bus,
+            device,
+            function,
+        };
+
+        let found = unsafe { readDevice(address, &*header) };
+        found.dump();
+        registry::add(found);
+
+        let Some(secondary) = (unsafe { (*header).getSecondaryBus() }) else {
+            return;
+        };
+
+        // BUGBUG: We don't number buses ourselves, so anything firmware didn't set up stays hidden
+        if secondary <= bus {
+            loggerWriteLine!(
+                "  Bridge {} has secondary bus {}, not following it",
+                address,
+                secondary
+            );
+            return;
+        }
+
+        self.scanBus(vmm, secondary);
+    }
+}
+
+fn readDevice(address: PciAddress, header: &PciCommonHeader) -> PciDevice {
+    PciDevice {
+        address,
+        vendorId: header.getVendorId(),
+        deviceId: header.getDeviceId(),
+        classCode: header.ClassCode,
+        subclass: header.Subclass,
+        progIf: header.ProgIF,
+        revision: header.getRevision(),
+        headerType: header.getType(),
+        config: header as *const _ as usize,
+        bars: Bar::readAll(header),
+    }
+}
+
+// Finds every function in every segment the MCFG lists, puts them in the registry, then offers them to whichever
+// drivers have registered
+pub fn enumerate(vmm: &mut VirtualMemoryManager) {
+    let Some(mcfg) = findTable(vmm, b"MCFG") else {
+        loggerWriteLine!("No MCFG, so no PCIe");
+        return;
+    };
+
+    let entries = unsafe { (*(mcfg as *const MCFG)).getEntries() };
+    for entry in entries {
+        Segment::new(entry).scanRoot(vmm);
+    }
+
+    loggerWriteLine!("Found {} PCI function(s)", registry::getDeviceCount());
+    driver::bindDrivers(vmm);
+}
@@ -0,0 +1,5 @@
+pub mod capabilities;
+pub mod driver;
+pub mod enumeration;
+pub mod msi;
+pub mod registry;
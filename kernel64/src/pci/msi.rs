@@ -0,0 +1,259 @@
+use alloc::vec::Vec;
+use core::ptr::write_volatile;
+
+use crate::{
+    acpi::pciCommonHeader::PciCommonHeader,
+    interupts::{
+        InteruptDescriptorTable::{InterruptHandler, allocateVectors, freeVectors},
+        localApic,
+    },
+    loggerWriteLine,
+};
+
+use super::{
+    capabilities::{CAPABILITY_MSI, CAPABILITY_MSI_X, findCapability},
+    driver::BoundDevice,
+};
+
+// PCI Local Bus 3.0, 6.8.1 MSI Capability Structure, offsets from the start of the capability
+const MSI_CONTROL: usize = 2;
+const MSI_ADDRESS: usize = 4;
+const MSI_ADDRESS_UPPER: usize = 8; // Only there when 64-bit capable
+const MSI_DATA_32: usize = 8;
+const MSI_DATA_64: usize = 12;
+const MSI_MASK_32: usize = 12; // Only there with per-vector masking
+const MSI_MASK_64: usize = 16;
+const MSI_CONTROL_ENABLE: u16 = 1 << 0;
+const MSI_CONTROL_MULTIPLE_CAPABLE_SHIFT: u16 = 1;
+const MSI_CONTROL_MULTIPLE_ENABLE_SHIFT: u16 = 4;
+const MSI_CONTROL_MULTIPLE_MASK: u16 = 0b111; // Both are log2 of the count
+const MSI_CONTROL_64BIT: u16 = 1 << 7;
+const MSI_CONTROL_PER_VECTOR_MASKING: u16 = 1 << 8;
+
+// The most messages MSI can have
+const MSI_MAX_VECTORS: usize = 32;
+
+// 6.8.2 MSI-X Capability and Table Structure
+const MSI_X_CONTROL: usize = 2;
+const MSI_X_TABLE: usize = 4;
+const MSI_X_CONTROL_TABLE_SIZE_MASK: u16 = 0x7FF; // One less than the number of entries
+const MSI_X_CONTROL_FUNCTION_MASK: u16 = 1 << 14;
+const MSI_X_CONTROL_ENABLE: u16 = 1 << 15;
+const MSI_X_BIR_MASK: u32 = 0b111; // Which BAR the table is in; the rest is the offset into it
+
+// Each table entry
+const MSI_X_ENTRY_SIZE: usize = 16;
+const MSI_X_ENTRY_ADDRESS: usize = 0;
+const MSI_X_ENTRY_ADDRESS_UPPER: usize = 4;
+const MSI_X_ENTRY_DATA: usize = 8;
+const MSI_X_ENTRY_CONTROL: usize = 12;
+const MSI_X_ENTRY_MASKED: u32 = 1 << 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageKind {
+    Msi,
+    MsiX,
+}
+
+// What the device ended up with. Message N shows up on vectors[N].
+pub struct MessageInterrupts {
+    pub kind: MessageKind,
+    pub vectors: Vec<u8>,
+}
+
+// Has the device write to this processor's local APIC instead of asserting INTx, with up to count messages all going
+// to handler. MSI-X is preferred when the device has both. It can come back with fewer than count, but never none.
+pub fn enableInterrupts(
+    device: &BoundDevice,
+    count: usize,
+    handler: InterruptHandler,
+) -> Result<MessageInterrupts, &'static str> {
+    if !localApic::isEnabled() {
+        return Err("No local APIC");
+    }
+
+    if count == 0 {
+        return Err("Asked for no interrupts");
+    }
+
+    let header = device.device.getCommonHeader();
+    let address = localApic::getMsiAddress(localApic::getId());
+
+    if let Some(capability) = findCapability(header, CAPABILITY_MSI_X) {
+        match enableMsiX(device, capability, address, count, handler) {
+            Ok(vectors) => {
+                return Ok(MessageInterrupts {
+                    kind: MessageKind::MsiX,
+                    vectors,
+                });
+            }
+            Err(reason) => {
+                loggerWriteLine!("{} MSI-X didn't work: {}", device.device.address, reason);
+            }
+        }
+    }
+
+    let Some(capability) = findCapability(header, CAPABILITY_MSI) else {
+        return Err("Device doesn't do MSI");
+    };
+
+    let vectors = enableMsi(header, capability, address, count, handler)?;
+    Ok(MessageInterrupts {
+        kind: MessageKind::Msi,
+        vectors,
+    })
+}
+
+// Undoes enableInterrupts and hands the device back to INTx
+pub fn disableInterrupts(device: &BoundDevice, interrupts: MessageInterrupts) {
+    let header = device.device.getCommonHeader();
+    match interrupts.kind {
+        MessageKind::Msi => {
+            if let Some(capability) = findCapability(header, CAPABILITY_MSI) {
+                let control = header.readConfig::<u16>(capability + MSI_CONTROL);
+                header.writeConfig::<u16>(capability + MSI_CONTROL, control & !MSI_CONTROL_ENABLE);
+            }
+
+            // MSI vectors are always one aligned block
+            if let Some(first) = interrupts.vectors.first() {
+                freeVectors(*first, interrupts.vectors.len());
+            }
+        }
+        MessageKind::MsiX => {
+            if let Some(capability) = findCapability(header, CAPABILITY_MSI_X) {
+                let control = header.readConfig::<u16>(capability + MSI_X_CONTROL);
+                header.writeConfig::<u16>(
+                    capability + MSI_X_CONTROL,
+                    control & !MSI_X_CONTROL_ENABLE,
+                );
+            }
+
+            for vector in interrupts.vectors.iter() {
+                freeVectors(*vector, 1);
+            }
+        }
+    }
+
+    header.enableLegacyInterrupts();
+}
+
+// Only a power of 2 can be asked for, and they have to be in one aligned block since the device just ORs the message
+// number into the data.
+fn enableMsi(
+    header: &PciCommonHeader,
+    capability: usize,
+    address: u64,
+    count: usize,
+    handler: InterruptHandler,
+) -> Result<Vec<u8>, &'static str> {
+    let control = header.readConfig::<u16>(capability + MSI_CONTROL);
+    let is64Bit = control & MSI_CONTROL_64BIT != 0;
+    if address > u32::MAX as u64 && !is64Bit {
+        return Err("MSI address needs 64 bits and the device only has 32");
+    }
+
+    let capable =
+        1 << ((control >> MSI_CONTROL_MULTIPLE_CAPABLE_SHIFT) & MSI_CONTROL_MULTIPLE_MASK);
+    let wanted = count.min(capable).min(MSI_MAX_VECTORS);
+    let granted = 1 << wanted.ilog2();
+    let Some(first) = allocateVectors(granted, handler) else {
+        return Err("Out of vectors");
+    };
+
+    header.writeConfig::<u32>(capability + MSI_ADDRESS, address as u32);
+    let (data, mask) = if is64Bit {
+        header.writeConfig::<u32>(capability + MSI_ADDRESS_UPPER, (address >> 32) as u32);
+        (MSI_DATA_64, MSI_MASK_64)
+    } else {
+        (MSI_DATA_32, MSI_MASK_32)
+    };
+
+    header.writeConfig::<u16>(capability + data, first as u16);
+    if control & MSI_CONTROL_PER_VECTOR_MASKING != 0 {
+        header.writeConfig::<u32>(capability + mask, 0);
+    }
+
+    let control = (control & !(MSI_CONTROL_MULTIPLE_MASK << MSI_CONTROL_MULTIPLE_ENABLE_SHIFT))
+        | ((granted.ilog2() as u16) << MSI_CONTROL_MULTIPLE_ENABLE_SHIFT)
+        | MSI_CONTROL_ENABLE;
+    header.writeConfig::<u16>(capability + MSI_CONTROL, control);
+    header.disableLegacyInterrupts();
+
+    Ok((0..granted).map(|message| first + message as u8).collect())
+}
+
+// Every entry gets its own vector, so there's no alignment to worry about. The table lives in one of the device's
+// BARs, which binding already mapped.
+fn enableMsiX(
+    device: &BoundDevice,
+    capability: usize,
+    address: u64,
+    count: usize,
+    handler: InterruptHandler,
+) -> Result<Vec<u8>, &'static str> {
+    let header = device.device.getCommonHeader();
+    let control = header.readConfig::<u16>(capability + MSI_X_CONTROL);
+    let tableSize = (control & MSI_X_CONTROL_TABLE_SIZE_MASK) as usize + 1;
+
+    let table = header.readConfig::<u32>(capability + MSI_X_TABLE);
+    let Some(bar) = device.getBar((table & MSI_X_BIR_MASK) as usize) else {
+        return Err("Table's BAR isn't mapped");
+    };
+
+    let tableOffset = (table & !MSI_X_BIR_MASK) as usize;
+    if tableOffset + tableSize * MSI_X_ENTRY_SIZE > bar.size {
+        return Err("Table runs past the end of its BAR");
+    }
+
+    let mut vectors = Vec::new();
+    while vectors.len() < count.min(tableSize) {
+        match allocateVectors(1, handler) {
+            Some(vector) => vectors.push(vector),
+            None => break,
+        }
+    }
+
+    if vectors.is_empty() {
+        return Err("Out of vectors");
+    }
+
+    // Can't have both on at once
+    if let Some(msi) = findCapability(header, CAPABILITY_MSI) {
+        let msiControl = header.readConfig::<u16>(msi + MSI_CONTROL);
+        header.writeConfig::<u16>(msi + MSI_CONTROL, msiControl & !MSI_CONTROL_ENABLE);
+    }
+
+    // The function mask holds every message off while the table gets filled in
+    header.writeConfig::<u16>(
+        capability + MSI_X_CONTROL,
+        control | MSI_X_CONTROL_ENABLE | MSI_X_CONTROL_FUNCTION_MASK,
+    );
+
+    let tableAddress = bar.address + tableOffset;
+    for index in 0..tableSize {
+        let entry = tableAddress + index * MSI_X_ENTRY_SIZE;
+        unsafe {
+            match vectors.get(index) {
+                Some(vector) => {
+                    writeEntry(entry, MSI_X_ENTRY_ADDRESS, address as u32);
+                    writeEntry(entry, MSI_X_ENTRY_ADDRESS_UPPER, (address >> 32) as u32);
+                    writeEntry(entry, MSI_X_ENTRY_DATA, *vector as u32);
+                    writeEntry(entry, MSI_X_ENTRY_CONTROL, 0);
+                }
+                None => writeEntry(entry, MSI_X_ENTRY_CONTROL, MSI_X_ENTRY_MASKED),
+            }
+        }
+    }
+
+    header.disableLegacyInterrupts();
+    header.writeConfig::<u16>(
+        capability + MSI_X_CONTROL,
+        (control | MSI_X_CONTROL_ENABLE) & !MSI_X_CONTROL_FUNCTION_MASK,
+    );
+
+    Ok(vectors)
+}
+
+unsafe fn writeEntry(entry: usize, offset: usize, value: u32) {
+    unsafe { write_volatile((entry + offset) as *mut u32, value) }
+}
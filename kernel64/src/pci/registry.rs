@@ -0,0 +1,141 @@
+use alloc::vec::Vec;
+use core::{cell::RefCell, fmt};
+
+use critical_section::Mutex;
+use kernel_shared::loggerWrite;
+
+use crate::{
+    acpi::{
+        bar::Bar,
+        pciCommonHeader::{PciCommonHeader, PciHeaderType},
+        pciGeneralDevice::PciGeneralDevice,
+    },
+    loggerWriteLine,
+};
+
+use super::capabilities::getCapabilities;
+
+// Where a function lives, the way lspci spells it
+#[derive(Clone, Copy, PartialEq)]
+pub struct PciAddress {
+    pub segment: u16,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04X}:{:02X}:{:02X}.{}",
+            self.segment, self.bus, self.device, self.function
+        )
+    }
+}
+
+// Everything enumeration learned about a function. Config space stays mapped for good, so config is always valid.
+// BARs are sized but left wherever firmware put them.
+#[derive(Clone, Copy)]
+pub struct PciDevice {
+    pub address: PciAddress,
+    pub vendorId: u16,
+    pub deviceId: u16,
+    pub classCode: u8,
+    pub subclass: u8,
+    pub progIf: u8,
+    pub revision: u8,
+    pub headerType: PciHeaderType,
+    pub config: usize,          // Virtual address of the function's config space
+    pub bars: [Option<Bar>; 6], // By BAR number; the upper half of a 64-bit one is None
+}
+
+impl PciDevice {
+    pub fn getCommonHeader(&self) -> &'static PciCommonHeader {
+        unsafe { &*(self.config as *const PciCommonHeader) }
+    }
+
+    // None for bridges
+    pub fn getGeneralDevice(&self) -> Option<*const PciGeneralDevice> {
+        PciGeneralDevice::tryGet(self.getCommonHeader())
+    }
+
+    pub fn dump(&self) {
+        loggerWriteLine!(
+            "  {} {:04X}:{:04X} class {:02X}:{:02X}:{:02X} rev {} {:?}",
+            self.address,
+            self.vendorId,
+            self.deviceId,
+            self.classCode,
+            self.subclass,
+            self.progIf,
+            self.revision,
+            self.headerType
+        );
+
+        for bar in self.bars.iter().flatten() {
+            loggerWriteLine!("    BAR{}: {}", bar.index, bar);
+        }
+
+        let mut capabilities = getCapabilities(self.getCommonHeader()).peekable();
+        if capabilities.peek().is_some() {
+            loggerWrite!("    Capabilities:");
+            for capability in capabilities {
+                loggerWrite!(" 0x{:02X}@0x{:X}", capability.id, capability.offset);
+            }
+
+            loggerWriteLine!("");
+        }
+    }
+}
+
+// Every function enumeration found, in the order it found them
+static DEVICES: Mutex<RefCell<Vec<PciDevice>>> = Mutex::new(RefCell::new(Vec::new()));
+
+pub fn add(device: PciDevice) {
+    critical_section::with(|cs| DEVICES.borrow_ref_mut(cs).push(device));
+}
+
+pub fn getDeviceCount() -> usize {
+    critical_section::with(|cs| DEVICES.borrow_ref(cs).len())
+}
+
+pub fn getDevice(index: usize) -> Option<PciDevice> {
+    critical_section::with(|cs| DEVICES.borrow_ref(cs).get(index).copied())
+}
+
+// progIf of None matches any interface
+pub fn findByClass(classCode: u8, subclass: u8, progIf: Option<u8>) -> Vec<PciDevice> {
+    find(|device| {
+        device.classCode == classCode
+            && device.subclass == subclass
+            && progIf.is_none_or(|progIf| device.progIf == progIf)
+    })
+}
+
+pub fn findById(vendorId: u16, deviceId: u16) -> Vec<PciDevice> {
+    find(|device| device.vendorId == vendorId && device.deviceId == deviceId)
+}
+
+pub fn findByAddress(address: PciAddress) -> Option<PciDevice> {
+    find(|device| device.address == address).first().copied()
+}
+
+fn find(matches: impl Fn(&PciDevice) -> bool) -> Vec<PciDevice> {
+    critical_section::with(|cs| {
+        DEVICES
+            .borrow_ref(cs)
+            .iter()
+            .filter(|device| matches(device))
+            .copied()
+            .collect()
+    })
+}
+
+pub fn dump() {
+    for index in 0..getDeviceCount() {
+        if let Some(device) = getDevice(index) {
+            device.dump();
+        }
+    }
+}
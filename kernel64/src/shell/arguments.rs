@@ -0,0 +1,123 @@
+pub const MAX_ARGUMENTS: usize = 16;
+
+// A command line split into words. Double quotes group words with spaces in them, there's no escaping.
+// Index 0 is the command itself, get() and friends count from the first real argument.
+pub struct Arguments<'a> {
+    words: [&'a str; MAX_ARGUMENTS],
+    count: usize,
+}
+
+impl<'a> Arguments<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, &'static str> {
+        let mut words = [""; MAX_ARGUMENTS];
+        let mut count = 0;
+        let mut remaining = line.trim_start();
+
+        while !remaining.is_empty() {
+            if count == MAX_ARGUMENTS {
+                return Err("Too many arguments");
+            }
+
+            let word;
+            if let Some(quoted) = remaining.strip_prefix('"') {
+                let Some(end) = quoted.find('"') else {
+                    return Err("Unterminated quote");
+                };
+
+                word = &quoted[..end];
+                remaining = &quoted[end + 1..];
+            } else {
+                let end = remaining
+                    .find(|c: char| c.is_ascii_whitespace())
+                    .unwrap_or(remaining.len());
+                word = &remaining[..end];
+                remaining = &remaining[end..];
+            }
+
+            words[count] = word;
+            count += 1;
+            remaining = remaining.trim_start();
+        }
+
+        Ok(Arguments { words, count })
+    }
+
+    pub fn command(&self) -> Option<&'a str> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.words[0])
+        }
+    }
+
+    // Number of arguments, not counting the command
+    pub fn len(&self) -> usize {
+        self.count.saturating_sub(1)
+    }
+
+    pub fn isEmpty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a str> {
+        if index + 1 < self.count {
+            Some(self.words[index + 1])
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.words[..self.count].iter().skip(1).copied()
+    }
+
+    // Accepts decimal or 0x prefixed hex. Underscores are allowed as separators like in Rust literals.
+    pub fn getNumber(&self, index: usize) -> Result<usize, &'static str> {
+        let Some(word) = self.get(index) else {
+            return Err("Missing argument");
+        };
+
+        parseNumber(word)
+    }
+
+    pub fn getNumberOr(&self, index: usize, default: usize) -> Result<usize, &'static str> {
+        if self.get(index).is_none() {
+            Ok(default)
+        } else {
+            self.getNumber(index)
+        }
+    }
+}
+
+pub fn parseNumber(word: &str) -> Result<usize, &'static str> {
+    let (digits, radix) = if let Some(hex) = word
+        .strip_prefix("0x")
+        .or_else(|| word.strip_prefix("0X"))
+    {
+        (hex, 16)
+    } else {
+        (word, 10)
+    };
+
+    if digits.is_empty() {
+        return Err("Not a number");
+    }
+
+    let mut result: usize = 0;
+    for c in digits.chars() {
+        if c == '_' {
+            continue;
+        }
+
+        let Some(digit) = c.to_digit(radix) else {
+            return Err("Not a number");
+        };
+
+        result = result
+            .checked_mul(radix as usize)
+            .and_then(|r| r.checked_add(digit as usize))
+            .ok_or("Number too big")?;
+    }
+
+    Ok(result)
+}
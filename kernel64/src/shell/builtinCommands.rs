@@ -0,0 +1,460 @@
+use kernel_shared::{
+    loggerWrite, loggerWriteLine,
+    magicConstants::SIZE_OF_PAGE,
+    memoryHelpers::{alignDown, alignUp},
+};
+
+use crate::{
+    acpi::rsdp,
+    diskStuff::{benchmark, drives},
+    memory::{heap, virtualMemory::VirtualMemoryManager},
+    pci::{driver, registry},
+    time::clock,
+    vfs::virtualFileSystem::{self, OPEN_READ},
+};
+
+use super::{
+    arguments::Arguments,
+    commandRegistry::{Command, findCommand, forEachCommand, registerCommand},
+};
+
+const BYTES_PER_DUMP_LINE: usize = 16;
+const DEFAULT_DUMP_LENGTH: usize = 0x40;
+const MAX_DUMP_LENGTH: usize = 0x1000;
+const DEFAULT_BENCHMARK_MEGABYTES: usize = 16;
+const CAT_CHUNK_SIZE: usize = 512;
+
+pub fn register() {
+    registerCommand(Command {
+        name: "help",
+        usage: "[command]",
+        help: "Lists commands or shows usage for one",
+        minArgs: 0,
+        maxArgs: 1,
+        handler: help,
+    });
+
+    registerCommand(Command {
+        name: "echo",
+        usage: "[words...]",
+        help: "Prints its arguments",
+        minArgs: 0,
+        maxArgs: usize::MAX,
+        handler: echo,
+    });
+
+    registerCommand(Command {
+        name: "peek",
+        usage: "<virtual address> [length]",
+        help: "Hex dumps memory",
+        minArgs: 1,
+        maxArgs: 2,
+        handler: peek,
+    });
+
+    registerCommand(Command {
+        name: "memmap",
+        usage: "",
+        help: "Shows physical memory reservations",
+        minArgs: 0,
+        maxArgs: 0,
+        handler: memmap,
+    });
+
+    registerCommand(Command {
+        name: "vmmap",
+        usage: "",
+        help: "Shows free dynamic virtual space",
+        minArgs: 0,
+        maxArgs: 0,
+        handler: vmmap,
+    });
+
+    registerCommand(Command {
+        name: "translate",
+        usage: "<virtual address>",
+        help: "Shows the physical address behind a virtual one",
+        minArgs: 1,
+        maxArgs: 1,
+        handler: translate,
+    });
+
+    registerCommand(Command {
+        name: "heap",
+        usage: "",
+        help: "Shows kernel heap usage",
+        minArgs: 0,
+        maxArgs: 0,
+        handler: heapStats,
+    });
+
+    registerCommand(Command {
+        name: "drives",
+        usage: "",
+        help: "Lists SATA drives and what they can do",
+        minArgs: 0,
+        maxArgs: 0,
+        handler: listDrives,
+    });
+
+    registerCommand(Command {
+        name: "acpi",
+        usage: "",
+        help: "Lists the ACPI tables",
+        minArgs: 0,
+        maxArgs: 0,
+        handler: listAcpiTables,
+    });
+
+    registerCommand(Command {
+        name: "lspci",
+        usage: "",
+        help: "Lists every PCI function that was found and which drivers took them",
+        minArgs: 0,
+        maxArgs: 0,
+        handler: listPci,
+    });
+
+    registerCommand(Command {
+        name: "readsector",
+        usage: "<drive> <lba>",
+        help: "Hex dumps a sector",
+        minArgs: 2,
+        maxArgs: 2,
+        handler: readSector,
+    });
+
+    registerCommand(Command {
+        name: "diskbench",
+        usage: "<drive> [megabytes]",
+        help: "Measures read throughput one request at a time and with the queue full",
+        minArgs: 1,
+        maxArgs: 2,
+        handler: diskBench,
+    });
+
+    registerCommand(Command {
+        name: "ls",
+        usage: "[path]",
+        help: "Lists a directory",
+        minArgs: 0,
+        maxArgs: 1,
+        handler: list,
+    });
+
+    registerCommand(Command {
+        name: "cat",
+        usage: "<path>",
+        help: "Prints a file",
+        minArgs: 1,
+        maxArgs: 1,
+        handler: cat,
+    });
+
+    registerCommand(Command {
+        name: "cd",
+        usage: "[path]",
+        help: "Changes the working directory, / if there's no path",
+        minArgs: 0,
+        maxArgs: 1,
+        handler: changeDirectory,
+    });
+
+    registerCommand(Command {
+        name: "pwd",
+        usage: "",
+        help: "Shows the working directory",
+        minArgs: 0,
+        maxArgs: 0,
+        handler: printWorkingDirectory,
+    });
+
+    registerCommand(Command {
+        name: "mounts",
+        usage: "",
+        help: "Lists mounted file systems",
+        minArgs: 0,
+        maxArgs: 0,
+        handler: listMounts,
+    });
+
+    registerCommand(Command {
+        name: "uptime",
+        usage: "",
+        help: "Shows time since the clock started",
+        minArgs: 0,
+        maxArgs: 0,
+        handler: uptime,
+    });
+
+    registerCommand(Command {
+        name: "sleep",
+        usage: "<milliseconds>",
+        help: "Does nothing for a while",
+        minArgs: 1,
+        maxArgs: 1,
+        handler: sleep,
+    });
+}
+
+fn help(_vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    if let Some(name) = args.get(0) {
+        let Some(command) = findCommand(name) else {
+            return Err("No such command");
+        };
+
+        loggerWriteLine!("{} {}", command.name, command.usage);
+        loggerWriteLine!("  {}", command.help);
+        return Ok(());
+    }
+
+    forEachCommand(|command| {
+        loggerWriteLine!("  {:<12} {}", command.name, command.help);
+    });
+
+    Ok(())
+}
+
+fn echo(_vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    for (index, word) in args.iter().enumerate() {
+        if index != 0 {
+            loggerWrite!(" ");
+        }
+
+        loggerWrite!("{}", word);
+    }
+
+    loggerWriteLine!("");
+    Ok(())
+}
+
+fn peek(vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    let address = args.getNumber(0)?;
+    let length = args.getNumberOr(1, DEFAULT_DUMP_LENGTH)?;
+    if length > MAX_DUMP_LENGTH {
+        return Err("Length too big");
+    }
+
+    // Would rather not page fault the shell. Stepping a range so the last page of the address space can't overflow.
+    let end = address.checked_add(length).ok_or("Range wraps")?;
+    for page in (alignDown(address, SIZE_OF_PAGE)..end).step_by(SIZE_OF_PAGE) {
+        if vmm.translate(page).is_none() {
+            return Err("Not mapped");
+        }
+    }
+
+    dumpMemory(address, length);
+    Ok(())
+}
+
+pub fn dumpMemory(address: usize, length: usize) {
+    // Everything below is inside the range, so once it's known not to wrap nothing else can overflow
+    if address.checked_add(length).is_none() {
+        loggerWriteLine!("0x{:X} for 0x{:X} wraps", address, length);
+        return;
+    }
+
+    let mut offset = 0;
+    while offset < length {
+        let lineLength = BYTES_PER_DUMP_LINE.min(length - offset);
+        let lineAddress = address + offset;
+        loggerWrite!("{:016X}: ", lineAddress);
+
+        for index in 0..BYTES_PER_DUMP_LINE {
+            if index < lineLength {
+                let byte = unsafe { core::ptr::read_volatile((lineAddress + index) as *const u8) };
+                loggerWrite!("{:02X} ", byte);
+            } else {
+                loggerWrite!("   ");
+            }
+        }
+
+        for index in 0..lineLength {
+            let byte = unsafe { core::ptr::read_volatile((lineAddress + index) as *const u8) };
+            if byte.is_ascii_graphic() || byte == b' ' {
+                loggerWrite!("{}", byte as char);
+            } else {
+                loggerWrite!(".");
+            }
+        }
+
+        loggerWriteLine!("");
+        offset += lineLength;
+    }
+}
+
+fn memmap(vmm: &mut VirtualMemoryManager, _args: &Arguments) -> Result<(), &'static str> {
+    vmm.dumpPhysical();
+    Ok(())
+}
+
+fn vmmap(vmm: &mut VirtualMemoryManager, _args: &Arguments) -> Result<(), &'static str> {
+    vmm.dumpVirtual();
+    Ok(())
+}
+
+fn translate(vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    let address = args.getNumber(0)?;
+    let Some(physicalAddress) = vmm.translate(address) else {
+        return Err("Not mapped");
+    };
+
+    loggerWriteLine!("0x{:X} -> 0x{:X}", address, physicalAddress);
+    Ok(())
+}
+
+fn heapStats(_vmm: &mut VirtualMemoryManager, _args: &Arguments) -> Result<(), &'static str> {
+    heap::dump();
+    Ok(())
+}
+
+fn listAcpiTables(vmm: &mut VirtualMemoryManager, _args: &Arguments) -> Result<(), &'static str> {
+    rsdp::dumpTables(vmm);
+    Ok(())
+}
+
+fn listPci(_vmm: &mut VirtualMemoryManager, _args: &Arguments) -> Result<(), &'static str> {
+    if registry::getDeviceCount() == 0 {
+        loggerWriteLine!("No PCI devices");
+    }
+
+    registry::dump();
+    driver::dump();
+    Ok(())
+}
+
+fn listDrives(_vmm: &mut VirtualMemoryManager, _args: &Arguments) -> Result<(), &'static str> {
+    if drives::getDriveCount() == 0 {
+        loggerWriteLine!("No drives");
+    }
+
+    drives::dump();
+    Ok(())
+}
+
+fn readSector(vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    let Some(drive) = drives::getDrive(args.getNumber(0)?) else {
+        return Err("No such drive");
+    };
+
+    let lba = args.getNumber(1)? as u64;
+    let sectorSize = drive.getSectorSize();
+    let pages = alignUp(sectorSize, SIZE_OF_PAGE) / SIZE_OF_PAGE;
+    let buffer = vmm.allocatePages("Sector buffer", pages);
+    let result = drive.read(vmm, lba, 1, buffer as *mut u8);
+    if result.is_ok() {
+        dumpMemory(buffer, sectorSize.min(MAX_DUMP_LENGTH));
+    }
+
+    vmm.freePages(buffer, pages);
+    result
+}
+
+fn diskBench(vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    let Some(drive) = drives::getDrive(args.getNumber(0)?) else {
+        return Err("No such drive");
+    };
+
+    let megabytes = args.getNumberOr(1, DEFAULT_BENCHMARK_MEGABYTES)?;
+    benchmark::run(vmm, drive, megabytes)
+}
+
+fn list(_vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    let path = args.get(0).unwrap_or(".");
+    if !virtualFileSystem::stat(path)?.isDirectory() {
+        loggerWriteLine!("{}", path);
+        return Ok(());
+    }
+
+    let mut entries = virtualFileSystem::readDirectory(path)?;
+    entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    for entry in entries {
+        if entry.info.isDirectory() {
+            loggerWriteLine!("  {:<24} {:>10}", entry.name, "<DIR>");
+        } else {
+            loggerWriteLine!("  {:<24} {:>10}", entry.name, entry.info.size);
+        }
+    }
+
+    Ok(())
+}
+
+fn cat(_vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    let fd = virtualFileSystem::open(args.get(0).ok_or("Missing argument")?, OPEN_READ)?;
+    let mut buffer = [0u8; CAT_CHUNK_SIZE];
+    let mut result = Ok(());
+
+    loop {
+        let length = match virtualFileSystem::read(fd, &mut buffer) {
+            Ok(0) => break,
+            Ok(length) => length,
+            Err(reason) => {
+                result = Err(reason);
+                break;
+            }
+        };
+
+        // Text files only get \n, the console wants \r\n. Anything else unprintable shows as a dot like peek.
+        for &byte in &buffer[..length] {
+            match byte {
+                b'\n' => {
+                    loggerWriteLine!("");
+                }
+                b'\r' => {}
+                byte if byte.is_ascii_graphic() || byte == b' ' || byte == b'\t' => {
+                    loggerWrite!("{}", byte as char);
+                }
+                _ => {
+                    loggerWrite!(".");
+                }
+            }
+        }
+    }
+
+    loggerWriteLine!("");
+    virtualFileSystem::close(fd)?;
+    result
+}
+
+fn changeDirectory(_vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    virtualFileSystem::changeDirectory(args.get(0).unwrap_or("/"))
+}
+
+fn printWorkingDirectory(
+    _vmm: &mut VirtualMemoryManager,
+    _args: &Arguments,
+) -> Result<(), &'static str> {
+    loggerWriteLine!("{}", virtualFileSystem::getWorkingDirectory()?);
+    Ok(())
+}
+
+fn listMounts(_vmm: &mut VirtualMemoryManager, _args: &Arguments) -> Result<(), &'static str> {
+    for (path, fileSystemType) in virtualFileSystem::getMounts()? {
+        loggerWriteLine!("  {:<24} {}", path, fileSystemType);
+    }
+
+    Ok(())
+}
+
+fn uptime(_vmm: &mut VirtualMemoryManager, _args: &Arguments) -> Result<(), &'static str> {
+    let now = clock::now();
+    let seconds = now / 1_000_000_000;
+    loggerWriteLine!(
+        "{}:{:02}:{:02}.{:09} ({} ticks)",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60,
+        now % 1_000_000_000,
+        clock::ticks()
+    );
+
+    Ok(())
+}
+
+fn sleep(_vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str> {
+    let milliseconds = args.getNumber(0)?;
+    let start = clock::now();
+    clock::sleepMilliseconds(milliseconds as u64);
+    loggerWriteLine!("Slept for {} ns", clock::now() - start);
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+use crate::{loggerWriteLine, memory::virtualMemory::VirtualMemoryManager};
+
+use super::arguments::Arguments;
+
+pub type CommandHandler =
+    fn(vmm: &mut VirtualMemoryManager, args: &Arguments) -> Result<(), &'static str>;
+
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str, // Argument synopsis, e.g. "<address> [length]"
+    pub help: &'static str,  // One line description for the help listing
+    pub minArgs: usize,
+    pub maxArgs: usize,
+    pub handler: CommandHandler,
+}
+
+// Subsystems call registerCommand during their init
+static COMMANDS: Mutex<RefCell<Vec<Command>>> = Mutex::new(RefCell::new(Vec::new()));
+
+pub fn registerCommand(command: Command) {
+    critical_section::with(|cs| {
+        let mut commands = COMMANDS.borrow_ref_mut(cs);
+
+        // Re-registering replaces, that way reinitializing a subsystem doesn't list it twice
+        match commands
+            .iter_mut()
+            .find(|existing| existing.name == command.name)
+        {
+            Some(existing) => *existing = command,
+            None => commands.push(command),
+        }
+    });
+}
+
+// Hands back a copy so the caller isn't holding the lock (and interrupts off) while the command runs
+pub fn findCommand(name: &str) -> Option<Command> {
+    critical_section::with(|cs| {
+        COMMANDS
+            .borrow_ref(cs)
+            .iter()
+            .find(|command| command.name == name)
+            .copied()
+    })
+}
+
+// Sorted by name so help output is stable regardless of init order
+pub fn forEachCommand(mut f: impl FnMut(&Command)) {
+    let mut snapshot = critical_section::with(|cs| COMMANDS.borrow_ref(cs).clone());
+    snapshot.sort_unstable_by(|a, b| a.name.cmp(b.name));
+
+    for command in &snapshot {
+        f(command);
+    }
+}
+
+pub fn execute(vmm: &mut VirtualMemoryManager, line: &str) {
+    let args = match Arguments::parse(line) {
+        Ok(args) => args,
+        Err(message) => {
+            loggerWriteLine!("{}", message);
+            return;
+        }
+    };
+
+    let Some(name) = args.command() else {
+        return;
+    };
+
+    let Some(command) = findCommand(name) else {
+        loggerWriteLine!("Unknown command '{}'. Try 'help'.", name);
+        return;
+    };
+
+    if args.len() < command.minArgs || args.len() > command.maxArgs {
+        loggerWriteLine!("Usage: {} {}", command.name, command.usage);
+        return;
+    }
+
+    if let Err(message) = (command.handler)(vmm, &args) {
+        loggerWriteLine!("{}: {}", command.name, message);
+    }
+}
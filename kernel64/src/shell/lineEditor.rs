@@ -0,0 +1,271 @@
+use core::str::from_utf8;
+
+use kernel_shared::{loggerWrite, loggerWriteLine};
+
+use crate::keyboard::keyEvent::{KeyCode, KeyEvent};
+
+pub const MAX_LINE_LENGTH: usize = 160;
+const HISTORY_SIZE: usize = 16;
+
+// Control characters as produced by the keyboard (Ctrl+<letter>)
+const CONTROL_A: char = '\x01';
+const CONTROL_C: char = '\x03';
+const CONTROL_E: char = '\x05';
+const CONTROL_K: char = '\x0B';
+const CONTROL_U: char = '\x15';
+const BACKSPACE: char = '\x08';
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EditResult {
+    Editing,
+    Submitted, // Enter was hit, line() has the result until the next key comes in
+    Cancelled,
+}
+
+#[derive(Clone, Copy)]
+struct Line {
+    bytes: [u8; MAX_LINE_LENGTH],
+    length: usize,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Line {
+            bytes: [0; MAX_LINE_LENGTH],
+            length: 0,
+        }
+    }
+
+    fn asStr(&self) -> &str {
+        // We only ever let ASCII in, so this can't fail
+        from_utf8(&self.bytes[..self.length]).unwrap_or("")
+    }
+}
+
+// Edits a single line in place on the console. Everything is drawn with plain characters and backspaces
+// so it works the same on VGA and a dumb serial terminal.
+pub struct LineEditor {
+    current: Line,
+    cursor: usize,
+    submitted: bool,
+
+    // Circular, historyNext is where the next entry goes
+    history: [Line; HISTORY_SIZE],
+    historyCount: usize,
+    historyNext: usize,
+
+    // How far back we're looking (0 is the most recent) and what was being typed before we started looking
+    browsing: Option<usize>,
+    pending: Line,
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        LineEditor {
+            current: Line::new(),
+            cursor: 0,
+            submitted: false,
+            history: [Line::new(); HISTORY_SIZE],
+            historyCount: 0,
+            historyNext: 0,
+            browsing: None,
+            pending: Line::new(),
+        }
+    }
+
+    pub fn line(&self) -> &str {
+        self.current.asStr()
+    }
+
+    pub fn handleKey(&mut self, event: &KeyEvent) -> EditResult {
+        if !event.pressed {
+            return EditResult::Editing;
+        }
+
+        // Previous line was handed out, start fresh
+        if self.submitted {
+            self.submitted = false;
+            self.current.length = 0;
+            self.cursor = 0;
+        }
+
+        match event.code {
+            KeyCode::ArrowLeft => self.moveTo(self.cursor.saturating_sub(1)),
+            KeyCode::ArrowRight => self.moveTo((self.cursor + 1).min(self.current.length)),
+            KeyCode::Home => self.moveTo(0),
+            KeyCode::End => self.moveTo(self.current.length),
+            KeyCode::ArrowUp => self.historyBack(),
+            KeyCode::ArrowDown => self.historyForward(),
+            KeyCode::Delete => self.deleteAtCursor(),
+            _ => match event.character {
+                Some('\n') => {
+                    loggerWriteLine!("");
+                    self.addToHistory();
+                    self.browsing = None;
+                    self.submitted = true;
+                    return EditResult::Submitted;
+                }
+                Some(CONTROL_C) => {
+                    loggerWriteLine!("^C");
+                    self.current.length = 0;
+                    self.cursor = 0;
+                    self.browsing = None;
+                    return EditResult::Cancelled;
+                }
+                Some(BACKSPACE) => {
+                    if self.cursor > 0 {
+                        self.moveTo(self.cursor - 1);
+                        self.deleteAtCursor();
+                    }
+                }
+                Some(CONTROL_A) => self.moveTo(0),
+                Some(CONTROL_E) => self.moveTo(self.current.length),
+                Some(CONTROL_K) => self.truncateAtCursor(),
+                Some(CONTROL_U) => {
+                    self.moveTo(0);
+                    self.truncateAtCursor();
+                }
+                Some(c) if c.is_ascii() && !c.is_ascii_control() => self.insert(c as u8),
+                _ => {}
+            },
+        }
+
+        EditResult::Editing
+    }
+
+    fn insert(&mut self, byte: u8) {
+        if self.current.length == MAX_LINE_LENGTH {
+            return;
+        }
+
+        let length = self.current.length;
+        self.current.bytes.copy_within(self.cursor..length, self.cursor + 1);
+        self.current.bytes[self.cursor] = byte;
+        self.current.length += 1;
+
+        // Redraw from the new character onwards then walk back to just after it
+        self.drawFrom(self.cursor, 0);
+        self.cursor += 1;
+        self.backUp(self.current.length - self.cursor);
+    }
+
+    fn deleteAtCursor(&mut self) {
+        if self.cursor == self.current.length {
+            return;
+        }
+
+        let length = self.current.length;
+        self.current.bytes.copy_within(self.cursor + 1..length, self.cursor);
+        self.current.length -= 1;
+
+        self.drawFrom(self.cursor, 1);
+        self.backUp(self.current.length - self.cursor + 1);
+    }
+
+    fn truncateAtCursor(&mut self) {
+        let removed = self.current.length - self.cursor;
+        self.current.length = self.cursor;
+        self.blank(removed);
+        self.backUp(removed);
+    }
+
+    fn moveTo(&mut self, position: usize) {
+        if position < self.cursor {
+            self.backUp(self.cursor - position);
+        } else if position > self.cursor {
+            self.drawRange(self.cursor, position);
+        }
+
+        self.cursor = position;
+    }
+
+    fn replaceLine(&mut self, line: Line) {
+        let oldLength = self.current.length;
+        self.moveTo(0);
+        self.current = line;
+
+        let extra = oldLength.saturating_sub(line.length);
+        self.drawFrom(0, extra);
+        self.backUp(extra);
+        self.cursor = self.current.length;
+    }
+
+    fn historyBack(&mut self) {
+        let next = match self.browsing {
+            None => 0,
+            Some(index) => index + 1,
+        };
+
+        if next >= self.historyCount {
+            return;
+        }
+
+        if self.browsing.is_none() {
+            self.pending = self.current;
+        }
+
+        self.browsing = Some(next);
+        self.replaceLine(self.getHistory(next));
+    }
+
+    fn historyForward(&mut self) {
+        match self.browsing {
+            None => {}
+            Some(0) => {
+                self.browsing = None;
+                self.replaceLine(self.pending);
+            }
+            Some(index) => {
+                self.browsing = Some(index - 1);
+                self.replaceLine(self.getHistory(index - 1));
+            }
+        }
+    }
+
+    // 0 is the most recent entry
+    fn getHistory(&self, index: usize) -> Line {
+        let slot = (self.historyNext + HISTORY_SIZE - 1 - index) % HISTORY_SIZE;
+        self.history[slot]
+    }
+
+    fn addToHistory(&mut self) {
+        if self.line().trim().is_empty() {
+            return;
+        }
+
+        // Hitting enter on the same thing over and over shouldn't bury everything else
+        if self.historyCount > 0 && self.getHistory(0).asStr() == self.line() {
+            return;
+        }
+
+        self.history[self.historyNext] = self.current;
+        self.historyNext = (self.historyNext + 1) % HISTORY_SIZE;
+        if self.historyCount < HISTORY_SIZE {
+            self.historyCount += 1;
+        }
+    }
+
+    fn drawRange(&self, start: usize, end: usize) {
+        if let Ok(text) = from_utf8(&self.current.bytes[start..end]) {
+            loggerWrite!("{}", text);
+        }
+    }
+
+    // Draws to the end of the line followed by some spaces to cover anything left over
+    fn drawFrom(&self, start: usize, blanks: usize) {
+        self.drawRange(start, self.current.length);
+        self.blank(blanks);
+    }
+
+    fn blank(&self, count: usize) {
+        for _ in 0..count {
+            loggerWrite!(" ");
+        }
+    }
+
+    fn backUp(&self, count: usize) {
+        for _ in 0..count {
+            loggerWrite!("\x08");
+        }
+    }
+}
@@ -1 +1,5 @@
+pub mod arguments;
+pub mod builtinCommands;
+pub mod commandRegistry;
 pub(crate) mod kernelShell;
+pub mod lineEditor;
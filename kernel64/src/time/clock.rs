@@ -0,0 +1,230 @@
+use core::{cell::Cell, hint::spin_loop};
+
+use critical_section::Mutex;
+use kernel_shared::assemblyStuff::misc::{
+    areInterruptsEnabled, disableInterrupts, enableInterrupts, enableInterruptsAndHalt,
+};
+
+use crate::{
+    acpi::hpet::getHpet,
+    interupts::{
+        InteruptDescriptorTable::registerHandler,
+        irq::{self, Controller},
+        localApic::{self, TIMER_VECTOR, TimerDivide},
+    },
+    loggerWriteLine,
+    memory::virtualMemory::VirtualMemoryManager,
+};
+
+use super::{hpet, pit, timers};
+
+// Monotonic time since init. The HPET main counter is used when there is one, otherwise it's counted in
+// ticks. The tick itself comes from the local APIC timer (calibrated against the HPET or PIT) when the APICs
+// are in charge, or PIT channel 0 when we're still on the 8259s. Either way it's what drives timer callbacks.
+const TICK_NANOSECONDS: u64 = 1_000_000;
+const CALIBRATION_NANOSECONDS: u64 = 10_000_000;
+const APIC_TIMER_DIVIDE: TimerDivide = TimerDivide::By16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TickSource {
+    None,
+    Pit,
+    LocalApic,
+}
+
+#[derive(Clone, Copy)]
+struct ClockState {
+    tickSource: TickSource,
+    ticks: u64,
+    tickNanoseconds: u64,
+}
+
+static CLOCK: Mutex<Cell<ClockState>> = Mutex::new(Cell::new(ClockState {
+    tickSource: TickSource::None,
+    ticks: 0,
+    tickNanoseconds: 0,
+}));
+
+fn getState() -> ClockState {
+    critical_section::with(|cs| CLOCK.borrow(cs).get())
+}
+
+// Call after the interrupt controller is settled (i.e. after irq::switchToApic) so the right tick source gets picked
+pub fn init(vmm: &mut VirtualMemoryManager) {
+    match getHpet(vmm) {
+        Some(info) => {
+            hpet::init(vmm, &info);
+        }
+        None => {
+            loggerWriteLine!("No HPET");
+        }
+    }
+
+    let wereEnabled = areInterruptsEnabled();
+    disableInterrupts();
+
+    let state = if irq::getController() == Controller::Apic {
+        startLocalApicTick().unwrap_or_else(startPitTick)
+    } else {
+        startPitTick()
+    };
+
+    critical_section::with(|cs| CLOCK.borrow(cs).set(state));
+
+    if wereEnabled {
+        enableInterrupts();
+    }
+
+    loggerWriteLine!(
+        "Ticking every {} ns from {:?}, clock is {}",
+        state.tickNanoseconds,
+        state.tickSource,
+        if hpet::isEnabled() { "HPET" } else { "ticks" }
+    );
+}
+
+fn startPitTick() -> ClockState {
+    let tickNanoseconds = pit::startPeriodic(TICK_NANOSECONDS);
+    irq::registerIrqHandler(pit::IRQ, onPitTick);
+
+    ClockState {
+        tickSource: TickSource::Pit,
+        ticks: 0,
+        tickNanoseconds,
+    }
+}
+
+// Count down from the top for a known amount of time to see how fast the APIC timer goes
+fn startLocalApicTick() -> Option<ClockState> {
+    localApic::startTimer(TIMER_VECTOR, u32::MAX, APIC_TIMER_DIVIDE, false);
+    calibrationWait(CALIBRATION_NANOSECONDS);
+    let remaining = localApic::getTimerCurrentCount();
+    localApic::stopTimer();
+
+    let elapsed = (u32::MAX - remaining) as u64;
+    let countsPerTick = elapsed * TICK_NANOSECONDS / CALIBRATION_NANOSECONDS;
+    if countsPerTick == 0 || countsPerTick > u32::MAX as u64 {
+        loggerWriteLine!(
+            "Local APIC timer calibration came back with {}, using the PIT",
+            elapsed
+        );
+        return None;
+    }
+
+    loggerWriteLine!(
+        "Local APIC timer runs at {} Hz (divided by {})",
+        elapsed * 1_000_000_000 / CALIBRATION_NANOSECONDS,
+        APIC_TIMER_DIVIDE.getValue()
+    );
+
+    registerHandler(TIMER_VECTOR, onLocalApicTick);
+    localApic::startTimer(TIMER_VECTOR, countsPerTick as u32, APIC_TIMER_DIVIDE, true);
+
+    Some(ClockState {
+        tickSource: TickSource::LocalApic,
+        ticks: 0,
+        tickNanoseconds: TICK_NANOSECONDS,
+    })
+}
+
+fn calibrationWait(nanoseconds: u64) {
+    if !hpet::busyWait(nanoseconds) {
+        pit::busyWait(nanoseconds);
+    }
+}
+
+fn onPitTick(_irq: u8) {
+    tick();
+}
+
+// Goes straight to the IDT rather than through irq, so it does its own EOI
+fn onLocalApicTick(_vector: u8) {
+    tick();
+    localApic::sendEoi();
+}
+
+fn tick() {
+    critical_section::with(|cs| {
+        let clock = CLOCK.borrow(cs);
+        let mut state = clock.get();
+        state.ticks += 1;
+        clock.set(state);
+    });
+
+    timers::runExpired(now());
+}
+
+pub fn getTickSource() -> TickSource {
+    getState().tickSource
+}
+
+pub fn getTickNanoseconds() -> u64 {
+    getState().tickNanoseconds
+}
+
+pub fn ticks() -> u64 {
+    getState().ticks
+}
+
+// Nanoseconds since init. Never goes backwards. Without an HPET it only moves while interrupts are on.
+pub fn now() -> u64 {
+    if let Some(nanoseconds) = hpet::nanoseconds() {
+        return nanoseconds;
+    }
+
+    let state = getState();
+    state.ticks * state.tickNanoseconds
+}
+
+// Will the clock move if we sit here waiting on it?
+fn isClockRunning() -> bool {
+    hpet::isEnabled() || (getState().tickSource != TickSource::None && areInterruptsEnabled())
+}
+
+pub fn sleep(nanoseconds: u64) {
+    if !isClockRunning() {
+        pit::busyWait(nanoseconds);
+        return;
+    }
+
+    let deadline = now().saturating_add(nanoseconds);
+    while now() < deadline {
+        // Nothing would wake us from a halt with interrupts off
+        if areInterruptsEnabled() && getState().tickSource != TickSource::None {
+            enableInterruptsAndHalt();
+        } else {
+            spin_loop();
+        }
+    }
+}
+
+pub fn sleepMicroseconds(microseconds: u64) {
+    sleep(microseconds.saturating_mul(1_000));
+}
+
+pub fn sleepMilliseconds(milliseconds: u64) {
+    sleep(milliseconds.saturating_mul(1_000_000));
+}
+
+// For polling loops that need to give up eventually, e.g. waiting on a device
+// BUGBUG: If the clock isn't running (no HPET and interrupts off) this never expires
+#[derive(Clone, Copy)]
+pub struct Deadline {
+    at: u64,
+}
+
+impl Deadline {
+    pub fn afterNanoseconds(nanoseconds: u64) -> Self {
+        Deadline {
+            at: now().saturating_add(nanoseconds),
+        }
+    }
+
+    pub fn afterMilliseconds(milliseconds: u64) -> Self {
+        Self::afterNanoseconds(milliseconds.saturating_mul(1_000_000))
+    }
+
+    pub fn hasExpired(&self) -> bool {
+        now() >= self.at
+    }
+}
@@ -0,0 +1,126 @@
+use core::{
+    cell::Cell,
+    hint::spin_loop,
+    ptr::{read_volatile, write_volatile},
+};
+
+use critical_section::Mutex;
+use kernel_shared::pageTable::enums::*;
+
+use crate::{acpi::hpet::HpetInfo, loggerWriteLine, memory::virtualMemory::VirtualMemoryManager};
+
+// https://www.intel.com/content/dam/www/public/us/en/documents/technical-specifications/software-developers-hpet-spec-1-0a.pdf
+// High Precision Event Timer. We only use the main counter as a clock; the comparators are left alone.
+const REGISTER_CAPABILITIES: usize = 0x00;
+const REGISTER_CONFIGURATION: usize = 0x10;
+const REGISTER_MAIN_COUNTER: usize = 0xF0;
+
+const CAPABILITIES_64BIT_COUNTER: u64 = 1 << 13;
+const CONFIGURATION_ENABLE: u64 = 1 << 0;
+const CONFIGURATION_LEGACY_REPLACEMENT: u64 = 1 << 1;
+
+// Spec caps the period at 100ns
+const MAX_PERIOD_FEMTOSECONDS: u64 = 100_000_000;
+const FEMTOSECONDS_PER_NANOSECOND: u64 = 1_000_000;
+
+#[derive(Clone, Copy)]
+struct Hpet {
+    base: usize, // Virtual
+    periodFemtoseconds: u64,
+    is64Bit: bool,
+}
+
+static HPET: Mutex<Cell<Option<Hpet>>> = Mutex::new(Cell::new(None));
+
+fn getHpet() -> Option<Hpet> {
+    critical_section::with(|cs| HPET.borrow(cs).get())
+}
+
+impl Hpet {
+    fn read(&self, offset: usize) -> u64 {
+        unsafe { read_volatile((self.base + offset) as *const u64) }
+    }
+
+    fn write(&self, offset: usize, value: u64) {
+        unsafe { write_volatile((self.base + offset) as *mut u64, value) }
+    }
+}
+
+pub fn init(vmm: &mut VirtualMemoryManager, info: &HpetInfo) -> bool {
+    let virtualAddress = vmm.mapPhysicalAnywhere(
+        info.address as usize,
+        0x1000,
+        Execute::Yes,
+        Present::Yes,
+        Writable::Yes,
+        Cachable::No,
+        UserSupervisor::Supervisor,
+        WriteThrough::WriteTrough,
+    );
+
+    let mut hpet = Hpet {
+        base: virtualAddress,
+        periodFemtoseconds: 0,
+        is64Bit: false,
+    };
+
+    let capabilities = hpet.read(REGISTER_CAPABILITIES);
+    hpet.periodFemtoseconds = capabilities >> 32;
+    hpet.is64Bit = capabilities & CAPABILITIES_64BIT_COUNTER != 0;
+
+    if hpet.periodFemtoseconds == 0 || hpet.periodFemtoseconds > MAX_PERIOD_FEMTOSECONDS {
+        loggerWriteLine!(
+            "HPET period of {} fs is bogus, ignoring it",
+            hpet.periodFemtoseconds
+        );
+        return false;
+    }
+
+    // BUGBUG: A 32-bit counter wraps every ~5 minutes at the usual 14.3MHz. Need to track the high bits
+    // ourselves before we can trust it for uptime.
+    if !hpet.is64Bit {
+        loggerWriteLine!("HPET only has a 32-bit counter, not using it");
+        return false;
+    }
+
+    // Stop, zero and restart the counter. Legacy replacement stays off so the PIT keeps IRQ0.
+    let configuration = hpet.read(REGISTER_CONFIGURATION);
+    let configuration = configuration & !(CONFIGURATION_ENABLE | CONFIGURATION_LEGACY_REPLACEMENT);
+    hpet.write(REGISTER_CONFIGURATION, configuration);
+    hpet.write(REGISTER_MAIN_COUNTER, 0);
+    hpet.write(REGISTER_CONFIGURATION, configuration | CONFIGURATION_ENABLE);
+
+    loggerWriteLine!(
+        "HPET {} @ 0x{:X} / 0x{:X} (P/V) ticking every {} fs",
+        info.number,
+        info.address,
+        virtualAddress,
+        hpet.periodFemtoseconds
+    );
+
+    critical_section::with(|cs| HPET.borrow(cs).set(Some(hpet)));
+    true
+}
+
+pub fn isEnabled() -> bool {
+    getHpet().is_some()
+}
+
+pub fn nanoseconds() -> Option<u64> {
+    let hpet = getHpet()?;
+    let counter = hpet.read(REGISTER_MAIN_COUNTER) as u128;
+    Some((counter * hpet.periodFemtoseconds as u128 / FEMTOSECONDS_PER_NANOSECOND as u128) as u64)
+}
+
+// Works with interrupts off. Returns false if there's no HPET to wait on.
+pub fn busyWait(nanoseconds: u64) -> bool {
+    let Some(start) = self::nanoseconds() else {
+        return false;
+    };
+
+    while self::nanoseconds().unwrap_or(u64::MAX) - start < nanoseconds {
+        spin_loop();
+    }
+
+    true
+}
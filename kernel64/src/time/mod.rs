@@ -0,0 +1,4 @@
+pub mod clock;
+pub mod hpet;
+pub mod pit;
+pub mod timers;
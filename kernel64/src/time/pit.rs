@@ -0,0 +1,103 @@
+use core::hint::spin_loop;
+
+use kernel_shared::assemblyStuff::ports::{inB, outB};
+
+// https://wiki.osdev.org/Programmable_Interval_Timer
+// 8253/8254. Always there, always the same frequency, which makes it the thing to calibrate everything
+// else against even though it's slow to talk to.
+pub const FREQUENCY: u64 = 1_193_182;
+pub const IRQ: u8 = 0;
+
+const CHANNEL0_DATA: u16 = 0x40;
+const CHANNEL2_DATA: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+
+// Channel 2's gate and output are wired to the keyboard controller's port B, along with the speaker
+const PORT_B: u16 = 0x61;
+const PORT_B_GATE2: u8 = 1 << 0;
+const PORT_B_SPEAKER: u8 = 1 << 1;
+const PORT_B_OUT2: u8 = 1 << 5;
+
+const COMMAND_CHANNEL0: u8 = 0b00 << 6;
+const COMMAND_CHANNEL2: u8 = 0b10 << 6;
+const COMMAND_ACCESS_LOW_HIGH: u8 = 0b11 << 4;
+const COMMAND_MODE0: u8 = 0b000 << 1; // Interrupt on terminal count (one-shot)
+const COMMAND_MODE2: u8 = 0b010 << 1; // Rate generator
+
+const MAX_DIVISOR: u64 = 0x1_0000; // Writing 0 means 65536
+
+// In u128 so long waits can't overflow; the answer always fits back in a u64 since FREQUENCY is under 1GHz
+fn toTicks(nanoseconds: u64) -> u64 {
+    (FREQUENCY as u128 * nanoseconds as u128 / 1_000_000_000) as u64
+}
+
+fn getDivisor(nanoseconds: u64) -> u64 {
+    toTicks(nanoseconds).clamp(1, MAX_DIVISOR)
+}
+
+fn writeDivisor(port: u16, divisor: u64) {
+    // 65536 is written as 0, which the truncation takes care of
+    unsafe {
+        outB(port, divisor as u8);
+        outB(port, (divisor >> 8) as u8);
+    }
+}
+
+// Fires IRQ0 every periodNanoseconds (as close as the divisor allows, max ~55ms).
+// Returns the period it actually ended up with.
+pub fn startPeriodic(periodNanoseconds: u64) -> u64 {
+    let divisor = getDivisor(periodNanoseconds);
+    unsafe {
+        outB(
+            COMMAND,
+            COMMAND_CHANNEL0 | COMMAND_ACCESS_LOW_HIGH | COMMAND_MODE2,
+        );
+    }
+
+    writeDivisor(CHANNEL0_DATA, divisor);
+    divisor * 1_000_000_000 / FREQUENCY
+}
+
+pub fn stop() {
+    // One-shot with nothing loaded never fires
+    unsafe {
+        outB(
+            COMMAND,
+            COMMAND_CHANNEL0 | COMMAND_ACCESS_LOW_HIGH | COMMAND_MODE0,
+        );
+    }
+}
+
+// Spins using channel 2 so it works with interrupts off and doesn't disturb channel 0.
+// Good enough for calibration and early boot delays, not much else.
+pub fn busyWait(nanoseconds: u64) {
+    let mut remaining = toTicks(nanoseconds);
+
+    while remaining > 0 {
+        let divisor = remaining.min(MAX_DIVISOR);
+        busyWaitTicks(divisor);
+        remaining -= divisor;
+    }
+}
+
+fn busyWaitTicks(divisor: u64) {
+    unsafe {
+        // Gate low while we program it, speaker stays off
+        let portB = inB(PORT_B) & !(PORT_B_GATE2 | PORT_B_SPEAKER);
+        outB(PORT_B, portB);
+
+        outB(
+            COMMAND,
+            COMMAND_CHANNEL2 | COMMAND_ACCESS_LOW_HIGH | COMMAND_MODE0,
+        );
+        writeDivisor(CHANNEL2_DATA, divisor);
+
+        // Rising gate starts the count, OUT2 goes high once it hits 0
+        outB(PORT_B, portB | PORT_B_GATE2);
+        while inB(PORT_B) & PORT_B_OUT2 == 0 {
+            spin_loop();
+        }
+
+        outB(PORT_B, portB);
+    }
+}
@@ -0,0 +1,142 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+use crate::loggerWriteLine;
+
+use super::clock::now;
+
+// Callbacks run from the tick interrupt with interrupts off. Keep them short and don't sleep in them;
+// hand real work off to something else.
+pub type TimerCallback = fn(id: TimerId);
+
+// A fixed number of outstanding timers, so adding one from a callback never allocates in the middle of the tick
+const MAX_TIMERS: usize = 32;
+
+// Slot plus a generation so a stale ID can't cancel whoever reused the slot
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimerId {
+    slot: usize,
+    generation: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Timer {
+    deadline: u64,
+    period: u64, // 0 for one-shot
+    callback: TimerCallback,
+    generation: u32,
+}
+
+struct TimerState {
+    timers: [Option<Timer>; MAX_TIMERS],
+    generation: u32,
+}
+
+static TIMERS: Mutex<RefCell<TimerState>> = Mutex::new(RefCell::new(TimerState {
+    timers: [None; MAX_TIMERS],
+    generation: 0,
+}));
+
+fn add(delayNanoseconds: u64, periodNanoseconds: u64, callback: TimerCallback) -> Option<TimerId> {
+    let deadline = now().saturating_add(delayNanoseconds);
+
+    let result = critical_section::with(|cs| {
+        let mut state = TIMERS.borrow_ref_mut(cs);
+        let slot = state.timers.iter().position(|t| t.is_none())?;
+
+        state.generation = state.generation.wrapping_add(1);
+        let generation = state.generation;
+        state.timers[slot] = Some(Timer {
+            deadline,
+            period: periodNanoseconds,
+            callback,
+            generation,
+        });
+
+        Some(TimerId { slot, generation })
+    });
+
+    if result.is_none() {
+        loggerWriteLine!("Out of timers");
+    }
+
+    result
+}
+
+// Calls callback once, delayNanoseconds from now. Resolution is whatever the tick is.
+pub fn oneShot(delayNanoseconds: u64, callback: TimerCallback) -> Option<TimerId> {
+    add(delayNanoseconds, 0, callback)
+}
+
+// Calls callback every periodNanoseconds until cancelled. If we fall behind, missed periods are
+// skipped rather than delivered in a burst.
+pub fn periodic(periodNanoseconds: u64, callback: TimerCallback) -> Option<TimerId> {
+    if periodNanoseconds == 0 {
+        return None;
+    }
+
+    add(periodNanoseconds, periodNanoseconds, callback)
+}
+
+// Returns false if it already fired (one-shot) or was already cancelled
+pub fn cancel(id: TimerId) -> bool {
+    critical_section::with(|cs| {
+        let mut state = TIMERS.borrow_ref_mut(cs);
+        match state.timers.get(id.slot) {
+            Some(Some(timer)) if timer.generation == id.generation => {
+                state.timers[id.slot] = None;
+                true
+            }
+            _ => false,
+        }
+    })
+}
+
+pub fn activeCount() -> usize {
+    critical_section::with(|cs| TIMERS.borrow_ref(cs).timers.iter().flatten().count())
+}
+
+// Called from the tick. Callbacks are invoked outside the borrow so they're free to add or cancel timers.
+pub(super) fn runExpired(now: u64) {
+    loop {
+        let expired = critical_section::with(|cs| {
+            let mut state = TIMERS.borrow_ref_mut(cs);
+            for slot in 0..MAX_TIMERS {
+                let Some(timer) = state.timers[slot] else {
+                    continue;
+                };
+
+                if timer.deadline > now {
+                    continue;
+                }
+
+                if timer.period == 0 {
+                    state.timers[slot] = None;
+                } else {
+                    let missed = (now - timer.deadline) / timer.period;
+                    state.timers[slot] = Some(Timer {
+                        deadline: timer
+                            .deadline
+                            .saturating_add((missed + 1).saturating_mul(timer.period)),
+                        ..timer
+                    });
+                }
+
+                let id = TimerId {
+                    slot,
+                    generation: timer.generation,
+                };
+
+                return Some((timer.callback, id));
+            }
+
+            None
+        });
+
+        match expired {
+            Some((callback, id)) => callback(id),
+            None => break,
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use kernel_shared::{
+    disk::blockDevice::BlockDevice,
+    fileSystem::{
+        SeekFrom,
+        fat::fatFileSystem::{FatFile, FatFileSystem},
+    },
+};
+
+use super::fileSystem::{DirectoryEntry, FileSystem, NodeId, NodeInfo, NodeKind};
+
+const ROOT: NodeId = 0;
+
+// FAT has nothing like an inode number, and the driver works on paths. So every path anyone has looked up gets a node,
+// with the name spelled the way the directory spells it so different cases of the same name share one. Files keep
+// their FatFile open, which saves walking the directories and cluster chain again on every read. Removed nodes leave a
+// hole rather than being reused, so a handle to something removed goes stale instead of finding something else.
+struct FatNode {
+    path: String,
+    kind: NodeKind,
+    file: Option<FatFile>,
+}
+
+pub struct FatFs<D: BlockDevice> {
+    fat: FatFileSystem<D>,
+    nodes: Vec<Option<FatNode>>,
+}
+
+impl<D: BlockDevice> FatFs<D> {
+    pub fn new(fat: FatFileSystem<D>) -> FatFs<D> {
+        let mut nodes = Vec::new();
+        nodes.push(Some(FatNode {
+            path: "/".to_string(),
+            kind: NodeKind::Directory,
+            file: None,
+        }));
+
+        FatFs { fat, nodes }
+    }
+
+    fn getNode(&mut self, node: NodeId) -> Result<&mut FatNode, &'static str> {
+        self.nodes
+            .get_mut(node as usize)
+            .and_then(|node| node.as_mut())
+            .ok_or("Stale node")
+    }
+
+    fn getPath(&mut self, directory: NodeId, name: &str) -> Result<String, &'static str> {
+        let parent = self.getNode(directory)?;
+        if parent.kind != NodeKind::Directory {
+            return Err("Not a directory");
+        }
+
+        Ok(match parent.path.as_str() {
+            "/" => format!("/{}", name),
+            path => format!("{}/{}", path, name),
+        })
+    }
+
+    // Finds or makes the node for path, which has to already be spelled the way the directory has it
+    fn getNodeFor(&mut self, path: String, kind: NodeKind, file: Option<FatFile>) -> NodeId {
+        if let Some(index) = self
+            .nodes
+            .iter()
+            .position(|node| matches!(node, Some(node) if node.path == path))
+        {
+            return index as NodeId;
+        }
+
+        self.nodes.push(Some(FatNode { path, kind, file }));
+        (self.nodes.len() - 1) as NodeId
+    }
+
+    // The open file behind node, opening it the first time
+    fn getFile(
+        &mut self,
+        node: NodeId,
+    ) -> Result<(&mut FatFileSystem<D>, &mut FatFile), &'static str> {
+        let fatNode = self
+            .nodes
+            .get_mut(node as usize)
+            .and_then(|node| node.as_mut())
+            .ok_or("Stale node")?;
+
+        if fatNode.kind != NodeKind::File {
+            return Err("Is a directory");
+        }
+
+        let file = match fatNode.file.take() {
+            Some(file) => file,
+            None => self.fat.open(&fatNode.path)?,
+        };
+
+        Ok((&mut self.fat, fatNode.file.insert(file)))
+    }
+}
+
+impl<D: BlockDevice + Send> FileSystem for FatFs<D> {
+    fn getType(&self) -> &'static str {
+        "fat"
+    }
+
+    fn getRoot(&self) -> NodeId {
+        ROOT
+    }
+
+    fn lookup(&mut self, directory: NodeId, name: &str) -> Result<NodeId, &'static str> {
+        let path = self.getPath(directory, name)?;
+        let info = self.fat.stat(&path)?;
+        let kind = if info.isDirectory() {
+            NodeKind::Directory
+        } else {
+            NodeKind::File
+        };
+
+        // Respelled from what the directory says, otherwise HI.TXT and hi.txt would get different nodes
+        let path = self.getPath(directory, info.getName())?;
+        Ok(self.getNodeFor(path, kind, None))
+    }
+
+    fn getInfo(&mut self, node: NodeId) -> Result<NodeInfo, &'static str> {
+        let fatNode = self.getNode(node)?;
+        let kind = fatNode.kind;
+        let size = match (&fatNode.file, kind) {
+            (Some(file), _) => file.getInfo().size as u64,
+            (None, NodeKind::Directory) => 0,
+            (None, NodeKind::File) => {
+                let path = fatNode.path.clone();
+                self.fat.stat(&path)?.size as u64
+            }
+        };
+
+        Ok(NodeInfo { kind, size })
+    }
+
+    fn readDirectory(&mut self, directory: NodeId) -> Result<Vec<DirectoryEntry>, &'static str> {
+        let path = self.getNode(directory)?.path.clone();
+        let mut handle = self.fat.openDirectory(&path)?;
+        let mut result = Vec::new();
+
+        while let Some(info) = self.fat.readDirectory(&mut handle)? {
+            let name = info.getName();
+            if name.as_bytes() == b"." || name.as_bytes() == b".." {
+                continue;
+            }
+
+            // Something we've got open may have grown since the directory entry was last read
+            let mut nodeInfo = NodeInfo {
+                kind: if info.isDirectory() {
+                    NodeKind::Directory
+                } else {
+                    NodeKind::File
+                },
+                size: info.size as u64,
+            };
+
+            let childPath = self.getPath(directory, name)?;
+            if let Some(Some(FatNode {
+                file: Some(file), ..
+            })) = self
+                .nodes
+                .iter()
+                .find(|node| matches!(node, Some(node) if node.path == childPath))
+            {
+                nodeInfo.size = file.getInfo().size as u64;
+            }
+
+            result.push(DirectoryEntry {
+                name: name.to_string(),
+                info: nodeInfo,
+            });
+        }
+
+        Ok(result)
+    }
+
+    fn read(
+        &mut self,
+        node: NodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let (fat, file) = self.getFile(node)?;
+        if offset >= file.getInfo().size as u64 {
+            return Ok(0);
+        }
+
+        fat.seek(file, SeekFrom::Start(offset))?;
+        fat.read(file, buffer)
+    }
+
+    fn write(&mut self, node: NodeId, offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        let (fat, file) = self.getFile(node)?;
+        fat.seek(file, SeekFrom::Start(offset))?;
+        fat.write(file, buffer)
+    }
+
+    fn truncate(&mut self, node: NodeId, length: u64) -> Result<(), &'static str> {
+        let (fat, file) = self.getFile(node)?;
+        fat.truncate(file, length)
+    }
+
+    fn create(
+        &mut self,
+        directory: NodeId,
+        name: &str,
+        kind: NodeKind,
+    ) -> Result<NodeId, &'static str> {
+        let path = self.getPath(directory, name)?;
+        let file = match kind {
+            NodeKind::File => Some(self.fat.create(&path)?),
+            NodeKind::Directory => {
+                self.fat.createDirectory(&path)?;
+                None
+            }
+        };
+
+        Ok(self.getNodeFor(path, kind, file))
+    }
+
+    fn remove(&mut self, directory: NodeId, name: &str) -> Result<(), &'static str> {
+        let node = self.lookup(directory, name)?;
+        let path = self.getNode(node)?.path.clone();
+        self.fat.remove(&path)?;
+
+        // Directories have to be empty to be removed, so there's nothing under it to forget about
+        self.nodes[node as usize] = None;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        self.fat.flush()
+    }
+}
@@ -0,0 +1,69 @@
+use alloc::{string::String, vec::Vec};
+
+// Whatever a file system uses to tell its files apart: an inode number, an index into a table, ... Only meaningful to
+// the file system that handed it out.
+pub type NodeId = u64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Directory,
+}
+
+#[derive(Clone, Copy)]
+pub struct NodeInfo {
+    pub kind: NodeKind,
+    pub size: u64,
+}
+
+impl NodeInfo {
+    pub fn isDirectory(&self) -> bool {
+        self.kind == NodeKind::Directory
+    }
+}
+
+// One name in a directory and what it points at
+pub struct DirectoryEntry {
+    pub name: String,
+    pub info: NodeInfo,
+}
+
+// What a file system has to provide to be mounted. Everything works on nodes; paths, mounts, . and .. are the VFS's
+// problem. Directories never list . or ..
+pub trait FileSystem: Send {
+    // Short name for the mount listing, like "fat" or "ramfs"
+    fn getType(&self) -> &'static str;
+
+    fn getRoot(&self) -> NodeId;
+
+    // The node called name in directory
+    fn lookup(&mut self, directory: NodeId, name: &str) -> Result<NodeId, &'static str>;
+
+    fn getInfo(&mut self, node: NodeId) -> Result<NodeInfo, &'static str>;
+
+    fn readDirectory(&mut self, directory: NodeId) -> Result<Vec<DirectoryEntry>, &'static str>;
+
+    // Reads from offset, returning how much was read; 0 means end of file
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8])
+    -> Result<usize, &'static str>;
+
+    // Writes at offset, which can be anywhere up to the end of the file
+    fn write(&mut self, node: NodeId, offset: u64, buffer: &[u8]) -> Result<usize, &'static str>;
+
+    // Shrinks a file to length
+    fn truncate(&mut self, node: NodeId, length: u64) -> Result<(), &'static str>;
+
+    // Makes an empty file or directory called name in directory. It can't already exist.
+    fn create(
+        &mut self,
+        directory: NodeId,
+        name: &str,
+        kind: NodeKind,
+    ) -> Result<NodeId, &'static str>;
+
+    // Directories have to be empty first
+    fn remove(&mut self, directory: NodeId, name: &str) -> Result<(), &'static str>;
+
+    // Everything written so far is on the media once this returns
+    fn flush(&mut self) -> Result<(), &'static str>;
+}
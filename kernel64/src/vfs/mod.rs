@@ -0,0 +1,5 @@
+pub mod fatFs;
+pub mod fileSystem;
+pub mod path;
+pub mod ramFs;
+pub mod virtualFileSystem;
@@ -0,0 +1,58 @@
+use alloc::{string::String, vec::Vec};
+
+// Biggest a single name in a path can be; FAT's long names are the longest thing we have
+const MAX_NAME_LENGTH: usize = 255;
+
+// Turns path into an absolute one with no . or .. in it, relative paths being from workingDirectory. .. at the root
+// stays at the root. There are no symlinks, so doing it on the text gives the same answer walking would.
+pub fn normalize(workingDirectory: &str, path: &str) -> Result<String, &'static str> {
+    if path.is_empty() {
+        return Err("Empty path");
+    }
+
+    let mut components: Vec<&str> = Vec::new();
+    let start = if path.starts_with('/') {
+        ""
+    } else {
+        workingDirectory
+    };
+
+    for component in start.split('/').chain(path.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name if name.len() > MAX_NAME_LENGTH => return Err("Name too long"),
+            name => components.push(name),
+        }
+    }
+
+    if components.is_empty() {
+        return Ok(String::from("/"));
+    }
+
+    let mut result = String::new();
+    for component in components {
+        result.push('/');
+        result.push_str(component);
+    }
+
+    Ok(result)
+}
+
+// Splits a normalized path into its parent directory and last name. The root doesn't have either.
+pub fn splitParent(path: &str) -> Option<(&str, &str)> {
+    let index = path.rfind('/')?;
+    let name = &path[index + 1..];
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((if index == 0 { "/" } else { &path[..index] }, name))
+}
+
+// The names in a normalized path, root first
+pub fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|component| !component.is_empty())
+}
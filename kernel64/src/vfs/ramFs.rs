@@ -0,0 +1,190 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::fileSystem::{DirectoryEntry, FileSystem, NodeId, NodeInfo, NodeKind};
+
+const ROOT: NodeId = 0;
+
+// Biggest a file can get, so one runaway write can't eat the whole heap
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+struct RamNode {
+    kind: NodeKind,
+    data: Vec<u8>,
+    children: Vec<(String, NodeId)>,
+}
+
+impl RamNode {
+    fn new(kind: NodeKind) -> RamNode {
+        RamNode {
+            kind,
+            data: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+// Everything lives on the heap and is gone on reboot. Nodes are indexes into a table. Removed ones leave a hole rather
+// than being reused, so a handle to something removed goes stale instead of finding something else.
+pub struct RamFs {
+    nodes: Vec<Option<RamNode>>,
+}
+
+impl RamFs {
+    pub fn new() -> RamFs {
+        let mut nodes = Vec::new();
+        nodes.push(Some(RamNode::new(NodeKind::Directory)));
+        RamFs { nodes }
+    }
+
+    fn getNode(&mut self, node: NodeId) -> Result<&mut RamNode, &'static str> {
+        self.nodes
+            .get_mut(node as usize)
+            .and_then(|node| node.as_mut())
+            .ok_or("Stale node")
+    }
+
+    fn getDirectory(&mut self, node: NodeId) -> Result<&mut RamNode, &'static str> {
+        let node = self.getNode(node)?;
+        if node.kind != NodeKind::Directory {
+            return Err("Not a directory");
+        }
+
+        Ok(node)
+    }
+
+    fn getFile(&mut self, node: NodeId) -> Result<&mut RamNode, &'static str> {
+        let node = self.getNode(node)?;
+        if node.kind != NodeKind::File {
+            return Err("Is a directory");
+        }
+
+        Ok(node)
+    }
+}
+
+impl FileSystem for RamFs {
+    fn getType(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn getRoot(&self) -> NodeId {
+        ROOT
+    }
+
+    fn lookup(&mut self, directory: NodeId, name: &str) -> Result<NodeId, &'static str> {
+        self.getDirectory(directory)?
+            .children
+            .iter()
+            .find(|(childName, _)| childName == name)
+            .map(|(_, node)| *node)
+            .ok_or("File not found")
+    }
+
+    fn getInfo(&mut self, node: NodeId) -> Result<NodeInfo, &'static str> {
+        let node = self.getNode(node)?;
+        Ok(NodeInfo {
+            kind: node.kind,
+            size: node.data.len() as u64,
+        })
+    }
+
+    fn readDirectory(&mut self, directory: NodeId) -> Result<Vec<DirectoryEntry>, &'static str> {
+        let children = self.getDirectory(directory)?.children.clone();
+        children
+            .into_iter()
+            .map(|(name, node)| {
+                Ok(DirectoryEntry {
+                    name,
+                    info: self.getInfo(node)?,
+                })
+            })
+            .collect()
+    }
+
+    fn read(
+        &mut self,
+        node: NodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let data = &self.getFile(node)?.data;
+        let Some(available) = data.get(offset as usize..) else {
+            return Ok(0);
+        };
+
+        let length = buffer.len().min(available.len());
+        buffer[..length].copy_from_slice(&available[..length]);
+        Ok(length)
+    }
+
+    fn write(&mut self, node: NodeId, offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        let data = &mut self.getFile(node)?.data;
+        if offset > data.len() as u64 {
+            return Err("Write past the end");
+        }
+
+        let end = offset + buffer.len() as u64;
+        if end > MAX_FILE_SIZE {
+            return Err("File too big");
+        }
+
+        let offset = offset as usize;
+        let end = end as usize;
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+
+        data[offset..end].copy_from_slice(buffer);
+        Ok(buffer.len())
+    }
+
+    fn truncate(&mut self, node: NodeId, length: u64) -> Result<(), &'static str> {
+        let data = &mut self.getFile(node)?.data;
+        if length > data.len() as u64 {
+            return Err("Truncate can only shrink");
+        }
+
+        data.truncate(length as usize);
+        data.shrink_to_fit();
+        Ok(())
+    }
+
+    fn create(
+        &mut self,
+        directory: NodeId,
+        name: &str,
+        kind: NodeKind,
+    ) -> Result<NodeId, &'static str> {
+        if self.lookup(directory, name).is_ok() {
+            return Err("File already exists");
+        }
+
+        self.nodes.push(Some(RamNode::new(kind)));
+        let node = (self.nodes.len() - 1) as NodeId;
+
+        self.getDirectory(directory)?
+            .children
+            .push((name.to_string(), node));
+        Ok(node)
+    }
+
+    fn remove(&mut self, directory: NodeId, name: &str) -> Result<(), &'static str> {
+        let node = self.lookup(directory, name)?;
+        if !self.getNode(node)?.children.is_empty() {
+            return Err("Directory isn't empty");
+        }
+
+        self.getDirectory(directory)?
+            .children
+            .retain(|(_, child)| *child != node);
+        self.nodes[node as usize] = None;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
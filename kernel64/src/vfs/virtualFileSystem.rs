@@ -0,0 +1,412 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use kernel_shared::fileSystem::SeekFrom;
+
+use crate::loggerWriteLine;
+
+use super::{
+    fileSystem::{DirectoryEntry, FileSystem, NodeId, NodeInfo, NodeKind},
+    path::{components, normalize, splitParent},
+    ramFs::RamFs,
+};
+
+// What open can be asked to do; combine with |
+pub const OPEN_READ: u32 = 1 << 0;
+pub const OPEN_WRITE: u32 = 1 << 1;
+pub const OPEN_CREATE: u32 = 1 << 2; // Make it if it isn't there
+pub const OPEN_TRUNCATE: u32 = 1 << 3; // Empty it first, needs OPEN_WRITE
+pub const OPEN_APPEND: u32 = 1 << 4; // Every write goes on the end
+
+// Handed back by open, and what everything else takes to say which file
+pub type FileDescriptor = usize;
+
+const MAX_OPEN_FILES: usize = 64;
+
+// Which file system and which node in it. The mount index is stable for as long as it's mounted.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Inode {
+    mount: usize,
+    node: NodeId,
+}
+
+struct Mount {
+    path: String, // Normalized, so it can be compared with the text of other normalized paths
+    fileSystem: Box<dyn FileSystem>,
+}
+
+struct OpenFile {
+    inode: Inode,
+    position: u64,
+    flags: u32,
+}
+
+struct VfsState {
+    mounts: Vec<Option<Mount>>, // Unmounting leaves a hole so everyone else's index stays put
+    files: Vec<Option<OpenFile>>,
+    workingDirectory: String,
+}
+
+// None until init. Operations take the whole thing out while they run, rather than holding the lock, since the lock
+// turns interrupts off and the disks need those to finish anything. Someone getting in the middle (an interrupt
+// handler, say) is told it's busy.
+static VFS: Mutex<RefCell<Option<VfsState>>> = Mutex::new(RefCell::new(None));
+
+// A ramfs at / to hang everything else off, with /tmp for scratch
+pub fn init() {
+    let mut state = VfsState {
+        mounts: Vec::new(),
+        files: Vec::new(),
+        workingDirectory: "/".to_string(),
+    };
+
+    let mut root = RamFs::new();
+    let rootNode = root.getRoot();
+    if let Err(reason) = root.create(rootNode, "tmp", NodeKind::Directory) {
+        loggerWriteLine!("Couldn't make /tmp: {}", reason);
+    }
+
+    state.mounts.push(Some(Mount {
+        path: "/".to_string(),
+        fileSystem: Box::new(root),
+    }));
+
+    critical_section::with(|cs| *VFS.borrow_ref_mut(cs) = Some(state));
+    loggerWriteLine!("VFS is up with a ramfs at /");
+}
+
+fn with<R>(f: impl FnOnce(&mut VfsState) -> Result<R, &'static str>) -> Result<R, &'static str> {
+    let Some(mut state) = critical_section::with(|cs| VFS.borrow_ref_mut(cs).take()) else {
+        return Err("VFS is busy or not initialized");
+    };
+
+    let result = f(&mut state);
+    critical_section::with(|cs| *VFS.borrow_ref_mut(cs) = Some(state));
+    result
+}
+
+impl VfsState {
+    fn getFileSystem(&mut self, mount: usize) -> Result<&mut dyn FileSystem, &'static str> {
+        match self.mounts.get_mut(mount) {
+            Some(Some(mount)) => Ok(mount.fileSystem.as_mut()),
+            _ => Err("Not mounted"),
+        }
+    }
+
+    fn getMountAt(&self, path: &str) -> Option<usize> {
+        self.mounts
+            .iter()
+            .position(|mount| matches!(mount, Some(mount) if mount.path == path))
+    }
+
+    fn getRoot(&self, mount: usize) -> Result<Inode, &'static str> {
+        match self.mounts.get(mount) {
+            Some(Some(entry)) => Ok(Inode {
+                mount,
+                node: entry.fileSystem.getRoot(),
+            }),
+            _ => Err("Not mounted"),
+        }
+    }
+
+    // Walks a normalized path from /, hopping onto whatever is mounted along the way
+    fn walk(&mut self, path: &str) -> Result<Inode, &'static str> {
+        let mut inode = self.getRoot(self.getMountAt("/").ok_or("Nothing mounted at /")?)?;
+        let mut current = String::new();
+
+        for name in components(path) {
+            current.push('/');
+            current.push_str(name);
+
+            inode = match self.getMountAt(&current) {
+                Some(mount) => self.getRoot(mount)?,
+                None => Inode {
+                    mount: inode.mount,
+                    node: self.getFileSystem(inode.mount)?.lookup(inode.node, name)?,
+                },
+            };
+        }
+
+        Ok(inode)
+    }
+
+    fn resolve(&mut self, path: &str) -> Result<(String, Inode), &'static str> {
+        let path = normalize(&self.workingDirectory, path)?;
+        let inode = self.walk(&path)?;
+        Ok((path, inode))
+    }
+
+    fn getInfo(&mut self, inode: Inode) -> Result<NodeInfo, &'static str> {
+        self.getFileSystem(inode.mount)?.getInfo(inode.node)
+    }
+
+    // Makes the last part of path in whatever directory the rest of it names
+    fn create(&mut self, path: &str, kind: NodeKind) -> Result<Inode, &'static str> {
+        let path = normalize(&self.workingDirectory, path)?;
+        let (parent, name) = splitParent(&path).ok_or("The root already exists")?;
+        let directory = self.walk(parent)?;
+        let node = self
+            .getFileSystem(directory.mount)?
+            .create(directory.node, name, kind)?;
+
+        Ok(Inode {
+            mount: directory.mount,
+            node,
+        })
+    }
+
+    fn getOpenFile(&mut self, fd: FileDescriptor) -> Result<&mut OpenFile, &'static str> {
+        self.files
+            .get_mut(fd)
+            .and_then(|file| file.as_mut())
+            .ok_or("Bad file descriptor")
+    }
+
+    // Something open, or someone's working directory, being on a mount keeps it from going away
+    fn isMountBusy(&self, mount: usize, path: &str) -> bool {
+        let below = |other: &str| {
+            other == path
+                || (other.starts_with(path) && other.as_bytes().get(path.len()) == Some(&b'/'))
+        };
+
+        self.files
+            .iter()
+            .flatten()
+            .any(|file| file.inode.mount == mount)
+            || below(&self.workingDirectory)
+            || self
+                .mounts
+                .iter()
+                .flatten()
+                .any(|other| other.path != path && below(&other.path))
+    }
+}
+
+// Puts fileSystem's root at path, which has to be an existing directory that nothing is mounted on yet
+pub fn mount(path: &str, fileSystem: Box<dyn FileSystem>) -> Result<(), &'static str> {
+    with(|state| {
+        let (path, inode) = state.resolve(path)?;
+        if state.getMountAt(&path).is_some() {
+            return Err("Something is already mounted there");
+        }
+
+        if !state.getInfo(inode)?.isDirectory() {
+            return Err("Not a directory");
+        }
+
+        loggerWriteLine!("Mounted {} at {}", fileSystem.getType(), path);
+        let mount = Some(Mount { path, fileSystem });
+        match state.mounts.iter().position(|mount| mount.is_none()) {
+            Some(index) => state.mounts[index] = mount,
+            None => state.mounts.push(mount),
+        }
+
+        Ok(())
+    })
+}
+
+// Flushes and forgets whatever is mounted at path. Not while anything is using it though.
+pub fn unmount(path: &str) -> Result<(), &'static str> {
+    with(|state| {
+        let path = normalize(&state.workingDirectory, path)?;
+        if path == "/" {
+            return Err("Can't unmount /");
+        }
+
+        let mount = state.getMountAt(&path).ok_or("Nothing mounted there")?;
+        if state.isMountBusy(mount, &path) {
+            return Err("Mount is busy");
+        }
+
+        state.getFileSystem(mount)?.flush()?;
+        state.mounts[mount] = None;
+        Ok(())
+    })
+}
+
+// Every mount as its path and file system type
+pub fn getMounts() -> Result<Vec<(String, &'static str)>, &'static str> {
+    with(|state| {
+        Ok(state
+            .mounts
+            .iter()
+            .flatten()
+            .map(|mount| (mount.path.clone(), mount.fileSystem.getType()))
+            .collect())
+    })
+}
+
+pub fn open(path: &str, flags: u32) -> Result<FileDescriptor, &'static str> {
+    if flags & OPEN_TRUNCATE != 0 && flags & OPEN_WRITE == 0 {
+        return Err("Truncating needs write access");
+    }
+
+    with(|state| {
+        let slot = match state.files.iter().position(|file| file.is_none()) {
+            Some(slot) => slot,
+            None if state.files.len() < MAX_OPEN_FILES => {
+                state.files.push(None);
+                state.files.len() - 1
+            }
+            None => return Err("Too many open files"),
+        };
+
+        let inode = match state.resolve(path) {
+            Ok((_, inode)) => inode,
+            Err(_) if flags & OPEN_CREATE != 0 => state.create(path, NodeKind::File)?,
+            Err(reason) => return Err(reason),
+        };
+
+        if state.getInfo(inode)?.isDirectory() {
+            return Err("Is a directory");
+        }
+
+        if flags & OPEN_TRUNCATE != 0 {
+            state.getFileSystem(inode.mount)?.truncate(inode.node, 0)?;
+        }
+
+        state.files[slot] = Some(OpenFile {
+            inode,
+            position: 0,
+            flags,
+        });
+
+        Ok(slot)
+    })
+}
+
+// Anything written is flushed to the media on the way out
+pub fn close(fd: FileDescriptor) -> Result<(), &'static str> {
+    with(|state| {
+        let file = state.files.get_mut(fd).and_then(|file| file.take());
+        let file = file.ok_or("Bad file descriptor")?;
+        if file.flags & OPEN_WRITE != 0 {
+            state.getFileSystem(file.inode.mount)?.flush()?;
+        }
+
+        Ok(())
+    })
+}
+
+// Reads from the current position, returning how much was read; 0 means end of file
+pub fn read(fd: FileDescriptor, buffer: &mut [u8]) -> Result<usize, &'static str> {
+    with(|state| {
+        let file = state.getOpenFile(fd)?;
+        if file.flags & OPEN_READ == 0 {
+            return Err("Not open for reading");
+        }
+
+        let (inode, position) = (file.inode, file.position);
+        let length = state
+            .getFileSystem(inode.mount)?
+            .read(inode.node, position, buffer)?;
+
+        state.getOpenFile(fd)?.position += length as u64;
+        Ok(length)
+    })
+}
+
+pub fn write(fd: FileDescriptor, buffer: &[u8]) -> Result<usize, &'static str> {
+    with(|state| {
+        let file = state.getOpenFile(fd)?;
+        if file.flags & OPEN_WRITE == 0 {
+            return Err("Not open for writing");
+        }
+
+        let (inode, flags) = (file.inode, file.flags);
+        let position = if flags & OPEN_APPEND != 0 {
+            state.getInfo(inode)?.size
+        } else {
+            state.getOpenFile(fd)?.position
+        };
+
+        let length = state
+            .getFileSystem(inode.mount)?
+            .write(inode.node, position, buffer)?;
+
+        state.getOpenFile(fd)?.position = position + length as u64;
+        Ok(length)
+    })
+}
+
+// Moves the position, returning where it ended up. The end is fine, past it isn't.
+pub fn seek(fd: FileDescriptor, from: SeekFrom) -> Result<u64, &'static str> {
+    with(|state| {
+        let inode = state.getOpenFile(fd)?.inode;
+        let size = state.getInfo(inode)?.size;
+        let file = state.getOpenFile(fd)?;
+        let position = from.resolve(file.position, size)?;
+        if position > size {
+            return Err("Seek past the end");
+        }
+
+        file.position = position;
+        Ok(position)
+    })
+}
+
+pub fn stat(path: &str) -> Result<NodeInfo, &'static str> {
+    with(|state| {
+        let (_, inode) = state.resolve(path)?;
+        state.getInfo(inode)
+    })
+}
+
+pub fn statOpen(fd: FileDescriptor) -> Result<NodeInfo, &'static str> {
+    with(|state| {
+        let inode = state.getOpenFile(fd)?.inode;
+        state.getInfo(inode)
+    })
+}
+
+// Everything in the directory except . and ..
+pub fn readDirectory(path: &str) -> Result<Vec<DirectoryEntry>, &'static str> {
+    with(|state| {
+        let (_, inode) = state.resolve(path)?;
+        state.getFileSystem(inode.mount)?.readDirectory(inode.node)
+    })
+}
+
+pub fn createDirectory(path: &str) -> Result<(), &'static str> {
+    with(|state| {
+        let inode = state.create(path, NodeKind::Directory)?;
+        state.getFileSystem(inode.mount)?.flush()
+    })
+}
+
+// Directories have to be empty, and can't have anything mounted on them
+pub fn remove(path: &str) -> Result<(), &'static str> {
+    with(|state| {
+        let path = normalize(&state.workingDirectory, path)?;
+        if state.getMountAt(&path).is_some() {
+            return Err("Something is mounted there");
+        }
+
+        let (parent, name) = splitParent(&path).ok_or("Can't remove /")?;
+        let directory = state.walk(parent)?;
+        let fileSystem = state.getFileSystem(directory.mount)?;
+        fileSystem.remove(directory.node, name)?;
+        fileSystem.flush()
+    })
+}
+
+pub fn changeDirectory(path: &str) -> Result<(), &'static str> {
+    with(|state| {
+        let (path, inode) = state.resolve(path)?;
+        if !state.getInfo(inode)?.isDirectory() {
+            return Err("Not a directory");
+        }
+
+        state.workingDirectory = path;
+        Ok(())
+    })
+}
+
+pub fn getWorkingDirectory() -> Result<String, &'static str> {
+    with(|state| Ok(state.workingDirectory.clone()))
+}
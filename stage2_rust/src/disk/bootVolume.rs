@@ -0,0 +1,121 @@
+use core::ptr::copy_nonoverlapping;
+
+use kernel_shared::{
+    disk::{
+        blockDevice::BlockDevice,
+        partition::Partition,
+        partitionTable::{PartitionInfo, readPartitions},
+    },
+    fileSystem::fat::fatFileSystem::FatFileSystem,
+    vgaWriteLine,
+};
+
+use super::diskDriver::DiskDriver;
+
+// The BIOS can only read below 64K, so files are pulled through this much stack on their way to wherever they go
+const LOAD_CHUNK_SIZE: usize = 4096;
+
+// Biggest chunk of a text file we'll bother printing
+const PRINT_LENGTH: usize = 512;
+
+// Only the first few partitions are considered, which leaves stack for the filesystem
+const MAX_PARTITIONS: usize = 8;
+
+// Mounts the FAT volume on the bootable partition, or the first FAT partition if nothing is marked bootable (which is
+// normal for GPT). Whichever partition table and whichever FAT.
+pub fn mountBootVolume(
+    disk: &mut DiskDriver,
+) -> Result<FatFileSystem<Partition<&mut DiskDriver>>, &'static str> {
+    let mut partitions = [PartitionInfo::EMPTY; MAX_PARTITIONS];
+    let (scheme, count) = readPartitions(disk, &mut partitions)?;
+    let partitions = &partitions[..count.min(MAX_PARTITIONS)];
+    vgaWriteLine!("{:?} partition table with {} partition(s)", scheme, count);
+
+    let mut active = None;
+    for partition in partitions.iter().filter(|partition| partition.bootable) {
+        if active.is_some() {
+            return Err("Multiple bootable partitions found");
+        }
+
+        active = Some(partition);
+    }
+
+    let partition = match active {
+        Some(partition) => partition,
+        None => partitions
+            .iter()
+            .find(|partition| partition.isFat())
+            .ok_or("No bootable or FAT partition found")?,
+    };
+
+    vgaWriteLine!(
+        "Booting from partition {} @ LBA 0x{:X} for {} sectors",
+        partition.number,
+        partition.startLba,
+        partition.sectorCount
+    );
+
+    if !partition.isFat() {
+        return Err("Boot partition isn't FAT");
+    }
+
+    let fat = FatFileSystem::mount(partition.open(disk)?)?;
+
+    vgaWriteLine!(
+        "{:?} volume '{}' with {} byte clusters",
+        fat.getFatType(),
+        fat.getVolumeLabel(),
+        fat.getClusterSize()
+    );
+
+    Ok(fat)
+}
+
+pub fn printFile<D: BlockDevice>(
+    fat: &mut FatFileSystem<D>,
+    path: &str,
+) -> Result<(), &'static str> {
+    let mut file = fat.open(path)?;
+    let mut buffer = [0u8; PRINT_LENGTH];
+    let length = fat.read(&mut file, &mut buffer)?;
+
+    vgaWriteLine!(
+        "{}: {}",
+        path,
+        core::str::from_utf8(&buffer[..length]).unwrap_or("Invalid string data")
+    );
+
+    Ok(())
+}
+
+// The address must be capable of holding the entire file
+pub unsafe fn loadFile<D: BlockDevice>(
+    fat: &mut FatFileSystem<D>,
+    path: &str,
+    address: usize,
+) -> Result<usize, &'static str> {
+    let mut file = fat.open(path)?;
+    let mut buffer = [0u8; LOAD_CHUNK_SIZE];
+    let mut loaded = 0;
+
+    vgaWriteLine!("Loading {} of size {} bytes", path, file.getInfo().size);
+
+    loop {
+        let length = fat.read(&mut file, &mut buffer)?;
+        if length == 0 {
+            break;
+        }
+
+        unsafe {
+            copy_nonoverlapping(buffer.as_ptr(), (address + loaded) as *mut u8, length);
+        }
+
+        loaded += length;
+    }
+
+    if loaded != file.getInfo().size as usize {
+        return Err("File ended early");
+    }
+
+    Ok(loaded)
+}
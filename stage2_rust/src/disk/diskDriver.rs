@@ -1,6 +1,17 @@
 use core::arch::asm;
 
-use kernel_shared::vgaWriteLine;
+use kernel_shared::{
+    disk::blockDevice::{BlockDevice, checkRequest},
+    vgaWriteLine,
+};
+
+// Everything the extended INT 13h functions move is in 512 byte sectors
+const SECTOR_SIZE: usize = 512;
+
+// https://en.wikipedia.org/wiki/INT_13H#INT_13h_AH=42h:_Extended_Read_Sectors_From_Drive
+const EXTENDED_READ: u8 = 0x42;
+const EXTENDED_WRITE: u8 = 0x43;
+const EXTENDED_GET_PARAMETERS: u8 = 0x48;
 
 // Disk Address Packet Structure
 #[repr(C, packed)]
@@ -13,34 +24,77 @@ struct DAPS {
     lbaStart: u64,
 }
 
+// Result Buffer for AH=48h
+#[repr(C, packed)]
+struct DriveParameters {
+    size: u16, // Has to be filled in with how big we think this is
+    flags: u16,
+    cylinders: u32,
+    heads: u32,
+    sectorsPerTrack: u32,
+    totalSectors: u64,
+    bytesPerSector: u16,
+}
+
+// What we go with when the BIOS won't say how big the drive is. Not knowing the size isn't the same as it being
+// empty, so nothing gets refused up front and the BIOS gets to fail anything that really is past the end.
+const UNKNOWN_SECTOR_COUNT: u64 = u64::MAX;
+
 pub struct DiskDriver {
-    drive: u8, // BIOS drive number
+    drive: u8,        // BIOS drive number
+    sectorCount: u64, // UNKNOWN_SECTOR_COUNT if the BIOS wouldn't say
 }
 
 impl DiskDriver {
     pub fn new(drive: u8) -> Self {
-        DiskDriver { drive }
+        let sectorCount = match Self::getTotalSectors(drive) {
+            Ok(sectorCount) => sectorCount,
+            Err(reason) => {
+                vgaWriteLine!("Couldn't get drive size: {}", reason);
+                UNKNOWN_SECTOR_COUNT
+            }
+        };
+
+        DiskDriver { drive, sectorCount }
     }
 
     // Read sectors from disk. The disk is read starting from the LBA address
     // into the buffer for the full length of the buffer. The buffer's length
     // must be a multiple of 512 bytes.
     pub fn read(&self, lba: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
-        if (buffer.as_ptr() as usize) > u16::MAX as usize {
+        self.transfer(
+            EXTENDED_READ,
+            lba,
+            buffer.as_mut_ptr() as usize,
+            buffer.len(),
+        )
+    }
+
+    // Same rules as read
+    pub fn write(&self, lba: u64, buffer: &[u8]) -> Result<(), &'static str> {
+        self.transfer(EXTENDED_WRITE, lba, buffer.as_ptr() as usize, buffer.len())
+    }
+
+    fn transfer(
+        &self,
+        function: u8,
+        lba: u64,
+        bufferAddress: usize,
+        length: usize,
+    ) -> Result<(), &'static str> {
+        if bufferAddress > u16::MAX as usize {
             return Err("Buffer address overflow");
         }
 
-        if buffer.len() % 512 != 0 {
+        if length % SECTOR_SIZE != 0 {
             return Err("Buffer length must be a multiple of 512 bytes");
         }
 
-        let bufferAddress = buffer.as_mut_ptr() as u16;
-
         let mut daps = DAPS {
             structSize: size_of::<DAPS>().try_into().unwrap(),
             zero: 0,
-            sectorsToRead: (buffer.len() / 512).try_into().unwrap(),
-            readToOffset: bufferAddress,
+            sectorsToRead: (length / SECTOR_SIZE).try_into().unwrap(),
+            readToOffset: bufferAddress as u16,
             readToSegment: 0,
             lbaStart: lba,
         };
@@ -51,26 +105,99 @@ impl DiskDriver {
             return Err("DAP address overflow");
         }
         let dapsAddress = dapsAddress as u16;
-        let mut ah: u8 = 0x42; // Extended Read function
 
-        // https://en.wikipedia.org/wiki/INT_13H#INT_13h_AH=42h:_Extended_Read_Sectors_From_Drive
+        // AL is the write flags for 43h, 0 means don't verify. 42h ignores it.
+        let mut ax: u16 = (function as u16) << 8;
+
         unsafe {
             asm!(
                 "push si",
                 "mov si, bx",
                 "int 0x13",
                 "pop si",
-                inout("ah") ah,
+                inout("ax") ax,
                 in("dl") self.drive,
                 in("bx") dapsAddress,
             );
         }
 
+        let ah = (ax >> 8) as u8;
+        if ah != 0 {
+            vgaWriteLine!("Disk function 0x{:X} error: 0x{:X}", function, ah);
+            return Err("Disk error");
+        }
+
+        Ok(())
+    }
+
+    // https://en.wikipedia.org/wiki/INT_13H#INT_13h_AH=48h:_Extended_Read_Drive_Parameters
+    fn getTotalSectors(drive: u8) -> Result<u64, &'static str> {
+        let mut parameters = DriveParameters {
+            size: size_of::<DriveParameters>() as u16,
+            flags: 0,
+            cylinders: 0,
+            heads: 0,
+            sectorsPerTrack: 0,
+            totalSectors: 0,
+            bytesPerSector: 0,
+        };
+
+        let parametersAddress = &mut parameters as *mut _ as usize;
+        if parametersAddress > u16::MAX as usize {
+            return Err("Parameters address overflow");
+        }
+
+        let mut ah = EXTENDED_GET_PARAMETERS;
+        unsafe {
+            asm!(
+                "push si",
+                "mov si, bx",
+                "int 0x13",
+                "pop si",
+                inout("ah") ah,
+                in("dl") drive,
+                in("bx") parametersAddress as u16,
+            );
+        }
+
         if ah != 0 {
-            vgaWriteLine!("Disk read error: 0x{:X}", ah);
-            return Err("Disk read error");
+            return Err("Get drive parameters failed");
         }
 
+        if parameters.bytesPerSector as usize != SECTOR_SIZE {
+            return Err("Drive doesn't use 512 byte sectors");
+        }
+
+        // Some BIOSes leave it at 0 for drives they emulate, like USB sticks
+        if parameters.totalSectors == 0 {
+            return Err("Drive size was left blank");
+        }
+
+        Ok(parameters.totalSectors)
+    }
+}
+
+impl BlockDevice for DiskDriver {
+    fn getSectorSize(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn getSectorCount(&self) -> u64 {
+        self.sectorCount
+    }
+
+    fn read(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+        checkRequest(self, lba, buffer.len())?;
+        DiskDriver::read(self, lba, buffer)
+    }
+
+    fn write(&mut self, lba: u64, buffer: &[u8]) -> Result<(), &'static str> {
+        checkRequest(self, lba, buffer.len())?;
+        DiskDriver::write(self, lba, buffer)
+    }
+
+    // The BIOS doesn't hold on to writes
+    fn flush(&mut self) -> Result<(), &'static str> {
         Ok(())
     }
 }
@@ -1,3 +1,2 @@
+pub mod bootVolume;
 pub mod diskDriver;
-pub mod fatDriver;
-pub mod mbs;
@@ -8,8 +8,8 @@ mod disk;
 
 use core::{arch::asm, panic::PanicInfo};
 use disk::{
+    bootVolume::{loadFile, mountBootVolume, printFile},
     diskDriver::DiskDriver,
-    fatDriver::FatDriver,
 };
 use kernel_shared::{
     assemblyStuff::{halt::haltLoop, misc::disablePic}, gdtStuff::Gdt, haltLoopWithMessage, magicConstants::KERNEL32_JUMP_ADDRESS, memory::{map::MemoryMap, mapEntry::MemoryMapEntryType}, textMode::teletype, vgaWriteLine
@@ -32,7 +32,7 @@ fn panic(pi: &PanicInfo) -> ! {
     } else {
         vgaWriteLine!("16-big panic!");
         // We're risking a further panic here, but really want to see the message
-        haltLoopWithMessage!("{:?}", pi);
+        haltLoopWithMessage!("{}", pi);
     }
 }
 
@@ -101,6 +101,14 @@ fn findSpaceForKernels(mm: &MemoryMap, needed_size: usize) -> usize {
     haltLoopWithMessage!("Failed to find space for kernel32");
 }
 
+// Rather than unwrap(), whose Debug formatting of the error costs more code than stage2 has room for under 64K
+fn orHalt<T>(result: Result<T, &'static str>, what: &str) -> T {
+    match result {
+        Ok(value) => value,
+        Err(msg) => haltLoopWithMessage!("{} failed: {}", what, msg),
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "fastcall" fn DanMain(driveNumber: u32) -> ! {
     #[cfg(not(relocation_model = "static"))]
@@ -132,35 +140,35 @@ pub extern "fastcall" fn DanMain(driveNumber: u32) -> ! {
 
     mm.dump();
 
-    let disk = DiskDriver::new(driveNumber.try_into().unwrap());
+    let mut disk = DiskDriver::new(driveNumber.try_into().unwrap());
 
-    let fat = FatDriver::new(disk).unwrap();
-    fat.printHiText().unwrap();
+    let mut fat = orHalt(mountBootVolume(&mut disk), "Mounting the boot volume");
+    orHalt(printFile(&mut fat, "/HI.TXT"), "Printing /HI.TXT");
 
-    let kernel32Info = fat.getFileInfo((b"KERNEL", b"BIN")).unwrap();
-    let kernel32PaddedSize = ((kernel32Info.file_size as usize + 1023) / 1024) * 1024;
+    let kernel32Info = orHalt(fat.stat("/KERNEL.BIN"), "Finding /KERNEL.BIN");
+    let kernel32PaddedSize = ((kernel32Info.size as usize + 1023) / 1024) * 1024;
 
-    let kernel64Info = fat.getFileInfo((b"KERNEL64", b"ELF")).unwrap();
-    let kernel64PaddedSize = ((kernel64Info.file_size as usize + 1023) / 1024) * 1024;
+    let kernel64Info = orHalt(fat.stat("/KERNEL64.ELF"), "Finding /KERNEL64.ELF");
+    let kernel64PaddedSize = ((kernel64Info.size as usize + 1023) / 1024) * 1024;
 
     let kernel32Address = findSpaceForKernels(&mm, kernel32PaddedSize + kernel64PaddedSize);
     let kernel64Address = kernel32Address + kernel32PaddedSize;
 
     unsafe {
-        fat.loadFile(kernel32Address, &kernel32Info).unwrap();
-        fat.loadFile(kernel64Address, &kernel64Info).unwrap();
+        orHalt(loadFile(&mut fat, "/KERNEL.BIN", kernel32Address), "Loading /KERNEL.BIN");
+        orHalt(loadFile(&mut fat, "/KERNEL64.ELF", kernel64Address), "Loading /KERNEL64.ELF");
     }
 
     vgaWriteLine!(
         "Loaded kernel32 to 0x{:X} for {} bytes",
         kernel32Address,
-        kernel32Info.file_size
+        kernel32Info.size
     );
 
     vgaWriteLine!(
         "Loaded kernel64 to 0x{:X} for {} bytes",
         kernel64Address,
-        kernel64Info.file_size
+        kernel64Info.size
     );
 
     unsafe {